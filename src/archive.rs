@@ -0,0 +1,228 @@
+use anyhow::{anyhow, Context, Result};
+use crate::project::Project;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Name of the archive entry recording which project the target came from
+const ORIGIN_ENTRY: &str = ".deepclean-origin";
+
+/// A filesystem is considered nearly full below this fraction of free space
+const NEARLY_FULL_RATIO: f64 = 0.10;
+
+/// A name for a project that is unique to its path: `<project-name>-<hash>`
+pub fn project_key(project_path: &Path) -> String {
+    let name = project_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("project");
+    let mut hasher = DefaultHasher::new();
+    project_path.hash(&mut hasher);
+    format!("{}-{:016x}", name, hasher.finish())
+}
+
+/// Build the archive file name for a project: `<project-name>-<hash>.tar.zst`
+pub fn archive_file_name(project_path: &Path) -> String {
+    format!("{}.tar.zst", project_key(project_path))
+}
+
+#[cfg(unix)]
+fn same_filesystem(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => true,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_filesystem(_a: &Path, _b: &Path) -> bool {
+    // Without a cheap device ID, assume the worst
+    true
+}
+
+/// Decide whether archiving a target into `archive_dir` is safe
+///
+/// Archiving onto the same filesystem only makes sense when there is room to
+/// hold the archive next to the original target. When that filesystem is
+/// nearly full (less than 10% free, or less free space than the target
+/// itself), plain cleaning is the better choice.
+pub fn should_archive(target_dir: &Path, archive_dir: &Path, target_bytes: u64) -> bool {
+    if !same_filesystem(target_dir, archive_dir) {
+        return true;
+    }
+    match (fs2::available_space(archive_dir), fs2::total_space(archive_dir)) {
+        (Ok(available), Ok(total)) => {
+            available >= target_bytes && (available as f64) >= (total as f64) * NEARLY_FULL_RATIO
+        }
+        _ => true,
+    }
+}
+
+/// Pack a project's target directory into `archive_dir`
+///
+/// The target is stored under its own name, so it is restored to the same
+/// place. Returns the archive path and its size in bytes. The original
+/// target directory is left untouched; removing it is up to the caller.
+pub fn archive_target(project: &Project, target_dir: &Path, archive_dir: &Path) -> Result<(PathBuf, u64)> {
+    let target_name = target_dir
+        .file_name()
+        .ok_or_else(|| anyhow!("Not a target directory: {:?}", target_dir))?;
+    fs::create_dir_all(archive_dir)
+        .with_context(|| format!("Failed to create archive directory: {:?}", archive_dir))?;
+
+    let archive_path = archive_dir.join(archive_file_name(&project.path));
+    let partial_path = archive_path.with_extension("zst.partial");
+
+    let write = || -> Result<()> {
+        let file = File::create(&partial_path)?;
+        let encoder = zstd::Encoder::new(file, 3)?;
+        let mut builder = tar::Builder::new(encoder);
+        builder.follow_symlinks(false);
+
+        let origin = project.path.to_string_lossy().to_string();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(origin.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, ORIGIN_ENTRY, origin.as_bytes())?;
+
+        builder.append_dir_all(target_name, target_dir)?;
+        builder.into_inner()?.finish()?;
+        Ok(())
+    };
+
+    if let Err(e) = write() {
+        let _ = fs::remove_file(&partial_path);
+        return Err(e).with_context(|| format!("Failed to archive {:?}", target_dir));
+    }
+
+    fs::rename(&partial_path, &archive_path)
+        .with_context(|| format!("Failed to finalize archive: {:?}", archive_path))?;
+    let archive_bytes = fs::metadata(&archive_path)?.len();
+    Ok((archive_path, archive_bytes))
+}
+
+/// Unpack an archive created by `archive_target` back into its project
+///
+/// Returns the target directory that was restored.
+pub fn restore_archive(archive: &Path) -> Result<PathBuf> {
+    let file = File::open(archive).with_context(|| format!("Failed to open archive: {:?}", archive))?;
+    let decoder = zstd::Decoder::new(file)?;
+    let mut tar = tar::Archive::new(decoder);
+    tar.set_preserve_mtime(true);
+
+    let mut project_dir: Option<PathBuf> = None;
+    let mut target_dir: Option<PathBuf> = None;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.as_os_str() == ORIGIN_ENTRY {
+            let mut origin = String::new();
+            entry.read_to_string(&mut origin)?;
+            let dir = PathBuf::from(origin);
+            if !dir.is_dir() {
+                return Err(anyhow!("Project directory no longer exists: {:?}", dir));
+            }
+            project_dir = Some(dir);
+            continue;
+        }
+
+        let dir = project_dir
+            .as_ref()
+            .ok_or_else(|| anyhow!("Not a deepclean archive (missing origin): {:?}", archive))?;
+        if target_dir.is_none() {
+            // Everything else is below the target directory's own entry
+            let name = entry
+                .path()?
+                .components()
+                .next()
+                .ok_or_else(|| anyhow!("Not a deepclean archive (empty entry): {:?}", archive))?
+                .as_os_str()
+                .to_owned();
+            let target = dir.join(name);
+            if target.exists() {
+                return Err(anyhow!("Refusing to restore over an existing target directory: {:?}", target));
+            }
+            target_dir = Some(target);
+        }
+        entry.unpack_in(dir)?;
+    }
+
+    project_dir.ok_or_else(|| anyhow!("Not a deepclean archive (missing origin): {:?}", archive))?;
+    target_dir.ok_or_else(|| anyhow!("Archive holds no target directory: {:?}", archive))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_archive_file_name_is_stable() {
+        let a = archive_file_name(Path::new("/work/app"));
+        let b = archive_file_name(Path::new("/work/app"));
+        let c = archive_file_name(Path::new("/other/app"));
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert!(a.starts_with("app-"));
+        assert!(a.ends_with(".tar.zst"));
+    }
+
+    #[test]
+    fn test_archive_and_restore_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_dir = temp_dir.path().join("app");
+        let target_dir = project_dir.join("target");
+        fs::create_dir_all(target_dir.join("debug/deps")).unwrap();
+        fs::write(target_dir.join("debug/deps/libfoo.rlib"), vec![7u8; 4096]).unwrap();
+        fs::write(target_dir.join(".rustc_info.json"), "{}").unwrap();
+
+        let project = Project::new(project_dir.clone(), false);
+        let archive_dir = temp_dir.path().join("archives");
+        let (archive_path, archive_bytes) = archive_target(&project, &target_dir, &archive_dir).unwrap();
+        assert!(archive_path.exists());
+        assert!(archive_bytes > 0);
+
+        fs::remove_dir_all(&target_dir).unwrap();
+        let restored = restore_archive(&archive_path).unwrap();
+        assert_eq!(restored, target_dir);
+        assert_eq!(
+            fs::read(target_dir.join("debug/deps/libfoo.rlib")).unwrap(),
+            vec![7u8; 4096]
+        );
+        assert_eq!(fs::read_to_string(target_dir.join(".rustc_info.json")).unwrap(), "{}");
+    }
+
+    #[test]
+    fn test_restore_refuses_existing_target() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_dir = temp_dir.path().join("app");
+        fs::create_dir_all(project_dir.join("target")).unwrap();
+        fs::write(project_dir.join("target/file"), "data").unwrap();
+
+        let project = Project::new(project_dir.clone(), false);
+        let (archive_path, _) = archive_target(&project, &project_dir.join("target"), temp_dir.path()).unwrap();
+        assert!(restore_archive(&archive_path).is_err());
+    }
+
+    #[test]
+    fn test_custom_target_name_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_dir = temp_dir.path().join("app");
+        let build_dir = project_dir.join("build");
+        fs::create_dir_all(build_dir.join("debug")).unwrap();
+        fs::write(build_dir.join("debug/app"), "binary").unwrap();
+
+        let project = Project::new(project_dir.clone(), false);
+        let (archive_path, _) = archive_target(&project, &build_dir, temp_dir.path()).unwrap();
+        fs::remove_dir_all(&build_dir).unwrap();
+        // A target/ made since doesn't get in the way
+        fs::create_dir_all(project_dir.join("target")).unwrap();
+
+        assert_eq!(restore_archive(&archive_path).unwrap(), build_dir);
+        assert_eq!(fs::read_to_string(build_dir.join("debug/app")).unwrap(), "binary");
+        assert!(restore_archive(&archive_path).is_err());
+    }
+}
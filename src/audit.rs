@@ -0,0 +1,148 @@
+use anyhow::{Context, Result};
+use crate::utils::format_rfc3339;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// One line of the audit log
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum AuditEntry {
+    /// Header written first by every run
+    Run {
+        at: String,
+        version: String,
+        pid: u32,
+        root: PathBuf,
+        args: Vec<String>,
+        dry_run: bool,
+    },
+    /// A directory or file removed directly (or that would be, in a dry run)
+    Removed {
+        at: String,
+        project: PathBuf,
+        path: PathBuf,
+        bytes: u64,
+    },
+    /// A `cargo clean` invocation, with the target size around it
+    CargoClean {
+        at: String,
+        project: PathBuf,
+        command: String,
+        bytes_before: u64,
+        bytes_after: u64,
+    },
+}
+
+impl AuditEntry {
+    /// Header for the current run
+    pub fn run(root: &Path, dry_run: bool) -> Self {
+        AuditEntry::Run {
+            at: format_rfc3339(SystemTime::now()),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            pid: std::process::id(),
+            root: root.to_path_buf(),
+            args: std::env::args().collect(),
+            dry_run,
+        }
+    }
+
+    /// `path` was removed from `project` just now
+    pub fn removed(project: &Path, path: &Path, bytes: u64) -> Self {
+        AuditEntry::Removed {
+            at: format_rfc3339(SystemTime::now()),
+            project: project.to_path_buf(),
+            path: path.to_path_buf(),
+            bytes,
+        }
+    }
+
+    /// `command` just finished in `project`
+    pub fn cargo_clean(project: &Path, command: &str, bytes_before: u64, bytes_after: u64) -> Self {
+        AuditEntry::CargoClean {
+            at: format_rfc3339(SystemTime::now()),
+            project: project.to_path_buf(),
+            command: command.to_string(),
+            bytes_before,
+            bytes_after,
+        }
+    }
+}
+
+/// JSON Lines record of everything a run deleted
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    /// Open `path` for appending and write the run's header
+    pub fn open(path: &Path, header: &AuditEntry) -> Result<AuditLog> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open audit log {:?}", path))?;
+        let log = AuditLog {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        };
+        log.record(std::slice::from_ref(header))?;
+        Ok(log)
+    }
+
+    /// Append entries, all in one write so concurrent projects never interleave lines
+    pub fn record(&self, entries: &[AuditEntry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut lines = String::new();
+        for entry in entries {
+            lines.push_str(&serde_json::to_string(entry)?);
+            lines.push('\n');
+        }
+
+        let mut file = self.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        file.write_all(lines.as_bytes())
+            .and_then(|()| file.sync_data())
+            .with_context(|| format!("Failed to write audit log {:?}", self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn read_lines(path: &Path) -> Vec<serde_json::Value> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_header_comes_first_and_runs_append() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("audit.jsonl");
+        let project = temp_dir.path().join("app");
+
+        let log = AuditLog::open(&path, &AuditEntry::run(temp_dir.path(), true)).unwrap();
+        log.record(&[AuditEntry::removed(&project, &project.join("target"), 100)]).unwrap();
+        drop(log);
+        AuditLog::open(&path, &AuditEntry::run(temp_dir.path(), false)).unwrap();
+
+        let lines = read_lines(&path);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["event"], "run");
+        assert_eq!(lines[0]["dry_run"], true);
+        assert_eq!(lines[0]["pid"], std::process::id());
+        assert_eq!(lines[1]["event"], "removed");
+        assert_eq!(lines[1]["bytes"], 100);
+        assert_eq!(lines[2]["dry_run"], false);
+    }
+}
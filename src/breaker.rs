@@ -0,0 +1,66 @@
+use crate::cleaner::CleanResult;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Exit code when a run stopped dispatching projects after too many failures
+pub const EXIT_ABORTED: i32 = 3;
+
+/// Stops handing out new projects once enough have failed
+///
+/// When cargo itself is broken every project fails, each one slowly; this
+/// turns that into a quick abort. Projects already running are left to finish.
+#[derive(Debug)]
+pub struct FailureBreaker {
+    limit: Option<usize>,
+    failures: AtomicUsize,
+}
+
+impl FailureBreaker {
+    /// A breaker that trips after `limit` failures, or never without one
+    pub fn new(limit: Option<usize>) -> Self {
+        FailureBreaker {
+            limit,
+            failures: AtomicUsize::new(0),
+        }
+    }
+
+    /// Whether projects that haven't started yet should be left alone
+    pub fn tripped(&self) -> bool {
+        self.limit.is_some_and(|limit| self.failures.load(Ordering::SeqCst) >= limit)
+    }
+
+    /// Count a finished project
+    pub fn record(&self, result: &CleanResult) {
+        if !result.success {
+            self.failures.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// How many finished projects failed so far
+    pub fn failures(&self) -> usize {
+        self.failures.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::Project;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_breaker_trips_at_limit() {
+        let project = Project::new(PathBuf::from("/code/app"), false);
+        let breaker = FailureBreaker::new(Some(2));
+        breaker.record(&CleanResult::new(&project, 100));
+        breaker.record(&CleanResult::failed(&project, "boom".to_string()));
+        assert!(!breaker.tripped());
+        breaker.record(&CleanResult::failed(&project, "boom".to_string()));
+        assert!(breaker.tripped());
+        assert_eq!(breaker.failures(), 2);
+
+        // Without a limit it never trips
+        let breaker = FailureBreaker::new(None);
+        breaker.record(&CleanResult::failed(&project, "boom".to_string()));
+        assert!(!breaker.tripped());
+    }
+}
@@ -0,0 +1,117 @@
+use std::time::{Duration, Instant};
+
+/// Where the time comes from, so tests can fake its passing
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// The real monotonic clock
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Stops handing out new projects once the cleaning phase has run too long
+///
+/// Like `FailureBreaker`, projects already running are left to finish, so a
+/// run can overshoot the budget by however long its slowest project takes.
+pub struct TimeBudget<C: Clock = SystemClock> {
+    budget: Option<Duration>,
+    started: Instant,
+    clock: C,
+}
+
+impl TimeBudget {
+    /// Start the clock on a budget, or on none
+    pub fn start(budget: Option<Duration>) -> Self {
+        TimeBudget::with_clock(budget, SystemClock)
+    }
+}
+
+impl<C: Clock> TimeBudget<C> {
+    pub fn with_clock(budget: Option<Duration>, clock: C) -> Self {
+        TimeBudget {
+            budget,
+            started: clock.now(),
+            clock,
+        }
+    }
+
+    /// Whether projects that haven't started yet should be left alone
+    pub fn exhausted(&self) -> bool {
+        self.budget
+            .is_some_and(|budget| self.clock.now().saturating_duration_since(self.started) >= budget)
+    }
+}
+
+/// Indices of `sizes` from largest to smallest, ties in their original order
+///
+/// With a time budget the biggest targets go first, so whatever is left
+/// when it runs out is what matters least.
+pub fn largest_first(sizes: &[u64]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(sizes[i]));
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cleaner::SkipReason;
+    use std::sync::Mutex;
+
+    const BUDGET_EXHAUSTED: &str = SkipReason::BudgetExhausted.as_str();
+
+    /// A clock that only moves when told to
+    struct MockClock(Mutex<Instant>);
+
+    impl MockClock {
+        fn advance(&self, by: Duration) {
+            *self.0.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for &MockClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    /// Dispatch projects one by one the way `main` does, each taking its delay
+    fn run(delays: &[u64], budget: Option<u64>) -> Vec<&'static str> {
+        let clock = MockClock(Mutex::new(Instant::now()));
+        let budget = TimeBudget::with_clock(budget.map(Duration::from_secs), &clock);
+        delays
+            .iter()
+            .map(|&delay| {
+                if budget.exhausted() {
+                    return BUDGET_EXHAUSTED;
+                }
+                clock.advance(Duration::from_secs(delay));
+                "cleaned"
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_budget_stops_dispatch_once_spent() {
+        // The third project starts at 20s, inside the budget, and is allowed to overrun it
+        assert_eq!(
+            run(&[10, 10, 30, 5, 5], Some(25)),
+            vec!["cleaned", "cleaned", "cleaned", BUDGET_EXHAUSTED, BUDGET_EXHAUSTED]
+        );
+        assert_eq!(run(&[10, 10], Some(20)), vec!["cleaned", "cleaned"]);
+        assert_eq!(run(&[10, 10, 1], Some(20)), vec!["cleaned", "cleaned", BUDGET_EXHAUSTED]);
+        // Without a budget every project is cleaned
+        assert!(run(&[1000; 4], None).iter().all(|&r| r == "cleaned"));
+    }
+
+    #[test]
+    fn test_largest_first() {
+        assert_eq!(largest_first(&[10, 300, 0, 300, 50]), vec![1, 3, 4, 0, 2]);
+        assert!(largest_first(&[]).is_empty());
+    }
+}
@@ -0,0 +1,483 @@
+use anyhow::{anyhow, Context, Result};
+use crate::sccache::SccacheReport;
+use crate::utils::get_directory_size;
+use fs2::FileExt;
+use semver::Version;
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// Cache directories inside CARGO_HOME, and whether a normal `--clean` removes them
+///
+/// Extracted sources and git checkouts are re-created from the downloaded
+/// `.crate` files and git databases, so they're always safe to drop. The
+/// downloads and indexes themselves are only removed with `--aggressive`.
+const CACHE_DIRS: &[(&str, bool)] = &[
+    ("registry/cache", false),
+    ("registry/src", true),
+    ("registry/index", false),
+    ("git/db", false),
+    ("git/checkouts", true),
+];
+
+#[derive(Debug, serde::Serialize)]
+pub struct CacheEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub removed: bool,
+}
+
+/// Old versions of one crate removed by `--prune-versions`
+#[derive(Debug, serde::Serialize)]
+pub struct PrunedCrate {
+    pub name: String,
+    /// Registry directory name, e.g. `index.crates.io-1949cf8c6b5b557f`
+    pub registry: String,
+    pub versions: Vec<String>,
+    /// `.crate` files and extracted source directories that were (or would be) removed
+    pub files: Vec<PathBuf>,
+    pub freed_bytes: u64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct CacheReport {
+    pub cargo_home: PathBuf,
+    pub dry_run: bool,
+    pub entries: Vec<CacheEntry>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pruned: Vec<PrunedCrate>,
+    /// Only populated with `--sccache` when a local sccache cache exists
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sccache: Option<SccacheReport>,
+    pub total_bytes: u64,
+    pub freed_bytes: u64,
+}
+
+/// Options controlling what the cache subcommand removes
+#[derive(Debug, Clone, Default)]
+pub struct CacheOptions {
+    /// Remove extracted sources and git checkouts
+    pub clean: bool,
+    /// Also remove downloads, indexes and git databases
+    pub aggressive: bool,
+    /// Keep only this many of the newest versions of each crate
+    pub keep_latest: Option<usize>,
+    pub dry_run: bool,
+}
+
+/// Resolve CARGO_HOME from the environment, defaulting to `~/.cargo`
+pub fn resolve_cargo_home(cargo_home_env: Option<OsString>, home_dir: Option<PathBuf>) -> Result<PathBuf> {
+    match cargo_home_env {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+        _ => home_dir
+            .map(|home| home.join(".cargo"))
+            .ok_or_else(|| anyhow!("Cannot locate CARGO_HOME: neither CARGO_HOME nor HOME is set")),
+    }
+}
+
+/// CARGO_HOME for the current process
+pub fn cargo_home() -> Result<PathBuf> {
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+    resolve_cargo_home(std::env::var_os("CARGO_HOME"), home)
+}
+
+/// Take cargo's package cache lock, failing if a cargo process holds it
+fn lock_package_cache(cargo_home: &Path) -> Result<fs::File> {
+    let lock_path = cargo_home.join(".package-cache");
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .with_context(|| format!("Failed to open package cache lock: {:?}", lock_path))?;
+    file.try_lock_exclusive().map_err(|_| {
+        anyhow!("The package cache is locked by a running cargo process; try again once it finishes")
+    })?;
+    Ok(file)
+}
+
+/// Parse a `--prune-versions` policy: `keep-latest` or `keep-latest=N`
+pub fn parse_prune_policy(policy: &str) -> Result<usize> {
+    let keep = match policy.strip_prefix("keep-latest") {
+        Some("") => 1,
+        Some(rest) => rest
+            .strip_prefix('=')
+            .and_then(|n| n.parse::<usize>().ok())
+            .ok_or_else(|| anyhow!("Invalid --prune-versions value: '{}'. Expected keep-latest[=N]", policy))?,
+        None => return Err(anyhow!("Invalid --prune-versions value: '{}'. Expected keep-latest[=N]", policy)),
+    };
+    if keep == 0 {
+        return Err(anyhow!("--prune-versions must keep at least one version"));
+    }
+    Ok(keep)
+}
+
+/// Split a `.crate` file or extracted source directory name into crate name and version
+///
+/// Crate names may contain dashes and so may pre-release versions, so the
+/// first dash followed by a valid semver version is taken as the separator.
+pub fn parse_crate_file_name(file_name: &str) -> Option<(String, Version)> {
+    let stem = file_name.strip_suffix(".crate").unwrap_or(file_name);
+    stem.match_indices('-').find_map(|(i, _)| {
+        let (name, version) = (&stem[..i], &stem[i + 1..]);
+        if name.is_empty() {
+            return None;
+        }
+        Version::parse(version).ok().map(|v| (name.to_string(), v))
+    })
+}
+
+/// Sort versions newest first; yanked and pre-release versions rank below every stable release
+pub fn sort_versions_newest_first(versions: &mut [Version], yanked: &HashSet<String>) {
+    versions.sort_by_key(|v| {
+        let stable = v.pre.is_empty() && !yanked.contains(&v.to_string());
+        std::cmp::Reverse((stable, v.clone()))
+    });
+}
+
+/// Path of a crate's entry in a registry's index cache
+fn index_cache_path(index_dir: &Path, name: &str) -> PathBuf {
+    let name = name.to_lowercase();
+    let cache = index_dir.join(".cache");
+    match name.len() {
+        1 => cache.join("1").join(&name),
+        2 => cache.join("2").join(&name),
+        3 => cache.join("3").join(&name[..1]).join(&name),
+        _ => cache.join(&name[..2]).join(&name[2..4]).join(&name),
+    }
+}
+
+/// Versions of a crate marked as yanked in cargo's local index cache
+///
+/// The cache entry is a small header followed by NUL-separated version and
+/// JSON pairs; anything that doesn't parse is ignored, so a missing or
+/// unfamiliar cache simply means nothing is known to be yanked.
+fn yanked_versions(index_dir: &Path, name: &str) -> HashSet<String> {
+    let Ok(data) = fs::read(index_cache_path(index_dir, name)) else {
+        return HashSet::new();
+    };
+    data.split(|b| *b == 0)
+        .filter_map(|chunk| serde_json::from_slice::<serde_json::Value>(chunk).ok())
+        .filter(|entry| entry["yanked"].as_bool() == Some(true))
+        .filter_map(|entry| entry["vers"].as_str().map(|v| v.to_string()))
+        .collect()
+}
+
+/// List the subdirectories of a directory, sorted
+fn sorted_subdirs(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_dir()).collect())
+        .unwrap_or_default();
+    dirs.sort();
+    dirs
+}
+
+/// Remove all but the `keep` newest versions of every crate in the registry cache
+///
+/// Both the downloaded `.crate` file and its extracted `registry/src`
+/// directory are removed. With `dry_run`, nothing is touched.
+pub fn prune_crate_versions(cargo_home: &Path, keep: usize, dry_run: bool) -> Result<Vec<PrunedCrate>> {
+    let mut pruned = Vec::new();
+
+    for registry_dir in sorted_subdirs(&cargo_home.join("registry/cache")) {
+        let registry = registry_dir.file_name().unwrap().to_string_lossy().to_string();
+        let src_dir = cargo_home.join("registry/src").join(&registry);
+        let index_dir = cargo_home.join("registry/index").join(&registry);
+
+        let mut crates: BTreeMap<String, Vec<Version>> = BTreeMap::new();
+        for entry in fs::read_dir(&registry_dir)?.filter_map(|e| e.ok()) {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if !file_name.ends_with(".crate") {
+                continue;
+            }
+            if let Some((name, version)) = parse_crate_file_name(&file_name) {
+                crates.entry(name).or_default().push(version);
+            }
+        }
+
+        for (name, mut versions) in crates {
+            if versions.len() <= keep {
+                continue;
+            }
+            sort_versions_newest_first(&mut versions, &yanked_versions(&index_dir, &name));
+
+            let mut removed = PrunedCrate {
+                name: name.clone(),
+                registry: registry.clone(),
+                versions: Vec::new(),
+                files: Vec::new(),
+                freed_bytes: 0,
+            };
+            for version in &versions[keep..] {
+                let crate_file = registry_dir.join(format!("{}-{}.crate", name, version));
+                let extracted = src_dir.join(format!("{}-{}", name, version));
+                for path in [crate_file, extracted] {
+                    if !path.exists() {
+                        continue;
+                    }
+                    removed.freed_bytes += get_directory_size(&path).unwrap_or(0);
+                    if !dry_run {
+                        if path.is_dir() {
+                            fs::remove_dir_all(&path)
+                        } else {
+                            fs::remove_file(&path)
+                        }
+                        .with_context(|| format!("Failed to remove {:?}", path))?;
+                    }
+                    removed.files.push(path);
+                }
+                removed.versions.push(version.to_string());
+            }
+            pruned.push(removed);
+        }
+    }
+
+    Ok(pruned)
+}
+
+/// Measure (and with `clean` or `keep_latest`, remove) the caches in CARGO_HOME
+///
+/// Nothing is removed with `dry_run`; the report then describes what would be.
+pub fn clean_cargo_cache(cargo_home: &Path, options: &CacheOptions) -> Result<CacheReport> {
+    if !cargo_home.is_dir() {
+        return Err(anyhow!("CARGO_HOME does not exist: {:?}", cargo_home));
+    }
+
+    let modifies = options.clean || options.keep_latest.is_some();
+    let _lock = if modifies && !options.dry_run {
+        Some(lock_package_cache(cargo_home)?)
+    } else {
+        None
+    };
+
+    // Sizes are measured before anything is removed
+    let mut entries: Vec<CacheEntry> = CACHE_DIRS
+        .iter()
+        .map(|(name, _)| {
+            let path = cargo_home.join(name);
+            CacheEntry {
+                name: name.to_string(),
+                bytes: get_directory_size(&path).unwrap_or(0),
+                path,
+                removed: false,
+            }
+        })
+        .collect();
+
+    let pruned = match options.keep_latest {
+        Some(keep) => prune_crate_versions(cargo_home, keep, options.dry_run)?,
+        None => Vec::new(),
+    };
+
+    for (entry, (_, safe)) in entries.iter_mut().zip(CACHE_DIRS) {
+        entry.removed = options.clean && (*safe || options.aggressive) && entry.path.exists();
+        if entry.removed && !options.dry_run {
+            fs::remove_dir_all(&entry.path).with_context(|| format!("Failed to remove {:?}", entry.path))?;
+        }
+    }
+
+    let total_bytes = entries.iter().map(|e| e.bytes).sum();
+    let freed_bytes = entries.iter().filter(|e| e.removed).map(|e| e.bytes).sum::<u64>()
+        + pruned.iter().map(|p| p.freed_bytes).sum::<u64>();
+    Ok(CacheReport {
+        cargo_home: cargo_home.to_path_buf(),
+        dry_run: options.dry_run,
+        entries,
+        pruned,
+        sccache: None,
+        total_bytes,
+        freed_bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn fake_cargo_home() -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        let files = [
+            ("registry/cache/index.crates.io-abc/serde-1.0.0.crate", 100),
+            ("registry/src/index.crates.io-abc/serde-1.0.0/lib.rs", 400),
+            ("registry/index/index.crates.io-abc/.cache/se/rd/serde", 50),
+            ("git/db/repo-abc/HEAD", 20),
+            ("git/checkouts/repo-abc/1234/lib.rs", 200),
+        ];
+        for (path, size) in files {
+            let path = temp_dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![0u8; size]).unwrap();
+        }
+        temp_dir
+    }
+
+    #[test]
+    fn test_resolve_cargo_home() {
+        assert_eq!(
+            resolve_cargo_home(Some("/opt/cargo".into()), Some("/home/me".into())).unwrap(),
+            PathBuf::from("/opt/cargo")
+        );
+        assert_eq!(
+            resolve_cargo_home(None, Some("/home/me".into())).unwrap(),
+            PathBuf::from("/home/me/.cargo")
+        );
+        assert!(resolve_cargo_home(None, None).is_err());
+    }
+
+    #[test]
+    fn test_measure_only() {
+        let home = fake_cargo_home();
+        let report = clean_cargo_cache(home.path(), &CacheOptions::default()).unwrap();
+        assert_eq!(report.total_bytes, 770);
+        assert_eq!(report.freed_bytes, 0);
+        assert!(home.path().join("registry/src").exists());
+    }
+
+    #[test]
+    fn test_clean_removes_recreatable_dirs() {
+        let home = fake_cargo_home();
+        let clean = CacheOptions {
+            clean: true,
+            ..Default::default()
+        };
+        let report = clean_cargo_cache(home.path(), &clean).unwrap();
+        assert_eq!(report.freed_bytes, 600);
+        assert!(!home.path().join("registry/src").exists());
+        assert!(!home.path().join("git/checkouts").exists());
+        assert!(home.path().join("registry/cache").exists());
+        assert!(home.path().join("registry/index").exists());
+        assert!(home.path().join("git/db").exists());
+    }
+
+    #[test]
+    fn test_aggressive_dry_run() {
+        let home = fake_cargo_home();
+        let options = CacheOptions {
+            clean: true,
+            aggressive: true,
+            dry_run: true,
+            ..Default::default()
+        };
+        let report = clean_cargo_cache(home.path(), &options).unwrap();
+        assert_eq!(report.freed_bytes, 770);
+        assert!(report.entries.iter().all(|e| e.removed));
+        assert!(home.path().join("registry/cache").exists());
+    }
+
+    #[test]
+    fn test_refuses_while_locked() {
+        let home = fake_cargo_home();
+        let clean = CacheOptions {
+            clean: true,
+            ..Default::default()
+        };
+        let held = lock_package_cache(home.path()).unwrap();
+        assert!(clean_cargo_cache(home.path(), &clean).is_err());
+        drop(held);
+        assert!(clean_cargo_cache(home.path(), &clean).is_ok());
+    }
+
+    fn versions(versions: &[&str]) -> Vec<Version> {
+        versions.iter().map(|v| Version::parse(v).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_parse_prune_policy() {
+        assert_eq!(parse_prune_policy("keep-latest").unwrap(), 1);
+        assert_eq!(parse_prune_policy("keep-latest=3").unwrap(), 3);
+        assert!(parse_prune_policy("keep-latest=0").is_err());
+        assert!(parse_prune_policy("keep-latest=x").is_err());
+        assert!(parse_prune_policy("keep-oldest").is_err());
+    }
+
+    #[test]
+    fn test_parse_crate_file_name() {
+        assert_eq!(
+            parse_crate_file_name("serde-1.0.210.crate"),
+            Some(("serde".to_string(), Version::parse("1.0.210").unwrap()))
+        );
+        assert_eq!(
+            parse_crate_file_name("wasm-bindgen-0.2.93.crate"),
+            Some(("wasm-bindgen".to_string(), Version::parse("0.2.93").unwrap()))
+        );
+        assert_eq!(
+            parse_crate_file_name("gix-2d-0.1.0-alpha-2.crate"),
+            Some(("gix-2d".to_string(), Version::parse("0.1.0-alpha-2").unwrap()))
+        );
+        assert_eq!(
+            parse_crate_file_name("tokio-1.40.0"),
+            Some(("tokio".to_string(), Version::parse("1.40.0").unwrap()))
+        );
+        assert_eq!(parse_crate_file_name("not-a-crate.crate"), None);
+    }
+
+    #[test]
+    fn test_pre_release_and_yanked_rank_below_stable() {
+        let mut list = versions(&["1.0.0", "2.0.0-beta.1", "1.2.0", "1.1.0"]);
+        let yanked: HashSet<String> = ["1.2.0".to_string()].into_iter().collect();
+        sort_versions_newest_first(&mut list, &yanked);
+        let order: Vec<String> = list.iter().map(|v| v.to_string()).collect();
+        assert_eq!(order, vec!["1.1.0", "1.0.0", "2.0.0-beta.1", "1.2.0"]);
+    }
+
+    #[test]
+    fn test_yanked_versions_from_index_cache() {
+        let home = TempDir::new().unwrap();
+        let index_dir = home.path().join("registry/index/index.crates.io-abc");
+        let cache_file = index_cache_path(&index_dir, "serde");
+        assert!(cache_file.ends_with(".cache/se/rd/serde"));
+        fs::create_dir_all(cache_file.parent().unwrap()).unwrap();
+        let mut data = vec![3u8, 2, 0, 0, 0];
+        data.extend_from_slice(b"etag\x001.0.0\0{\"vers\":\"1.0.0\",\"yanked\":false}\0");
+        data.extend_from_slice(b"1.0.1\0{\"vers\":\"1.0.1\",\"yanked\":true}\0");
+        fs::write(&cache_file, data).unwrap();
+
+        let yanked = yanked_versions(&index_dir, "serde");
+        assert_eq!(yanked.into_iter().collect::<Vec<_>>(), vec!["1.0.1".to_string()]);
+    }
+
+    #[test]
+    fn test_prune_keeps_latest_versions() {
+        let home = TempDir::new().unwrap();
+        let cache = home.path().join("registry/cache/index.crates.io-abc");
+        let src = home.path().join("registry/src/index.crates.io-abc");
+        fs::create_dir_all(&cache).unwrap();
+        for version in ["1.0.0", "1.0.1", "1.1.0"] {
+            fs::write(cache.join(format!("serde-{}.crate", version)), vec![0u8; 10]).unwrap();
+            fs::create_dir_all(src.join(format!("serde-{}", version))).unwrap();
+            fs::write(src.join(format!("serde-{}/lib.rs", version)), vec![0u8; 5]).unwrap();
+        }
+        fs::write(cache.join("libc-0.2.0.crate"), vec![0u8; 10]).unwrap();
+
+        let options = CacheOptions {
+            keep_latest: Some(2),
+            dry_run: true,
+            ..Default::default()
+        };
+        let report = clean_cargo_cache(home.path(), &options).unwrap();
+        assert_eq!(report.pruned.len(), 1);
+        assert_eq!(report.pruned[0].versions, vec!["1.0.0"]);
+        assert_eq!(report.pruned[0].files.len(), 2);
+        assert_eq!(report.freed_bytes, 15);
+        assert!(cache.join("serde-1.0.0.crate").exists());
+
+        let options = CacheOptions {
+            keep_latest: Some(1),
+            ..Default::default()
+        };
+        let report = clean_cargo_cache(home.path(), &options).unwrap();
+        assert_eq!(report.pruned[0].versions, vec!["1.0.1", "1.0.0"]);
+        assert_eq!(report.freed_bytes, 30);
+        assert!(cache.join("serde-1.1.0.crate").exists());
+        assert!(src.join("serde-1.1.0").exists());
+        assert!(!cache.join("serde-1.0.1.crate").exists());
+        assert!(!src.join("serde-1.0.0").exists());
+        assert!(cache.join("libc-0.2.0.crate").exists());
+    }
+}
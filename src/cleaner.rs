@@ -1,77 +1,1632 @@
-use anyhow::{Context, Result};
-use crate::project::Project;
-use crate::utils::get_directory_size;
-use std::process::Command;
+use anyhow::{anyhow, Context, Result};
+use crate::archive::{archive_target, should_archive};
+use crate::audit::AuditEntry;
+use crate::debris::clean_debris;
+use crate::dedupe::{find_superseded_sets, DedupeGroup};
+use crate::estimate::{estimate_size, SizeEstimate};
+use crate::extra::{clean_extra_dirs, ExtraDirs};
+use crate::git::{has_uncommitted_changes, last_commit_age};
+use crate::interrupt::shield_from_interrupt;
+use crate::keep::{bin_artifacts, bin_names, keep_dir, keep_executables, restore_criterion, stash_criterion, KeptExecutables, CRITERION_DIR};
+use crate::project::{default_members, workspace_members, Project};
+use crate::removal::{prune_empty_dirs, remove_tree};
+use crate::retry::{error_from_output, with_retries, INITIAL_BACKOFF};
+use crate::sweep::{sweep_target, timestamps_reliable};
+use crate::toolchain::{artifact_sets_by_package, find_stale_artifact_sets, remove_artifact_set};
+use crate::trash::move_to_trash;
+use crate::utils::{
+    cargo_command, cargo_program, cutoff_for_days, get_directory_size, has_source_newer_than, last_build_time, looks_like_target,
+    readable_size, readable_usage, cargo, DirUsage, long_path, offline_hint, run_cargo, serialize_rfc3339, stuck_path, target_in_use, triple_dirs, TARGET_DIR_NAME,
+};
+use crate::vacuum::{vacuum_project, OrphanedPackage};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant, SystemTime};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Why a project was left alone
+///
+/// Serialized (and displayed) as the short phrase shown in summaries, so
+/// JSON output and journals read the same as the text report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize)]
+pub enum SkipReason {
+    /// `publish = false` with `--exclude-private`
+    #[serde(rename = "private")]
+    Private,
+    /// Published to a registry, with `--only-private`
+    #[serde(rename = "published")]
+    Published,
+    /// A custom-named target directory that cargo didn't make
+    #[serde(rename = "not a build directory")]
+    NotATarget,
+    /// A build is holding the target's lock
+    #[serde(rename = "in use")]
+    InUse,
+    #[serde(rename = "recently modified")]
+    RecentlyModified,
+    #[serde(rename = "recent git activity")]
+    RecentGitActivity,
+    #[serde(rename = "not under git")]
+    NotUnderGit,
+    #[serde(rename = "uncommitted changes")]
+    UncommittedChanges,
+    /// The project failed `--verify-build`
+    #[serde(rename = "build failed")]
+    BuildFailed,
+    /// `--sweep` can't trust access times on this filesystem
+    #[serde(rename = "timestamps unreliable")]
+    TimestampsUnreliable,
+    #[serde(rename = "no Cargo.lock")]
+    NoLockfile,
+    /// None of the `--package` patterns matched
+    #[serde(rename = "no matching package")]
+    NoMatchingPackage,
+    /// Never started because the run was interrupted
+    #[serde(rename = "interrupted")]
+    Interrupted,
+    /// Never started because of earlier failures
+    #[serde(rename = "aborted after failures")]
+    Aborted,
+    /// Never started because `--time-budget` ran out
+    #[serde(rename = "time budget exhausted")]
+    BudgetExhausted,
+    /// Left alone because `--until-free` was already met
+    #[serde(rename = "enough free space")]
+    EnoughFreeSpace,
+    /// A resumed run found it already cleaned in the journal
+    #[serde(rename = "previously cleaned")]
+    PreviouslyCleaned,
+    /// The `--pre-hook` exited non-zero
+    #[serde(rename = "pre-hook rejected")]
+    PreHookRejected,
+}
+
+impl SkipReason {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            SkipReason::Private => "private",
+            SkipReason::Published => "published",
+            SkipReason::NotATarget => "not a build directory",
+            SkipReason::InUse => "in use",
+            SkipReason::RecentlyModified => "recently modified",
+            SkipReason::RecentGitActivity => "recent git activity",
+            SkipReason::NotUnderGit => "not under git",
+            SkipReason::UncommittedChanges => "uncommitted changes",
+            SkipReason::BuildFailed => "build failed",
+            SkipReason::TimestampsUnreliable => "timestamps unreliable",
+            SkipReason::NoLockfile => "no Cargo.lock",
+            SkipReason::NoMatchingPackage => "no matching package",
+            SkipReason::Interrupted => "interrupted",
+            SkipReason::Aborted => "aborted after failures",
+            SkipReason::BudgetExhausted => "time budget exhausted",
+            SkipReason::EnoughFreeSpace => "enough free space",
+            SkipReason::PreviouslyCleaned => "previously cleaned",
+            SkipReason::PreHookRejected => "pre-hook rejected",
+        }
+    }
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How a project's artifacts were (or would be) removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CleanStrategy {
+    /// `cargo clean` (including `cargo clean -p`) did the work
+    Cargo,
+    /// `cargo clean` failed and the target directory was removed directly
+    Fallback,
+    /// Files were removed directly, by a partial mode (sweep, dedupe,
+    /// triples, ...), with `--no-cargo` or with `--prefer-fallback`
+    Direct,
+    DryRun,
+    Skipped,
+}
+
+impl std::fmt::Display for CleanStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CleanStrategy::Cargo => "cargo",
+            CleanStrategy::Fallback => "fallback",
+            CleanStrategy::Direct => "direct",
+            CleanStrategy::DryRun => "dry-run",
+            CleanStrategy::Skipped => "skipped",
+        })
+    }
+}
 
 #[derive(Debug, serde::Serialize)]
 pub struct CleanResult {
     pub path: String,
+    pub name: String,
+    pub version: Option<String>,
+    pub workspace_members: Option<usize>,
+    /// Other paths at which the same project directory was discovered
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Discovered by following a symlink
+    pub via_symlink: bool,
     pub success: bool,
     pub freed_bytes: u64,
+    /// Files removed with the whole target, as counted while measuring it or as
+    /// cargo reported them; unset for partial cleans and estimates
+    pub freed_files: Option<u64>,
+    /// With `--estimate`, marks `freed_bytes` as extrapolated and gives its range
+    #[serde(flatten)]
+    pub estimate: Option<SizeEstimate>,
     pub error: Option<String>,
+    pub skip_reason: Option<SkipReason>,
+    /// Unset for failed projects
+    pub strategy: Option<CleanStrategy>,
+    pub warnings: Vec<String>,
+    /// Set when the target was archived instead of simply deleted
+    pub archive_path: Option<String>,
+    pub archive_bytes: Option<u64>,
+    /// Number of stale artifact files removed by a sweep
+    pub swept_files: Option<usize>,
+    /// Artifact sets (`<profile>/<name>-<hash>`) built by compilers no longer installed
+    pub stale_artifact_sets: Option<Vec<String>>,
+    /// Subdirectories of the target removed by a partial clean
+    pub removed_subdirs: Option<Vec<String>>,
+    /// Final binaries and their `.d`/`.pdb` files removed by `--bin-artifacts-only`, relative to the target
+    pub removed_bin_artifacts: Option<Vec<String>>,
+    /// Directories a partial clean left empty, then removed
+    pub pruned_dirs: Option<usize>,
+    /// Superseded artifact hashes removed by `--dedupe-artifacts`
+    pub deduped: Option<Vec<DedupeGroup>>,
+    /// Packages missing from `Cargo.lock` whose artifacts were removed by `--vacuum`
+    pub vacuumed: Option<Vec<OrphanedPackage>>,
+    /// Workspace members cleaned with `cargo clean -p`
+    pub cleaned_packages: Option<Vec<String>>,
+    /// Days since the last commit in the project's repository, with `--git-idle`
+    pub git_commit_age_days: Option<u64>,
+    /// When the target was last built into, measured before cleaning
+    #[serde(serialize_with = "serialize_rfc3339")]
+    pub last_build: Option<SystemTime>,
+    /// Tries `cargo clean` or the fallback removal took, for whole-target cleans
+    pub attempts: Option<u32>,
+    /// The cargo binary that cleaned the project
+    pub cargo_binary: Option<String>,
+    /// The fallback toolchain cargo ran on, because the one the project pins isn't installed
+    pub cargo_toolchain: Option<String>,
+    /// Bytes left in the target when removing it failed partway
+    pub remaining_bytes: Option<u64>,
+    /// Where a failed removal got stuck
+    pub failed_path: Option<String>,
+    /// Time spent removing files directly, for the throughput shown in verbose output
+    pub removal_millis: Option<u64>,
+    /// Where `--defer-delete` moved the target, to be deleted once all projects are done
+    pub deferred_path: Option<String>,
+    /// Executables copied out of the target before it was cleaned, with `--keep-executables`
+    pub kept_executables: Option<KeptExecutables>,
+    /// Size of `target/criterion`, kept by `--keep-criterion` and not counted as freed
+    pub kept_criterion_bytes: Option<u64>,
+    /// Bytes freed from directories next to the target by `--extra`/`--extra-dir`, by name,
+    /// included in `freed_bytes`
+    pub extra_freed_bytes: Option<BTreeMap<String, u64>>,
+    /// Files removed by `--debris`: ICE dumps, coverage profiles and test scratch files
+    pub debris_files: Option<usize>,
+    /// Bytes those files held, included in `freed_bytes`
+    pub debris_freed_bytes: Option<u64>,
+    /// Removals and `cargo clean` runs (planned ones in a dry run), for `--audit-log`
+    #[serde(skip)]
+    pub audit: Vec<AuditEntry>,
 }
 
-/// Clean a single Cargo project
-pub fn clean_project(project: &Project, dry_run: bool, _verbose: bool) -> Result<CleanResult> {
-    let target_dir = project.path.join("target");
-    let freed_bytes = if target_dir.exists() {
-        get_directory_size(&target_dir).unwrap_or(0)
-    } else {
-        0
-    };
-
-    if dry_run {
-        return Ok(CleanResult {
+impl CleanResult {
+    /// Successful result for a project
+    pub fn new(project: &Project, freed_bytes: u64) -> Self {
+        CleanResult {
             path: project.path.to_string_lossy().to_string(),
+            name: project.name.clone(),
+            version: project.version.clone(),
+            workspace_members: project.workspace_members,
+            aliases: project.aliases.iter().map(|p| p.to_string_lossy().to_string()).collect(),
+            via_symlink: project.via_symlink,
             success: true,
             freed_bytes,
+            freed_files: None,
+            estimate: None,
             error: None,
-        });
+            skip_reason: None,
+            strategy: Some(CleanStrategy::Direct),
+            warnings: Vec::new(),
+            archive_path: None,
+            archive_bytes: None,
+            swept_files: None,
+            stale_artifact_sets: None,
+            removed_subdirs: None,
+            removed_bin_artifacts: None,
+            pruned_dirs: None,
+            deduped: None,
+            vacuumed: None,
+            cleaned_packages: None,
+            git_commit_age_days: None,
+            last_build: None,
+            attempts: None,
+            cargo_binary: None,
+            cargo_toolchain: None,
+            remaining_bytes: None,
+            failed_path: None,
+            removal_millis: None,
+            deferred_path: None,
+            kept_executables: None,
+            kept_criterion_bytes: None,
+            extra_freed_bytes: None,
+            debris_files: None,
+            debris_freed_bytes: None,
+            audit: Vec::new(),
+        }
+    }
+
+    /// Result for a project that could not be cleaned
+    pub fn failed(project: &Project, error: String) -> Self {
+        CleanResult {
+            success: false,
+            error: Some(error),
+            strategy: None,
+            ..CleanResult::new(project, 0)
+        }
+    }
+
+    /// Result for a project that was deliberately left alone
+    pub fn skipped(project: &Project, reason: SkipReason) -> Self {
+        CleanResult {
+            skip_reason: Some(reason),
+            strategy: Some(CleanStrategy::Skipped),
+            ..CleanResult::new(project, 0)
+        }
+    }
+
+    pub fn is_skipped(&self) -> bool {
+        self.skip_reason.is_some()
     }
 
-    // Try cargo clean first
-    let output = Command::new("cargo")
-        .arg("clean")
-        .current_dir(&project.path)
-        .output();
+    /// Apply `adjust` to the bytes freed and, for an estimate, to both ends of its range
+    fn adjust_freed(&mut self, adjust: impl Fn(u64) -> u64) {
+        self.freed_bytes = adjust(self.freed_bytes);
+        if let Some(ref mut estimate) = self.estimate {
+            estimate.bytes = self.freed_bytes;
+            estimate.low = adjust(estimate.low);
+            estimate.high = adjust(estimate.high);
+        }
+    }
+}
+
+/// Options controlling how a single project is cleaned
+#[derive(Debug, Clone, Default)]
+pub struct CleanOptions {
+    pub dry_run: bool,
+    /// Archive targets into this directory instead of deleting them
+    pub archive_dir: Option<PathBuf>,
+    /// Archive even when the destination filesystem is nearly full
+    pub archive_force: bool,
+    /// Only remove artifacts unused for this many days (never runs `cargo clean`)
+    pub sweep_days: Option<u64>,
+    /// Skip sweeping when filesystem access times can't be trusted
+    pub sweep_strict: bool,
+    /// Remove artifacts from compilers other than these installed rustc versions
+    pub installed_rustc: Option<Vec<String>>,
+    /// Only remove `target/<triple>` directories matching these patterns
+    pub triples: Vec<glob::Pattern>,
+    /// Remove every `target/<triple>` directory except those matching these patterns
+    pub keep_triples: Vec<glob::Pattern>,
+    /// Remove everything in `target/` except these direct children (never runs `cargo clean`)
+    pub keep_subdirs: Vec<String>,
+    /// Only remove artifact sets superseded by a newer build of the same crate
+    pub dedupe_artifacts: bool,
+    /// Only remove artifacts of packages no longer in `Cargo.lock`
+    pub vacuum: bool,
+    /// Only clean workspace members whose package name matches these patterns
+    pub packages: Vec<glob::Pattern>,
+    /// Clean only a workspace's `default-members` when it declares them
+    pub default_members_only: bool,
+    /// Leave projects whose git repository had a commit more recently than this
+    pub git_idle: Option<Duration>,
+    /// Leave projects with uncommitted changes or untracked files alone
+    pub only_committed: bool,
+    /// With `git_idle` or `only_committed`, also leave projects that aren't under git control
+    pub git_only: bool,
+    /// Leave projects whose sources were modified more recently than this
+    pub src_idle: Option<Duration>,
+    /// Run `cargo build` first and leave projects that don't build alone
+    pub verify_build: bool,
+    /// Clean even when a cargo process holds the target's build lock
+    pub force: bool,
+    /// Retry `cargo clean` and the fallback removal this many times after transient failures
+    pub retries: u32,
+    /// Remove targets directly instead of running `cargo clean`
+    pub no_cargo: bool,
+    /// Remove targets directly first, running `cargo clean` only when that fails
+    pub prefer_fallback: bool,
+    /// Move targets aside to be deleted later instead of deleting them in place
+    pub defer_delete: bool,
+    /// Leave projects with `publish = false` alone
+    pub exclude_private: bool,
+    /// Leave publishable projects alone
+    pub only_private: bool,
+    /// Name of each project's build output directory, when it isn't `target`
+    pub target_dir_name: Option<String>,
+    /// Copy final executables out of targets cleaned entirely, to `target-keep/`
+    pub keep_executables: bool,
+    /// With `keep_executables`, copy them into this shared directory instead
+    pub keep_executables_to: Option<PathBuf>,
+    /// Keep `target/criterion` and the benchmark baselines in it
+    pub keep_criterion: bool,
+    /// Build output of other tools next to each `Cargo.toml`, cleaned along with the target
+    pub extra: ExtraDirs,
+    /// Also remove rustc ICE dumps, coverage profiles, `.cargo-ok` and `target/tmp/*`
+    pub debris: bool,
+    /// Only remove the final binaries in each profile directory, keeping `deps/` for quick rebuilds
+    pub bin_artifacts_only: bool,
+    /// After a partial clean, also remove the target itself when nothing is left in it
+    pub remove_empty_target: bool,
+    /// In a dry run, estimate target sizes by measuring this fraction of their directories
+    pub estimate: Option<f64>,
+}
+
+impl CleanOptions {
+    /// The build output directory of a project
+    pub fn target_dir(&self, project: &Project) -> PathBuf {
+        project.path.join(self.target_dir_name())
+    }
+
+    pub fn target_dir_name(&self) -> &str {
+        self.target_dir_name.as_deref().unwrap_or(TARGET_DIR_NAME)
+    }
 
-    match output {
-        Ok(output) if output.status.success() => {
-            let after_size = if target_dir.exists() {
-                get_directory_size(&target_dir).unwrap_or(0)
+    /// Whether cleaning removes each target entirely, rather than a selection from it
+    pub fn removes_whole_target(&self) -> bool {
+        self.sweep_days.is_none()
+            && self.installed_rustc.is_none()
+            && self.triples.is_empty()
+            && self.keep_triples.is_empty()
+            && self.keep_subdirs.is_empty()
+            && !self.dedupe_artifacts
+            && !self.vacuum
+            && self.packages.is_empty()
+            && !self.default_members_only
+            && !self.bin_artifacts_only
+    }
+
+    /// The options that decide what a run removes, for telling journals apart
+    ///
+    /// Settings that only change how targets are removed (`--retries`,
+    /// `--force`, `--no-cargo`, deferred deletion) are left out, so resuming
+    /// with them changed still finds the journal. Keys are spelled out rather
+    /// than derived from the struct, so renaming a field keeps old journals.
+    pub fn journal_key(&self) -> String {
+        let patterns = |patterns: &[glob::Pattern]| patterns.iter().map(|p| p.as_str().to_string()).collect::<Vec<_>>();
+        serde_json::json!({
+            "archive_dir": self.archive_dir,
+            "sweep_days": self.sweep_days,
+            "installed_rustc": self.installed_rustc,
+            "triples": patterns(&self.triples),
+            "keep_triples": patterns(&self.keep_triples),
+            "keep_subdirs": self.keep_subdirs,
+            "dedupe_artifacts": self.dedupe_artifacts,
+            "vacuum": self.vacuum,
+            "packages": patterns(&self.packages),
+            "default_members_only": self.default_members_only,
+            "git_idle_secs": self.git_idle.map(|idle| idle.as_secs()),
+            "only_committed": self.only_committed,
+            "git_only": self.git_only,
+            "src_idle_secs": self.src_idle.map(|idle| idle.as_secs()),
+            "verify_build": self.verify_build,
+            "exclude_private": self.exclude_private,
+            "only_private": self.only_private,
+            "target_dir_name": self.target_dir_name(),
+            "keep_executables": self.keep_executables,
+            "keep_criterion": self.keep_criterion,
+            "extra": self.extra.presets.iter().map(|preset| preset.dir_name()).chain(self.extra.names.iter().map(String::as_str)).collect::<Vec<_>>(),
+            "debris": self.debris,
+            "bin_artifacts_only": self.bin_artifacts_only,
+        })
+        .to_string()
+    }
+}
+
+/// Clean a single Cargo project
+pub fn clean_project(project: &Project, options: &CleanOptions) -> Result<CleanResult> {
+    let last_build = last_build_time(&options.target_dir(project));
+    let mut result = clean_unless_guarded(project, options)?;
+    result.last_build = last_build;
+    Ok(result)
+}
+
+/// Skip the project if a guard applies, otherwise clean it
+fn clean_unless_guarded(project: &Project, options: &CleanOptions) -> Result<CleanResult> {
+    if options.exclude_private && project.private {
+        return Ok(CleanResult::skipped(project, SkipReason::Private));
+    }
+    if options.only_private && !project.private {
+        return Ok(CleanResult::skipped(project, SkipReason::Published));
+    }
+
+    // A mistyped --target-dir-name must not point cleaning at sources
+    let target_dir = options.target_dir(project);
+    if options.target_dir_name.is_some() && target_dir.exists() && !looks_like_target(&target_dir) {
+        let mut result = CleanResult::skipped(project, SkipReason::NotATarget);
+        result.warnings.push(format!(
+            "{}/ has no CACHEDIR.TAG or .rustc_info.json, so it doesn't look like cargo build output",
+            options.target_dir_name()
+        ));
+        return Ok(result);
+    }
+
+    if !options.force && target_in_use(&target_dir) {
+        let mut result = CleanResult::skipped(project, SkipReason::InUse);
+        result
+            .warnings
+            .push("a cargo build is running in this project, skipped (use --force to clean anyway)".to_string());
+        return Ok(result);
+    }
+
+    if let Some(idle) = options.src_idle {
+        let cutoff = SystemTime::now().checked_sub(idle).unwrap_or(SystemTime::UNIX_EPOCH);
+        if has_source_newer_than(&project.path, options.target_dir_name(), cutoff) {
+            return Ok(CleanResult::skipped(project, SkipReason::RecentlyModified));
+        }
+    }
+
+    // Projects outside git count as idle unless --git-only
+    let commit_age = options.git_idle.and_then(|_| last_commit_age(&project.path));
+    let commit_age_days = commit_age.map(|age| age.as_secs() / SECONDS_PER_DAY);
+    if let Some(idle) = options.git_idle {
+        let skip_reason = match commit_age {
+            Some(age) if age < idle => Some(SkipReason::RecentGitActivity),
+            None if options.git_only => Some(SkipReason::NotUnderGit),
+            _ => None,
+        };
+        if let Some(reason) = skip_reason {
+            let mut result = CleanResult::skipped(project, reason);
+            result.git_commit_age_days = commit_age_days;
+            return Ok(result);
+        }
+    }
+
+    if options.only_committed {
+        match has_uncommitted_changes(&project.path) {
+            Some(true) => return Ok(CleanResult::skipped(project, SkipReason::UncommittedChanges)),
+            None if options.git_only => return Ok(CleanResult::skipped(project, SkipReason::NotUnderGit)),
+            _ => {}
+        }
+    }
+
+    if options.verify_build && !options.dry_run {
+        if let Err(error) = verify_build(project, &target_dir) {
+            let mut result = CleanResult::skipped(project, SkipReason::BuildFailed);
+            result.warnings.push(format!("{}, not cleaned", error));
+            return Ok(result);
+        }
+    }
+
+    let mut result = clean_with_mode(project, options)?;
+    result.git_commit_age_days = commit_age_days;
+    // Partial cleans leave the directories they emptied behind
+    if result.success && !result.is_skipped() && !options.dry_run && !options.removes_whole_target() && target_dir.is_dir() {
+        let pruned = prune_empty_dirs(&target_dir, options.remove_empty_target);
+        if pruned > 0 {
+            result.pruned_dirs = Some(pruned);
+        }
+    }
+    if result.success && !result.is_skipped() && !options.extra.is_empty() {
+        let extra = clean_extra_dirs(&project.path, &options.extra, options.dry_run);
+        if !extra.freed_bytes.is_empty() {
+            let extra_bytes = extra.freed_bytes.values().sum::<u64>();
+            result.adjust_freed(|bytes| bytes + extra_bytes);
+            result.extra_freed_bytes = Some(extra.freed_bytes);
+        }
+        result.audit.extend(extra.audit);
+        result.warnings.extend(extra.warnings);
+    }
+    if result.success && !result.is_skipped() && options.debris {
+        // Debris inside a target that was removed entirely went with it
+        let target_dir = (!options.removes_whole_target()).then_some(target_dir.as_path());
+        let debris = clean_debris(&project.path, target_dir, options.dry_run);
+        if debris.files > 0 {
+            result.adjust_freed(|bytes| bytes + debris.bytes);
+            result.debris_files = Some(debris.files);
+            result.debris_freed_bytes = Some(debris.bytes);
+        }
+        result.audit.extend(debris.audit);
+        result.warnings.extend(debris.warnings);
+    }
+    if options.dry_run && !result.is_skipped() {
+        result.strategy = Some(CleanStrategy::DryRun);
+    }
+    Ok(result)
+}
+
+/// Clean a project in the mode selected by `options`
+fn clean_with_mode(project: &Project, options: &CleanOptions) -> Result<CleanResult> {
+    if let Some(days) = options.sweep_days {
+        return sweep_project(project, days, options);
+    }
+    if let Some(ref installed) = options.installed_rustc {
+        return remove_stale_toolchain_artifacts(project, installed, options);
+    }
+    if !options.triples.is_empty() || !options.keep_triples.is_empty() {
+        return clean_triples(project, options);
+    }
+    if !options.keep_subdirs.is_empty() {
+        return clean_keeping_subdirs(project, options);
+    }
+    if options.bin_artifacts_only {
+        return clean_bin_artifacts(project, options);
+    }
+    if options.dedupe_artifacts {
+        return dedupe_artifacts(project, options);
+    }
+    if options.vacuum {
+        return vacuum(project, options);
+    }
+    if !options.packages.is_empty() {
+        return clean_packages(project, options);
+    }
+    if options.default_members_only && project.is_workspace {
+        if let Some(members) = default_members(&project.path)? {
+            return clean_members(project, members, options);
+        }
+    }
+
+    let target_dir = options.target_dir(project);
+    let kept = if options.keep_executables {
+        let keep_dir = keep_dir(&project.path, &target_dir, options.keep_executables_to.as_deref());
+        let bin_names = bin_names(&project.path.join("Cargo.toml"));
+        keep_executables(&target_dir, &keep_dir, bin_names.as_ref(), options.dry_run)?
+    } else {
+        None
+    };
+    let criterion_bytes = if options.keep_criterion {
+        stash_criterion(&target_dir, options.dry_run)?
+    } else {
+        None
+    };
+
+    let cleaned = clean_whole_target(project, options);
+    let restored = match criterion_bytes {
+        Some(_) if !options.dry_run => restore_criterion(&target_dir),
+        _ => Ok(()),
+    };
+    // The baselines were cleaned around, so a failed restore only warns
+    let mut result = match (cleaned, restored) {
+        (Ok(result), Ok(())) => result,
+        (Ok(mut result), Err(e)) => {
+            result.warnings.push(format!("{:#}", e));
+            result
+        }
+        (Err(e), Ok(())) => return Err(e),
+        (Err(e), Err(restore)) => return Err(anyhow!("{:#}; {:#}", e, restore)),
+    };
+    result.kept_executables = kept;
+    if let Some(bytes) = criterion_bytes {
+        // A dry run measured the target with the baselines still in it
+        if options.dry_run {
+            result.adjust_freed(|freed| freed.saturating_sub(bytes));
+        }
+        result.kept_criterion_bytes = Some(bytes);
+    }
+    Ok(result)
+}
+
+/// Clean a project's entire target, with `cargo clean` or by removing it
+fn clean_whole_target(project: &Project, options: &CleanOptions) -> Result<CleanResult> {
+    let target_dir = options.target_dir(project);
+    let estimate = options
+        .estimate
+        .filter(|_| options.dry_run && target_dir.exists())
+        .map(|fraction| estimate_size(&target_dir, fraction));
+    let usage = match estimate {
+        Some(_) => None,
+        None if target_dir.exists() => Some(readable_usage(&target_dir)),
+        None => Some(DirUsage::default()),
+    };
+    let freed_bytes = match estimate {
+        Some(estimate) => estimate.bytes,
+        None => usage.unwrap_or_default().bytes,
+    };
+
+    if options.dry_run {
+        let mut result = CleanResult::new(project, freed_bytes);
+        result.estimate = estimate;
+        result.freed_files = usage.map(|usage| usage.files);
+        if target_dir.exists() {
+            result.audit.push(AuditEntry::removed(&project.path, &target_dir, freed_bytes));
+        }
+        return Ok(result);
+    }
+
+    let mut result = match options.archive_dir {
+        Some(ref archive_dir) if target_dir.exists() => {
+            std::fs::create_dir_all(archive_dir)
+                .with_context(|| format!("Failed to create archive directory: {:?}", archive_dir))?;
+            if options.archive_force || should_archive(&target_dir, archive_dir, freed_bytes) {
+                archive_project(project, &target_dir, archive_dir, freed_bytes)?
             } else {
-                0
-            };
-            let actually_freed = freed_bytes.saturating_sub(after_size);
-
-            Ok(CleanResult {
-                path: project.path.to_string_lossy().to_string(),
-                success: true,
-                freed_bytes: actually_freed,
-                error: None,
-            })
-        }
-        _ => {
-            // Fallback: remove target directory directly
-            if target_dir.exists() {
-                std::fs::remove_dir_all(&target_dir)
-                    .with_context(|| format!("Failed to remove target directory: {:?}", target_dir))?;
-
-                Ok(CleanResult {
-                    path: project.path.to_string_lossy().to_string(),
-                    success: true,
-                    freed_bytes,
-                    error: None,
-                })
+                let mut result = clean_target(project, freed_bytes, options)?;
+                result
+                    .warnings
+                    .push("archive destination is nearly full, cleaned without archiving".to_string());
+                result
+            }
+        }
+        _ => clean_target(project, freed_bytes, options)?,
+    };
+    // Unless cargo said how many files it removed, all of them went only if all the bytes did
+    if result.freed_files.is_none() && result.success && result.freed_bytes == freed_bytes {
+        result.freed_files = usage.map(|usage| usage.files);
+    }
+    Ok(result)
+}
+
+/// Check that a project still builds, returning cargo's last error line if not
+fn verify_build(project: &Project, target_dir: &Path) -> Result<()> {
+    let mut cmd = cargo_command("build", &project.path, target_dir);
+    cmd.arg("--quiet");
+    let (output, _) = run_cargo(cmd, shield_from_interrupt).context("Failed to run cargo build")?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let last_error = stderr.lines().rev().find(|line| !line.trim().is_empty()).unwrap_or("cargo build failed");
+    Err(anyhow!("build failed: {}{}", last_error.trim(), offline_hint(&stderr)))
+}
+
+/// Remove stale artifacts from a project's target, keeping recently used ones
+fn sweep_project(project: &Project, days: u64, options: &CleanOptions) -> Result<CleanResult> {
+    let target_dir = options.target_dir(project);
+    if !target_dir.exists() {
+        return Ok(CleanResult::new(project, 0));
+    }
+
+    if options.sweep_strict && !timestamps_reliable(&target_dir, days) {
+        let mut result = CleanResult::skipped(project, SkipReason::TimestampsUnreliable);
+        result
+            .warnings
+            .push("file access times are not trustworthy on this filesystem, sweep skipped".to_string());
+        return Ok(result);
+    }
+
+    let stats = sweep_target(&target_dir, cutoff_for_days(days), options.dry_run)
+        .with_context(|| format!("Failed to sweep target directory: {:?}", target_dir))?;
+    let mut result = CleanResult::new(project, stats.freed_bytes);
+    result.swept_files = Some(stats.removed_files);
+    Ok(result)
+}
+
+/// Remove artifact sets built by compilers that are no longer installed
+fn remove_stale_toolchain_artifacts(project: &Project, installed: &[String], options: &CleanOptions) -> Result<CleanResult> {
+    let target_dir = options.target_dir(project);
+    let stale = find_stale_artifact_sets(&target_dir, installed);
+
+    let mut freed_bytes = 0;
+    let mut names = Vec::new();
+    for set in &stale {
+        freed_bytes += remove_artifact_set(set, options.dry_run)
+            .with_context(|| format!("Failed to remove stale artifacts {:?} in {:?}", set.stem, set.profile_dir))?;
+        let profile = set.profile_dir.strip_prefix(&target_dir).unwrap_or(&set.profile_dir);
+        names.push(format!("{}/{}", profile.display(), set.stem));
+    }
+
+    let mut result = CleanResult::new(project, freed_bytes);
+    result.stale_artifact_sets = Some(names);
+    Ok(result)
+}
+
+/// Remove artifact sets superseded by newer builds of the same crate
+fn dedupe_artifacts(project: &Project, options: &CleanOptions) -> Result<CleanResult> {
+    let target_dir = options.target_dir(project);
+    let mut freed_bytes = 0;
+    let mut groups = Vec::new();
+
+    for (group, sets) in find_superseded_sets(&target_dir) {
+        for set in &sets {
+            freed_bytes += remove_artifact_set(set, options.dry_run)
+                .with_context(|| format!("Failed to remove superseded artifacts {:?} in {:?}", set.stem, set.profile_dir))?;
+        }
+        groups.push(group);
+    }
+
+    let mut result = CleanResult::new(project, freed_bytes);
+    result.deduped = Some(groups);
+    Ok(result)
+}
+
+/// Remove artifacts of packages that are no longer in the lockfile
+fn vacuum(project: &Project, options: &CleanOptions) -> Result<CleanResult> {
+    let Some(purged) = vacuum_project(&project.path, &options.target_dir(project), options.dry_run)? else {
+        return Ok(CleanResult::skipped(project, SkipReason::NoLockfile));
+    };
+
+    let mut result = CleanResult::new(project, purged.iter().map(|p| p.bytes).sum());
+    result.vacuumed = Some(purged);
+    Ok(result)
+}
+
+/// Clean the workspace members matching `options.packages`
+fn clean_packages(project: &Project, options: &CleanOptions) -> Result<CleanResult> {
+    let matched: Vec<String> = workspace_members(&project.path)?
+        .into_iter()
+        .filter(|name| options.packages.iter().any(|p| p.matches(name)))
+        .collect();
+    if matched.is_empty() {
+        return Ok(CleanResult::skipped(project, SkipReason::NoMatchingPackage));
+    }
+    clean_members(project, matched, options)
+}
+
+/// Clean individual workspace members with `cargo clean -p`
+///
+/// Older cargo versions don't report what `cargo clean -p` removed, so the
+/// freed bytes are the difference in target size. A dry run estimates them
+/// from the members' artifact sets.
+fn clean_members(project: &Project, members: Vec<String>, options: &CleanOptions) -> Result<CleanResult> {
+    let target_dir = options.target_dir(project);
+    let mut audit = Vec::new();
+    let mut used_toolchain = None;
+    let freed_bytes = if options.dry_run {
+        let mut freed = 0;
+        for sets in artifact_sets_by_package(&target_dir, |name| members.iter().any(|m| m == name)).values() {
+            for set in sets {
+                freed += remove_artifact_set(set, true)?;
+            }
+        }
+        freed
+    } else {
+        let before = get_directory_size(&target_dir).unwrap_or(0);
+        let mut cmd = cargo_command("clean", &project.path, &target_dir);
+        for name in &members {
+            cmd.args(["-p", name]);
+        }
+        let (output, toolchain) = run_cargo(cmd, shield_from_interrupt).context("Failed to run cargo clean")?;
+        used_toolchain = toolchain;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow!("cargo clean -p failed: {}{}", stderr.trim(), offline_hint(&stderr)));
+        }
+        let after = get_directory_size(&target_dir).unwrap_or(0);
+        let command = format!("cargo clean -p {}", members.join(" -p "));
+        audit.push(AuditEntry::cargo_clean(&project.path, &command, before, after));
+        before.saturating_sub(after)
+    };
+
+    let mut result = CleanResult::new(project, freed_bytes);
+    result.strategy = Some(CleanStrategy::Cargo);
+    result.cleaned_packages = Some(members);
+    result.audit = audit;
+    if !options.dry_run {
+        record_cargo(&mut result, used_toolchain);
+    }
+    Ok(result)
+}
+
+/// Remove selected `target/<triple>` directories, leaving host profiles alone
+///
+/// Always a direct removal: `cargo clean --target <triple>` without `-p`
+/// deletes the whole target directory, host profiles included, and with `-p`
+/// it leaves the triple's dependencies behind.
+fn clean_triples(project: &Project, options: &CleanOptions) -> Result<CleanResult> {
+    let target_dir = options.target_dir(project);
+    let mut freed_bytes = 0;
+    let mut removed = Vec::new();
+    let mut removal_time = Duration::ZERO;
+
+    let mut audit = Vec::new();
+
+    for triple_dir in triple_dirs(&target_dir) {
+        let name = triple_dir.file_name().unwrap().to_string_lossy().to_string();
+        let selected = if options.triples.is_empty() {
+            !options.keep_triples.iter().any(|p| p.matches(&name))
+        } else {
+            options.triples.iter().any(|p| p.matches(&name))
+        };
+        if !selected {
+            continue;
+        }
+
+        let bytes = get_directory_size(&triple_dir).unwrap_or(0);
+        if !options.dry_run {
+            let started = Instant::now();
+            remove_tree(&triple_dir, bytes)
+                .with_context(|| format!("Failed to remove target directory: {:?}", triple_dir))?;
+            removal_time += started.elapsed();
+        }
+        freed_bytes += bytes;
+        audit.push(AuditEntry::removed(&project.path, &triple_dir, bytes));
+        removed.push(name);
+    }
+
+    let mut result = CleanResult::new(project, freed_bytes);
+    result.removed_subdirs = Some(removed);
+    result.removal_millis = (!options.dry_run).then_some(removal_time.as_millis() as u64);
+    result.audit = audit;
+    Ok(result)
+}
+
+/// Remove the children of `target/` except the ones named in `keep_subdirs`
+///
+/// `cargo clean` can't keep parts of a target, so this removes entries
+/// directly. Only the removed portion is counted as freed.
+fn clean_keeping_subdirs(project: &Project, options: &CleanOptions) -> Result<CleanResult> {
+    let target_dir = options.target_dir(project);
+    let mut freed_bytes = 0;
+    let mut removed = Vec::new();
+    let mut removal_time = Duration::ZERO;
+
+    let mut audit = Vec::new();
+    let mut kept_criterion_bytes = None;
+
+    let mut entries: Vec<PathBuf> = match std::fs::read_dir(&target_dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => Vec::new(),
+    };
+    entries.sort();
+
+    for path in entries {
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        if options.keep_subdirs.contains(&name) {
+            continue;
+        }
+        if options.keep_criterion && name == CRITERION_DIR {
+            kept_criterion_bytes = Some(get_directory_size(&path).unwrap_or(0));
+            continue;
+        }
+
+        let is_dir = path.is_dir();
+        let bytes = if is_dir {
+            get_directory_size(&path).unwrap_or(0)
+        } else {
+            std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0)
+        };
+        if !options.dry_run {
+            let started = Instant::now();
+            if is_dir {
+                remove_tree(&path, bytes)
             } else {
-                Ok(CleanResult {
-                    path: project.path.to_string_lossy().to_string(),
-                    success: true,
-                    freed_bytes: 0,
-                    error: None,
-                })
+                std::fs::remove_file(&path)
             }
+            .with_context(|| format!("Failed to remove {:?}", path))?;
+            removal_time += started.elapsed();
+        }
+        freed_bytes += bytes;
+        audit.push(AuditEntry::removed(&project.path, &path, bytes));
+        removed.push(name);
+    }
+
+    let mut result = CleanResult::new(project, freed_bytes);
+    result.removed_subdirs = Some(removed);
+    result.removal_millis = (!options.dry_run).then_some(removal_time.as_millis() as u64);
+    result.kept_criterion_bytes = kept_criterion_bytes;
+    result.audit = audit;
+    Ok(result)
+}
+
+/// Remove only the final binaries from a target, leaving what incremental
+/// rebuilds start from
+///
+/// Only the binaries the manifest declares are picked when cargo can tell.
+fn clean_bin_artifacts(project: &Project, options: &CleanOptions) -> Result<CleanResult> {
+    let target_dir = options.target_dir(project);
+    let bin_names = bin_names(&project.path.join("Cargo.toml"));
+    let started = Instant::now();
+    let mut freed_bytes = 0;
+    let mut removed = Vec::new();
+    let mut audit = Vec::new();
+
+    for path in bin_artifacts(&target_dir, bin_names.as_ref()) {
+        let bytes = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if !options.dry_run {
+            std::fs::remove_file(&path).with_context(|| format!("Failed to remove {:?}", path))?;
+        }
+        freed_bytes += bytes;
+        audit.push(AuditEntry::removed(&project.path, &path, bytes));
+        removed.push(path.strip_prefix(&target_dir).unwrap_or(&path).to_string_lossy().to_string());
+    }
+
+    let mut result = CleanResult::new(project, freed_bytes);
+    result.removed_bin_artifacts = Some(removed);
+    result.removal_millis = (!options.dry_run).then_some(started.elapsed().as_millis() as u64);
+    result.audit = audit;
+    Ok(result)
+}
+
+/// Archive a project's target directory, then remove the original
+fn archive_project(project: &Project, target_dir: &Path, archive_dir: &Path, freed_bytes: u64) -> Result<CleanResult> {
+    let (archive_path, archive_bytes) = archive_target(project, target_dir, archive_dir)?;
+    std::fs::remove_dir_all(long_path(target_dir))
+        .with_context(|| format!("Failed to remove target directory: {:?}", target_dir))?;
+
+    let mut result = CleanResult::new(project, freed_bytes);
+    result.archive_path = Some(archive_path.to_string_lossy().to_string());
+    result.archive_bytes = Some(archive_bytes);
+    result.audit.push(AuditEntry::removed(&project.path, target_dir, freed_bytes));
+    Ok(result)
+}
+
+/// Remove a project's target directory, via `cargo clean` unless `--no-cargo`
+///
+/// Both `cargo clean` and the direct removal are retried up to
+/// `options.retries` times after transient failures.
+fn clean_target(project: &Project, freed_bytes: u64, options: &CleanOptions) -> Result<CleanResult> {
+    let target_dir = options.target_dir(project);
+
+    if options.defer_delete {
+        if let Some(trash) = move_to_trash(&target_dir) {
+            let mut result = CleanResult::new(project, freed_bytes);
+            result.deferred_path = Some(trash.to_string_lossy().to_string());
+            result.audit.push(AuditEntry::removed(&project.path, &target_dir, freed_bytes));
+            return Ok(result);
         }
     }
+
+    if options.prefer_fallback {
+        // The other way round: cargo clean only runs when removing the target directly fails
+        return Ok(match remove_target(project, &target_dir, freed_bytes, options, CleanStrategy::Direct) {
+            Ok(result) => result,
+            Err((error, attempts)) => cargo_clean(project, &target_dir, freed_bytes, options)
+                .unwrap_or_else(|| partially_removed(project, &target_dir, freed_bytes, attempts, error)),
+        });
+    }
+
+    if !options.no_cargo {
+        if let Some(result) = cargo_clean(project, &target_dir, freed_bytes, options) {
+            return Ok(result);
+        }
+    }
+
+    // Remove the target directory directly, as asked or because cargo clean failed
+    let strategy = if options.no_cargo { CleanStrategy::Direct } else { CleanStrategy::Fallback };
+    Ok(remove_target(project, &target_dir, freed_bytes, options, strategy)
+        .unwrap_or_else(|(error, attempts)| partially_removed(project, &target_dir, freed_bytes, attempts, error)))
+}
+
+/// Run `cargo clean` in a project, or `None` when it fails
+fn cargo_clean(project: &Project, target_dir: &Path, freed_bytes: u64, options: &CleanOptions) -> Option<CleanResult> {
+    let (cleaned, attempts) = with_retries(options.retries, INITIAL_BACKOFF, || {
+        let (output, toolchain) = run_cargo(cargo_command("clean", &project.path, target_dir), shield_from_interrupt)?;
+        if output.status.success() {
+            Ok((toolchain, removed_files(&String::from_utf8_lossy(&output.stderr))))
+        } else {
+            Err(error_from_output(&String::from_utf8_lossy(&output.stderr)))
+        }
+    });
+    let (toolchain, removed_files) = cleaned.ok()?;
+
+    let after_size = if target_dir.exists() {
+        get_directory_size(target_dir).unwrap_or(0)
+    } else {
+        0
+    };
+    let actually_freed = freed_bytes.saturating_sub(after_size);
+
+    let mut result = CleanResult::new(project, actually_freed);
+    result.strategy = Some(CleanStrategy::Cargo);
+    result.freed_files = removed_files;
+    result.attempts = Some(attempts);
+    record_cargo(&mut result, toolchain);
+    result
+        .audit
+        .push(AuditEntry::cargo_clean(&project.path, "cargo clean", freed_bytes, after_size));
+    Some(result)
+}
+
+/// The file count from the line `cargo clean` ends with, e.g. `Removed 21 files, 8.4MiB total`
+///
+/// Older cargo doesn't print it.
+fn removed_files(stderr: &str) -> Option<u64> {
+    stderr.lines().find_map(|line| {
+        let (count, rest) = line.trim().strip_prefix("Removed ")?.split_once(' ')?;
+        rest.starts_with("file").then(|| count.parse().ok())?
+    })
+}
+
+/// Note which cargo cleaned a project, warning when it had to leave the pinned toolchain
+fn record_cargo(result: &mut CleanResult, toolchain: Option<&str>) {
+    result.cargo_binary = Some(cargo_program().to_string_lossy().to_string());
+    if let Some(toolchain) = toolchain {
+        result.warnings.push(format!(
+            "the toolchain this project pins isn't installed, cleaned with `cargo +{}` instead",
+            toolchain
+        ));
+        result.cargo_toolchain = Some(toolchain.to_string());
+    }
+}
+
+/// Remove a project's target directory without cargo
+///
+/// On failure returns the error along with how many attempts were made.
+fn remove_target(
+    project: &Project,
+    target_dir: &Path,
+    freed_bytes: u64,
+    options: &CleanOptions,
+    strategy: CleanStrategy,
+) -> std::result::Result<CleanResult, (std::io::Error, u32)> {
+    let mut result = CleanResult::new(project, 0);
+    result.strategy = Some(strategy);
+    if target_dir.exists() {
+        let started = Instant::now();
+        let (removed, attempts) = with_retries(options.retries, INITIAL_BACKOFF, || remove_tree(target_dir, freed_bytes));
+        removed.map_err(|e| (e, attempts))?;
+        result.attempts = Some(attempts);
+        result.removal_millis = Some(started.elapsed().as_millis() as u64);
+        result.freed_bytes = freed_bytes;
+        result.audit.push(AuditEntry::removed(&project.path, target_dir, freed_bytes));
+    }
+    Ok(result)
+}
+
+/// Failed result for a target whose removal stopped partway
+///
+/// Whatever did get removed is still reported as freed, so a run that
+/// deleted most of a large target doesn't claim to have freed nothing.
+fn partially_removed(project: &Project, target_dir: &Path, before: u64, attempts: u32, error: std::io::Error) -> CleanResult {
+    let remaining = readable_size(target_dir);
+    let freed = before.saturating_sub(remaining);
+    let failed_path = stuck_path(target_dir);
+    let message = match attempts {
+        1 => format!("Failed to remove {:?}: {}", failed_path, error),
+        n => format!("Failed to remove {:?} after {} attempts: {}", failed_path, n, error),
+    };
+
+    let mut result = CleanResult {
+        freed_bytes: freed,
+        remaining_bytes: Some(remaining),
+        failed_path: Some(failed_path.to_string_lossy().to_string()),
+        attempts: Some(attempts),
+        ..CleanResult::failed(project, message)
+    };
+    if freed > 0 {
+        result.audit.push(AuditEntry::removed(&project.path, target_dir, freed));
+    }
+    result
+}
+
+/// Whether a `cargo` binary can be run from PATH
+pub fn cargo_on_path() -> bool {
+    cargo()
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// A target with host profiles and three cross-compiled triples
+    fn triple_fixture() -> (TempDir, Project) {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        for dir in [
+            "debug/deps",
+            "release/deps",
+            "wasm32-unknown-unknown/release/deps",
+            "wasm32-wasip1/debug/deps",
+            "aarch64-linux-android/debug/deps",
+        ] {
+            fs::create_dir_all(target.join(dir)).unwrap();
+            fs::write(target.join(dir).join("libfoo.rlib"), vec![0u8; 100]).unwrap();
+        }
+        let project = Project::new(temp_dir.path().to_path_buf(), false);
+        (temp_dir, project)
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<glob::Pattern> {
+        patterns.iter().map(|p| glob::Pattern::new(p).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_journal_key_ignores_how_targets_are_removed() {
+        let options = CleanOptions {
+            packages: patterns(&["api-*"]),
+            ..Default::default()
+        };
+        let retried = CleanOptions {
+            retries: 3,
+            force: true,
+            no_cargo: true,
+            ..options.clone()
+        };
+        assert_eq!(options.journal_key(), retried.journal_key());
+
+        let other_packages = CleanOptions {
+            packages: patterns(&["cli"]),
+            ..options.clone()
+        };
+        assert_ne!(options.journal_key(), other_packages.journal_key());
+        assert_ne!(options.journal_key(), CleanOptions::default().journal_key());
+    }
+
+    #[test]
+    fn test_locked_target_is_skipped_unless_forced() {
+        let (temp_dir, project) = triple_fixture();
+        let lock = fs::File::create(temp_dir.path().join("target/debug/.cargo-lock")).unwrap();
+        fs2::FileExt::lock_exclusive(&lock).unwrap();
+        let options = CleanOptions {
+            triples: patterns(&["wasm32-*"]),
+            ..Default::default()
+        };
+
+        let result = clean_project(&project, &options).unwrap();
+        assert_eq!(result.skip_reason, Some(SkipReason::InUse));
+        assert!(temp_dir.path().join("target/wasm32-wasip1").exists());
+
+        let result = clean_project(&project, &CleanOptions { force: true, ..options }).unwrap();
+        assert!(!result.is_skipped());
+        assert!(!temp_dir.path().join("target/wasm32-wasip1").exists());
+    }
+
+    #[test]
+    fn test_private_filters() {
+        let (temp_dir, mut project) = triple_fixture();
+        let dry_run = |exclude_private: bool, only_private: bool, project: &Project| {
+            let options = CleanOptions {
+                dry_run: true,
+                exclude_private,
+                only_private,
+                ..Default::default()
+            };
+            clean_project(project, &options).unwrap().skip_reason
+        };
+
+        assert_eq!(dry_run(true, false, &project), None);
+        assert_eq!(dry_run(false, true, &project), Some(SkipReason::Published));
+        project.private = true;
+        assert_eq!(dry_run(true, false, &project), Some(SkipReason::Private));
+        assert_eq!(dry_run(false, true, &project), None);
+        assert!(temp_dir.path().join("target").exists());
+    }
+
+    #[test]
+    fn test_only_committed_outside_git() {
+        let (temp_dir, project) = triple_fixture();
+        let options = CleanOptions {
+            dry_run: true,
+            only_committed: true,
+            ..Default::default()
+        };
+
+        assert_eq!(clean_project(&project, &options).unwrap().skip_reason, None);
+        let result = clean_project(&project, &CleanOptions { git_only: true, ..options }).unwrap();
+        assert_eq!(result.skip_reason, Some(SkipReason::NotUnderGit));
+        assert!(temp_dir.path().join("target").exists());
+    }
+
+    /// A workspace with members `alpha` and `beta`, each with fake artifacts
+    fn workspace_fixture() -> (TempDir, Project) {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"alpha\", \"beta\"]\nresolver = \"2\"\n",
+        )
+        .unwrap();
+        let debug = temp_dir.path().join("target/debug");
+        for name in ["alpha", "beta"] {
+            let member = temp_dir.path().join(name);
+            fs::create_dir_all(member.join("src")).unwrap();
+            fs::write(
+                member.join("Cargo.toml"),
+                format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n", name),
+            )
+            .unwrap();
+            fs::write(member.join("src/lib.rs"), "").unwrap();
+            fs::create_dir_all(debug.join(".fingerprint").join(format!("{}-1234", name))).unwrap();
+            fs::create_dir_all(debug.join("deps")).unwrap();
+            fs::write(debug.join("deps").join(format!("lib{}-1234.rlib", name)), vec![0u8; 10_000]).unwrap();
+        }
+        let project = Project::new(temp_dir.path().to_path_buf(), true);
+        (temp_dir, project)
+    }
+
+    #[test]
+    fn test_clean_matching_packages() {
+        let (temp_dir, project) = workspace_fixture();
+        let deps = temp_dir.path().join("target/debug/deps");
+        let options = CleanOptions {
+            packages: patterns(&["al*"]),
+            dry_run: true,
+            ..Default::default()
+        };
+
+        let result = clean_project(&project, &options).unwrap();
+        assert_eq!(result.cleaned_packages, Some(vec!["alpha".to_string()]));
+        assert_eq!(result.freed_bytes, 10_000);
+        assert!(deps.join("libalpha-1234.rlib").exists());
+
+        // cargo writes its own bookkeeping files, so only a lower bound is exact
+        let result = clean_project(&project, &CleanOptions { dry_run: false, ..options }).unwrap();
+        assert!(result.freed_bytes > 5_000);
+        assert_eq!(result.strategy, Some(CleanStrategy::Cargo));
+        assert!(!deps.join("libalpha-1234.rlib").exists());
+        assert!(deps.join("libbeta-1234.rlib").exists());
+    }
+
+    #[test]
+    fn test_verify_build_skips_broken_projects() {
+        let (temp_dir, project) = workspace_fixture();
+        let options = CleanOptions {
+            verify_build: true,
+            ..Default::default()
+        };
+        fs::write(temp_dir.path().join("beta/src/lib.rs"), "fn broken(").unwrap();
+
+        let result = clean_project(&project, &options).unwrap();
+        assert_eq!(result.skip_reason, Some(SkipReason::BuildFailed));
+        assert!(result.warnings[0].starts_with("build failed: "));
+        assert!(temp_dir.path().join("target/debug/deps/libalpha-1234.rlib").exists());
+    }
+
+    #[test]
+    fn test_results_carry_project_name() {
+        let (_temp_dir, workspace) = workspace_fixture();
+        let json = serde_json::to_value(CleanResult::new(&workspace, 0)).unwrap();
+        assert_eq!(json["name"], workspace.path.file_name().unwrap().to_string_lossy().as_ref());
+        assert_eq!(json["version"], serde_json::Value::Null);
+        assert_eq!(json["workspace_members"], 2);
+
+        let standalone = Project::new(workspace.path.join("alpha"), false);
+        let json = serde_json::to_value(CleanResult::skipped(&standalone, SkipReason::InUse)).unwrap();
+        assert_eq!(json["name"], "alpha");
+        assert_eq!(json["version"], "0.1.0");
+        assert_eq!(json["workspace_members"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_last_build_is_recorded_before_cleaning() {
+        let (_temp_dir, workspace) = workspace_fixture();
+        let result = clean_project(&workspace, &CleanOptions::default()).unwrap();
+        assert!(!workspace.path.join("target").exists());
+        let json = serde_json::to_value(&result).unwrap();
+        assert!(json["last_build"].as_str().unwrap().ends_with('Z'));
+
+        let json = serde_json::to_value(clean_project(&workspace, &CleanOptions::default()).unwrap()).unwrap();
+        assert_eq!(json["last_build"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_default_members_only() {
+        let (temp_dir, project) = workspace_fixture();
+        let options = CleanOptions {
+            default_members_only: true,
+            dry_run: true,
+            ..Default::default()
+        };
+
+        // Without default-members this is a full clean
+        let result = clean_project(&project, &options).unwrap();
+        assert_eq!(result.cleaned_packages, None);
+
+        let manifest = temp_dir.path().join("Cargo.toml");
+        let content = fs::read_to_string(&manifest).unwrap();
+        fs::write(&manifest, content.replace("resolver", "default-members = [\"beta\"]\nresolver")).unwrap();
+        let result = clean_project(&project, &options).unwrap();
+        assert_eq!(result.cleaned_packages, Some(vec!["beta".to_string()]));
+        assert_eq!(result.freed_bytes, 10_000);
+    }
+
+    #[test]
+    fn test_workspace_without_matching_package_is_skipped() {
+        let (_temp_dir, project) = workspace_fixture();
+        let options = CleanOptions {
+            packages: patterns(&["gamma"]),
+            ..Default::default()
+        };
+
+        let result = clean_project(&project, &options).unwrap();
+        assert_eq!(result.skip_reason, Some(SkipReason::NoMatchingPackage));
+    }
+
+    #[test]
+    fn test_strategy_records_fallback_and_dry_run() {
+        let (temp_dir, project) = triple_fixture();
+
+        let result = clean_project(&project, &CleanOptions { dry_run: true, ..Default::default() }).unwrap();
+        assert_eq!(result.strategy, Some(CleanStrategy::DryRun));
+
+        // No Cargo.toml, so `cargo clean` fails and the target is removed directly
+        let result = clean_project(&project, &CleanOptions::default()).unwrap();
+        assert_eq!(result.strategy, Some(CleanStrategy::Fallback));
+        assert_eq!(result.freed_bytes, 500);
+        assert!(!temp_dir.path().join("target").exists());
+    }
+
+    #[test]
+    fn test_audit_entries_for_fallback_and_cargo() {
+        let (temp_dir, project) = triple_fixture();
+        let target = temp_dir.path().join("target");
+        let planned = clean_project(&project, &CleanOptions { dry_run: true, ..Default::default() }).unwrap();
+        let result = clean_project(&project, &CleanOptions::default()).unwrap();
+        for result in [planned, result] {
+            match &result.audit[..] {
+                [AuditEntry::Removed { project: p, path, bytes, .. }] => {
+                    assert_eq!((p, path, *bytes), (&project.path, &target, 500));
+                }
+                other => panic!("unexpected audit entries: {:?}", other),
+            }
+        }
+
+        let (temp_dir, project) = workspace_fixture();
+        let result = clean_project(&project, &CleanOptions::default()).unwrap();
+        assert_eq!(result.strategy, Some(CleanStrategy::Cargo));
+        match &result.audit[..] {
+            [AuditEntry::CargoClean { command, bytes_before, bytes_after, .. }] => {
+                assert_eq!(command, "cargo clean");
+                assert!(*bytes_before >= 20_000);
+                assert_eq!(*bytes_after, 0);
+            }
+            other => panic!("unexpected audit entries: {:?}", other),
+        }
+        assert!(!temp_dir.path().join("target").exists());
+    }
+
+    #[test]
+    fn test_no_cargo_removes_target_directly() {
+        let (temp_dir, project) = workspace_fixture();
+        let result = clean_project(&project, &CleanOptions { no_cargo: true, ..Default::default() }).unwrap();
+        assert_eq!(result.strategy, Some(CleanStrategy::Direct));
+        assert_eq!(result.attempts, Some(1));
+        assert!(matches!(result.audit[..], [AuditEntry::Removed { .. }]));
+        assert!(!temp_dir.path().join("target").exists());
+    }
+
+    #[test]
+    fn test_freed_files_are_counted() {
+        let (_temp_dir, project) = workspace_fixture();
+        let dry_run = clean_project(&project, &CleanOptions { dry_run: true, ..Default::default() }).unwrap();
+        assert_eq!(dry_run.freed_files, Some(2));
+        // Only whole-target cleans count files
+        let partial = CleanOptions {
+            dry_run: true,
+            keep_subdirs: vec!["release".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(clean_project(&project, &partial).unwrap().freed_files, None);
+
+        let result = clean_project(&project, &CleanOptions { no_cargo: true, ..Default::default() }).unwrap();
+        assert_eq!((result.freed_bytes, result.freed_files), (20_000, Some(2)));
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["freed_files"], 2);
+    }
+
+    #[test]
+    fn test_removed_files_from_cargo_output() {
+        assert_eq!(removed_files("     Removed 21 files, 8.4MiB total\n"), Some(21));
+        assert_eq!(removed_files("     Removed 1 file, 512B total\n"), Some(1));
+        assert_eq!(removed_files("warning: some note\n     Removed 412331 files, 8.2GiB total\n"), Some(412_331));
+        assert_eq!(removed_files(""), None);
+        assert_eq!(removed_files("     Removed target directory\n"), None);
+    }
+
+    #[test]
+    fn test_defer_delete_moves_target_aside() {
+        let (temp_dir, project) = workspace_fixture();
+        let options = CleanOptions {
+            defer_delete: true,
+            ..Default::default()
+        };
+        let result = clean_project(&project, &options).unwrap();
+        assert_eq!(result.strategy, Some(CleanStrategy::Direct));
+        assert!(result.freed_bytes > 0);
+        let trash = PathBuf::from(result.deferred_path.unwrap());
+        assert!(trash.join("debug").exists());
+        assert!(!temp_dir.path().join("target").exists());
+    }
+
+    #[test]
+    fn test_prefer_fallback_skips_cargo_when_removal_works() {
+        let (temp_dir, project) = workspace_fixture();
+        let options = CleanOptions {
+            prefer_fallback: true,
+            ..Default::default()
+        };
+        let result = clean_project(&project, &options).unwrap();
+        assert_eq!(result.strategy, Some(CleanStrategy::Direct));
+        assert!(result.freed_bytes > 0);
+        assert!(matches!(result.audit[..], [AuditEntry::Removed { .. }]));
+        assert!(!temp_dir.path().join("target").exists());
+    }
+
+    #[test]
+    fn test_custom_target_dir_name() {
+        let (temp_dir, project) = workspace_fixture();
+        let build = temp_dir.path().join("build");
+        fs::create_dir_all(build.join("debug")).unwrap();
+        fs::write(build.join("debug/app"), vec![0u8; 300]).unwrap();
+        let options = CleanOptions {
+            no_cargo: true,
+            target_dir_name: Some("build".to_string()),
+            ..Default::default()
+        };
+
+        // Without cargo's markers the directory could be anything, e.g. sources
+        let result = clean_project(&project, &options).unwrap();
+        assert_eq!(result.skip_reason, Some(SkipReason::NotATarget));
+        assert!(build.join("debug/app").exists());
+
+        fs::write(build.join("CACHEDIR.TAG"), "Signature: 8a477f597d28d172789f06886806bc55").unwrap();
+        let result = clean_project(&project, &options).unwrap();
+        assert!(result.success);
+        assert!(result.freed_bytes >= 300);
+        assert!(!build.exists());
+        assert!(temp_dir.path().join("target").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_partial_removal_reports_what_was_freed() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (temp_dir, project) = workspace_fixture();
+        let target = temp_dir.path().join("target");
+        fs::create_dir_all(target.join("release")).unwrap();
+        fs::write(target.join("release/app"), vec![0u8; 500]).unwrap();
+        let before = get_directory_size(&target).unwrap();
+        let locked = target.join("debug/locked");
+        fs::create_dir_all(&locked).unwrap();
+        fs::write(locked.join("secret"), vec![0u8; 100]).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        if fs::read_dir(&locked).is_ok() {
+            // Permissions don't stop root
+            fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+            return;
+        }
+
+        let result = clean_project(&project, &CleanOptions { no_cargo: true, ..Default::default() });
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        let result = result.unwrap();
+
+        assert!(!result.success);
+        assert_eq!(result.strategy, None);
+        assert_eq!(result.failed_path, Some(locked.to_string_lossy().to_string()));
+        assert!(result.error.unwrap().contains("Permission denied"));
+        let remaining = result.remaining_bytes.unwrap();
+        assert_eq!(result.freed_bytes + remaining, before);
+        assert!(locked.join("secret").exists());
+    }
+
+    #[test]
+    fn test_clean_matching_triples() {
+        let (temp_dir, project) = triple_fixture();
+        let options = CleanOptions {
+            triples: patterns(&["wasm32-*"]),
+            ..Default::default()
+        };
+
+        let result = clean_project(&project, &options).unwrap();
+        let target = temp_dir.path().join("target");
+        assert_eq!(result.freed_bytes, 200);
+        assert_eq!(
+            result.removed_subdirs.unwrap(),
+            vec!["wasm32-unknown-unknown", "wasm32-wasip1"]
+        );
+        assert!(!target.join("wasm32-unknown-unknown").exists());
+        assert!(target.join("aarch64-linux-android").exists());
+        assert!(target.join("debug").exists());
+        assert!(target.join("release").exists());
+    }
+
+    #[test]
+    fn test_keep_triples_removes_the_rest() {
+        let (temp_dir, project) = triple_fixture();
+        let options = CleanOptions {
+            keep_triples: patterns(&["aarch64-linux-android"]),
+            ..Default::default()
+        };
+
+        let result = clean_project(&project, &options).unwrap();
+        let target = temp_dir.path().join("target");
+        assert_eq!(result.removed_subdirs.unwrap().len(), 2);
+        assert!(target.join("aarch64-linux-android").exists());
+        assert!(target.join("debug").exists());
+    }
+
+    #[test]
+    fn test_keep_subdirs_removes_the_rest() {
+        let (temp_dir, project) = triple_fixture();
+        let target = temp_dir.path().join("target");
+        fs::write(target.join(".rustc_info.json"), "{}").unwrap();
+        let options = CleanOptions {
+            keep_subdirs: vec!["release".to_string()],
+            ..Default::default()
+        };
+
+        let result = clean_project(&project, &options).unwrap();
+        assert_eq!(result.freed_bytes, 402);
+        assert_eq!(
+            result.removed_subdirs.unwrap(),
+            vec![
+                ".rustc_info.json",
+                "aarch64-linux-android",
+                "debug",
+                "wasm32-unknown-unknown",
+                "wasm32-wasip1"
+            ]
+        );
+        assert!(target.join("release/deps/libfoo.rlib").exists());
+        assert!(!target.join("debug").exists());
+        assert!(!target.join(".rustc_info.json").exists());
+    }
+
+    #[test]
+    fn test_keep_criterion_survives_a_clean() {
+        let (temp_dir, project) = workspace_fixture();
+        let target = temp_dir.path().join("target");
+        let estimates = target.join("criterion/parse/base/estimates.json");
+        fs::create_dir_all(estimates.parent().unwrap()).unwrap();
+        fs::write(&estimates, vec![b'0'; 1000]).unwrap();
+        let options = CleanOptions { keep_criterion: true, ..Default::default() };
+
+        let whole = clean_project(&project, &CleanOptions { dry_run: true, ..Default::default() }).unwrap();
+        let planned = clean_project(&project, &CleanOptions { dry_run: true, ..options.clone() }).unwrap();
+        assert_eq!(planned.kept_criterion_bytes, Some(1000));
+        assert_eq!(planned.freed_bytes, whole.freed_bytes - 1000);
+        assert!(estimates.exists());
+
+        // cargo clean removes the whole target; the baselines are put back afterwards
+        let result = clean_project(&project, &options).unwrap();
+        assert_eq!(result.strategy, Some(CleanStrategy::Cargo));
+        assert_eq!(result.kept_criterion_bytes, Some(1000));
+        assert_eq!(result.freed_bytes, planned.freed_bytes);
+        assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+        assert_eq!(fs::read(&estimates).unwrap().len(), 1000);
+        assert!(!target.join("debug").exists());
+        assert!(!temp_dir.path().join(".rclean-criterion").exists());
+
+        // A stash an interrupted run left behind is restored, unless that would overwrite newer results
+        fs::rename(target.join("criterion"), temp_dir.path().join(".rclean-criterion")).unwrap();
+        let result = clean_project(&project, &CleanOptions { no_cargo: true, ..options.clone() }).unwrap();
+        assert_eq!(result.kept_criterion_bytes, Some(1000));
+        assert!(estimates.exists());
+        fs::create_dir(temp_dir.path().join(".rclean-criterion")).unwrap();
+        let error = clean_project(&project, &options).unwrap_err();
+        assert!(error.to_string().contains("from an earlier run"), "{}", error);
+        assert!(estimates.exists());
+    }
+
+    #[test]
+    fn test_keep_criterion_with_keep_subdirs() {
+        let (temp_dir, project) = triple_fixture();
+        let target = temp_dir.path().join("target");
+        fs::create_dir_all(target.join("criterion/parse")).unwrap();
+        fs::write(target.join("criterion/parse/estimates.json"), vec![b'0'; 50]).unwrap();
+        let options = CleanOptions {
+            keep_subdirs: vec!["release".to_string()],
+            keep_criterion: true,
+            ..Default::default()
+        };
+
+        let result = clean_project(&project, &options).unwrap();
+        assert_eq!(result.freed_bytes, 400);
+        assert_eq!(result.kept_criterion_bytes, Some(50));
+        assert!(!result.removed_subdirs.unwrap().contains(&"criterion".to_string()));
+        assert!(target.join("criterion/parse/estimates.json").exists());
+    }
+
+    #[test]
+    fn test_triple_dry_run_keeps_everything() {
+        let (temp_dir, project) = triple_fixture();
+        let options = CleanOptions {
+            dry_run: true,
+            triples: patterns(&["*"]),
+            ..Default::default()
+        };
+
+        let result = clean_project(&project, &options).unwrap();
+        assert_eq!(result.freed_bytes, 300);
+        assert_eq!(result.removed_subdirs.unwrap().len(), 3);
+        assert!(temp_dir.path().join("target/wasm32-wasip1").exists());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::cleaner::CleanResult;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// What `--compare` needs from the `--json` summary of an earlier run
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct PreviousRun {
+    pub dry_run: bool,
+    pub results: Vec<PreviousResult>,
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct PreviousResult {
+    pub path: String,
+    pub freed_bytes: u64,
+    pub error: Option<String>,
+    pub skip_reason: Option<String>,
+}
+
+/// Read the `--json` summary an earlier run printed
+pub fn load_previous_run(path: &Path) -> Result<PreviousRun> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    serde_json::from_str(&content)
+        .with_context(|| format!("Invalid previous run {:?}: expected the --json output of an earlier run", path))
+}
+
+/// A project found in only one of the two runs
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ProjectSize {
+    pub path: String,
+    pub bytes: u64,
+}
+
+/// A project cleaned in both runs that freed a different amount
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ProjectChange {
+    pub path: String,
+    pub previous_bytes: u64,
+    pub bytes: u64,
+    pub delta_bytes: i64,
+}
+
+/// How this run differs from an earlier one, by project
+///
+/// Sizes are freed bytes (or would-free bytes in a dry run), so between two
+/// runs they show how much was rebuilt in the meantime.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Comparison {
+    /// The earlier run was a dry run, so its sizes were never actually freed
+    pub previous_dry_run: bool,
+    /// Found now but not last time
+    pub new: Vec<ProjectSize>,
+    /// Found last time but no longer present
+    pub gone: Vec<ProjectSize>,
+    /// Largest change first
+    pub changed: Vec<ProjectChange>,
+    /// Cleaned in both runs, freeing the same amount
+    pub unchanged: usize,
+    /// Difference in the total over the projects cleaned in both runs
+    pub delta_bytes: i64,
+}
+
+impl Comparison {
+    pub fn grew(&self) -> usize {
+        self.changed.iter().filter(|c| c.delta_bytes > 0).count()
+    }
+
+    pub fn shrank(&self) -> usize {
+        self.changed.iter().filter(|c| c.delta_bytes < 0).count()
+    }
+}
+
+/// Compare this run's results with an earlier run's, matching projects by path
+///
+/// A project skipped or failed in either run has no size to compare, so it
+/// only counts towards `new` and `gone`.
+pub fn compare_runs(previous: &PreviousRun, results: &[CleanResult]) -> Comparison {
+    let was_cleaned = |r: &PreviousResult| r.error.is_none() && r.skip_reason.is_none();
+    let before: BTreeMap<&str, &PreviousResult> = previous.results.iter().map(|r| (r.path.as_str(), r)).collect();
+    let now: BTreeMap<&str, &CleanResult> = results.iter().map(|r| (r.path.as_str(), r)).collect();
+
+    let mut comparison = Comparison {
+        previous_dry_run: previous.dry_run,
+        ..Default::default()
+    };
+    for (path, result) in &now {
+        let Some(earlier) = before.get(path) else {
+            comparison.new.push(ProjectSize {
+                path: path.to_string(),
+                bytes: result.freed_bytes,
+            });
+            continue;
+        };
+        if !was_cleaned(earlier) || result.is_skipped() || result.error.is_some() {
+            continue;
+        }
+        let delta_bytes = result.freed_bytes as i64 - earlier.freed_bytes as i64;
+        comparison.delta_bytes += delta_bytes;
+        if delta_bytes == 0 {
+            comparison.unchanged += 1;
+        } else {
+            comparison.changed.push(ProjectChange {
+                path: path.to_string(),
+                previous_bytes: earlier.freed_bytes,
+                bytes: result.freed_bytes,
+                delta_bytes,
+            });
+        }
+    }
+    comparison.gone = before
+        .iter()
+        .filter(|(path, _)| !now.contains_key(*path))
+        .map(|(path, earlier)| ProjectSize {
+            path: path.to_string(),
+            bytes: earlier.freed_bytes,
+        })
+        .collect();
+    comparison
+        .changed
+        .sort_by(|a, b| b.delta_bytes.abs().cmp(&a.delta_bytes.abs()).then_with(|| a.path.cmp(&b.path)));
+    comparison
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cleaner::SkipReason;
+    use crate::output::Summary;
+    use crate::project::Project;
+    use std::path::PathBuf;
+
+    fn project(path: &str) -> Project {
+        Project::new(PathBuf::from(path), false)
+    }
+
+    #[test]
+    fn test_compare_runs() {
+        // The earlier run, round-tripped through its JSON like --compare reads it
+        let earlier = Summary {
+            results: vec![
+                CleanResult::new(&project("/src/app"), 1000),
+                CleanResult::new(&project("/src/lib"), 5000),
+                CleanResult::new(&project("/src/same"), 70),
+                CleanResult::new(&project("/src/old"), 300),
+                CleanResult::skipped(&project("/src/busy"), SkipReason::InUse),
+            ],
+            ..Default::default()
+        };
+        let previous: PreviousRun = serde_json::from_value(serde_json::to_value(&earlier).unwrap()).unwrap();
+
+        let results = vec![
+            CleanResult::new(&project("/src/app"), 4000),
+            CleanResult::new(&project("/src/lib"), 2000),
+            CleanResult::new(&project("/src/same"), 70),
+            CleanResult::new(&project("/src/busy"), 900),
+            CleanResult::new(&project("/src/fresh"), 800),
+        ];
+        let comparison = compare_runs(&previous, &results);
+
+        let size = |path: &str, bytes| ProjectSize {
+            path: path.to_string(),
+            bytes,
+        };
+        assert_eq!(comparison.new, vec![size("/src/fresh", 800)]);
+        assert_eq!(comparison.gone, vec![size("/src/old", 300)]);
+        let changed: Vec<(&str, i64)> = comparison.changed.iter().map(|c| (c.path.as_str(), c.delta_bytes)).collect();
+        assert_eq!(changed, vec![("/src/app", 3000), ("/src/lib", -3000)]);
+        assert_eq!((comparison.grew(), comparison.shrank(), comparison.unchanged), (1, 1, 1));
+        // Skipped last time, so there's nothing to compare its size with
+        assert!(!changed.iter().any(|(path, _)| *path == "/src/busy"));
+        assert_eq!(comparison.delta_bytes, 0);
+
+        let json = serde_json::to_value(&comparison).unwrap();
+        assert_eq!(json["changed"][0], serde_json::json!({"path": "/src/app", "previous_bytes": 1000, "bytes": 4000, "delta_bytes": 3000}));
+        assert_eq!(json["previous_dry_run"], false);
+    }
+
+    #[test]
+    fn test_load_previous_run() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("last.json");
+        std::fs::write(&path, r#"{"dry_run": true, "total_projects": 1, "results": [{"path": "/src/app", "freed_bytes": 42, "success": true}]}"#).unwrap();
+        let previous = load_previous_run(&path).unwrap();
+        assert!(previous.dry_run);
+        assert_eq!(previous.results[0].freed_bytes, 42);
+
+        std::fs::write(&path, "[INFO] === SUMMARY ===\n").unwrap();
+        let error = format!("{:#}", load_previous_run(&path).unwrap_err());
+        assert!(error.contains("expected the --json output"), "{}", error);
+        assert!(load_previous_run(&temp_dir.path().join("missing.json")).is_err());
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Per-repository config file, looked up in the scan root
+pub const LOCAL_CONFIG: &str = "rclean.toml";
+
+/// Defaults read from a config file
+///
+/// Keys are the long names of the matching flags, e.g.
+/// `exclude = ["vendor"]` or `keep-subdir = ["release"]`.
+#[derive(Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub exclude: Vec<String>,
+    pub keep_subdir: Vec<String>,
+    pub min_size: Option<String>,
+    pub target_older_than: Option<u64>,
+    pub git_idle: Option<String>,
+    pub src_idle: Option<String>,
+    pub target_dir_name: Option<String>,
+}
+
+/// Read `rclean.toml` from `root`, returning its path along with it
+pub fn load_local_config(root: &Path) -> Result<Option<(PathBuf, Config)>> {
+    let path = root.join(LOCAL_CONFIG);
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+    };
+    let config = toml::from_str(&content).with_context(|| format!("Invalid config file {:?}", path))?;
+    Ok(Some((path, config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_local_config() {
+        let temp_dir = TempDir::new().unwrap();
+        assert_eq!(load_local_config(temp_dir.path()).unwrap(), None);
+
+        std::fs::write(
+            temp_dir.path().join(LOCAL_CONFIG),
+            "exclude = [\"vendor\"]\nkeep-subdir = [\"release\"]\nmin-size = \"1GB\"\n",
+        )
+        .unwrap();
+        let (path, config) = load_local_config(temp_dir.path()).unwrap().unwrap();
+        assert_eq!(path, temp_dir.path().join(LOCAL_CONFIG));
+        assert_eq!(
+            config,
+            Config {
+                exclude: vec!["vendor".to_string()],
+                keep_subdir: vec!["release".to_string()],
+                min_size: Some("1GB".to_string()),
+                ..Default::default()
+            }
+        );
+
+        // A typo shouldn't silently clean more than intended
+        std::fs::write(temp_dir.path().join(LOCAL_CONFIG), "excludes = [\"vendor\"]\n").unwrap();
+        let error = load_local_config(temp_dir.path()).unwrap_err();
+        assert!(format!("{:#}", error).contains("unknown field `excludes`"));
+    }
+}
@@ -0,0 +1,241 @@
+use crate::audit::AuditEntry;
+use crate::removal::remove_tree;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Reports rustc writes to the working directory when it crashes, named
+/// `rustc-ice-<timestamp>-<pid>.txt`
+const ICE_PREFIX: &str = "rustc-ice-";
+
+/// Raw coverage profiles written by `-C instrument-coverage` builds
+const PROFRAW_EXTENSION: &str = "profraw";
+
+/// Left by cargo in unpacked package sources
+const CARGO_OK: &str = ".cargo-ok";
+
+/// Scratch space for integration tests (`CARGO_TARGET_TMPDIR`), inside the target
+const TARGET_TMP: &str = "tmp";
+
+/// Junk found in a project, removed or about to be
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Item {
+    path: PathBuf,
+    is_dir: bool,
+    files: usize,
+    bytes: u64,
+}
+
+/// What removing a project's debris freed, or would free
+#[derive(Debug, Default)]
+pub struct DebrisCleaned {
+    pub files: usize,
+    pub bytes: u64,
+    pub audit: Vec<AuditEntry>,
+    /// Debris that could not be removed, as messages
+    pub warnings: Vec<String>,
+}
+
+fn is_ice_dump(name: &str) -> bool {
+    name.strip_prefix(ICE_PREFIX)
+        .and_then(|rest| rest.strip_suffix(".txt"))
+        .is_some_and(|stamp| stamp.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+fn is_profraw(name: &str) -> bool {
+    Path::new(name).extension().is_some_and(|e| e == PROFRAW_EXTENSION)
+}
+
+/// Regular files directly in `dir` whose names match `is_debris`
+fn debris_files(dir: &Path, is_debris: impl Fn(&str) -> bool) -> Vec<Item> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()) && is_debris(&e.file_name().to_string_lossy()))
+        .map(|e| Item {
+            path: e.path(),
+            is_dir: false,
+            files: 1,
+            bytes: e.metadata().map(|m| m.len()).unwrap_or(0),
+        })
+        .collect()
+}
+
+/// Everything inside `target/tmp`, which stays in place itself
+fn target_tmp(target_dir: &Path) -> Vec<Item> {
+    let tmp = target_dir.join(TARGET_TMP);
+    if !fs::symlink_metadata(&tmp).is_ok_and(|m| m.is_dir()) {
+        return Vec::new();
+    }
+    let Ok(entries) = fs::read_dir(&tmp) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| {
+            let is_dir = e.file_type().is_ok_and(|t| t.is_dir());
+            let (files, bytes) = WalkDir::new(e.path())
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| !e.file_type().is_dir())
+                .fold((0, 0), |(files, bytes), e| {
+                    (files + 1, bytes + e.metadata().map(|m| m.len()).unwrap_or(0))
+                });
+            Item {
+                path: e.path(),
+                is_dir,
+                files,
+                bytes,
+            }
+        })
+        .collect()
+}
+
+/// The debris in a project: ICE dumps, coverage profiles and `.cargo-ok` in
+/// its root, and with `target_dir`, coverage profiles in the target and the
+/// contents of `target/tmp`
+///
+/// Only these fixed names are looked for, never anything a pattern could
+/// stretch to cover sources, and only directly where each tool leaves them.
+fn find_debris(project_dir: &Path, target_dir: Option<&Path>) -> Vec<Item> {
+    let mut found = debris_files(project_dir, |name| is_ice_dump(name) || is_profraw(name) || name == CARGO_OK);
+    if let Some(target_dir) = target_dir {
+        found.extend(debris_files(target_dir, is_profraw));
+        found.extend(target_tmp(target_dir));
+    }
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    found
+}
+
+/// Measure and, unless `dry_run`, remove a project's debris
+///
+/// `target_dir` is left out when the target is cleaned entirely anyway.
+pub fn clean_debris(project_dir: &Path, target_dir: Option<&Path>, dry_run: bool) -> DebrisCleaned {
+    let mut cleaned = DebrisCleaned::default();
+    for item in find_debris(project_dir, target_dir) {
+        if !dry_run {
+            let removed = if item.is_dir {
+                remove_tree(&item.path, item.bytes)
+            } else {
+                fs::remove_file(&item.path)
+            };
+            if let Err(e) = removed {
+                cleaned.warnings.push(format!("Failed to remove {:?}: {}", item.path, e));
+                continue;
+            }
+        }
+        cleaned.files += item.files;
+        cleaned.bytes += item.bytes;
+        cleaned.audit.push(AuditEntry::removed(project_dir, &item.path, item.bytes));
+    }
+    cleaned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, size: usize) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, vec![0u8; size]).unwrap();
+    }
+
+    /// A project with one of each kind of debris, next to files that only look like it
+    fn fixture() -> (TempDir, PathBuf) {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path().join("app");
+        write(&project.join("rustc-ice-2024-03-05T12_34_56-4242.txt"), 100);
+        write(&project.join("default_123_456.profraw"), 20);
+        write(&project.join(".cargo-ok"), 1);
+        write(&project.join("target/app-99.profraw"), 30);
+        write(&project.join("target/tmp/fixture.db"), 40);
+        write(&project.join("target/tmp/case-1/output.json"), 50);
+        write(&project.join("target/tmp/case-1/nested/log"), 5);
+
+        // Legitimate files that must survive
+        write(&project.join("rustc-ice-notes.txt"), 7);
+        write(&project.join("rustc-ice-2024-03-05.md"), 7);
+        write(&project.join("src/profraw.rs"), 7);
+        write(&project.join("coverage.profraw.bak"), 7);
+        write(&project.join("src/rustc-ice-2024-03-05T12_34_56-4242.txt"), 7);
+        write(&project.join("tests/data/run.profraw"), 7);
+        write(&project.join("src/.cargo-ok"), 7);
+        write(&project.join("target/debug/tmp/keep"), 7);
+        fs::create_dir_all(project.join("archive.profraw")).unwrap();
+        (temp_dir, project)
+    }
+
+    fn remaining(project: &Path) -> Vec<String> {
+        let mut files: Vec<String> = WalkDir::new(project)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.path().strip_prefix(project).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_debris_is_removed_and_lookalikes_survive() {
+        let (_temp_dir, project) = fixture();
+        let target = project.join("target");
+
+        let planned = clean_debris(&project, Some(&target), true);
+        assert_eq!((planned.files, planned.bytes), (7, 246));
+        assert_eq!(remaining(&project).len(), 15);
+
+        let cleaned = clean_debris(&project, Some(&target), false);
+        assert_eq!((cleaned.files, cleaned.bytes), (planned.files, planned.bytes));
+        assert_eq!(cleaned.audit.len(), 6);
+        assert!(cleaned.warnings.is_empty());
+        assert_eq!(
+            remaining(&project),
+            vec![
+                "coverage.profraw.bak",
+                "rustc-ice-2024-03-05.md",
+                "rustc-ice-notes.txt",
+                "src/.cargo-ok",
+                "src/profraw.rs",
+                "src/rustc-ice-2024-03-05T12_34_56-4242.txt",
+                "target/debug/tmp/keep",
+                "tests/data/run.profraw",
+            ]
+        );
+        // target/tmp itself stays for the next test run
+        assert!(target.join("tmp").is_dir());
+        assert!(project.join("archive.profraw").is_dir());
+    }
+
+    #[test]
+    fn test_debris_without_target() {
+        let (_temp_dir, project) = fixture();
+        // When the whole target goes anyway, only the project root is searched
+        let cleaned = clean_debris(&project, None, false);
+        assert_eq!((cleaned.files, cleaned.bytes), (3, 121));
+        assert!(project.join("target/app-99.profraw").exists());
+        assert!(project.join("target/tmp/fixture.db").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_debris_never_follows_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path().join("app");
+        write(&temp_dir.path().join("elsewhere/rustc-ice-2024-01-01T00_00_00-1.txt"), 10);
+        fs::create_dir_all(project.join("target")).unwrap();
+        std::os::unix::fs::symlink(temp_dir.path().join("elsewhere"), project.join("target/tmp")).unwrap();
+        std::os::unix::fs::symlink(
+            temp_dir.path().join("elsewhere/rustc-ice-2024-01-01T00_00_00-1.txt"),
+            project.join("rustc-ice-2024-01-01T00_00_00-1.txt"),
+        )
+        .unwrap();
+
+        let cleaned = clean_debris(&project, Some(&project.join("target")), false);
+        assert_eq!(cleaned.files, 0);
+        assert!(temp_dir.path().join("elsewhere/rustc-ice-2024-01-01T00_00_00-1.txt").exists());
+    }
+}
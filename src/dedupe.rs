@@ -0,0 +1,246 @@
+use crate::cache::parse_crate_file_name;
+use crate::toolchain::{belongs_to_set, newest_mtime, ArtifactSet};
+use crate::utils::profile_dirs;
+use semver::Version;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Sets built this close to the newest one may come from the same build, and
+/// so may still be in use (e.g. the same crate with different features for
+/// build scripts and for the final binary)
+const SAME_BUILD_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Kept and superseded fingerprint hashes of one crate in one profile
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DedupeGroup {
+    pub profile: String,
+    pub name: String,
+    pub kept: String,
+    pub removed: Vec<String>,
+}
+
+/// One fingerprint directory and what can be learned about it
+#[derive(Debug)]
+struct Candidate {
+    hash: String,
+    built: SystemTime,
+    version: Option<Version>,
+}
+
+/// The fingerprint JSON files in a fingerprint directory, which name the unit kinds
+fn fingerprint_units(fingerprint_dir: &Path) -> Vec<String> {
+    let mut units: Vec<String> = fs::read_dir(fingerprint_dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .filter(|name| name.ends_with(".json"))
+                .collect()
+        })
+        .unwrap_or_default();
+    units.sort();
+    units
+}
+
+/// Crate version of a set, from the registry source paths in its dep-info file
+fn set_version(profile_dir: &Path, stem: &str, name: &str) -> Option<Version> {
+    let deps_dir = profile_dir.join("deps");
+    let dep_info = fs::read_dir(&deps_dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .find(|file| file.ends_with(".d") && belongs_to_set(file, stem))?;
+    let content = fs::read_to_string(deps_dir.join(dep_info)).ok()?;
+    let name = name.replace('-', "_");
+
+    content
+        .split_whitespace()
+        .flat_map(|path| Path::new(path).components().collect::<Vec<_>>())
+        .find_map(|component| {
+            let (dir_name, version) = parse_crate_file_name(&component.as_os_str().to_string_lossy())?;
+            (dir_name.replace('-', "_") == name).then_some(version)
+        })
+}
+
+/// Whether two versions can coexist in one dependency graph
+///
+/// Cargo unifies semver-compatible versions, so only incompatible ones
+/// (different major, or different minor below 1.0) can be used side by side.
+fn semver_incompatible(a: &Version, b: &Version) -> bool {
+    if a.major != b.major {
+        return true;
+    }
+    a.major == 0 && (a.minor != b.minor || (a.minor == 0 && a.patch != b.patch))
+}
+
+/// Find artifact sets superseded by a newer build of the same crate
+///
+/// Within each profile, fingerprints are grouped by crate name and unit
+/// kind. The newest set of a group is kept and the rest are superseded,
+/// unless the group is ambiguous, in which case it is left alone entirely:
+/// when a fingerprint's unit kind can't be read, when another set was built
+/// within minutes of the newest, or when the versions are semver-incompatible
+/// and could be in use side by side.
+pub fn find_superseded_sets(target_dir: &Path) -> Vec<(DedupeGroup, Vec<ArtifactSet>)> {
+    let mut superseded = Vec::new();
+
+    for profile_dir in profile_dirs(target_dir) {
+        let profile = profile_dir
+            .strip_prefix(target_dir)
+            .unwrap_or(&profile_dir)
+            .display()
+            .to_string();
+        let Ok(entries) = fs::read_dir(profile_dir.join(".fingerprint")) else {
+            continue;
+        };
+
+        // (name, units) -> candidates; unreadable units poison the whole name
+        let mut groups: BTreeMap<(String, Vec<String>), Vec<Candidate>> = BTreeMap::new();
+        let mut ambiguous_names = Vec::new();
+        for entry in entries.filter_map(|e| e.ok()) {
+            let stem = entry.file_name().to_string_lossy().to_string();
+            let Some((name, hash)) = stem.rsplit_once('-') else {
+                continue;
+            };
+            let units = fingerprint_units(&entry.path());
+            if units.is_empty() {
+                ambiguous_names.push(name.to_string());
+                continue;
+            }
+            // Unit kinds (lib, build script, ...) are grouped separately
+            groups.entry((name.to_string(), units)).or_default().push(Candidate {
+                hash: hash.to_string(),
+                built: newest_mtime(&entry.path()),
+                version: set_version(&profile_dir, &stem, name),
+            });
+        }
+
+        for ((name, _), mut candidates) in groups {
+            if candidates.len() < 2 || ambiguous_names.contains(&name) {
+                continue;
+            }
+            candidates.sort_by_key(|c| std::cmp::Reverse(c.built));
+            let newest = &candidates[0];
+            let ambiguous = candidates[1..].iter().any(|c| {
+                newest.built.duration_since(c.built).unwrap_or_default() < SAME_BUILD_WINDOW
+                    || match (&newest.version, &c.version) {
+                        (Some(a), Some(b)) => semver_incompatible(a, b),
+                        _ => false,
+                    }
+            });
+            if ambiguous {
+                continue;
+            }
+
+            let sets: Vec<ArtifactSet> = candidates[1..]
+                .iter()
+                .map(|c| ArtifactSet {
+                    profile_dir: profile_dir.clone(),
+                    stem: format!("{}-{}", name, c.hash),
+                })
+                .collect();
+            superseded.push((
+                DedupeGroup {
+                    profile: profile.clone(),
+                    name,
+                    kept: newest.hash.clone(),
+                    removed: candidates[1..].iter().map(|c| c.hash.clone()).collect(),
+                },
+                sets,
+            ));
+        }
+    }
+
+    superseded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filetime::FileTime;
+    use tempfile::TempDir;
+
+    /// Fabricate a fingerprint, deps files and dep-info for one artifact set
+    fn write_set(profile: &Path, name: &str, hash: &str, version: Option<&str>, age_days: u64) {
+        let crate_name = name.replace('-', "_");
+        let fingerprint = profile.join(".fingerprint").join(format!("{}-{}", name, hash));
+        fs::create_dir_all(&fingerprint).unwrap();
+        fs::create_dir_all(profile.join("deps")).unwrap();
+        fs::write(fingerprint.join(format!("lib-{}.json", crate_name)), "{}").unwrap();
+        fs::write(fingerprint.join(format!("lib-{}", crate_name)), "0").unwrap();
+        fs::write(
+            profile.join("deps").join(format!("lib{}-{}.rlib", crate_name, hash)),
+            vec![0u8; 100],
+        )
+        .unwrap();
+        let source = match version {
+            Some(v) => format!("/home/me/.cargo/registry/src/index.crates.io-abc/{}-{}/src/lib.rs", name, v),
+            None => format!("/work/{}/src/lib.rs", name),
+        };
+        fs::write(
+            profile.join("deps").join(format!("{}-{}.d", crate_name, hash)),
+            format!("{}: {}\n", hash, source),
+        )
+        .unwrap();
+
+        let time = FileTime::from_system_time(crate::utils::cutoff_for_days(age_days));
+        for entry in fs::read_dir(&fingerprint).unwrap() {
+            filetime::set_file_mtime(entry.unwrap().path(), time).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_superseded_versions_are_found() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        let debug = target.join("debug");
+        write_set(&debug, "serde-json", "1111111111111111", Some("1.0.100"), 90);
+        write_set(&debug, "serde-json", "2222222222222222", Some("1.0.110"), 30);
+        write_set(&debug, "serde-json", "3333333333333333", Some("1.0.120"), 1);
+
+        let superseded = find_superseded_sets(&target);
+        assert_eq!(superseded.len(), 1);
+        let (group, sets) = &superseded[0];
+        assert_eq!(group.profile, "debug");
+        assert_eq!(group.name, "serde-json");
+        assert_eq!(group.kept, "3333333333333333");
+        assert_eq!(group.removed, vec!["2222222222222222", "1111111111111111"]);
+        assert_eq!(sets.len(), 2);
+    }
+
+    #[test]
+    fn test_incompatible_versions_are_kept() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        let debug = target.join("debug");
+        write_set(&debug, "syn", "1111111111111111", Some("1.0.109"), 90);
+        write_set(&debug, "syn", "2222222222222222", Some("2.0.77"), 1);
+
+        assert!(find_superseded_sets(&target).is_empty());
+    }
+
+    #[test]
+    fn test_same_build_is_ambiguous() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        let debug = target.join("debug");
+        write_set(&debug, "app", "1111111111111111", None, 1);
+        write_set(&debug, "app", "2222222222222222", None, 1);
+
+        assert!(find_superseded_sets(&target).is_empty());
+    }
+
+    #[test]
+    fn test_unreadable_fingerprint_keeps_everything() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        let debug = target.join("debug");
+        write_set(&debug, "app", "1111111111111111", None, 60);
+        write_set(&debug, "app", "2222222222222222", None, 1);
+        fs::create_dir_all(debug.join(".fingerprint/app-3333333333333333")).unwrap();
+
+        assert!(find_superseded_sets(&target).is_empty());
+    }
+}
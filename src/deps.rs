@@ -1,10 +1,14 @@
 use anyhow::{Context, Result};
+use cargo_metadata::{Metadata, Package, PackageId};
 use crate::project::Project;
+use crate::timing::exec_metadata;
+use crate::utils::{cargo, metadata_command, offline_hint, run_command};
 use colored::Colorize;
-use std::collections::HashSet;
+use rayon::prelude::*;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::Command;
 use walkdir::WalkDir;
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -13,6 +17,29 @@ pub struct UnusedDependency {
     pub location: String, // e.g., "[dependencies]", "[dev-dependencies]"
 }
 
+/// Dependency keys that Rust code refers to by the same identifier
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NameCollision {
+    pub identifier: String,
+    pub names: Vec<String>,
+}
+
+/// A crate resolved at more than one version in the same dependency graph
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DuplicateDependency {
+    pub name: String,
+    /// Oldest version first
+    pub versions: Vec<DuplicateVersion>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DuplicateVersion {
+    pub version: String,
+    /// One shortest chain from a workspace member per crate that requires
+    /// this version, e.g. `app 0.1.0 -> hyper 0.14.28 -> http 0.2.12`
+    pub paths: Vec<String>,
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct DependencyCleanResult {
     pub path: String,
@@ -20,6 +47,11 @@ pub struct DependencyCleanResult {
     pub unused_deps: Vec<UnusedDependency>,
     pub removed_count: usize,
     pub error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub name_collisions: Vec<NameCollision>,
+    /// Only checked with `--duplicates`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub duplicates: Vec<DuplicateDependency>,
 }
 
 /// Extract dependency names from Cargo.toml
@@ -63,10 +95,67 @@ fn normalize_crate_name(name: &str) -> String {
     name.replace('-', "_")
 }
 
+/// Find dependency keys (e.g. `foo-bar` and `foo_bar`) that normalize to the same identifier
+fn find_name_collisions(deps: &[(String, String)]) -> Vec<NameCollision> {
+    let mut by_identifier: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (name, _) in deps {
+        let names = by_identifier.entry(normalize_crate_name(name)).or_default();
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    by_identifier
+        .into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .map(|(identifier, mut names)| {
+            names.sort();
+            NameCollision { identifier, names }
+        })
+        .collect()
+}
+
+/// Project sources searched for dependency references, read once per project
+struct SourceCorpus {
+    /// Every `.rs` file under `src/`, `examples/` and `tests/`, plus `build.rs`
+    rust: String,
+    manifest: String,
+}
+
+impl SourceCorpus {
+    fn load(project_path: &Path) -> Self {
+        let mut rust = String::new();
+        for dir in ["src", "examples", "tests"] {
+            for entry in WalkDir::new(project_path.join(dir)).into_iter().filter_map(|e| e.ok()) {
+                if entry.file_type().is_file() && entry.path().extension().is_some_and(|ext| ext == "rs") {
+                    append_file(&mut rust, entry.path());
+                }
+            }
+        }
+        append_file(&mut rust, &project_path.join("build.rs"));
+
+        SourceCorpus {
+            rust,
+            manifest: fs::read_to_string(project_path.join("Cargo.toml")).unwrap_or_default(),
+        }
+    }
+}
+
+/// Append a file's contents, on its own line so no pattern spans two files
+fn append_file(corpus: &mut String, path: &Path) {
+    if let Ok(content) = fs::read_to_string(path) {
+        corpus.push_str(&content);
+        corpus.push('\n');
+    }
+}
+
 /// Check if a dependency is used in the source code
-fn is_dependency_used(dep_name: &str, project_path: &Path) -> bool {
+///
+/// Source code can't tell colliding keys apart, so they share the result of
+/// the source search. In `Cargo.toml`, where keys are written as declared, a
+/// colliding key only matches its own spelling.
+fn is_dependency_used(dep_name: &str, corpus: &SourceCorpus, collides: bool) -> bool {
     let normalized_dep = normalize_crate_name(dep_name);
-    let search_patterns = vec![
+    let search_patterns = [
         // Direct use statements
         format!("use {}::", normalized_dep),
         format!("use {};", normalized_dep),
@@ -81,54 +170,19 @@ fn is_dependency_used(dep_name: &str, project_path: &Path) -> bool {
         format!("#[{}", normalized_dep),
     ];
     
-    // Search in src/ directory
-    let src_dir = project_path.join("src");
-    if src_dir.exists() {
-        if search_in_directory(&src_dir, &search_patterns) {
-            return true;
-        }
-    }
-    
-    // Search in examples/ directory
-    let examples_dir = project_path.join("examples");
-    if examples_dir.exists() {
-        if search_in_directory(&examples_dir, &search_patterns) {
-            return true;
-        }
-    }
-    
-    // Search in tests/ directory
-    let tests_dir = project_path.join("tests");
-    if tests_dir.exists() {
-        if search_in_directory(&tests_dir, &search_patterns) {
-            return true;
-        }
-    }
-    
-    // Check build.rs
-    let build_rs = project_path.join("build.rs");
-    if build_rs.exists() {
-        if let Ok(content) = fs::read_to_string(&build_rs) {
-            for pattern in &search_patterns {
-                if content.contains(pattern) {
-                    return true;
-                }
-            }
-        }
+    // Search src/, examples/, tests/ and build.rs
+    if search_patterns.iter().any(|pattern| corpus.rust.contains(pattern.as_str())) {
+        return true;
     }
     
     // Check Cargo.toml for feature flags or other references
-    let cargo_toml = project_path.join("Cargo.toml");
-    if let Ok(content) = fs::read_to_string(&cargo_toml) {
-        // Check if it's used in feature definitions or other places
-        // This is a simple check - might need refinement
-        let normalized = normalize_crate_name(dep_name);
-        if content.contains(&format!("{}/", dep_name)) 
-            || content.contains(&format!("{}-", dep_name))
-            || content.contains(&format!("{}/", normalized))
-            || content.contains(&format!("{}-", normalized)) {
-            return true;
-        }
+    // This is a simple check - might need refinement
+    let content = &corpus.manifest;
+    if content.contains(&format!("{}/", dep_name)) 
+        || content.contains(&format!("{}-", dep_name))
+        || (!collides && content.contains(&format!("{}/", normalized_dep)))
+        || (!collides && content.contains(&format!("{}-", normalized_dep))) {
+        return true;
     }
     
     // Check for proc-macro usage (they're used via attributes, not imports)
@@ -142,66 +196,144 @@ fn is_dependency_used(dep_name: &str, project_path: &Path) -> bool {
     false
 }
 
-/// Search for patterns in a directory
-fn search_in_directory(dir: &Path, patterns: &[String]) -> bool {
-    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
-        if entry.file_type().is_file() {
-            let path = entry.path();
-            // Only check Rust files
-            if let Some(ext) = path.extension() {
-                if ext == "rs" {
-                    if let Ok(content) = fs::read_to_string(path) {
-                        for pattern in patterns {
-                            if content.contains(pattern) {
-                                return true;
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
-    false
-}
+/// Some common dependencies that might be used indirectly
+///
+/// These are often used in macros, build scripts, or procedural macros.
+const INDIRECT_DEPENDENCIES: &[&str] = &[
+    "proc-macro2",
+    "quote",
+    "syn",
+    "serde",
+    "serde_derive",
+    "serde_json", // Often used in build scripts
+];
+
+/// Unused dependencies and colliding names found in one project
+pub type DependencyCheck = (Vec<UnusedDependency>, Vec<NameCollision>);
 
 /// Check for unused dependencies in a project
-pub fn check_unused_dependencies(project: &Project) -> Result<Vec<UnusedDependency>> {
+///
+/// The sources are read once, then dependencies are checked against them in
+/// parallel on the current rayon pool, so callers running inside
+/// `ThreadPool::install` share its `--jobs` limit. Results keep the manifest
+/// order regardless of scheduling. Also returns the dependency keys whose
+/// names collide once normalized.
+pub fn check_unused_dependencies(project: &Project) -> Result<DependencyCheck> {
     let cargo_toml = project.path.join("Cargo.toml");
     if !cargo_toml.exists() {
-        return Ok(vec![]);
+        return Ok((vec![], vec![]));
     }
     
     let all_deps = extract_dependencies(&cargo_toml)?;
-    let mut unused = Vec::new();
+    let collisions = find_name_collisions(&all_deps);
+    let corpus = SourceCorpus::load(&project.path);
+    let unused = all_deps
+        .into_par_iter()
+        .filter(|(dep_name, _)| {
+            // Also skip if it's a proc-macro crate (they're used via attributes)
+            !(INDIRECT_DEPENDENCIES.contains(&dep_name.as_str())
+                || dep_name.ends_with("_derive")
+                || dep_name.contains("proc-macro"))
+        })
+        .filter(|(dep_name, _)| {
+            let collides = collisions.iter().any(|c| c.names.contains(dep_name));
+            !is_dependency_used(dep_name, &corpus, collides)
+        })
+        .map(|(name, location)| UnusedDependency { name, location })
+        .collect();
     
-    for (dep_name, location) in all_deps {
-        // Skip some common dependencies that might be used indirectly
-        // These are often used in macros, build scripts, or procedural macros
-        let skip_list = vec![
-            "proc-macro2",
-            "quote",
-            "syn",
-            "serde",
-            "serde_derive",
-            "serde_json", // Often used in build scripts
-        ];
-        
-        // Also skip if it's a proc-macro crate (they're used via attributes)
-        if skip_list.contains(&dep_name.as_str()) 
-            || dep_name.ends_with("_derive")
-            || dep_name.contains("proc-macro") {
-            continue;
+    Ok((unused, collisions))
+}
+
+/// Find crates resolved at more than one version in a project's dependency graph
+///
+/// Runs `cargo metadata --locked`, offline unless `--online` was given, so the
+/// lock file is read but never written and nothing is downloaded: a project
+/// without an up-to-date `Cargo.lock`, or whose dependencies aren't in the
+/// local cache, is reported as an error rather than resolved afresh.
+pub fn find_duplicate_dependencies(project: &Project) -> Result<Vec<DuplicateDependency>> {
+    let metadata = exec_metadata(&metadata_command(&project.path.join("Cargo.toml"), &["--locked"])).map_err(|e| {
+        let hint = offline_hint(&e.to_string());
+        anyhow::Error::from(e).context(format!("Failed to resolve the dependency graph{}", hint))
+    })?;
+    Ok(duplicates_in(&metadata))
+}
+
+fn duplicates_in(metadata: &Metadata) -> Vec<DuplicateDependency> {
+    let Some(ref resolve) = metadata.resolve else {
+        return Vec::new();
+    };
+    let packages: HashMap<&PackageId, &Package> = metadata.packages.iter().map(|p| (&p.id, p)).collect();
+
+    // Breadth-first from the workspace members, so each crate remembers the
+    // dependent it was first reached through and chains come out shortest
+    let edges: HashMap<&PackageId, Vec<&PackageId>> = resolve
+        .nodes
+        .iter()
+        .map(|node| (&node.id, node.deps.iter().map(|dep| &dep.pkg).collect()))
+        .collect();
+    let mut reached_from: HashMap<&PackageId, Option<&PackageId>> = HashMap::new();
+    let mut queue: VecDeque<&PackageId> = VecDeque::new();
+    for member in &metadata.workspace_members {
+        reached_from.insert(member, None);
+        queue.push_back(member);
+    }
+    let mut dependents: HashMap<&PackageId, Vec<&PackageId>> = HashMap::new();
+    while let Some(id) = queue.pop_front() {
+        for &dep in edges.get(id).into_iter().flatten() {
+            dependents.entry(dep).or_default().push(id);
+            if let Entry::Vacant(entry) = reached_from.entry(dep) {
+                entry.insert(Some(id));
+                queue.push_back(dep);
+            }
         }
-        
-        if !is_dependency_used(&dep_name, &project.path) {
-            unused.push(UnusedDependency {
-                name: dep_name,
-                location,
-            });
+    }
+
+    let describe = |id: &PackageId| packages.get(id).map_or_else(|| id.repr.clone(), |p| format!("{} {}", p.name, p.version));
+    let chain_to = |id: &PackageId| {
+        let mut chain = vec![describe(id)];
+        let mut parent = reached_from.get(id).copied().flatten();
+        while let Some(id) = parent {
+            chain.push(describe(id));
+            parent = reached_from.get(id).copied().flatten();
+        }
+        chain.reverse();
+        chain.join(" -> ")
+    };
+
+    let mut by_name: BTreeMap<String, Vec<&Package>> = BTreeMap::new();
+    for id in reached_from.keys() {
+        if let Some(package) = packages.get(id) {
+            by_name.entry(package.name.to_string()).or_default().push(package);
         }
     }
-    
-    Ok(unused)
+    by_name
+        .into_iter()
+        .filter(|(_, versions)| versions.len() > 1)
+        .map(|(name, mut versions)| {
+            versions.sort_by(|a, b| a.version.cmp(&b.version).then_with(|| a.id.repr.cmp(&b.id.repr)));
+            let versions = versions
+                .into_iter()
+                .map(|package| {
+                    let mut paths: Vec<String> = match dependents.get(&package.id) {
+                        Some(parents) => parents
+                            .iter()
+                            .map(|parent| format!("{} -> {}", chain_to(parent), describe(&package.id)))
+                            .collect(),
+                        // A workspace member nothing else depends on
+                        None => vec![describe(&package.id)],
+                    };
+                    paths.sort();
+                    paths.dedup();
+                    DuplicateVersion {
+                        version: package.version.to_string(),
+                        paths,
+                    }
+                })
+                .collect();
+            DuplicateDependency { name, versions }
+        })
+        .collect()
 }
 
 /// Remove unused dependencies from Cargo.toml
@@ -216,8 +348,8 @@ pub fn remove_unused_dependencies(
     }
 
     // Check if cargo-remove is available first
-    let check_output = Command::new("cargo")
-        .args(&["remove", "--help"])
+    let check_output = cargo()
+        .args(["remove", "--help"])
         .output();
     
     match check_output {
@@ -252,10 +384,7 @@ pub fn remove_unused_dependencies(
             cmd_args.push("--build".to_string());
         }
         
-        let output = Command::new("cargo")
-            .args(&cmd_args)
-            .current_dir(&project.path)
-            .output()
+        let output = run_command(cargo().args(&cmd_args).current_dir(&project.path))
             .with_context(|| format!("Failed to run `cargo remove {}`", dep.name))?;
 
         if output.status.success() {
@@ -265,7 +394,7 @@ pub fn remove_unused_dependencies(
             }
         } else {
             let stderr = String::from_utf8_lossy(&output.stderr);
-            let error_msg = format!("Failed to remove {} ({}): {}", dep.name, dep.location, stderr);
+            let error_msg = format!("Failed to remove {} ({}): {}{}", dep.name, dep.location, stderr, offline_hint(&stderr));
             errors.push(error_msg.clone());
             if verbose {
                 println!("  {} Failed to remove {} ({}): {}", "[DEBUG]".red(), dep.name, dep.location, stderr);
@@ -283,15 +412,25 @@ pub fn remove_unused_dependencies(
     Ok(removed)
 }
 
-/// Clean unused dependencies for a project
+/// Clean unused dependencies for a project, and report duplicate versions if asked to
+///
+/// `checked` is an analysis of this project that was already made, such as
+/// the one shown when confirming `--remove-deps`; without it the project is
+/// checked here.
 pub fn clean_dependencies(
     project: &Project,
+    checked: Option<DependencyCheck>,
     dry_run: bool,
     remove: bool,
+    duplicates: bool,
     verbose: bool,
 ) -> Result<DependencyCleanResult> {
-    let unused_deps = check_unused_dependencies(project)
-        .with_context(|| format!("Failed to check unused dependencies in {:?}", project.path))?;
+    let (unused_deps, name_collisions) = match checked {
+        Some(checked) => checked,
+        None => check_unused_dependencies(project)
+            .with_context(|| format!("Failed to check unused dependencies in {:?}", project.path))?,
+    };
+    let duplicates = if duplicates { find_duplicate_dependencies(project)? } else { Vec::new() };
 
     let removed_count = if remove && !unused_deps.is_empty() {
         match remove_unused_dependencies(project, &unused_deps, dry_run, verbose) {
@@ -304,6 +443,8 @@ pub fn clean_dependencies(
                     unused_deps,
                     removed_count: 0,
                     error: Some(e.to_string()),
+                    name_collisions,
+                    duplicates,
                 });
             }
         }
@@ -317,12 +458,15 @@ pub fn clean_dependencies(
         unused_deps,
         removed_count,
         error: None,
+        name_collisions,
+        duplicates,
     })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::process::Command;
 
     #[test]
     fn test_normalize_crate_name() {
@@ -357,4 +501,177 @@ tempfile = "3.0"
         assert!(dep_names.contains(&"serde".to_string()));
         assert!(dep_names.contains(&"tokio".to_string()));
     }
+
+    #[test]
+    fn test_colliding_names_are_not_marked_unused() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("Cargo.toml"),
+            r#"
+[package]
+name = "test"
+version = "0.1.0"
+
+[dependencies]
+foo-bar = "1.0"
+
+[dev-dependencies]
+foo_bar = { version = "1.0", package = "foo-bar-testing" }
+"#,
+        ).unwrap();
+        fs::create_dir(temp_dir.path().join("src")).unwrap();
+        fs::write(temp_dir.path().join("src/lib.rs"), "pub fn f() { foo_bar::run(); }").unwrap();
+        let project = Project::new(temp_dir.path().to_path_buf(), false);
+
+        let (unused, collisions) = check_unused_dependencies(&project).unwrap();
+        assert!(unused.is_empty());
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].identifier, "foo_bar");
+        assert_eq!(collisions[0].names, vec!["foo-bar", "foo_bar"]);
+    }
+
+    #[test]
+    fn test_colliding_names_match_manifest_references_precisely() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("Cargo.toml"),
+            r#"
+[package]
+name = "test"
+version = "0.1.0"
+
+[features]
+std = ["foo-bar/std"]
+
+[dependencies]
+foo-bar = "1.0"
+
+[dev-dependencies]
+foo_bar = { version = "1.0", package = "foo-bar-testing" }
+"#,
+        ).unwrap();
+        let project = Project::new(temp_dir.path().to_path_buf(), false);
+
+        let (unused, _) = check_unused_dependencies(&project).unwrap();
+        let names: Vec<&str> = unused.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, vec!["foo_bar"]);
+    }
+
+    #[test]
+    fn test_clean_dependencies_acts_on_the_confirmed_check() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("Cargo.toml"),
+            "[package]\nname = \"test\"\nversion = \"0.1.0\"\n\n[dependencies]\ntokio = \"1.0\"\n",
+        ).unwrap();
+        let project = Project::new(temp_dir.path().to_path_buf(), false);
+
+        let result = clean_dependencies(&project, None, true, false, false, false).unwrap();
+        assert_eq!(result.unused_deps.len(), 1);
+
+        // Given the analysis that was confirmed, it isn't redone
+        let result = clean_dependencies(&project, Some((Vec::new(), Vec::new())), true, false, false, false).unwrap();
+        assert!(result.unused_deps.is_empty());
+    }
+
+    #[test]
+    fn test_parallel_check_is_deterministic() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let deps: String = (0..40).map(|i| format!("dep{:02} = \"1.0\"\n", i)).collect();
+        fs::write(
+            temp_dir.path().join("Cargo.toml"),
+            format!("[package]\nname = \"test\"\nversion = \"0.1.0\"\n\n[dependencies]\n{}", deps),
+        ).unwrap();
+        fs::create_dir(temp_dir.path().join("src")).unwrap();
+        let uses: String = (0..40).step_by(3).map(|i| format!("use dep{:02}::X;\n", i)).collect();
+        fs::write(temp_dir.path().join("src/lib.rs"), uses).unwrap();
+        let project = Project::new(temp_dir.path().to_path_buf(), false);
+
+        let names_with_jobs = |jobs: usize| -> Vec<String> {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build().unwrap();
+            let (unused, _) = pool.install(|| check_unused_dependencies(&project)).unwrap();
+            unused.into_iter().map(|d| d.name).collect()
+        };
+        let expected: Vec<String> = (0..40).filter(|i| i % 3 != 0).map(|i| format!("dep{:02}", i)).collect();
+        assert_eq!(names_with_jobs(1), expected);
+        for _ in 0..5 {
+            assert_eq!(names_with_jobs(8), expected);
+        }
+    }
+
+    fn write_package(dir: &Path, name: &str, version: &str, deps: &str) {
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            format!("[package]\nname = \"{}\"\nversion = \"{}\"\nedition = \"2021\"\n\n[dependencies]\n{}", name, version, deps),
+        ).unwrap();
+        fs::write(dir.join("src/lib.rs"), "").unwrap();
+    }
+
+    #[test]
+    fn test_duplicate_versions_with_requiring_paths() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        write_package(&root.join("shared1"), "shared", "1.0.0", "");
+        write_package(&root.join("shared2"), "shared", "2.0.0", "");
+        write_package(&root.join("mid"), "mid", "0.1.0", "shared = { path = \"../shared2\" }\n");
+        write_package(
+            root,
+            "app",
+            "0.1.0",
+            "mid = { path = \"mid\" }\nshared = { path = \"shared1\" }\n",
+        );
+        let project = Project::new(root.to_path_buf(), false);
+
+        // Without a lock file nothing is resolved, since that would write one
+        assert!(find_duplicate_dependencies(&project).is_err());
+        assert!(!root.join("Cargo.lock").exists());
+
+        let status = Command::new("cargo")
+            .args(["generate-lockfile", "--offline", "--quiet"])
+            .current_dir(root)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let duplicates = find_duplicate_dependencies(&project).unwrap();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].name, "shared");
+        let versions: Vec<(&str, &[String])> = duplicates[0]
+            .versions
+            .iter()
+            .map(|v| (v.version.as_str(), v.paths.as_slice()))
+            .collect();
+        assert_eq!(
+            versions,
+            vec![
+                ("1.0.0", &["app 0.1.0 -> shared 1.0.0".to_string()][..]),
+                ("2.0.0", &["app 0.1.0 -> mid 0.1.0 -> shared 2.0.0".to_string()][..]),
+            ]
+        );
+
+        let json = serde_json::to_value(&duplicates).unwrap();
+        assert_eq!(json[0]["versions"][1]["paths"][0], "app 0.1.0 -> mid 0.1.0 -> shared 2.0.0");
+    }
+
+    #[test]
+    fn test_source_corpus_covers_every_source_dir() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        for dir in ["src", "tests"] {
+            fs::create_dir(temp_dir.path().join(dir)).unwrap();
+        }
+        fs::write(temp_dir.path().join("src/a.rs"), "const X: u8 = 1; type T = foo").unwrap();
+        fs::write(temp_dir.path().join("src/b.rs"), "::bar();").unwrap();
+        fs::write(temp_dir.path().join("tests/it.rs"), "use quux::X;").unwrap();
+        fs::write(temp_dir.path().join("build.rs"), "fn main() { baz!(); }").unwrap();
+        fs::write(temp_dir.path().join("Cargo.toml"), "[features]\nstd = [\"zed/std\"]\n").unwrap();
+
+        let corpus = SourceCorpus::load(temp_dir.path());
+        for used in ["quux", "baz", "zed"] {
+            assert!(is_dependency_used(used, &corpus, false), "{} should be used", used);
+        }
+        // Files are separated, so `foo` at the end of one file and `::` at the
+        // start of the next don't add up to a reference
+        assert!(!is_dependency_used("foo", &corpus, false));
+    }
 }
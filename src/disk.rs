@@ -0,0 +1,494 @@
+use anyhow::Result;
+use crate::utils::{format_bytes, units};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// How far, in percent of the bytes freed, the free space a filesystem
+/// gained may differ from them before the summary points it out
+const FS_DELTA_TOLERANCE_PCT: u128 = 10;
+
+/// Differences below this are block rounding and metadata, never worth a note
+const FS_DELTA_MIN_DIFFERENCE: u128 = 1024 * 1024;
+
+/// Free and total space of the filesystem containing a path
+///
+/// The system implementation asks the OS (`statvfs` on Unix,
+/// `GetDiskFreeSpaceExW` on Windows); tests substitute their own numbers.
+pub trait SpaceProvider {
+    fn available_space(&self, path: &Path) -> io::Result<u64>;
+    fn total_space(&self, path: &Path) -> io::Result<u64>;
+}
+
+/// Space as the OS reports it
+pub struct SystemSpace;
+
+impl SpaceProvider for SystemSpace {
+    fn available_space(&self, path: &Path) -> io::Result<u64> {
+        fs2::available_space(path)
+    }
+
+    fn total_space(&self, path: &Path) -> io::Result<u64> {
+        fs2::total_space(path)
+    }
+}
+
+/// Free space on one filesystem containing cleaned targets
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FilesystemSpace {
+    pub mount_point: PathBuf,
+    /// Number of projects on this filesystem
+    pub projects: usize,
+    pub free_bytes_before: u64,
+    pub free_bytes_after: u64,
+    /// Capacity of the filesystem
+    pub total_bytes: u64,
+    /// Bytes freed by the projects on this filesystem
+    pub freed_bytes: u64,
+    /// `freed_bytes` as a percentage of `total_bytes`
+    pub freed_pct_of_volume: Option<f64>,
+    /// Change in free space measured on the filesystem, negative if it shrank;
+    /// unset in a dry run
+    pub fs_delta_bytes: Option<i64>,
+}
+
+/// Find the mount point of the filesystem containing `path`
+///
+/// Walks up the ancestors until the device ID changes, so the result is the
+/// same directory `df` reports for that path.
+#[cfg(unix)]
+pub fn mount_point(path: &Path) -> PathBuf {
+    use std::os::unix::fs::MetadataExt;
+
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let dev = match std::fs::metadata(&path) {
+        Ok(metadata) => metadata.dev(),
+        Err(_) => return path,
+    };
+    let mut mount = path.clone();
+    for ancestor in path.ancestors().skip(1) {
+        match std::fs::metadata(ancestor) {
+            Ok(metadata) if metadata.dev() == dev => mount = ancestor.to_path_buf(),
+            _ => break,
+        }
+    }
+    mount
+}
+
+/// Without device IDs, treat each path prefix (drive) as its own filesystem
+#[cfg(not(unix))]
+pub fn mount_point(path: &Path) -> PathBuf {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    path.ancestors().last().map(Path::to_path_buf).unwrap_or(path)
+}
+
+/// Identifier of the filesystem containing `path`
+#[cfg(unix)]
+pub fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    std::fs::metadata(path).ok().map(|metadata| metadata.dev())
+}
+
+/// Identifier of the volume containing `path`
+#[cfg(windows)]
+pub fn device_id(path: &Path) -> Option<u64> {
+    let handle = winapi_util::Handle::from_path_any(path).ok()?;
+    winapi_util::file::information(&handle)
+        .ok()
+        .map(|information| information.volume_serial_number())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn device_id(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// A mounted filesystem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mount {
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+}
+
+/// Parse the contents of `/proc/self/mountinfo`
+///
+/// Each line reads `ID PARENT MAJOR:MINOR ROOT MOUNT_POINT OPTIONS
+/// [OPTIONAL...] - FS_TYPE SOURCE SUPER_OPTIONS`, with spaces and other
+/// special characters in paths escaped as octal (`\040`).
+pub fn parse_mountinfo(content: &str) -> Vec<Mount> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(' ').collect();
+            let separator = fields.iter().position(|f| *f == "-")?;
+            Some(Mount {
+                mount_point: PathBuf::from(unescape_mount_path(fields.get(4)?)),
+                fs_type: fields.get(separator + 1)?.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn unescape_mount_path(path: &str) -> String {
+    let mut result = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(index) = rest.find('\\') {
+        result.push_str(&rest[..index]);
+        let escaped = rest.get(index + 1..index + 4).and_then(|octal| u8::from_str_radix(octal, 8).ok());
+        match escaped {
+            Some(byte) => {
+                result.push(byte as char);
+                rest = &rest[index + 4..];
+            }
+            None => {
+                result.push('\\');
+                rest = &rest[index + 1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Mounts whose filesystem type is one of `fs_types` (e.g. `nfs`, `fuse.sshfs`)
+#[cfg(target_os = "linux")]
+pub fn mounts_of_type(fs_types: &[String]) -> Result<Vec<Mount>> {
+    use anyhow::Context;
+
+    let content = std::fs::read_to_string("/proc/self/mountinfo").context("Failed to read /proc/self/mountinfo")?;
+    Ok(parse_mountinfo(&content)
+        .into_iter()
+        .filter(|mount| fs_types.contains(&mount.fs_type))
+        .collect())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn mounts_of_type(_fs_types: &[String]) -> Result<Vec<Mount>> {
+    Err(anyhow::anyhow!("--exclude-fs-type is only supported on Linux"))
+}
+
+/// Group project paths by filesystem, recording the free space on each
+///
+/// The result is sorted by mount point; `free_bytes_after` starts out equal
+/// to `free_bytes_before` until `record_free_space_after` is called.
+pub fn free_space_before(paths: &[&Path]) -> Vec<FilesystemSpace> {
+    free_space_before_with(paths, &SystemSpace)
+}
+
+pub fn free_space_before_with(paths: &[&Path], space: &impl SpaceProvider) -> Vec<FilesystemSpace> {
+    let mut filesystems: Vec<FilesystemSpace> = Vec::new();
+    for path in paths {
+        add_free_space_before(&mut filesystems, path, space);
+    }
+    filesystems
+}
+
+/// Count one more project path towards its filesystem, recording the free
+/// space there the first time the filesystem comes up
+///
+/// Streaming runs call this as projects are found, before any of them on
+/// that filesystem is cleaned. `filesystems` stays sorted by mount point.
+pub fn add_free_space_before(filesystems: &mut Vec<FilesystemSpace>, path: &Path, space: &impl SpaceProvider) {
+    let mount = mount_point(path);
+    match filesystems.binary_search_by(|fs| fs.mount_point.cmp(&mount)) {
+        Ok(i) => filesystems[i].projects += 1,
+        Err(i) => {
+            let free = space.available_space(&mount).unwrap_or(0);
+            filesystems.insert(
+                i,
+                FilesystemSpace {
+                    total_bytes: space.total_space(&mount).unwrap_or(0),
+                    mount_point: mount,
+                    projects: 1,
+                    free_bytes_before: free,
+                    free_bytes_after: free,
+                    freed_bytes: 0,
+                    freed_pct_of_volume: None,
+                    fs_delta_bytes: None,
+                },
+            );
+        }
+    }
+}
+
+/// Re-measure free space on each filesystem after cleaning, and how much it changed
+pub fn record_free_space_after(filesystems: &mut [FilesystemSpace], dry_run: bool) {
+    record_free_space_after_with(filesystems, dry_run, &SystemSpace)
+}
+
+pub fn record_free_space_after_with(filesystems: &mut [FilesystemSpace], dry_run: bool, space: &impl SpaceProvider) {
+    for fs in filesystems {
+        fs.free_bytes_after = space.available_space(&fs.mount_point).unwrap_or(fs.free_bytes_before);
+        fs.fs_delta_bytes = (!dry_run).then(|| fs.free_bytes_after as i64 - fs.free_bytes_before as i64);
+    }
+}
+
+/// A note for when the free space the filesystems gained differs from what
+/// was freed by more than `FS_DELTA_TOLERANCE_PCT`
+///
+/// Freed bytes are what the removed files held. Hardlinked and reflinked
+/// files free nothing until their last copy goes, compressed filesystems
+/// free less, and other programs write in the meantime.
+pub fn fs_delta_note(freed_bytes: u64, fs_delta_bytes: i64) -> Option<String> {
+    let difference = (freed_bytes as i128 - fs_delta_bytes as i128).unsigned_abs();
+    if freed_bytes == 0
+        || difference < FS_DELTA_MIN_DIFFERENCE
+        || difference * 100 <= freed_bytes as u128 * FS_DELTA_TOLERANCE_PCT
+    {
+        return None;
+    }
+    let measured = if fs_delta_bytes < 0 {
+        format!("lost {} of free space", format_bytes(fs_delta_bytes.unsigned_abs(), units()))
+    } else {
+        format!("gained {} of free space", format_bytes(fs_delta_bytes as u64, units()))
+    };
+    Some(format!(
+        "the filesystem(s) {} while {} was freed; hardlinks, reflinks, compression or other programs writing can account for the difference",
+        measured,
+        format_bytes(freed_bytes, units())
+    ))
+}
+
+/// Attribute the bytes freed in each project to the filesystem it's on
+pub fn record_freed_bytes<'a>(filesystems: &mut [FilesystemSpace], freed: impl IntoIterator<Item = (&'a Path, u64)>) {
+    for (path, bytes) in freed {
+        let mount = mount_point(path);
+        if let Some(fs) = filesystems.iter_mut().find(|fs| fs.mount_point == mount) {
+            fs.freed_bytes += bytes;
+        }
+    }
+    for fs in filesystems {
+        fs.freed_pct_of_volume = percent_of(fs.freed_bytes, fs.total_bytes);
+    }
+}
+
+/// Free space on a filesystem once the run is over
+///
+/// A dry run deletes nothing, so its figure is the free space it started
+/// with plus what it would have freed there.
+pub fn free_after(fs: &FilesystemSpace, dry_run: bool) -> u64 {
+    if dry_run {
+        fs.free_bytes_before.saturating_add(fs.freed_bytes)
+    } else {
+        fs.free_bytes_after
+    }
+}
+
+/// Stops starting projects on a filesystem once it has `--until-free` bytes available
+///
+/// Free space is measured again before each project. Like `TimeBudget`,
+/// projects already running are left to finish, so with several jobs a run
+/// can free more than it strictly needed.
+pub struct FreeSpaceGoal {
+    goal: Option<u64>,
+    dry_run: bool,
+    /// Mount point, free space at the start and what a dry run would have freed on it since
+    filesystems: Mutex<Vec<(PathBuf, u64, u64)>>,
+}
+
+impl FreeSpaceGoal {
+    pub fn new(goal: Option<u64>, dry_run: bool, filesystems: &[FilesystemSpace]) -> Self {
+        FreeSpaceGoal {
+            goal,
+            dry_run,
+            filesystems: Mutex::new(
+                filesystems
+                    .iter()
+                    .map(|fs| (fs.mount_point.clone(), fs.free_bytes_before, 0))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Whether the filesystem holding `project` already has enough free space
+    pub fn met(&self, project: &Path) -> bool {
+        let Some(goal) = self.goal else {
+            return false;
+        };
+        let mount = mount_point(project);
+        let projected = self
+            .filesystems
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(mount_point, _, _)| *mount_point == mount)
+            .map(|(_, before, freed)| before.saturating_add(*freed));
+        let free = if self.dry_run {
+            projected
+        } else {
+            SystemSpace.available_space(&mount).ok().or(projected)
+        };
+        free.is_some_and(|free| free >= goal)
+    }
+
+    /// Count a project's projected frees towards its filesystem, for dry runs
+    pub fn record_freed(&self, project: &Path, bytes: u64) {
+        if self.goal.is_none() || !self.dry_run {
+            return;
+        }
+        let mount = mount_point(project);
+        if let Some(fs) = self.filesystems.lock().unwrap().iter_mut().find(|(mount_point, _, _)| *mount_point == mount) {
+            fs.2 += bytes;
+        }
+    }
+}
+
+/// `part` as a percentage of `whole`, to two decimals, or `None` when `whole` is unknown (zero)
+pub fn percent_of(part: u64, whole: u64) -> Option<f64> {
+    (whole > 0).then(|| (part as f64 * 10_000.0 / whole as f64).round() / 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_mount_point_is_an_ancestor() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().canonicalize().unwrap();
+        assert!(path.starts_with(mount_point(&path)));
+    }
+
+    #[test]
+    fn test_projects_on_one_filesystem_are_grouped() {
+        let temp_dir = TempDir::new().unwrap();
+        let a = temp_dir.path().join("a");
+        let b = temp_dir.path().join("b");
+        std::fs::create_dir_all(&a).unwrap();
+        std::fs::create_dir_all(&b).unwrap();
+
+        let mut filesystems = free_space_before(&[&a, &b]);
+        assert_eq!(filesystems.len(), 1);
+        assert_eq!(filesystems[0].projects, 2);
+        assert!(filesystems[0].free_bytes_before > 0);
+
+        record_free_space_after(&mut filesystems, false);
+        assert!(filesystems[0].free_bytes_after > 0);
+        assert!(filesystems[0].fs_delta_bytes.is_some());
+
+        let total = filesystems[0].total_bytes;
+        assert!(total >= filesystems[0].free_bytes_before);
+        record_freed_bytes(&mut filesystems, [(a.as_path(), total / 8), (b.as_path(), total / 8)]);
+        assert_eq!(filesystems[0].freed_bytes, total / 4);
+        assert_eq!(filesystems[0].freed_pct_of_volume, Some(25.0));
+    }
+
+    #[test]
+    fn test_free_space_goal() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path().join("app");
+        std::fs::create_dir_all(&project).unwrap();
+        let filesystems = free_space_before(&[&project]);
+        let free = filesystems[0].free_bytes_before;
+
+        // A dry run projects its own frees, since nothing is actually deleted
+        let goal = FreeSpaceGoal::new(Some(free + 1000), true, &filesystems);
+        assert!(!goal.met(&project));
+        goal.record_freed(&project, 600);
+        assert!(!goal.met(&project));
+        goal.record_freed(&project, 400);
+        assert!(goal.met(&project));
+
+        // A real run measures the filesystem again
+        assert!(FreeSpaceGoal::new(Some(1), false, &filesystems).met(&project));
+        assert!(!FreeSpaceGoal::new(Some(u64::MAX), false, &filesystems).met(&project));
+        assert!(!FreeSpaceGoal::new(None, true, &filesystems).met(&project));
+    }
+
+    #[test]
+    fn test_free_after() {
+        let fs = FilesystemSpace {
+            mount_point: PathBuf::from("/"),
+            projects: 1,
+            free_bytes_before: 100,
+            free_bytes_after: 100,
+            total_bytes: 1000,
+            freed_bytes: 50,
+            freed_pct_of_volume: None,
+            fs_delta_bytes: None,
+        };
+        assert_eq!(free_after(&fs, true), 150);
+        assert_eq!(free_after(&fs, false), 100);
+    }
+
+    /// A filesystem whose free space only changes when told to
+    struct MockSpace(Mutex<u64>);
+
+    impl SpaceProvider for MockSpace {
+        fn available_space(&self, _path: &Path) -> io::Result<u64> {
+            Ok(*self.0.lock().unwrap())
+        }
+
+        fn total_space(&self, _path: &Path) -> io::Result<u64> {
+            Ok(1 << 40)
+        }
+    }
+
+    #[test]
+    fn test_fs_delta_with_mocked_space() {
+        let gib: u64 = 1 << 30;
+        let space = MockSpace(Mutex::new(10 * gib));
+        let mut filesystems = free_space_before_with(&[Path::new("/")], &space);
+        assert_eq!(filesystems[0].total_bytes, 1 << 40);
+
+        *space.0.lock().unwrap() = 13 * gib;
+        record_free_space_after_with(&mut filesystems, false, &space);
+        assert_eq!(filesystems[0].free_bytes_after, 13 * gib);
+        assert_eq!(filesystems[0].fs_delta_bytes, Some(3 * gib as i64));
+
+        // Something else filled the disk meanwhile
+        *space.0.lock().unwrap() = 9 * gib;
+        record_free_space_after_with(&mut filesystems, false, &space);
+        assert_eq!(filesystems[0].fs_delta_bytes, Some(-(gib as i64)));
+
+        // A dry run deletes nothing, so there's no delta to speak of
+        record_free_space_after_with(&mut filesystems, true, &space);
+        assert_eq!(filesystems[0].fs_delta_bytes, None);
+    }
+
+    #[test]
+    fn test_fs_delta_note() {
+        let gib: i64 = 1 << 30;
+        // Within 10% is rounding, block sizes and the like
+        assert_eq!(fs_delta_note(10 * gib as u64, 9 * gib + gib / 2), None);
+        assert_eq!(fs_delta_note(10 * gib as u64, 11 * gib), None);
+        // Tiny runs differ by a few blocks, proportionally a lot
+        assert_eq!(fs_delta_note(4096, 0), None);
+        assert_eq!(fs_delta_note(0, gib), None);
+
+        let note = fs_delta_note(10 * gib as u64, 2 * gib).unwrap();
+        assert!(note.starts_with("the filesystem(s) gained 2.00 GiB of free space while 10.00 GiB was freed"));
+        assert!(fs_delta_note(10 * gib as u64, -gib).unwrap().contains("lost 1.00 GiB of free space"));
+    }
+
+    #[test]
+    fn test_percent_of() {
+        assert_eq!(percent_of(1, 3), Some(33.33));
+        assert_eq!(percent_of(0, 100), Some(0.0));
+        assert_eq!(percent_of(5, 0), None);
+    }
+
+    #[test]
+    fn test_parse_mountinfo() {
+        let content = "\
+22 1 0:21 / / rw,relatime shared:1 - ext4 /dev/sda1 rw
+36 22 0:32 / /home/me/nas rw,relatime shared:12 master:3 - nfs server:/export rw
+40 22 0:35 / /home/me/My\\040Drive rw - fuse.sshfs me@host: rw
+malformed line";
+        let mounts = parse_mountinfo(content);
+        assert_eq!(mounts.len(), 3);
+        assert_eq!(mounts[1].mount_point, PathBuf::from("/home/me/nas"));
+        assert_eq!(mounts[1].fs_type, "nfs");
+        assert_eq!(mounts[2].mount_point, PathBuf::from("/home/me/My Drive"));
+        assert_eq!(mounts[2].fs_type, "fuse.sshfs");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_mounts_of_type_reads_this_system() {
+        let mounts = mounts_of_type(&["no-such-fs".to_string()]).unwrap();
+        assert!(mounts.is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+use anyhow::Result;
+use crate::cleaner::CleanResult;
+use std::path::{Path, PathBuf};
+
+/// What kind of device a drive letter refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriveKind {
+    Fixed,
+    #[cfg_attr(not(windows), allow(dead_code))]
+    Removable,
+    Network,
+    #[cfg_attr(not(windows), allow(dead_code))]
+    Other,
+}
+
+/// A logical drive, e.g. `C:\`
+#[derive(Debug, Clone)]
+pub struct Drive {
+    pub root: PathBuf,
+    pub kind: DriveKind,
+}
+
+/// Something that can list the logical drives of the machine
+pub trait DriveSource {
+    fn drives(&self) -> Result<Vec<Drive>>;
+}
+
+/// The drives reported by `GetLogicalDrives` and `GetDriveTypeW`
+pub struct SystemDrives;
+
+#[cfg(windows)]
+impl DriveSource for SystemDrives {
+    fn drives(&self) -> Result<Vec<Drive>> {
+        use std::os::windows::ffi::OsStrExt;
+        use windows_sys::Win32::Storage::FileSystem::{GetDriveTypeW, GetLogicalDrives};
+        use windows_sys::Win32::System::WindowsProgramming::{DRIVE_FIXED, DRIVE_REMOTE, DRIVE_REMOVABLE};
+
+        // Bit 0 is A:, bit 1 is B: and so on
+        let mask = unsafe { GetLogicalDrives() };
+        if mask == 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        Ok((0..26u8)
+            .filter(|bit| mask & (1 << bit) != 0)
+            .map(|bit| {
+                let root = PathBuf::from(format!("{}:\\", (b'A' + bit) as char));
+                let wide: Vec<u16> = root.as_os_str().encode_wide().chain(Some(0)).collect();
+                let kind = match unsafe { GetDriveTypeW(wide.as_ptr()) } {
+                    DRIVE_FIXED => DriveKind::Fixed,
+                    DRIVE_REMOVABLE => DriveKind::Removable,
+                    DRIVE_REMOTE => DriveKind::Network,
+                    _ => DriveKind::Other,
+                };
+                Drive { root, kind }
+            })
+            .collect())
+    }
+}
+
+#[cfg(not(windows))]
+impl DriveSource for SystemDrives {
+    fn drives(&self) -> Result<Vec<Drive>> {
+        Err(anyhow::anyhow!("--all-drives is only supported on Windows"))
+    }
+}
+
+/// Roots to scan: every fixed drive, plus network drives when asked for
+///
+/// Removable and other drives (CD-ROM, RAM disks) are never scanned.
+pub fn scan_roots(source: &dyn DriveSource, include_network: bool) -> Result<Vec<PathBuf>> {
+    Ok(source
+        .drives()?
+        .into_iter()
+        .filter(|drive| drive.kind == DriveKind::Fixed || (include_network && drive.kind == DriveKind::Network))
+        .map(|drive| drive.root)
+        .collect())
+}
+
+/// Exclude patterns applied to every drive root
+///
+/// System directories never hold projects worth cleaning, and CARGO_HOME is
+/// handled by `cargo deepclean cache`.
+pub fn default_excludes(drive_root: &Path, cargo_home: Option<&Path>) -> Vec<String> {
+    let mut excludes: Vec<String> = ["Windows", "Program Files", "Program Files (x86)", "ProgramData"]
+        .iter()
+        .map(|dir| dir.to_string())
+        .collect();
+    if let Some(relative) = cargo_home.and_then(|home| home.strip_prefix(drive_root).ok()) {
+        excludes.push(glob::Pattern::escape(&relative.to_string_lossy()));
+    }
+    excludes
+}
+
+/// Cleaning results on one drive
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DriveSummary {
+    pub drive: PathBuf,
+    pub projects: usize,
+    pub cleaned: usize,
+    pub freed_bytes: u64,
+}
+
+/// Group results by the drive root their project lives under
+///
+/// Drives without results are still listed, in the order given.
+pub fn group_by_drive(results: &[CleanResult], roots: &[PathBuf]) -> Vec<DriveSummary> {
+    roots
+        .iter()
+        .map(|root| {
+            let on_drive: Vec<&CleanResult> = results.iter().filter(|r| Path::new(&r.path).starts_with(root)).collect();
+            DriveSummary {
+                drive: root.clone(),
+                projects: on_drive.len(),
+                cleaned: on_drive.iter().filter(|r| r.success && !r.is_skipped()).count(),
+                freed_bytes: on_drive.iter().map(|r| r.freed_bytes).sum(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cleaner::SkipReason;
+    use crate::project::Project;
+
+    struct FakeDrives(Vec<(&'static str, DriveKind)>);
+
+    impl DriveSource for FakeDrives {
+        fn drives(&self) -> Result<Vec<Drive>> {
+            Ok(self
+                .0
+                .iter()
+                .map(|(root, kind)| Drive {
+                    root: PathBuf::from(root),
+                    kind: *kind,
+                })
+                .collect())
+        }
+    }
+
+    fn fake_drives() -> FakeDrives {
+        FakeDrives(vec![
+            ("/c", DriveKind::Fixed),
+            ("/d", DriveKind::Removable),
+            ("/e", DriveKind::Fixed),
+            ("/n", DriveKind::Network),
+            ("/r", DriveKind::Other),
+        ])
+    }
+
+    #[test]
+    fn test_scan_roots_skips_removable_and_network() {
+        let roots = scan_roots(&fake_drives(), false).unwrap();
+        assert_eq!(roots, vec![PathBuf::from("/c"), PathBuf::from("/e")]);
+
+        let roots = scan_roots(&fake_drives(), true).unwrap();
+        assert_eq!(roots, vec![PathBuf::from("/c"), PathBuf::from("/e"), PathBuf::from("/n")]);
+    }
+
+    #[test]
+    fn test_default_excludes_cargo_home_on_its_drive() {
+        let cargo_home = Path::new("/c/tools/cargo[1]");
+        assert!(default_excludes(Path::new("/c"), Some(cargo_home)).contains(&"tools/cargo[[]1[]]".to_string()));
+        assert_eq!(default_excludes(Path::new("/e"), Some(cargo_home)).len(), 4);
+    }
+
+    #[test]
+    fn test_group_by_drive() {
+        let project = |path: &str| Project::new(PathBuf::from(path), false);
+        let results = vec![
+            CleanResult::new(&project("/c/code/app"), 100),
+            CleanResult::new(&project("/c/code/lib"), 50),
+            CleanResult::skipped(&project("/e/work/tool"), SkipReason::InUse),
+        ];
+        let roots = scan_roots(&fake_drives(), false).unwrap();
+
+        let drives = group_by_drive(&results, &roots);
+        assert_eq!(drives.len(), 2);
+        assert_eq!((drives[0].projects, drives[0].cleaned, drives[0].freed_bytes), (2, 2, 150));
+        assert_eq!((drives[1].projects, drives[1].cleaned, drives[1].freed_bytes), (1, 0, 0));
+    }
+}
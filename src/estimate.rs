@@ -0,0 +1,260 @@
+use crate::timing::count_dirs_walked;
+use crate::utils::{fnv1a, long_path};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Levels below the measured directory whose files are always measured, e.g.
+/// `target/.rustc_info.json` and `target/debug/<binary>`
+const FULL_DEPTH: usize = 2;
+
+/// Samples smaller than this say too little about the rest, so at least
+/// this many directories are measured (or all of them, when there are fewer)
+const MIN_SAMPLED_DIRS: usize = 12;
+
+/// How many standard errors either side of the estimate its range spans
+const RANGE_STD_ERRORS: f64 = 3.0;
+
+/// A directory's size extrapolated from a sample of its subdirectories
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct SizeEstimate {
+    /// The best guess, reported wherever the exact size would be
+    #[serde(skip)]
+    pub bytes: u64,
+    /// Always true, so JSON consumers can tell estimates from measurements
+    pub estimated: bool,
+    /// Range the true size falls in with high confidence
+    pub low: u64,
+    pub high: u64,
+}
+
+impl SizeEstimate {
+    /// An exact size, for parts that were measured in full
+    pub fn exact(bytes: u64) -> Self {
+        SizeEstimate {
+            bytes,
+            estimated: true,
+            low: bytes,
+            high: bytes,
+        }
+    }
+
+    /// Both estimates added up, ranges included
+    pub fn add(self, other: SizeEstimate) -> Self {
+        SizeEstimate {
+            bytes: self.bytes + other.bytes,
+            estimated: true,
+            low: self.low + other.low,
+            high: self.high + other.high,
+        }
+    }
+}
+
+/// SplitMix64: tiny, seedable and plenty random for picking a sample
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// A directory below `FULL_DEPTH` and how many files it holds directly
+struct Unit {
+    dir: PathBuf,
+    files: u64,
+}
+
+/// Total size of the files directly in `dir`
+fn direct_file_bytes(dir: &Path) -> u64 {
+    fs::read_dir(dir).map_or(0, |entries| {
+        entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+            .filter_map(|e| e.metadata().ok())
+            .map(|m| m.len())
+            .sum()
+    })
+}
+
+/// Estimate the size of everything below `path` by measuring only part of it
+///
+/// The sample is seeded by the path, so the same tree gives the same estimate.
+pub fn estimate_size(path: &Path, fraction: f64) -> SizeEstimate {
+    estimate_size_seeded(path, fraction, fnv1a(path.to_string_lossy().as_bytes()))
+}
+
+/// Estimate the size below `path`, picking the sample with `seed`
+///
+/// Listing directories is cheap next to reading every file's metadata, so
+/// the whole tree is listed, but only files in the top `FULL_DEPTH` levels
+/// and in a `fraction` of the directories below them are measured. The rest
+/// is extrapolated from their file counts at the sampled bytes per file (a
+/// ratio estimator), with a range from how well that ratio fit the sample.
+/// Directories holding a large share of all files, like `deps/`, would sway
+/// the result too much to leave to chance and are always measured.
+fn estimate_size_seeded(path: &Path, fraction: f64, seed: u64) -> SizeEstimate {
+    let mut exact = 0u64;
+    let mut units: Vec<Unit> = Vec::new();
+    let mut unit_of: HashMap<PathBuf, usize> = HashMap::new();
+    let mut dirs = 0;
+    for entry in WalkDir::new(long_path(path)).into_iter().filter_map(|e| e.ok()) {
+        if entry.file_type().is_dir() {
+            dirs += 1;
+            if entry.depth() >= FULL_DEPTH {
+                unit_of.insert(entry.path().to_path_buf(), units.len());
+                units.push(Unit {
+                    dir: entry.path().to_path_buf(),
+                    files: 0,
+                });
+            }
+        } else if entry.file_type().is_file() {
+            if entry.depth() <= FULL_DEPTH {
+                exact += entry.metadata().map_or(0, |m| m.len());
+            } else if let Some(&unit) = entry.path().parent().and_then(|parent| unit_of.get(parent)) {
+                units[unit].files += 1;
+            }
+        }
+    }
+    count_dirs_walked(dirs);
+    units.retain(|unit| unit.files > 0);
+
+    let total_files: u64 = units.iter().map(|unit| unit.files).sum();
+    let sample_size = ((units.len() as f64 * fraction).ceil() as usize).max(MIN_SAMPLED_DIRS);
+    let (mut measured, mut sampled): (Vec<Unit>, Vec<Unit>) = units
+        .into_iter()
+        .partition(|unit| unit.files.saturating_mul(sample_size as u64) >= total_files);
+    let rest = sampled.len();
+    let sample_size = sample_size.min(rest);
+    if sample_size == rest {
+        measured.append(&mut sampled);
+    } else {
+        // A partial Fisher-Yates shuffle moves the sample to the front
+        let mut rng = SplitMix64(seed);
+        for i in 0..sample_size {
+            let j = i + rng.below(rest - i);
+            sampled.swap(i, j);
+        }
+    }
+    let unsampled = sampled.split_off(sample_size);
+    exact += measured.iter().map(|unit| direct_file_bytes(&unit.dir)).sum::<u64>();
+    if unsampled.is_empty() {
+        return SizeEstimate::exact(exact);
+    }
+
+    let sizes: Vec<(f64, f64)> = sampled
+        .iter()
+        .map(|unit| (unit.files as f64, direct_file_bytes(&unit.dir) as f64))
+        .collect();
+    let sampled_bytes: f64 = sizes.iter().map(|(_, bytes)| bytes).sum();
+    let sampled_files: f64 = sizes.iter().map(|(files, _)| files).sum();
+    let bytes_per_file = sampled_bytes / sampled_files;
+    let unsampled_files: u64 = unsampled.iter().map(|unit| unit.files).sum();
+
+    // Variance of a ratio estimate of the total over the sampled population
+    let (n, m) = (rest as f64, sample_size as f64);
+    let residuals: f64 = sizes
+        .iter()
+        .map(|(files, bytes)| (bytes - bytes_per_file * files).powi(2))
+        .sum::<f64>()
+        / (m - 1.0);
+    let std_error = (n * n * (1.0 - m / n) / m * residuals).sqrt();
+
+    let known = exact + sampled_bytes as u64;
+    let bytes = known + (bytes_per_file * unsampled_files as f64).round() as u64;
+    let spread = (RANGE_STD_ERRORS * std_error).round() as u64;
+    SizeEstimate {
+        bytes,
+        estimated: true,
+        low: bytes.saturating_sub(spread).max(known),
+        high: bytes + spread,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::get_directory_size;
+    use std::fs::File;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, size: u64) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(path).unwrap().set_len(size).unwrap();
+    }
+
+    /// A target shaped like cargo's: a few files at the top, one big `deps/`,
+    /// and many small directories of varying size below them
+    fn generate_target(root: &Path, seed: u64) {
+        let mut rng = SplitMix64(seed);
+        write(&root.join(".rustc_info.json"), 4_000);
+        write(&root.join("debug/app"), 40_000_000);
+        for i in 0..300 {
+            write(&root.join(format!("debug/deps/libdep{}.rlib", i)), 200_000 + rng.below(2_000_000) as u64);
+        }
+        for i in 0..400 {
+            let dir = match i % 3 {
+                0 => format!("debug/.fingerprint/dep{}", i),
+                1 => format!("debug/build/dep{}/out", i),
+                _ => format!("debug/incremental/app-{}/s-{}", i % 7, i),
+            };
+            // Files within a directory are alike; directories differ
+            let typical = 1_000 + rng.below(40_000) as u64;
+            for j in 0..1 + rng.below(20) {
+                write(&root.join(&dir).join(format!("f{}", j)), typical / 2 + rng.below(typical as usize) as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_estimate_range_holds_the_exact_size() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        generate_target(&target, 7);
+        let exact = get_directory_size(&target).unwrap();
+
+        for (fraction, seed) in [(0.1, 1), (0.1, 2), (0.25, 3), (0.05, 4)] {
+            let estimate = estimate_size_seeded(&target, fraction, seed);
+            assert!(estimate.estimated);
+            assert!(
+                estimate.low <= exact && exact <= estimate.high,
+                "{} outside {:?} at fraction {}",
+                exact,
+                estimate,
+                fraction
+            );
+            assert!(estimate.low <= estimate.bytes && estimate.bytes <= estimate.high);
+            // A useful estimate, not just a range wide enough to hold anything
+            assert!(estimate.bytes.abs_diff(exact) < exact / 10, "{} vs {}", estimate.bytes, exact);
+            assert!(estimate.high - estimate.low < exact / 4);
+        }
+        // The same seed picks the same sample
+        assert_eq!(estimate_size_seeded(&target, 0.1, 1), estimate_size_seeded(&target, 0.1, 1));
+    }
+
+    #[test]
+    fn test_small_trees_are_measured_exactly() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        write(&target.join("debug/app"), 1_000);
+        write(&target.join("debug/deps/libdep.rlib"), 5_000);
+        for i in 0..5 {
+            write(&target.join(format!("debug/build/dep{}/out/gen.rs", i)), 300);
+        }
+
+        let estimate = estimate_size_seeded(&target, 0.1, 1);
+        assert_eq!(estimate, SizeEstimate::exact(get_directory_size(&target).unwrap()));
+        assert_eq!(estimate.bytes, 7_500);
+        assert_eq!(estimate_size(&temp_dir.path().join("missing"), 0.1), SizeEstimate::exact(0));
+    }
+}
@@ -0,0 +1,80 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// How `--progress` shows a run's progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProgressFormat {
+    /// Progress bars on the terminal
+    Bars,
+    /// Newline-delimited JSON events on stderr, for programs wrapping deepclean
+    Json,
+}
+
+/// One line of `--progress json`
+///
+/// Serialized with its variant as `"event"`, e.g.
+/// `{"event":"project_done","path":"/src/app","freed":1024}`.
+#[derive(Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// Discovery finished, having walked `dirs` directories
+    Discovery { dirs: u64, projects: usize },
+    ProjectStart { path: &'a str },
+    /// Bytes freed, or that would be in a dry run
+    ProjectDone { path: &'a str, freed: u64 },
+    /// Sent last, once the summary is out
+    Done,
+}
+
+static EVENTS: AtomicBool = AtomicBool::new(false);
+
+/// Write progress events to stderr from now on (`--progress json`)
+pub fn emit_events(enabled: bool) {
+    EVENTS.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether progress goes out as events instead of bars
+pub fn events_enabled() -> bool {
+    EVENTS.load(Ordering::Relaxed)
+}
+
+/// Write `event` to stderr, if events are on
+///
+/// Each event is flushed as it's written, so a wrapper sees it right away.
+/// A reader that went away doesn't stop the run.
+pub fn emit(event: &Event) {
+    if events_enabled() {
+        let _ = write_event(&mut std::io::stderr().lock(), event);
+    }
+}
+
+fn write_event(out: &mut impl Write, event: &Event) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(event)?;
+    line.push(b'\n');
+    // One write per event, so events from parallel workers never interleave
+    out.write_all(&line)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_event() {
+        let mut out = Vec::new();
+        write_event(&mut out, &Event::Discovery { dirs: 12, projects: 2 }).unwrap();
+        write_event(&mut out, &Event::ProjectStart { path: "/src/app" }).unwrap();
+        write_event(&mut out, &Event::ProjectDone { path: "/src/app", freed: 1024 }).unwrap();
+        write_event(&mut out, &Event::Done).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                "{\"event\":\"discovery\",\"dirs\":12,\"projects\":2}\n",
+                "{\"event\":\"project_start\",\"path\":\"/src/app\"}\n",
+                "{\"event\":\"project_done\",\"path\":\"/src/app\",\"freed\":1024}\n",
+                "{\"event\":\"done\"}\n",
+            )
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use crate::audit::AuditEntry;
+use crate::removal::remove_tree;
+use crate::utils::get_directory_size;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Build output of other tools commonly found next to a `Cargo.toml`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExtraPreset {
+    /// `pkg/` written by wasm-pack
+    Pkg,
+    /// `dist/` written by trunk
+    Dist,
+    /// `.wrangler/`, Cloudflare wrangler's local state
+    Wrangler,
+}
+
+impl ExtraPreset {
+    /// The directory this preset removes, relative to the project root
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            ExtraPreset::Pkg => "pkg",
+            ExtraPreset::Dist => "dist",
+            ExtraPreset::Wrangler => ".wrangler",
+        }
+    }
+
+    /// Whether `dir` was really written by this preset's tool
+    ///
+    /// The names are common enough that a directory by that name alone could
+    /// be sources, so each preset looks for what its tool leaves behind.
+    fn recognizes(self, project_dir: &Path, dir: &Path) -> bool {
+        match self {
+            // wasm-pack writes an npm package around `<crate>_bg.wasm`
+            ExtraPreset::Pkg => dir.join("package.json").is_file() && has_wasm_bindgen_output(dir),
+            // trunk writes the page it serves next to the wasm it built
+            ExtraPreset::Dist => dir.join("index.html").is_file() && has_wasm_bindgen_output(dir),
+            ExtraPreset::Wrangler => ["wrangler.toml", "wrangler.json", "wrangler.jsonc"]
+                .iter()
+                .any(|config| project_dir.join(config).is_file()),
+        }
+    }
+}
+
+fn has_wasm_bindgen_output(dir: &Path) -> bool {
+    fs::read_dir(dir).is_ok_and(|entries| {
+        entries
+            .filter_map(|e| e.ok())
+            .any(|e| e.file_name().to_string_lossy().ends_with("_bg.wasm"))
+    })
+}
+
+/// Directories next to a project's `Cargo.toml` to clean along with its target
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtraDirs {
+    pub presets: Vec<ExtraPreset>,
+    /// Plain directory names from `--extra-dir`, removed without a marker check
+    pub names: Vec<String>,
+}
+
+impl ExtraDirs {
+    pub fn is_empty(&self) -> bool {
+        self.presets.is_empty() && self.names.is_empty()
+    }
+
+    /// The directories present in `project_dir`, by name
+    ///
+    /// Only real directories directly in the project root count: a symlink
+    /// by that name is left alone, so removal never leaves the project.
+    fn present_in(&self, project_dir: &Path) -> Vec<String> {
+        let is_real_dir = |name: &str| fs::symlink_metadata(project_dir.join(name)).is_ok_and(|m| m.is_dir());
+        let mut found: Vec<String> = self
+            .presets
+            .iter()
+            .filter(|preset| is_real_dir(preset.dir_name()))
+            .filter(|preset| preset.recognizes(project_dir, &project_dir.join(preset.dir_name())))
+            .map(|preset| preset.dir_name().to_string())
+            .chain(self.names.iter().filter(|name| is_real_dir(name)).cloned())
+            .collect();
+        found.sort();
+        found.dedup();
+        found
+    }
+}
+
+/// What cleaning a project's extra directories freed, or would free
+#[derive(Debug, Default)]
+pub struct ExtraCleaned {
+    /// Bytes by directory name
+    pub freed_bytes: BTreeMap<String, u64>,
+    pub audit: Vec<AuditEntry>,
+    /// Directories that could not be removed, as messages
+    pub warnings: Vec<String>,
+}
+
+/// Measure and, unless `dry_run`, remove a project's extra directories
+///
+/// A directory that can't be removed is a warning, since the project itself
+/// was cleaned; whatever did get removed still counts.
+pub fn clean_extra_dirs(project_dir: &Path, extra: &ExtraDirs, dry_run: bool) -> ExtraCleaned {
+    let mut cleaned = ExtraCleaned::default();
+    for name in extra.present_in(project_dir) {
+        let dir = project_dir.join(&name);
+        let bytes = get_directory_size(&dir).unwrap_or(0);
+        let freed = if dry_run {
+            bytes
+        } else {
+            match remove_tree(&dir, bytes) {
+                Ok(()) => bytes,
+                Err(e) => {
+                    cleaned.warnings.push(format!("Failed to remove {}/: {}", name, e));
+                    bytes.saturating_sub(get_directory_size(&dir).unwrap_or(0))
+                }
+            }
+        };
+        if freed > 0 {
+            cleaned.audit.push(AuditEntry::removed(project_dir, &dir, freed));
+        }
+        cleaned.freed_bytes.insert(name, freed);
+    }
+    cleaned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, size: usize) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, vec![0u8; size]).unwrap();
+    }
+
+    fn presets(presets: &[ExtraPreset]) -> ExtraDirs {
+        ExtraDirs {
+            presets: presets.to_vec(),
+            names: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_pkg_preset_needs_wasm_pack_output() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path();
+        write(&project.join("pkg/package.json"), 10);
+        let extra = presets(&[ExtraPreset::Pkg]);
+        // A plain npm package named pkg is left alone
+        assert!(clean_extra_dirs(project, &extra, false).freed_bytes.is_empty());
+        assert!(project.join("pkg").exists());
+
+        write(&project.join("pkg/app_bg.wasm"), 100);
+        let planned = clean_extra_dirs(project, &extra, true);
+        assert_eq!(planned.freed_bytes, BTreeMap::from([("pkg".to_string(), 110)]));
+        assert!(project.join("pkg").exists());
+        let cleaned = clean_extra_dirs(project, &extra, false);
+        assert_eq!(cleaned.freed_bytes, planned.freed_bytes);
+        assert_eq!(cleaned.audit.len(), 1);
+        assert!(!project.join("pkg").exists());
+    }
+
+    #[test]
+    fn test_dist_preset_needs_trunk_output() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path();
+        write(&project.join("dist/index.html"), 10);
+        write(&project.join("dist/app.js"), 20);
+        let extra = presets(&[ExtraPreset::Dist]);
+        assert!(clean_extra_dirs(project, &extra, false).freed_bytes.is_empty());
+
+        write(&project.join("dist/app-9f8e7d_bg.wasm"), 100);
+        let cleaned = clean_extra_dirs(project, &extra, false);
+        assert_eq!(cleaned.freed_bytes["dist"], 130);
+        assert!(!project.join("dist").exists());
+    }
+
+    #[test]
+    fn test_wrangler_preset_needs_wrangler_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path();
+        write(&project.join(".wrangler/state/v3/cache.sqlite"), 50);
+        let extra = presets(&[ExtraPreset::Wrangler]);
+        assert!(clean_extra_dirs(project, &extra, false).freed_bytes.is_empty());
+
+        fs::write(project.join("wrangler.toml"), "name = \"worker\"\n").unwrap();
+        let cleaned = clean_extra_dirs(project, &extra, false);
+        assert_eq!(cleaned.freed_bytes[".wrangler"], 50);
+        assert!(!project.join(".wrangler").exists());
+    }
+
+    #[test]
+    fn test_named_extra_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path().join("app");
+        write(&project.join("out/bundle.js"), 40);
+        write(&temp_dir.path().join("elsewhere/data"), 70);
+        let extra = ExtraDirs {
+            presets: vec![ExtraPreset::Dist],
+            names: vec!["out".to_string(), "missing".to_string(), "linked".to_string()],
+        };
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(temp_dir.path().join("elsewhere"), project.join("linked")).unwrap();
+
+        let cleaned = clean_extra_dirs(&project, &extra, false);
+        // Named directories need no marker, but symlinks out of the project are never followed
+        assert_eq!(cleaned.freed_bytes, BTreeMap::from([("out".to_string(), 40)]));
+        assert!(temp_dir.path().join("elsewhere/data").exists());
+    }
+}
@@ -0,0 +1,114 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+/// Time of the most recent commit in the git repository containing `dir`
+///
+/// Returns `None` when `dir` is not under git control, the repository has no
+/// commits yet, or git isn't installed.
+pub fn last_commit_time(dir: &Path) -> Option<SystemTime> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["log", "-1", "--format=%ct"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let seconds: u64 = String::from_utf8_lossy(&output.stdout).trim().parse().ok()?;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// How long ago the repository containing `dir` last had a commit
+pub fn last_commit_age(dir: &Path) -> Option<Duration> {
+    let committed = last_commit_time(dir)?;
+    Some(SystemTime::now().duration_since(committed).unwrap_or_default())
+}
+
+/// Whether `dir` has uncommitted changes or untracked files
+///
+/// Only paths below `dir` count, so one dirty crate doesn't hold back the
+/// rest of a monorepo, and its own `target/` is ignored even when it isn't
+/// in `.gitignore`. Returns `None` when `dir` is not under git control or
+/// git isn't installed.
+pub fn has_uncommitted_changes(dir: &Path) -> Option<bool> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["status", "--porcelain", "--", ".", ":(exclude)target"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(!output.stdout.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Create a repository whose only commit is `age_days` old
+    fn repo_with_commit(age_days: u64) -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        let date = SystemTime::now()
+            .checked_sub(Duration::from_secs(age_days * 86400))
+            .unwrap()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(temp_dir.path())
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .env("GIT_AUTHOR_DATE", format!("@{} +0000", date))
+                .env("GIT_COMMITTER_DATE", format!("@{} +0000", date))
+                .stdout(Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "-q"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "initial"]);
+        temp_dir
+    }
+
+    #[test]
+    fn test_last_commit_age() {
+        let repo = repo_with_commit(90);
+        std::fs::create_dir_all(repo.path().join("crates/app")).unwrap();
+
+        let age = last_commit_age(&repo.path().join("crates/app")).unwrap();
+        assert_eq!(age.as_secs() / 86400, 90);
+    }
+
+    #[test]
+    fn test_directory_outside_git_has_no_commit() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(last_commit_time(temp_dir.path()).is_none());
+    }
+
+    #[test]
+    fn test_has_uncommitted_changes() {
+        let repo = repo_with_commit(1);
+        let app = repo.path().join("app");
+        std::fs::create_dir_all(app.join("target/debug")).unwrap();
+        std::fs::write(app.join("target/debug/app"), "binary").unwrap();
+        assert_eq!(has_uncommitted_changes(&app), Some(false));
+
+        std::fs::write(repo.path().join("notes.txt"), "elsewhere in the repo").unwrap();
+        assert_eq!(has_uncommitted_changes(&app), Some(false));
+
+        std::fs::write(app.join("Cargo.toml"), "[package]").unwrap();
+        assert_eq!(has_uncommitted_changes(&app), Some(true));
+
+        let outside = TempDir::new().unwrap();
+        assert_eq!(has_uncommitted_changes(outside.path()), None);
+    }
+}
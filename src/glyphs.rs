@@ -0,0 +1,145 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Every non-ASCII character output may use, so `--ascii` can swap them all
+///
+/// Output code takes its glyphs from here rather than writing them inline;
+/// the test below keeps `ASCII` free of anything a legacy codepage mangles.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Glyphs {
+    /// Spinner frames; the last one stays on screen once a spinner finishes
+    pub spinner: &'static [&'static str],
+    /// Filled, current and empty parts of a progress bar
+    pub progress: &'static str,
+    pub check: &'static str,
+    pub bullet: &'static str,
+    /// Marks an estimated size
+    pub approx: &'static str,
+    /// Between the ends of a range, e.g. `12.00–15.00 GB`
+    pub range: &'static str,
+    pub ellipsis: &'static str,
+    /// Repeated to draw a horizontal rule
+    pub rule: &'static str,
+    /// Tree drawing, each four columns wide
+    pub branch: &'static str,
+    pub last_branch: &'static str,
+    pub pipe: &'static str,
+}
+
+pub const UNICODE: Glyphs = Glyphs {
+    spinner: &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"],
+    progress: "#>-",
+    check: "✓",
+    bullet: "•",
+    approx: "≈",
+    range: "–",
+    ellipsis: "…",
+    rule: "─",
+    branch: "├── ",
+    last_branch: "└── ",
+    pipe: "│   ",
+};
+
+pub const ASCII: Glyphs = Glyphs {
+    spinner: &["|", "/", "-", "\\", "|"],
+    progress: "#>-",
+    check: "ok",
+    bullet: "*",
+    approx: "~",
+    range: "-",
+    ellipsis: "...",
+    rule: "-",
+    branch: "|-- ",
+    last_branch: "`-- ",
+    pipe: "|   ",
+};
+
+static ASCII_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Print only ASCII from now on (`--ascii`, or a locale that isn't UTF-8)
+pub fn use_ascii(enabled: bool) {
+    ASCII_ONLY.store(enabled, Ordering::Relaxed);
+}
+
+/// The glyphs to print with
+pub fn glyphs() -> &'static Glyphs {
+    if ASCII_ONLY.load(Ordering::Relaxed) {
+        &ASCII
+    } else {
+        &UNICODE
+    }
+}
+
+/// Whether the terminal is set up for UTF-8, judged from the locale
+#[cfg(not(windows))]
+pub fn locale_is_utf8() -> bool {
+    utf8_locale(|name| std::env::var(name).ok())
+}
+
+/// Whether the console shows UTF-8, judged from its output codepage
+#[cfg(windows)]
+pub fn locale_is_utf8() -> bool {
+    const CP_UTF8: u32 = 65001;
+    // SAFETY: takes no arguments and only reads the console's state
+    unsafe { windows_sys::Win32::System::Console::GetConsoleOutputCP() == CP_UTF8 }
+}
+
+/// Whether the locale `var` describes uses UTF-8
+///
+/// The first of `LC_ALL`, `LC_CTYPE` and `LANG` that is set decides, as it
+/// does for `setlocale`. None at all means the C locale, which is ASCII.
+#[cfg_attr(windows, allow(dead_code))]
+fn utf8_locale(var: impl Fn(&str) -> Option<String>) -> bool {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|name| var(name).filter(|value| !value.is_empty()))
+        .is_some_and(|locale| {
+            let locale = locale.to_ascii_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_glyphs_are_ascii() {
+        let Glyphs {
+            spinner,
+            progress,
+            check,
+            bullet,
+            approx,
+            range,
+            ellipsis,
+            rule,
+            branch,
+            last_branch,
+            pipe,
+        } = ASCII;
+        // Listed field by field, so a new glyph can't be left out of this check
+        let all = [progress, check, bullet, approx, range, ellipsis, rule, branch, last_branch, pipe];
+        assert!(all.iter().chain(spinner).all(|glyph| glyph.is_ascii()));
+        assert!(spinner.len() >= 2);
+        // Swapping glyphs keeps layouts intact
+        for (unicode, ascii) in [(UNICODE.branch, branch), (UNICODE.last_branch, last_branch), (UNICODE.pipe, pipe)] {
+            assert_eq!(unicode.chars().count(), ascii.len());
+        }
+        assert_eq!(UNICODE.progress.chars().count(), progress.len());
+    }
+
+    #[test]
+    fn test_utf8_locale() {
+        let locale = |vars: &'static [(&'static str, &'static str)]| {
+            utf8_locale(|name| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string()))
+        };
+        assert!(locale(&[("LANG", "en_US.UTF-8")]));
+        assert!(locale(&[("LANG", "de_DE.utf8")]));
+        assert!(!locale(&[]));
+        assert!(!locale(&[("LANG", "C")]));
+        assert!(!locale(&[("LANG", "en_US.ISO-8859-1")]));
+        // LC_ALL overrides LANG, but an empty one doesn't count
+        assert!(!locale(&[("LC_ALL", "POSIX"), ("LANG", "en_US.UTF-8")]));
+        assert!(locale(&[("LC_ALL", ""), ("LC_CTYPE", "C.UTF-8"), ("LANG", "C")]));
+    }
+}
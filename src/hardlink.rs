@@ -0,0 +1,340 @@
+use rayon::prelude::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{File, Metadata};
+use std::hash::{DefaultHasher, Hasher};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Files smaller than this save too little to be worth sharing
+const MIN_FILE_BYTES: u64 = 64 * 1024;
+
+/// Directories rustc rewrites in place, where a shared file would change
+/// under every project linked to it
+const SKIPPED_DIRS: &[&str] = &["incremental"];
+
+/// Bytes read at a time when hashing and comparing
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// One file's data on disk, however many names it has
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct FileId {
+    device: u64,
+    index: u64,
+}
+
+/// A file's data and every name it has in the scanned targets
+#[derive(Debug)]
+struct Inode {
+    id: FileId,
+    size: u64,
+    /// Names it has in total, including any outside the targets
+    links: u64,
+    /// Permission bits, so an executable is never linked to a plain copy
+    mode: u32,
+    paths: Vec<PathBuf>,
+}
+
+/// What `--hardlink-dedup` found and did
+#[derive(Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct HardlinkReport {
+    /// Nothing was linked; counts are what would have been
+    pub dry_run: bool,
+    pub targets: usize,
+    /// Files large enough to consider
+    pub files_scanned: usize,
+    /// Files replaced by a hardlink to an identical one
+    pub linked_files: usize,
+    /// Space freed by the files no longer stored separately
+    pub bytes_saved: u64,
+    /// Identical copies left separate because they're on different filesystems
+    pub cross_device_skipped: usize,
+    pub failures: Vec<LinkFailure>,
+}
+
+#[derive(Debug, PartialEq, Eq, serde::Serialize)]
+pub struct LinkFailure {
+    pub path: String,
+    pub error: String,
+}
+
+/// Replace byte-identical files across `targets` with hardlinks to one copy
+///
+/// Files are grouped by size and a hash of their contents, and every pair
+/// is compared byte for byte before anything is linked. Only files on the
+/// same filesystem and with the same permissions share a copy. A file's
+/// space comes back only once every name it had points elsewhere, so files
+/// also linked from outside the targets don't count towards `bytes_saved`.
+pub fn hardlink_duplicates(targets: &[PathBuf], dry_run: bool) -> HardlinkReport {
+    let inodes = scan_targets(targets);
+    let mut report = HardlinkReport {
+        dry_run,
+        targets: targets.len(),
+        files_scanned: inodes.iter().map(|inode| inode.paths.len()).sum(),
+        ..Default::default()
+    };
+
+    let mut by_size: BTreeMap<u64, Vec<Inode>> = BTreeMap::new();
+    for inode in inodes {
+        by_size.entry(inode.size).or_default().push(inode);
+    }
+    let candidates: Vec<Inode> = by_size.into_values().filter(|inodes| inodes.len() > 1).flatten().collect();
+
+    // Hashing reads every candidate in full, so it's spread over the pool
+    let hashed: Vec<(u64, Inode)> = candidates
+        .into_par_iter()
+        .filter_map(|inode| content_hash(&inode.paths[0]).ok().map(|hash| (hash, inode)))
+        .collect();
+    let mut groups: BTreeMap<(u64, u64), Vec<Inode>> = BTreeMap::new();
+    for (hash, inode) in hashed {
+        groups.entry((inode.size, hash)).or_default().push(inode);
+    }
+
+    for inodes in groups.into_values().filter(|inodes| inodes.len() > 1) {
+        let mut shareable: BTreeMap<(u64, u32), Vec<Inode>> = BTreeMap::new();
+        for inode in inodes {
+            shareable.entry((inode.id.device, inode.mode)).or_default().push(inode);
+        }
+        let devices = shareable.keys().map(|(device, _)| device).collect::<BTreeSet<_>>().len();
+        report.cross_device_skipped += devices - 1;
+        for (_, inodes) in shareable {
+            link_group(inodes, dry_run, &mut report);
+        }
+    }
+    report
+}
+
+/// Link every inode in `inodes` to the one with the most names already
+fn link_group(mut inodes: Vec<Inode>, dry_run: bool, report: &mut HardlinkReport) {
+    if inodes.len() < 2 {
+        return;
+    }
+    inodes.sort_by(|a, b| b.links.cmp(&a.links).then_with(|| a.paths.cmp(&b.paths)));
+    let (keeper, duplicates) = inodes.split_first().unwrap();
+    let original = &keeper.paths[0];
+    for duplicate in duplicates {
+        // The hash only groups candidates; linking needs the bytes to match
+        match same_contents(original, &duplicate.paths[0]) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                report.failures.push(LinkFailure::new(&duplicate.paths[0], &e));
+                continue;
+            }
+        }
+        let mut linked = 0;
+        for path in &duplicate.paths {
+            match if dry_run { Ok(()) } else { link_over(original, path) } {
+                Ok(()) => linked += 1,
+                Err(e) => report.failures.push(LinkFailure::new(path, &e)),
+            }
+        }
+        report.linked_files += linked;
+        if linked as u64 == duplicate.links {
+            report.bytes_saved += duplicate.size;
+        }
+    }
+}
+
+impl LinkFailure {
+    fn new(path: &Path, error: &io::Error) -> Self {
+        LinkFailure {
+            path: path.to_string_lossy().to_string(),
+            error: error.to_string(),
+        }
+    }
+}
+
+/// Every file of at least `MIN_FILE_BYTES` in `targets`, by the data it names
+fn scan_targets(targets: &[PathBuf]) -> Vec<Inode> {
+    let mut inodes: HashMap<FileId, Inode> = HashMap::new();
+    for target in targets {
+        let entries = WalkDir::new(target)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|e| !(e.file_type().is_dir() && SKIPPED_DIRS.iter().any(|dir| e.file_name() == *dir)))
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file());
+        for entry in entries {
+            let Ok(metadata) = entry.metadata() else { continue };
+            if metadata.len() < MIN_FILE_BYTES {
+                continue;
+            }
+            let Some((id, links, mode)) = identity(entry.path(), &metadata) else { continue };
+            inodes
+                .entry(id)
+                .or_insert_with(|| Inode {
+                    id,
+                    size: metadata.len(),
+                    links,
+                    mode,
+                    paths: Vec::new(),
+                })
+                .paths
+                .push(entry.path().to_path_buf());
+        }
+    }
+    let mut inodes: Vec<Inode> = inodes.into_values().collect();
+    inodes.sort_by_key(|inode| inode.id);
+    inodes
+}
+
+/// Where a file's data lives, how many names it has, and its permissions
+#[cfg(unix)]
+fn identity(_path: &Path, metadata: &Metadata) -> Option<(FileId, u64, u32)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let id = FileId {
+        device: metadata.dev(),
+        index: metadata.ino(),
+    };
+    Some((id, metadata.nlink(), metadata.mode()))
+}
+
+/// Where a file's data lives, how many names it has, and whether it's read-only
+#[cfg(windows)]
+fn identity(path: &Path, metadata: &Metadata) -> Option<(FileId, u64, u32)> {
+    let handle = winapi_util::Handle::from_path_any(path).ok()?;
+    let information = winapi_util::file::information(&handle).ok()?;
+    let id = FileId {
+        device: information.volume_serial_number(),
+        index: information.file_index(),
+    };
+    Some((id, information.number_of_links(), metadata.permissions().readonly() as u32))
+}
+
+/// Without file IDs there's no telling files already linked apart, so nothing is linked
+#[cfg(not(any(unix, windows)))]
+fn identity(_path: &Path, _metadata: &Metadata) -> Option<(FileId, u64, u32)> {
+    None
+}
+
+fn content_hash(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hasher = DefaultHasher::new();
+    let mut buffer = vec![0; CHUNK_BYTES];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write(&buffer[..read]);
+    }
+}
+
+fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    let (mut a, mut b) = (File::open(a)?, File::open(b)?);
+    let (mut chunk_a, mut chunk_b) = (vec![0; CHUNK_BYTES], vec![0; CHUNK_BYTES]);
+    loop {
+        let read = read_chunk(&mut a, &mut chunk_a)?;
+        if read != read_chunk(&mut b, &mut chunk_b)? || chunk_a[..read] != chunk_b[..read] {
+            return Ok(false);
+        }
+        if read == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Fill `buffer` as far as the file allows, so two files read in step
+fn read_chunk(file: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match file.read(&mut buffer[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+/// Make `duplicate` another name for `original`
+///
+/// The link is made under a temporary name and renamed over the duplicate,
+/// so the duplicate's path never goes missing, even if this fails halfway.
+fn link_over(original: &Path, duplicate: &Path) -> io::Result<()> {
+    let name = duplicate.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = duplicate.with_file_name(format!(".{}.deepclean-link", name));
+    let _ = std::fs::remove_file(&temporary);
+    std::fs::hard_link(original, &temporary)?;
+    std::fs::rename(&temporary, duplicate).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temporary);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, contents: &[u8]) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    #[cfg(unix)]
+    fn inode(path: &Path) -> u64 {
+        use std::os::unix::fs::MetadataExt;
+        std::fs::metadata(path).unwrap().ino()
+    }
+
+    #[test]
+    fn test_hardlink_duplicates() {
+        let temp_dir = TempDir::new().unwrap();
+        let (a, b) = (temp_dir.path().join("a/target"), temp_dir.path().join("b/target"));
+        let shared = vec![7u8; 100_000];
+        let mut different = shared.clone();
+        different[99_999] = 8;
+        write(&a.join("debug/deps/libserde-1.rlib"), &shared);
+        write(&b.join("debug/deps/libserde-1.rlib"), &shared);
+        write(&b.join("release/deps/libserde-2.rlib"), &different);
+        // Rewritten in place by rustc, and too small to bother with
+        write(&a.join("debug/incremental/s-1/query-cache.bin"), &shared);
+        write(&b.join("debug/incremental/s-1/query-cache.bin"), &shared);
+        write(&a.join("debug/small"), b"same");
+        write(&b.join("debug/small"), b"same");
+        let targets = vec![a.clone(), b.clone()];
+
+        let planned = hardlink_duplicates(&targets, true);
+        assert_eq!((planned.files_scanned, planned.linked_files, planned.bytes_saved), (3, 1, 100_000));
+        assert!(planned.failures.is_empty());
+
+        let linked = hardlink_duplicates(&targets, false);
+        assert_eq!(linked, HardlinkReport { dry_run: false, ..planned });
+        assert_eq!(std::fs::read(b.join("debug/deps/libserde-1.rlib")).unwrap(), shared);
+        assert_eq!(std::fs::read(b.join("release/deps/libserde-2.rlib")).unwrap(), different);
+        #[cfg(unix)]
+        {
+            assert_eq!(inode(&a.join("debug/deps/libserde-1.rlib")), inode(&b.join("debug/deps/libserde-1.rlib")));
+            assert_ne!(inode(&a.join("debug/small")), inode(&b.join("debug/small")));
+        }
+        assert!(!b.join("debug/deps/.libserde-1.rlib.deepclean-link").exists());
+
+        // Already sharing one copy, so there's nothing left to do
+        let again = hardlink_duplicates(&targets, false);
+        assert_eq!((again.files_scanned, again.linked_files, again.bytes_saved), (3, 0, 0));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_permissions_and_outside_links_are_respected() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        let contents = vec![1u8; 70_000];
+        for name in ["app", "app-copy", "lib"] {
+            write(&target.join("debug").join(name), &contents);
+        }
+        let executable = std::fs::Permissions::from_mode(0o755);
+        std::fs::set_permissions(target.join("debug/app"), executable.clone()).unwrap();
+        std::fs::set_permissions(target.join("debug/app-copy"), executable).unwrap();
+        // Another name outside the target keeps lib's data around anyway
+        std::fs::hard_link(target.join("debug/lib"), temp_dir.path().join("kept")).unwrap();
+
+        let report = hardlink_duplicates(std::slice::from_ref(&target), false);
+        assert_eq!((report.linked_files, report.bytes_saved), (1, 70_000));
+        assert_eq!(inode(&target.join("debug/app")), inode(&target.join("debug/app-copy")));
+        assert_ne!(inode(&target.join("debug/app")), inode(&target.join("debug/lib")));
+    }
+}
@@ -0,0 +1,150 @@
+use anyhow::{Context, Result};
+use crate::output::Summary;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+/// Information passed to a hook through its environment
+pub struct HookContext<'a> {
+    pub project_path: &'a Path,
+    pub target_path: &'a Path,
+    pub dry_run: bool,
+    /// Only set for post-hooks, once the clean has finished
+    pub freed_bytes: Option<u64>,
+}
+
+/// Build a shell command for a user-supplied hook string
+fn shell_command(cmd: &str) -> Command {
+    if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", cmd]);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.args(["-c", cmd]);
+        command
+    }
+}
+
+/// Run a hook command for a single project and return its exit status
+pub fn run_hook(cmd: &str, ctx: &HookContext) -> Result<ExitStatus> {
+    let mut command = shell_command(cmd);
+    command
+        .current_dir(ctx.project_path)
+        .env("RCLEAN_PROJECT_PATH", ctx.project_path)
+        .env("RCLEAN_TARGET_PATH", ctx.target_path)
+        .env("RCLEAN_DRY_RUN", if ctx.dry_run { "1" } else { "0" });
+
+    if let Some(freed) = ctx.freed_bytes {
+        command.env("RCLEAN_FREED_BYTES", freed.to_string());
+    }
+
+    command
+        .status()
+        .with_context(|| format!("Failed to run hook `{}`", cmd))
+}
+
+/// Run the `--on-complete` command once a run has finished, with the run's
+/// totals in its environment
+pub fn run_completion_hook(cmd: &str, root: &Path, summary: &Summary) -> Result<ExitStatus> {
+    shell_command(cmd)
+        .current_dir(root)
+        .env("RCLEAN_ROOT", root)
+        .env("RCLEAN_DRY_RUN", if summary.dry_run { "1" } else { "0" })
+        .env("RCLEAN_FREED_BYTES", summary.total_freed_bytes.to_string())
+        .env("RCLEAN_CLEANED", summary.cleaned.to_string())
+        .env("RCLEAN_FAILED", summary.failed.to_string())
+        .env("RCLEAN_SKIPPED", summary.skipped_count.to_string())
+        .status()
+        .with_context(|| format!("Failed to run on-complete hook `{}`", cmd))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_pre_hook_receives_environment() {
+        let temp_dir = TempDir::new().unwrap();
+        let marker = temp_dir.path().join("marker");
+        let target = temp_dir.path().join("target");
+        let ctx = HookContext {
+            project_path: temp_dir.path(),
+            target_path: &target,
+            dry_run: true,
+            freed_bytes: None,
+        };
+
+        let cmd = format!(
+            "echo \"$RCLEAN_PROJECT_PATH|$RCLEAN_TARGET_PATH|$RCLEAN_DRY_RUN|${{RCLEAN_FREED_BYTES:-unset}}\" > {}",
+            marker.display()
+        );
+        let status = run_hook(&cmd, &ctx).unwrap();
+        assert!(status.success());
+
+        let content = fs::read_to_string(&marker).unwrap();
+        assert_eq!(
+            content.trim(),
+            format!("{}|{}|1|unset", temp_dir.path().display(), target.display())
+        );
+    }
+
+    #[test]
+    fn test_post_hook_receives_freed_bytes() {
+        let temp_dir = TempDir::new().unwrap();
+        let marker = temp_dir.path().join("marker");
+        let target = temp_dir.path().join("target");
+        let ctx = HookContext {
+            project_path: temp_dir.path(),
+            target_path: &target,
+            dry_run: false,
+            freed_bytes: Some(4096),
+        };
+
+        let cmd = format!("echo \"$RCLEAN_FREED_BYTES|$RCLEAN_DRY_RUN\" > {}", marker.display());
+        run_hook(&cmd, &ctx).unwrap();
+
+        let content = fs::read_to_string(&marker).unwrap();
+        assert_eq!(content.trim(), "4096|0");
+    }
+
+    #[test]
+    fn test_failing_hook_reports_status() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        let ctx = HookContext {
+            project_path: temp_dir.path(),
+            target_path: &target,
+            dry_run: false,
+            freed_bytes: None,
+        };
+
+        let status = run_hook("exit 3", &ctx).unwrap();
+        assert!(!status.success());
+        assert_eq!(status.code(), Some(3));
+    }
+
+    #[test]
+    fn test_completion_hook_receives_totals() {
+        let temp_dir = TempDir::new().unwrap();
+        let marker = temp_dir.path().join("marker");
+        let summary = Summary {
+            cleaned: 3,
+            failed: 1,
+            skipped_count: 2,
+            total_freed_bytes: 8192,
+            ..Default::default()
+        };
+
+        let cmd = format!(
+            "echo \"$RCLEAN_FREED_BYTES|$RCLEAN_CLEANED|$RCLEAN_FAILED|$RCLEAN_SKIPPED|$RCLEAN_DRY_RUN\" > {}; exit 4",
+            marker.display()
+        );
+        let status = run_completion_hook(&cmd, temp_dir.path(), &summary).unwrap();
+        assert_eq!(status.code(), Some(4));
+
+        let content = fs::read_to_string(&marker).unwrap();
+        assert_eq!(content.trim(), "8192|3|1|2|0");
+    }
+}
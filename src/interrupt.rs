@@ -0,0 +1,50 @@
+use anyhow::{Context, Result};
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Exit code after an interrupted run (128 + SIGINT, as shells report it)
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Install the Ctrl-C handler
+///
+/// The first Ctrl-C only asks the run to stop: projects that haven't started
+/// are skipped and in-flight ones finish, so a partial summary can be
+/// printed. A second Ctrl-C exits immediately.
+pub fn install_handler() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            restore_terminal();
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+    })
+    .context("Failed to install Ctrl-C handler")
+}
+
+/// Whether Ctrl-C has been pressed
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Show the cursor again in case a progress bar hid it
+pub fn restore_terminal() {
+    let mut stderr = std::io::stderr();
+    if stderr.is_terminal() {
+        let _ = write!(stderr, "\x1b[?25h");
+        let _ = stderr.flush();
+    }
+}
+
+/// Put a child process in its own process group so Ctrl-C in the terminal
+/// doesn't kill it halfway; interrupted runs let in-flight commands finish
+#[cfg(unix)]
+pub fn shield_from_interrupt(cmd: &mut std::process::Command) -> &mut std::process::Command {
+    use std::os::unix::process::CommandExt;
+    cmd.process_group(0)
+}
+
+#[cfg(not(unix))]
+pub fn shield_from_interrupt(cmd: &mut std::process::Command) -> &mut std::process::Command {
+    cmd
+}
@@ -0,0 +1,224 @@
+use anyhow::{Context, Result};
+use crate::cleaner::{CleanResult, SkipReason};
+use crate::project::Project;
+use crate::utils::fnv1a;
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// One finished project, stored as a line of JSON
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Entry {
+    path: String,
+    success: bool,
+    skip_reason: Option<SkipReason>,
+    freed_bytes: u64,
+}
+
+/// Append-only record of the projects a run has finished
+///
+/// Every real run writes one, so a crash or reboot halfway through a long
+/// run can be picked up with `--resume` instead of starting over.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    file: Mutex<File>,
+    /// Projects an earlier run cleaned, when resuming
+    cleaned: HashSet<String>,
+}
+
+/// Directory holding journals
+///
+/// Unlike run locks these must survive a reboot, so they live in the user's
+/// cache directory (`$XDG_CACHE_HOME/deepclean`, `~/.cache/deepclean` or
+/// `%LOCALAPPDATA%\deepclean`), falling back to the temp directory.
+pub fn journal_dir() -> PathBuf {
+    let cache_home = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    };
+    cache_home
+        .map(|dir| dir.join("deepclean"))
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// Journal file for a scan root cleaned with the given options
+///
+/// `options_key` is any stable rendering of the options, so a run with
+/// different options never resumes from another's journal.
+pub fn journal_path(journal_dir: &Path, root: &Path, options_key: &str) -> PathBuf {
+    let hash = fnv1a(format!("{}\0{}", root.to_string_lossy(), options_key).as_bytes());
+    journal_dir.join(format!("deepclean-{:016x}.journal", hash))
+}
+
+impl Journal {
+    /// Open the journal for `root`, reading what an earlier run finished when `resume` is set
+    ///
+    /// Without `resume` any earlier journal is discarded.
+    pub fn open(journal_dir: &Path, root: &Path, options_key: &str, resume: bool) -> Result<Journal> {
+        fs::create_dir_all(journal_dir)
+            .with_context(|| format!("Failed to create journal directory {:?}", journal_dir))?;
+        let path = journal_path(journal_dir, root, options_key);
+
+        let cleaned = if resume {
+            read_cleaned(&path)?
+        } else {
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => {
+                    return Err(e).with_context(|| format!("Failed to remove journal {:?}", path));
+                }
+                _ => HashSet::new(),
+            }
+        };
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open journal {:?}", path))?;
+        Ok(Journal {
+            path,
+            file: Mutex::new(file),
+            cleaned,
+        })
+    }
+
+    /// Number of projects an earlier run already cleaned
+    pub fn cleaned_count(&self) -> usize {
+        self.cleaned.len()
+    }
+
+    /// Whether an earlier run already cleaned `project`
+    pub fn was_cleaned(&self, project: &Project) -> bool {
+        self.cleaned.contains(project.path.to_string_lossy().as_ref())
+    }
+
+    /// Append a finished project
+    ///
+    /// Each entry goes out as a single write of one whole line and is synced
+    /// before returning, so a crash can at most leave the last line truncated.
+    pub fn record(&self, result: &CleanResult) -> Result<()> {
+        let entry = Entry {
+            path: result.path.clone(),
+            success: result.success,
+            skip_reason: result.skip_reason,
+            freed_bytes: result.freed_bytes,
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        file.write_all(line.as_bytes())
+            .and_then(|()| file.sync_data())
+            .with_context(|| format!("Failed to write journal {:?}", self.path))
+    }
+
+    /// Delete the journal once a run has nothing left to resume
+    pub fn finish(self) -> Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path).with_context(|| format!("Failed to remove journal {:?}", self.path))
+    }
+}
+
+/// Paths of the projects a journal records as cleaned
+///
+/// A truncated trailing line is ignored, and cut off so the next entry
+/// starts on a fresh line.
+fn read_cleaned(path: &Path) -> Result<HashSet<String>> {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read journal {:?}", path)),
+    };
+
+    let complete = content.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    if complete < content.len() {
+        OpenOptions::new()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_len(complete as u64))
+            .with_context(|| format!("Failed to repair journal {:?}", path))?;
+    }
+
+    Ok(content[..complete]
+        .split(|&b| b == b'\n')
+        .filter_map(|line| serde_json::from_slice::<Entry>(line).ok())
+        .filter(|entry| entry.success && entry.skip_reason.is_none())
+        .map(|entry| entry.path)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn projects(root: &Path) -> Vec<Project> {
+        (0..4)
+            .map(|i| {
+                let path = root.join(format!("app{}", i));
+                fs::create_dir_all(path.join("target/debug")).unwrap();
+                fs::write(path.join("target/debug/app"), vec![0u8; 100]).unwrap();
+                Project::new(path, false)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_torn_last_entry_is_dropped() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("code");
+        let projects = projects(&root);
+
+        let journal = Journal::open(temp_dir.path(), &root, "options", false).unwrap();
+        journal.record(&CleanResult::new(&projects[0], 100)).unwrap();
+        journal.record(&CleanResult::new(&projects[1], 100)).unwrap();
+        drop(journal);
+        // A crash tore the entry being written
+        let path = journal_path(temp_dir.path(), &root, "options");
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"path":"/half-writ"#).unwrap();
+
+        let journal = Journal::open(temp_dir.path(), &root, "options", true).unwrap();
+        assert_eq!(journal.cleaned_count(), 2);
+        // The next entry starts on a line of its own
+        journal.record(&CleanResult::new(&projects[2], 100)).unwrap();
+        drop(journal);
+        let journal = Journal::open(temp_dir.path(), &root, "options", true).unwrap();
+        assert_eq!(journal.cleaned_count(), 3);
+
+        // A completed run leaves nothing to resume
+        journal.finish().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_journal_keeps_only_cleaned_projects() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("code");
+        let projects = projects(&root);
+
+        let journal = Journal::open(temp_dir.path(), &root, "options", false).unwrap();
+        journal.record(&CleanResult::new(&projects[0], 100)).unwrap();
+        journal.record(&CleanResult::skipped(&projects[1], SkipReason::InUse)).unwrap();
+        journal.record(&CleanResult::failed(&projects[2], "boom".to_string())).unwrap();
+        drop(journal);
+
+        let journal = Journal::open(temp_dir.path(), &root, "options", true).unwrap();
+        assert_eq!(journal.cleaned_count(), 1);
+        assert!(journal.was_cleaned(&projects[0]));
+        assert!(!journal.was_cleaned(&projects[1]));
+
+        // Other options, or a fresh run, start over
+        assert_eq!(Journal::open(temp_dir.path(), &root, "other", true).unwrap().cleaned_count(), 0);
+        drop(journal);
+        Journal::open(temp_dir.path(), &root, "options", false).unwrap();
+        assert_eq!(Journal::open(temp_dir.path(), &root, "options", true).unwrap().cleaned_count(), 0);
+    }
+}
@@ -0,0 +1,350 @@
+use anyhow::{anyhow, Context, Result};
+use crate::archive::project_key;
+use crate::metadata::workspace_metadata;
+use crate::utils::get_directory_size;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Profiles whose final executables `--keep-executables` preserves
+const PROFILES: [&str; 2] = ["debug", "release"];
+
+/// Where criterion.rs saves benchmark results and baselines, inside the target
+pub const CRITERION_DIR: &str = "criterion";
+
+/// Where `--keep-criterion` moves `target/criterion` while the target is cleaned
+const CRITERION_STASH: &str = ".rclean-criterion";
+
+/// Executables copied out of a target before it was cleaned
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct KeptExecutables {
+    /// Where the copies are
+    pub dir: String,
+    /// Copies relative to `dir`, laid out like the target (`release/tool`)
+    pub files: Vec<String>,
+    pub bytes: u64,
+}
+
+/// Where a project's executables are kept
+///
+/// Next to the target as `target-keep/`, or in a shared directory under a
+/// name made unique by the project's path (`<name>-<hash>`), like archives,
+/// so projects with the same directory name don't overwrite each other.
+pub fn keep_dir(project_path: &Path, target_dir: &Path, shared: Option<&Path>) -> PathBuf {
+    match shared {
+        Some(dir) => dir.join(project_key(project_path)),
+        None => {
+            let name = target_dir.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+            target_dir.with_file_name(format!("{}-keep", name))
+        }
+    }
+}
+
+/// Names of the binary targets of a project's packages, or `None` when
+/// cargo can't tell, in which case every executable file counts
+pub fn bin_names(manifest: &Path) -> Option<BTreeSet<String>> {
+    let metadata = workspace_metadata(manifest).ok()?;
+    Some(
+        metadata
+            .packages
+            .iter()
+            .flat_map(|package| &package.targets)
+            .filter(|target| target.is_bin())
+            .map(|target| target.name.clone())
+            .collect(),
+    )
+}
+
+/// Final executables in a target's `debug/` and `release/` directories
+///
+/// Only files directly in a profile directory count: `deps/` holds the
+/// hashed copies cargo builds them from. With `bin_names`, only those
+/// binaries are picked.
+pub fn find_executables(target_dir: &Path, bin_names: Option<&BTreeSet<String>>) -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = PROFILES
+        .iter()
+        .flat_map(|profile| executables_in(&target_dir.join(profile), bin_names))
+        .collect();
+    found.sort();
+    found
+}
+
+/// Final executables directly in one profile directory
+fn executables_in(profile_dir: &Path, bin_names: Option<&BTreeSet<String>>) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(profile_dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()) && is_executable(&entry.path()))
+        .map(|entry| entry.path())
+        .filter(|path| match bin_names {
+            Some(names) => path.file_stem().and_then(|s| s.to_str()).is_some_and(|stem| names.contains(stem)),
+            None => true,
+        })
+        .collect()
+}
+
+/// Every profile directory in a target, for any profile and target triple
+///
+/// Profile directories are the ones cargo gave a `deps/`: `target/<profile>`
+/// and `target/<triple>/<profile>`.
+fn profile_dirs(target_dir: &Path) -> Vec<PathBuf> {
+    let subdirs = |dir: &Path| -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+                    .map(|e| e.path())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let mut profiles = Vec::new();
+    for dir in subdirs(target_dir) {
+        if dir.join("deps").is_dir() {
+            profiles.push(dir);
+        } else {
+            profiles.extend(subdirs(&dir).into_iter().filter(|dir| dir.join("deps").is_dir()));
+        }
+    }
+    profiles
+}
+
+/// The final binaries in every profile directory of a target, with the
+/// `.d` dep-info and `.pdb` debug info cargo writes next to them
+///
+/// `deps/` and everything else incremental rebuilds need are left out.
+pub fn bin_artifacts(target_dir: &Path, bin_names: Option<&BTreeSet<String>>) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for profile_dir in profile_dirs(target_dir) {
+        for executable in executables_in(&profile_dir, bin_names) {
+            let stem = executable.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            // The linker names debug info after the crate, with underscores
+            let companions = [format!("{}.d", stem), format!("{}.pdb", stem), format!("{}.pdb", stem.replace('-', "_"))];
+            found.push(executable);
+            for companion in companions {
+                let path = profile_dir.join(companion);
+                if path.is_file() && !found.contains(&path) {
+                    found.push(path);
+                }
+            }
+        }
+    }
+    found.sort();
+    found
+}
+
+/// Executables have no extension and an executable bit, which leaves out
+/// `.d` files and shared libraries
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.extension().is_none() && fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("exe"))
+}
+
+/// Copy a target's final executables into `keep_dir` before the target is cleaned
+///
+/// Copies replace what an earlier run kept. Each is written next to its
+/// destination and renamed over it, since a kept binary may be running. A
+/// dry run only lists them. `None` when there is nothing to keep.
+pub fn keep_executables(
+    target_dir: &Path,
+    keep_dir: &Path,
+    bin_names: Option<&BTreeSet<String>>,
+    dry_run: bool,
+) -> Result<Option<KeptExecutables>> {
+    let executables = find_executables(target_dir, bin_names);
+    if executables.is_empty() {
+        return Ok(None);
+    }
+
+    let mut files = Vec::new();
+    let mut bytes = 0;
+    for executable in executables {
+        let relative = executable.strip_prefix(target_dir).unwrap_or(&executable);
+        if dry_run {
+            bytes += fs::metadata(&executable).map(|m| m.len()).unwrap_or(0);
+        } else {
+            let dest = keep_dir.join(relative);
+            let partial = dest.with_file_name(format!(
+                "{}.partial",
+                dest.file_name().unwrap_or_default().to_string_lossy()
+            ));
+            fs::create_dir_all(dest.parent().unwrap_or(keep_dir))
+                .with_context(|| format!("Failed to create keep directory: {:?}", keep_dir))?;
+            bytes += fs::copy(&executable, &partial)
+                .and_then(|copied| fs::rename(&partial, &dest).map(|_| copied))
+                .with_context(|| format!("Failed to keep {:?} in {:?}", executable, keep_dir))?;
+        }
+        files.push(relative.to_string_lossy().to_string());
+    }
+
+    Ok(Some(KeptExecutables {
+        dir: keep_dir.to_string_lossy().to_string(),
+        files,
+        bytes,
+    }))
+}
+
+/// Move `target/criterion` next to the target before it is cleaned, and
+/// return its size, or `None` when there is none
+///
+/// Renaming within a filesystem is instant and keeps the baselines out of
+/// the freed bytes. A dry run only measures it. A stash left behind by a run
+/// that stopped before restoring it is put back first; if the target has a
+/// new `criterion/` by then, the project is left alone rather than lose either.
+pub fn stash_criterion(target_dir: &Path, dry_run: bool) -> Result<Option<u64>> {
+    let criterion = target_dir.join(CRITERION_DIR);
+    let stash = criterion_stash(target_dir);
+    if stash.exists() && !dry_run {
+        if criterion.exists() {
+            return Err(anyhow!(
+                "criterion baselines from an earlier run are still at {:?}; move them back into {:?} or remove them",
+                stash,
+                criterion
+            ));
+        }
+        restore_criterion(target_dir)?;
+    }
+    if !criterion.is_dir() {
+        return Ok(None);
+    }
+    let bytes = get_directory_size(&criterion).unwrap_or(0);
+    if !dry_run {
+        fs::rename(&criterion, &stash)
+            .with_context(|| format!("Failed to move {:?} aside to {:?}", criterion, stash))?;
+    }
+    Ok(Some(bytes))
+}
+
+/// Put a stashed `criterion/` back into the target, recreating the target if it was removed
+pub fn restore_criterion(target_dir: &Path) -> Result<()> {
+    let stash = criterion_stash(target_dir);
+    fs::create_dir_all(target_dir)
+        .and_then(|_| fs::rename(&stash, target_dir.join(CRITERION_DIR)))
+        .with_context(|| format!("Failed to restore criterion baselines, they are still at {:?}", stash))
+}
+
+fn criterion_stash(target_dir: &Path) -> PathBuf {
+    target_dir.with_file_name(CRITERION_STASH)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, contents: &str, mode: u32) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    /// A target with two binaries and the clutter around them
+    fn fixture() -> (TempDir, PathBuf) {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("app/target");
+        write(&target.join("release/tool"), "release tool", 0o755);
+        write(&target.join("debug/tool"), "debug tool", 0o755);
+        write(&target.join("debug/helper"), "helper", 0o755);
+        write(&target.join("release/tool.d"), "deps", 0o644);
+        write(&target.join("release/libtool.so"), "cdylib", 0o755);
+        write(&target.join("release/libtool.rlib"), "rlib", 0o644);
+        write(&target.join("release/deps/tool-0123456789abcdef"), "hashed copy", 0o755);
+        write(&target.join("release/build/tool-0123/build-script-build"), "build script", 0o755);
+        write(&target.join("release/examples/demo"), "example", 0o755);
+        write(&target.join("release/notes"), "not executable", 0o644);
+        (temp_dir, target)
+    }
+
+    #[test]
+    fn test_finds_final_executables_only() {
+        let (_temp_dir, target) = fixture();
+        let relative = |names: Option<&BTreeSet<String>>| -> Vec<String> {
+            find_executables(&target, names)
+                .iter()
+                .map(|p| p.strip_prefix(&target).unwrap().to_string_lossy().to_string())
+                .collect()
+        };
+        assert_eq!(relative(None), vec!["debug/helper", "debug/tool", "release/tool"]);
+        // The manifest's binaries narrow it down when cargo can tell
+        let names = BTreeSet::from(["tool".to_string()]);
+        assert_eq!(relative(Some(&names)), vec!["debug/tool", "release/tool"]);
+    }
+
+    #[test]
+    fn test_bin_artifacts_in_every_profile() {
+        let (_temp_dir, target) = fixture();
+        write(&target.join("release/deps/.keep"), "", 0o644);
+        write(&target.join("debug/deps/.keep"), "", 0o644);
+        write(&target.join("profiling/deps/.keep"), "", 0o644);
+        write(&target.join("profiling/tool"), "profiling tool", 0o755);
+        write(&target.join("wasm32-wasip1/release/deps/.keep"), "", 0o644);
+        write(&target.join("wasm32-wasip1/release/tool"), "wasi tool", 0o755);
+        write(&target.join("wasm32-wasip1/release/tool.d"), "deps", 0o644);
+        write(&target.join("release/tool.pdb"), "debug info", 0o644);
+        write(&target.join("tmp/tool"), "not a profile", 0o755);
+
+        let relative: Vec<String> = bin_artifacts(&target, Some(&BTreeSet::from(["tool".to_string()])))
+            .iter()
+            .map(|p| p.strip_prefix(&target).unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(
+            relative,
+            vec![
+                "debug/tool",
+                "profiling/tool",
+                "release/tool",
+                "release/tool.d",
+                "release/tool.pdb",
+                "wasm32-wasip1/release/tool",
+                "wasm32-wasip1/release/tool.d",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keep_executables_copies_and_replaces() {
+        let (temp_dir, target) = fixture();
+        let names = BTreeSet::from(["tool".to_string()]);
+        let keep = keep_dir(&temp_dir.path().join("app"), &target, None);
+        assert_eq!(keep, temp_dir.path().join("app/target-keep"));
+
+        let planned = keep_executables(&target, &keep, Some(&names), true).unwrap().unwrap();
+        assert_eq!(planned.files, vec!["debug/tool", "release/tool"]);
+        assert_eq!(planned.bytes, 22);
+        assert!(!keep.exists());
+
+        fs::create_dir_all(keep.join("release")).unwrap();
+        fs::write(keep.join("release/tool"), "kept last time").unwrap();
+        let kept = keep_executables(&target, &keep, Some(&names), false).unwrap().unwrap();
+        assert_eq!(kept, planned);
+        assert_eq!(fs::read_to_string(keep.join("release/tool")).unwrap(), "release tool");
+        let mode = fs::metadata(keep.join("debug/tool")).unwrap().permissions().mode();
+        assert_ne!(mode & 0o111, 0);
+        assert!(!keep.join("release/tool.partial").exists());
+
+        // Nothing to keep in a target without binaries
+        assert_eq!(keep_executables(&temp_dir.path().join("missing"), &keep, None, false).unwrap(), None);
+    }
+
+    #[test]
+    fn test_shared_keep_dir_tells_projects_apart() {
+        let shared = Path::new("/var/keep");
+        let a = keep_dir(Path::new("/src/one/app"), Path::new("/src/one/app/target"), Some(shared));
+        let b = keep_dir(Path::new("/src/two/app"), Path::new("/src/two/app/target"), Some(shared));
+        assert_ne!(a, b);
+        assert!(a.starts_with(shared) && b.starts_with(shared));
+        assert!(a.file_name().unwrap().to_string_lossy().starts_with("app-"));
+        // The same project always lands in the same place
+        assert_eq!(a, keep_dir(Path::new("/src/one/app"), Path::new("/src/one/app/target"), Some(shared)));
+    }
+}
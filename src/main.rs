@@ -1,59 +1,601 @@
+mod archive;
+mod audit;
+mod breaker;
+mod budget;
+mod cache;
 mod cleaner;
+mod compare;
+mod config;
+mod debris;
+mod dedupe;
 mod deps;
+mod disk;
+mod drives;
+mod estimate;
+mod events;
+mod extra;
+mod git;
+mod glyphs;
+mod hardlink;
+mod hooks;
+mod interrupt;
+mod journal;
+mod keep;
+mod metadata;
 mod output;
+mod priority;
+mod removal;
+mod render;
 mod project;
+mod report;
+mod retry;
+mod runlock;
+mod sccache;
+mod sweep;
+mod timing;
+mod toolchain;
+mod trash;
 mod utils;
+mod vacuum;
 
-use anyhow::{Context, Result};
-use clap::Parser;
+use anyhow::{anyhow, Context, Result};
+use clap::{ArgGroup, Parser, Subcommand};
 use colored::*;
-use cleaner::{clean_project, CleanResult};
-use deps::clean_dependencies;
-use output::{create_progress_bars, create_project_progress_bar, print_summary, print_verbose_cleaned, print_error, Summary};
-use project::find_cargo_projects;
+use archive::restore_archive;
+use audit::{AuditEntry, AuditLog};
+use breaker::{FailureBreaker, EXIT_ABORTED};
+use budget::{largest_first, TimeBudget};
+use cache::{cargo_home, clean_cargo_cache, parse_prune_policy, CacheOptions};
+use cleaner::{cargo_on_path, clean_project, CleanOptions, CleanResult, SkipReason};
+use compare::{compare_runs, load_previous_run};
+use config::{load_local_config, Config, LOCAL_CONFIG};
+use deps::{check_unused_dependencies, clean_dependencies, DependencyCheck, DependencyCleanResult, UnusedDependency};
+use disk::{add_free_space_before, free_after, free_space_before, fs_delta_note, percent_of, record_free_space_after, record_freed_bytes, FilesystemSpace, FreeSpaceGoal, SystemSpace};
+use drives::{default_excludes, group_by_drive, scan_roots, SystemDrives};
+use estimate::{estimate_size, SizeEstimate};
+use events::{emit, emit_events, events_enabled, Event, ProgressFormat};
+use extra::{ExtraDirs, ExtraPreset};
+use glyphs::{glyphs, locale_is_utf8, use_ascii};
+use hardlink::hardlink_duplicates;
+use hooks::{run_completion_hook, run_hook, HookContext};
+use interrupt::{install_handler, is_interrupted, restore_terminal, EXIT_INTERRUPTED};
+use journal::{journal_dir, Journal};
+use output::{create_progress_bars, create_project_progress_bar, print_summary, print_verbose_cleaned, print_error, print_failed, error_message, failure_message, print_warning, print_dependency_result, print_dependency_summary, print_size_list, print_dry_run_plan, render_clean_plan, render_removal_plan, freed_so_far_message, print_cache_report, print_hardlink_report, print_toolchain_report, print_dep_sizes, render_markdown_summary, print_timings, render_prometheus_metrics, skipped_projects, OutputFormat, Summary};
+use priority::lower_priority;
+use project::{discover_projects, enclosing_project, stream_projects, Discovery, DiscoveryOptions, project_from_manifest, projects_from_lines, sort_projects, workspace_root_manifest, InvalidPath, Project, SeenProjects};
 use rayon::prelude::*;
-use utils::{get_directory_size, parse_size};
+use runlock::{lock_dir, LockHeld, EXIT_LOCKED};
+use render::{SizeColors, TableStyle};
+use report::{aggregate_dep_sizes, build_size_tree, dep_sizes, render_size_tree, ProjectDepSizes, ProjectSize};
+use sccache::{clean_sccache, sccache_dir};
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use timing::Phase;
+use trash::{purge, spawn_purge, stale_trash, DeferMode};
+use toolchain::{installed_rustc_versions, measure_toolchains, rustup_home, uninstall_toolchain};
+use utils::{allow_network, common_ancestor, cutoff_for_days, format_bytes, get_directory_size, is_glob, last_build_time, parse_duration, parse_size, resolve_roots, cargo_program, forbid_subprocesses, show_commands, units, use_cargo, use_units, validate_directory, write_atomically, Units, TARGET_DIR_NAME};
 
 #[derive(Parser, Debug)]
 #[command(name = "cargo-deepclean")]
 #[command(about = "Recursively clean Cargo projects with workspace support", long_about = None)]
 #[command(bin_name = "cargo deepclean")]
+#[command(group(ArgGroup::new("git_filters").multiple(true).args(["git_idle", "only_committed"])))]
 struct Args {
-    /// Directory to start cleaning from
-    #[arg(default_value = ".")]
-    directory: std::path::PathBuf,
+    /// Directories to start cleaning from, or quoted globs like 'repos/*/backend' (default: the current directory, or the project enclosing it)
+    #[arg(value_name = "DIRECTORY")]
+    directories: Vec<PathBuf>,
+
+    /// Ignore the rclean.toml in the scanned directory
+    #[arg(long, global = true)]
+    no_local_config: bool,
+
+    /// Scan the current directory as given instead of the project enclosing it
+    #[arg(long, global = true)]
+    no_auto_root: bool,
+
+    /// Leave out a project located exactly at a scan root, cleaning only the projects below it
+    #[arg(long, global = true, conflicts_with = "manifest_path")]
+    exclude_root: bool,
+
+    /// Clean only the project with this Cargo.toml (or its workspace), skipping discovery
+    #[arg(long, value_name = "PATH", global = true)]
+    manifest_path: Option<PathBuf>,
+
+    /// Skip workspace detection and treat every Cargo.toml as a standalone project
+    #[arg(long, global = true)]
+    assume_no_workspace: bool,
+
+    /// Look at most N directories up from each Cargo.toml for its workspace (never above the scan root)
+    #[arg(long, global = true, value_name = "N", conflicts_with = "assume_no_workspace")]
+    workspace_search_depth: Option<usize>,
+
+    /// Also discover projects inside target/ directories (normally pruned as build output)
+    #[arg(long, global = true)]
+    scan_targets: bool,
+
+    /// Name of the build output directory in every project, for teams that renamed `target` [default: target]
+    #[arg(long, value_name = "NAME", global = true)]
+    target_dir_name: Option<String>,
+
+    /// Descend into symlinked directories during discovery (each real directory is visited once)
+    #[arg(long, global = true)]
+    follow_symlinks: bool,
+
+    /// With --follow-symlinks, also follow links that lead outside the scanned directory
+    #[arg(long, global = true, requires = "follow_symlinks")]
+    allow_escape: bool,
+
+    /// Don't descend into directories on other filesystems than the scanned directory
+    #[arg(long, global = true)]
+    one_file_system: bool,
+
+    /// Skip mounts of this filesystem type, e.g. "nfs" or "fuse.sshfs" (Linux only, can be specified multiple times)
+    #[arg(long = "exclude-fs-type", value_name = "TYPE", global = true)]
+    exclude_fs_types: Vec<String>,
+
+    /// Scan every fixed drive instead of one directory (Windows only)
+    #[arg(long, global = true, conflicts_with = "manifest_path")]
+    all_drives: bool,
+
+    /// With --all-drives, also scan network drives
+    #[arg(long, global = true, requires = "all_drives")]
+    include_network_drives: bool,
+
+    /// Read newline-separated project roots from stdin instead of discovering them
+    #[arg(long, conflicts_with = "manifest_path")]
+    stdin: bool,
 
     /// Dry run mode (don't actually clean, just show what would be cleaned)
-    #[arg(long)]
+    #[arg(long, global = true)]
     dry_run: bool,
 
     /// Exclude patterns (glob patterns, can be specified multiple times)
-    #[arg(short = 'e', long = "exclude")]
+    #[arg(short = 'e', long = "exclude", global = true)]
     exclude_patterns: Vec<String>,
 
     /// Number of parallel jobs
-    #[arg(short = 'j', long = "jobs", default_value_t = num_cpus::get())]
+    #[arg(short = 'j', long = "jobs", default_value_t = num_cpus::get(), global = true)]
     jobs: usize,
 
+    /// Run at the lowest CPU and I/O priority, so cleaning only uses spare capacity
+    #[arg(long, global = true)]
+    nice: bool,
+
     /// Verbose output
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     verbose: bool,
 
+    /// Print only ASCII, for consoles that garble Unicode (automatic when the locale isn't UTF-8)
+    #[arg(long, global = true)]
+    ascii: bool,
+
+    /// Show sizes in binary (KiB, MiB: powers of 1024) or decimal (KB, MB: powers of 1000) units
+    #[arg(long, value_enum, global = true, default_value_t = Units::Binary)]
+    units: Units,
+
     /// JSON output
-    #[arg(long)]
+    #[arg(long, global = true)]
     json: bool,
 
+    /// Output format for the clean summary (`--json` is shorthand for `--format json`)
+    #[arg(long, value_enum, global = true, conflicts_with = "json")]
+    format: Option<OutputFormat>,
+
+    /// Print nothing but the summary JSON of a dry run, and run no other programs (implies --dry-run --json)
+    #[arg(long, conflicts_with = "format")]
+    dry_run_json_only: bool,
+
     /// Minimum size threshold (e.g., "100MB", "1GB") - only clean projects above this size
-    #[arg(long)]
+    #[arg(long, global = true)]
     min_size: Option<String>,
 
-    /// Check for unused dependencies (native detection)
+    /// Only clean projects whose newest build artifact is older than DAYS days
+    #[arg(long, value_name = "DAYS", global = true)]
+    target_older_than: Option<u64>,
+
+    /// Only clean projects whose git repository has had no commit for this long (e.g., "60d", "2w")
+    #[arg(long, value_name = "DURATION")]
+    git_idle: Option<String>,
+
+    /// Skip projects with uncommitted changes or untracked files (per `git status`)
     #[arg(long)]
+    only_committed: bool,
+
+    /// With --git-idle or --only-committed, skip projects that aren't under git control instead of letting them pass
+    #[arg(long, requires = "git_filters")]
+    git_only: bool,
+
+    /// Only clean projects whose sources and manifests haven't been modified for this long (e.g., "14d")
+    #[arg(long, value_name = "DURATION")]
+    src_idle: Option<String>,
+
+    /// Check for unused dependencies (native detection)
+    #[arg(long, visible_alias = "deps")]
     clean_deps: bool,
 
     /// Remove unused dependencies (automatically enables --clean-deps, requires cargo-remove)
-    #[arg(long)]
+    #[arg(long, visible_alias = "remove")]
     remove_deps: bool,
+
+    /// Report crates resolved at more than one version, with what requires each (automatically enables --clean-deps, needs Cargo.lock)
+    #[arg(long)]
+    duplicates: bool,
+
+    /// Command to run before cleaning each project (a non-zero exit skips the project)
+    #[arg(long, value_name = "CMD")]
+    pre_hook: Option<String>,
+
+    /// Command to run after cleaning each project (failures are reported as warnings)
+    #[arg(long, value_name = "CMD")]
+    post_hook: Option<String>,
+
+    /// Command to run once after the summary, with the run's totals in RCLEAN_* variables
+    #[arg(long, value_name = "CMD")]
+    on_complete: Option<String>,
+
+    /// Write the run's totals to PATH as Prometheus metrics, e.g. for node_exporter's textfile collector
+    #[arg(long, value_name = "PATH")]
+    metrics_file: Option<PathBuf>,
+
+    /// Archive each target into DIR as a .tar.zst before removing it
+    #[arg(long, value_name = "DIR")]
+    archive: Option<PathBuf>,
+
+    /// Archive even when the destination filesystem is nearly full
+    #[arg(long, requires = "archive")]
+    archive_force: bool,
+
+    /// Run cargo build before cleaning and skip projects that fail to build
+    #[arg(long)]
+    verify_build: bool,
+
+    /// Clean projects even while a cargo build holds their target lock
+    #[arg(long)]
+    force: bool,
+
+    /// Show the plan and ask before freeing more than this much in total (e.g. 50GB)
+    #[arg(long, value_name = "SIZE")]
+    confirm_over: Option<String>,
+
+    /// Answer yes to every confirmation prompt, e.g. in scripts
+    #[arg(short = 'y', long, global = true)]
+    yes: bool,
+
+    /// Remove target directories directly instead of running cargo clean (for machines without cargo)
+    #[arg(long, conflicts_with_all = ["packages", "default_members_only", "verify_build", "remove_deps", "duplicates"])]
+    no_cargo: bool,
+
+    /// Remove target directories directly first and only run cargo clean if that fails (faster, but skips cargo's own cleanup)
+    #[arg(long, conflicts_with = "no_cargo")]
+    prefer_fallback: bool,
+
+    /// Rename each target aside so the project is usable at once, and delete it after the last project (or, with =background, in a detached process)
+    #[arg(long, value_enum, value_name = "WHEN", num_args = 0..=1, require_equals = true, default_missing_value = "wait")]
+    defer_delete: Option<DeferMode>,
+
+    /// Delete these directories moved aside by --defer-delete (what --defer-delete=background runs)
+    #[arg(long, value_name = "DIR", num_args = 1.., hide = true)]
+    purge_trash: Vec<PathBuf>,
+
+    /// Retry cargo clean and the fallback removal up to N times after transient errors (stale NFS handles, busy files)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    retries: u32,
+
+    /// Wait up to SECS for another run cleaning the same directory instead of exiting
+    #[arg(long, value_name = "SECS")]
+    wait_lock: Option<u64>,
+
+    /// Append every directory removed and every `cargo clean` run to PATH, as JSON Lines
+    #[arg(long, value_name = "PATH")]
+    audit_log: Option<PathBuf>,
+
+    /// Stop starting new projects after the first failure
+    #[arg(long, conflicts_with = "max_failures")]
+    fail_fast: bool,
+
+    /// Stop starting new projects after N failures
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    max_failures: Option<u64>,
+
+    /// Stop starting new projects once cleaning has run this long (e.g. 30m), largest targets first
+    #[arg(long, value_name = "DURATION")]
+    time_budget: Option<String>,
+
+    /// Clean the largest targets first until each filesystem has SIZE free (e.g. 20GB), then leave the rest alone
+    #[arg(long, value_name = "SIZE", conflicts_with = "defer_delete")]
+    until_free: Option<String>,
+
+    /// Let cargo use the network; by default every cargo command runs with --offline
+    #[arg(long)]
+    online: bool,
+
+    /// Cargo binary to run instead of the one in $CARGO or on PATH
+    #[arg(long, value_name = "PATH")]
+    cargo: Option<PathBuf>,
+
+    /// Toolchain to retry cargo on when the one a project pins isn't installed [default: stable]
+    #[arg(long, value_name = "NAME")]
+    fallback_toolchain: Option<String>,
+
+    /// Report how long each phase of the run took, to find out what makes it slow
+    #[arg(long)]
+    timings: bool,
+
+    /// Skip projects an interrupted earlier run with the same directory and options already cleaned
+    #[arg(long, conflicts_with = "dry_run")]
+    resume: bool,
+
+    /// Skip projects whose Cargo.toml sets `publish = false` (for workspaces, the root manifest)
+    #[arg(long)]
+    exclude_private: bool,
+
+    /// Only clean projects whose Cargo.toml sets `publish = false`
+    #[arg(long, conflicts_with = "exclude_private")]
+    only_private: bool,
+
+    /// Restore a target directory from an archive created with --archive
+    #[arg(long, value_name = "ARCHIVE", conflicts_with = "archive")]
+    restore: Option<PathBuf>,
+
+    /// Only delete artifacts in deps/.fingerprint/build/incremental unused for DAYS days
+    #[arg(long, value_name = "DAYS", conflicts_with = "archive")]
+    sweep: Option<u64>,
+
+    /// Skip the sweep when the filesystem's access times can't be trusted
+    #[arg(long, requires = "sweep")]
+    sweep_strict: bool,
+
+    /// Only delete artifacts built by rustc versions that are no longer installed
+    #[arg(long, conflicts_with_all = ["archive", "sweep"])]
+    stale_toolchains: bool,
+
+    /// Only delete target/<TRIPLE> directories matching this glob (can be specified multiple times)
+    #[arg(long = "target-triple", value_name = "TRIPLE", conflicts_with_all = ["archive", "sweep", "stale_toolchains"])]
+    target_triples: Vec<String>,
+
+    /// Delete every target/<TRIPLE> directory except those matching this glob (can be specified multiple times)
+    #[arg(long = "keep-triple", value_name = "TRIPLE", conflicts_with_all = ["target_triples", "archive", "sweep", "stale_toolchains"])]
+    keep_triples: Vec<String>,
+
+    /// Keep this direct subdirectory of target/ and remove everything else (can be specified multiple times)
+    #[arg(long = "keep-subdir", value_name = "NAME", conflicts_with_all = ["target_triples", "keep_triples", "archive", "sweep", "stale_toolchains"])]
+    keep_subdirs: Vec<String>,
+
+    /// Only delete old copies of artifacts superseded by a newer build of the same crate
+    #[arg(long, conflicts_with_all = ["keep_subdirs", "target_triples", "keep_triples", "archive", "sweep", "stale_toolchains"])]
+    dedupe_artifacts: bool,
+
+    /// Only delete artifacts of packages that are no longer in Cargo.lock
+    #[arg(long, conflicts_with_all = ["dedupe_artifacts", "keep_subdirs", "target_triples", "keep_triples", "archive", "sweep", "stale_toolchains"])]
+    vacuum: bool,
+
+    /// Only clean workspace members whose package name matches this glob, via cargo clean -p (can be specified multiple times)
+    #[arg(short = 'p', long = "package", value_name = "NAME", conflicts_with_all = ["vacuum", "dedupe_artifacts", "keep_subdirs", "target_triples", "keep_triples", "archive", "sweep", "stale_toolchains"])]
+    packages: Vec<String>,
+
+    /// In workspaces that declare default-members, only clean those members via cargo clean -p
+    #[arg(long, conflicts_with_all = ["packages", "vacuum", "dedupe_artifacts", "keep_subdirs", "target_triples", "keep_triples", "archive", "sweep", "stale_toolchains"])]
+    default_members_only: bool,
+
+    /// Copy the final executables in target/debug and target/release to target-keep/ before cleaning
+    #[arg(long, conflicts_with_all = ["default_members_only", "packages", "vacuum", "dedupe_artifacts", "keep_subdirs", "target_triples", "keep_triples", "sweep", "stale_toolchains"])]
+    keep_executables: bool,
+
+    /// With --keep-executables, copy them into DIR instead, one subdirectory per project
+    #[arg(long, value_name = "DIR", requires = "keep_executables")]
+    keep_executables_to: Option<PathBuf>,
+
+    /// Only remove the final binaries in target/<profile>/ and their .d/.pdb files, keeping deps/ so rebuilds stay fast
+    #[arg(long, conflicts_with_all = ["keep_executables", "default_members_only", "packages", "vacuum", "dedupe_artifacts", "keep_subdirs", "target_triples", "keep_triples", "archive", "sweep", "stale_toolchains"])]
+    bin_artifacts_only: bool,
+
+    /// After a partial clean, also remove the target directory when nothing is left in it
+    #[arg(long)]
+    remove_empty_target: bool,
+
+    /// Keep target/criterion, with its saved benchmark baselines, when cleaning
+    #[arg(long)]
+    keep_criterion: bool,
+
+    /// Also remove the build output of other tools next to each Cargo.toml, when their markers are there (comma-separated)
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "PRESET")]
+    extra: Vec<ExtraPreset>,
+
+    /// Also remove this directory next to each Cargo.toml, without checking what's in it (can be specified multiple times)
+    #[arg(long = "extra-dir", value_name = "NAME")]
+    extra_dirs: Vec<String>,
+
+    /// Also remove rustc-ice-*.txt and *.profraw files, .cargo-ok leftovers and the contents of target/tmp
+    #[arg(long)]
+    debris: bool,
+
+    /// In a dry run, estimate target sizes from a sample of their directories instead of measuring every file (default fraction 0.1)
+    #[arg(long, value_name = "FRACTION", num_args = 0..=1, require_equals = true, default_missing_value = "0.1")]
+    estimate: Option<f64>,
+
+    /// Sizes below which the summary shows sizes green, yellow and bold yellow, larger ones red (default "100MB,1GB,10GB")
+    #[arg(long, value_name = "SMALL,MEDIUM,LARGE")]
+    size_colors: Option<String>,
+
+    /// Clean projects as they are found instead of discovering them all first, for trees too big to list up front
+    #[arg(long, conflicts_with_all = ["stdin", "manifest_path", "confirm_over", "time_budget", "until_free", "remove_deps"])]
+    streaming: bool,
+
+    /// Experimental: instead of cleaning, replace byte-identical files across targets with hardlinks to one copy
+    #[arg(long, conflicts_with_all = ["streaming", "compare", "confirm_over", "time_budget", "until_free", "remove_deps", "archive"])]
+    hardlink_dedup: bool,
+
+    /// Compare with the --json summary of an earlier run, reporting new, grown, shrunk and vanished projects
+    #[arg(long, value_name = "PREVIOUS.json")]
+    compare: Option<PathBuf>,
+
+    /// How to show progress: `bars` in a terminal, or `json` events on stderr for programs wrapping deepclean
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    progress: Option<ProgressFormat>,
+
+    /// Print nothing but failures, to stderr, for cron jobs that mail any output
+    #[arg(long, conflicts_with_all = ["json", "format", "verbose", "progress", "confirm_over", "hardlink_dedup"])]
+    errors_only: bool,
+
+    /// Also write the JSON summary to PATH, whatever the terminal shows
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Fail the run when no Cargo projects are found
+    #[arg(long, conflicts_with = "streaming")]
+    fail_if_empty: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+impl Args {
+    fn target_dir_name(&self) -> &str {
+        self.target_dir_name.as_deref().unwrap_or(TARGET_DIR_NAME)
+    }
+
+    /// A project's build output directory
+    fn target_dir(&self, project: &Project) -> PathBuf {
+        project.path.join(self.target_dir_name())
+    }
+
+    /// Whether progress, per-project and summary text goes to stdout
+    fn text_output(&self) -> bool {
+        !self.json && !self.errors_only && self.format.is_none_or(|f| f == OutputFormat::Text)
+    }
+
+    /// Whether a flag selected what to remove, instead of the whole target
+    fn has_clean_mode(&self) -> bool {
+        self.archive.is_some()
+            || self.sweep.is_some()
+            || self.stale_toolchains
+            || !self.target_triples.is_empty()
+            || !self.keep_triples.is_empty()
+            || !self.keep_subdirs.is_empty()
+            || self.dedupe_artifacts
+            || self.vacuum
+            || !self.packages.is_empty()
+            || self.default_members_only
+            || self.keep_executables
+            || self.bin_artifacts_only
+    }
+
+    /// Whether cleaning will run cargo, rather than only removing files itself
+    fn needs_cargo(&self) -> bool {
+        let whole_target = !self.has_clean_mode() || self.archive.is_some() || self.keep_executables;
+        (whole_target && !self.no_cargo && !self.prefer_fallback)
+            || !self.packages.is_empty()
+            || self.default_members_only
+            || self.verify_build
+            || self.remove_deps
+            || self.duplicates
+    }
+
+    /// The first option given that would run another program, which `--dry-run-json-only` rules out
+    fn spawning_option(&self) -> Option<&'static str> {
+        [
+            (self.pre_hook.is_some(), "--pre-hook"),
+            (self.post_hook.is_some(), "--post-hook"),
+            (self.on_complete.is_some(), "--on-complete"),
+            (self.git_idle.is_some(), "--git-idle"),
+            (self.only_committed, "--only-committed"),
+            (self.git_only, "--git-only"),
+            (self.verify_build, "--verify-build"),
+            (self.stale_toolchains, "--stale-toolchains"),
+            (!self.packages.is_empty(), "--package"),
+            (self.default_members_only, "--default-members-only"),
+            (self.duplicates, "--duplicates"),
+            (self.remove_deps, "--remove-deps"),
+            (self.manifest_path.is_some(), "--manifest-path"),
+            (self.audit_log.is_some(), "--audit-log"),
+            (self.restore.is_some(), "--restore"),
+            (self.command.is_some(), "a subcommand"),
+        ]
+        .into_iter()
+        .find_map(|(given, option)| given.then_some(option))
+    }
+
+    /// Whether dependencies are analyzed; --remove-deps and --duplicates imply --clean-deps
+    fn check_deps(&self) -> bool {
+        self.clean_deps || self.remove_deps || self.duplicates
+    }
+
+    /// Fill in defaults from a config file
+    ///
+    /// Flags given on the command line win, except that excludes add up: a
+    /// repository that always excludes `vendor/` keeps doing so. The config's
+    /// `keep-subdir` only applies when no flag picked a clean mode.
+    fn apply_config(&mut self, config: Config) {
+        self.exclude_patterns.extend(config.exclude);
+        if !self.has_clean_mode() {
+            self.keep_subdirs = config.keep_subdir;
+        }
+        self.min_size = self.min_size.take().or(config.min_size);
+        self.target_older_than = self.target_older_than.or(config.target_older_than);
+        self.git_idle = self.git_idle.take().or(config.git_idle);
+        self.src_idle = self.src_idle.take().or(config.src_idle);
+        self.target_dir_name = self.target_dir_name.take().or(config.target_dir_name);
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Report target directory sizes without cleaning anything
+    List(ListArgs),
+    /// Report and clean cargo's download caches in CARGO_HOME
+    Cache(CacheArgs),
+    /// Report disk usage of installed rustup toolchains
+    Toolchains(ToolchainsArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ListArgs {
+    /// Directory to scan (overrides the top-level directory)
+    directory: Option<PathBuf>,
+
+    /// Show projects as a tree grouped by directory, annotated with aggregate sizes
+    #[arg(long)]
+    tree: bool,
+
+    /// Show which compiled dependencies take the most space in each target
+    #[arg(long, conflicts_with = "tree")]
+    dep_sizes: bool,
+
+    /// Number of dependencies to show per project and in the aggregate
+    #[arg(long, value_name = "N", default_value_t = 10, requires = "dep_sizes")]
+    top: usize,
+}
+
+#[derive(clap::Args, Debug)]
+struct CacheArgs {
+    /// Remove extracted registry sources and git checkouts
+    #[arg(long)]
+    clean: bool,
+
+    /// Also remove downloaded .crate files, registry indexes and git databases
+    #[arg(long, requires = "clean")]
+    aggressive: bool,
+
+    /// Keep only the N newest versions of each crate in the registry cache (default N = 1)
+    #[arg(long, value_name = "keep-latest[=N]", conflicts_with = "clean")]
+    prune_versions: Option<String>,
+
+    /// Also report (and with --clean, remove) sccache's local cache
+    #[arg(long)]
+    sccache: bool,
+
+    /// With --clean, prune the oldest sccache entries down to this size instead of deleting the cache
+    #[arg(long, value_name = "SIZE", requires = "sccache")]
+    sccache_max_size: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct ToolchainsArgs {
+    /// Uninstall this toolchain with rustup after confirming interactively
+    #[arg(long, value_name = "NAME")]
+    uninstall: Option<String>,
 }
 
 fn main() -> Result<()> {
@@ -64,7 +606,7 @@ fn main() -> Result<()> {
     
     // Check if we're being called as `cargo deepclean` (first arg is "deepclean")
     let first_arg = args_iter.next();
-    let args = if first_arg.as_deref() == Some("deepclean") {
+    let mut args = if first_arg.as_deref() == Some("deepclean") {
         // Skip "deepclean" and parse the rest
         Args::parse_from(args_iter)
     } else {
@@ -77,22 +619,341 @@ fn main() -> Result<()> {
         all_args.extend(args_iter);
         Args::parse_from(all_args)
     };
+    allow_network(args.online);
+    use_cargo(args.cargo.clone(), args.fallback_toolchain.clone());
+    use_ascii(args.ascii || !locale_is_utf8());
+    use_units(args.units);
+    if args.format == Some(OutputFormat::Json) {
+        args.json = true;
+    }
+    if args.dry_run_json_only {
+        args.dry_run = true;
+        args.json = true;
+        forbid_subprocesses();
+    }
+    // Before any worker threads or cargo processes exist, so they all inherit it
+    if args.nice {
+        for warning in lower_priority(&priority::System) {
+            eprintln!("{} {}", "[WARNING]".yellow().bold(), warning);
+        }
+    }
     
-    let root = args.directory.canonicalize()
-        .with_context(|| format!("Failed to canonicalize path: {:?}", args.directory))?;
+    if let Some(ref archive) = args.restore {
+        let target_dir = restore_archive(archive)
+            .with_context(|| format!("Failed to restore archive: {:?}", archive))?;
+        if args.json {
+            println!(
+                "{}",
+                serde_json::json!({ "archive": archive, "restored_to": target_dir.parent() })
+            );
+        } else {
+            println!(
+                "{} Restored {:?} into {:?}",
+                "[SUCCESS]".green().bold(),
+                archive,
+                target_dir
+            );
+        }
+        return Ok(());
+    }
 
-    if !args.json {
-        println!("{} {}", "[INFO]".blue().bold(), format!("Starting cargo clean from: {:?}", root));
-        println!("{} Searching for Cargo projects...", "[INFO]".blue().bold());
+    if let Some(Command::Cache(ref cache)) = args.command {
+        let home = cargo_home()?;
+        let options = CacheOptions {
+            clean: cache.clean,
+            aggressive: cache.aggressive,
+            keep_latest: cache.prune_versions.as_deref().map(parse_prune_policy).transpose()?,
+            dry_run: args.dry_run,
+        };
+        let mut report = clean_cargo_cache(&home, &options)?;
+        if cache.sccache {
+            let max_bytes = cache
+                .sccache_max_size
+                .as_deref()
+                .map(|size| {
+                    parse_size(size).with_context(|| format!("Invalid --sccache-max-size value: '{}'", size))
+                })
+                .transpose()?;
+            report.sccache = match sccache_dir() {
+                Some(dir) => clean_sccache(&dir, cache.clean, max_bytes, args.dry_run)?,
+                None => None,
+            };
+            if report.sccache.is_none() && args.verbose && !args.json {
+                println!("{} No sccache cache found, skipping", "[INFO]".blue().bold());
+            }
+            if let Some(ref sccache) = report.sccache {
+                report.total_bytes += sccache.bytes_before;
+                report.freed_bytes += sccache.bytes_before.saturating_sub(sccache.bytes_after);
+            }
+        }
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            print_cache_report(&report);
+        }
+        return Ok(());
     }
 
-    let projects = find_cargo_projects(&root, &args.exclude_patterns)
-        .context("Failed to find Cargo projects")?;
+    if let Some(Command::Toolchains(ref toolchains)) = args.command {
+        return run_toolchains(&args, toolchains);
+    }
 
-    if projects.is_empty() {
-        if !args.json {
+    if !args.purge_trash.is_empty() {
+        // Detached, with nobody to report to: the next run retries whatever is left
+        purge(&args.purge_trash);
+        return Ok(());
+    }
+
+    let explicit_directories: Vec<&PathBuf> = match args.command {
+        Some(Command::List(ListArgs { directory: Some(ref dir), .. })) => vec![dir],
+        _ => args.directories.iter().collect(),
+    };
+    let explicit_directory = explicit_directories.first().copied();
+    let directory = explicit_directory.map(PathBuf::as_path).unwrap_or(Path::new("."));
+    // A manifest path or a list on stdin bypasses discovery entirely
+    let mut invalid_paths: Vec<InvalidPath> = Vec::new();
+    let explicit_projects = if let Some(ref manifest) = args.manifest_path {
+        // Members share their workspace's target, so clean the workspace root
+        let manifest = match workspace_root_manifest(manifest) {
+            Some(root_manifest) => {
+                if args.text_output() {
+                    println!(
+                        "{} {:?} is a workspace member, cleaning the workspace at {:?}",
+                        "[INFO]".blue().bold(),
+                        manifest,
+                        root_manifest.parent().unwrap_or(&root_manifest)
+                    );
+                }
+                root_manifest
+            }
+            None => manifest.clone(),
+        };
+        Some(vec![project_from_manifest(&manifest)?])
+    } else if args.stdin {
+        let (projects, invalid) = projects_from_lines(std::io::stdin().lock())?;
+        invalid_paths = invalid;
+        Some(projects)
+    } else if args.all_drives {
+        None
+    } else if explicit_directory.is_none() && !args.no_auto_root {
+        // Like cargo, run from inside a project acts on the enclosing project
+        let enclosing = enclosing_project(directory)?;
+        if let (Some(project), true) = (&enclosing, args.text_output()) {
+            println!(
+                "{} Auto-detected enclosing project: {:?} (use --no-auto-root to scan only the current directory)",
+                "[INFO]".blue().bold(),
+                project.path
+            );
+        }
+        enclosing.map(|project| vec![project])
+    } else {
+        None
+    };
+
+    // Several roots to discover from: every drive, several directories or the matches of a quoted glob
+    let roots = if args.all_drives {
+        scan_roots(&SystemDrives, args.include_network_drives)?
+    } else if explicit_directories.len() > 1 || explicit_directories.iter().any(|dir| is_glob(dir) && !dir.exists()) {
+        resolve_roots(&explicit_directories)?
+    } else {
+        Vec::new()
+    };
+    let root = match explicit_projects {
+        Some(ref projects) if !args.stdin => projects[0].path.clone(),
+        _ if !roots.is_empty() => common_ancestor(&roots),
+        _ => {
+            validate_directory(directory)?;
+            // Canonicalize to resolve symlinks
+            directory.canonicalize()
+                .with_context(|| format!("Failed to canonicalize path: {:?}", directory))?
+        }
+    };
+
+    // Precedence: command line, then the scan root's rclean.toml
+    if !args.no_local_config {
+        if let Some((path, config)) = load_local_config(&root)? {
+            if args.text_output() {
+                println!("{} Using {} from {:?}", "[INFO]".blue().bold(), LOCAL_CONFIG, path);
+            }
+            args.apply_config(config);
+        }
+    }
+    // Subcommands don't take the top-level directories, so clap can't check these
+    if args.manifest_path.is_some() && !args.directories.is_empty() {
+        return Err(anyhow!("--manifest-path cleans a single project, so it can't be combined with a directory"));
+    }
+    if args.all_drives && (!args.directories.is_empty() || args.stdin) {
+        return Err(anyhow!("--all-drives scans every fixed drive, so it can't be combined with a directory or --stdin"));
+    }
+    if args.dry_run_json_only {
+        if let Some(option) = args.spawning_option() {
+            return Err(anyhow!(
+                "--dry-run-json-only runs no other programs, so it can't be combined with {} (set on the command line or in {})",
+                option,
+                LOCAL_CONFIG
+            ));
+        }
+    }
+    if let Some(ref name) = args.target_dir_name {
+        validate_dir_name(name).with_context(|| format!("Invalid --target-dir-name value: '{}'", name))?;
+    }
+    if args.remove_deps && args.json && !args.dry_run && !args.yes {
+        return Err(anyhow!(
+            "--remove-deps asks before removing dependencies, which it can't do with --json; pass --yes to remove them without asking"
+        ));
+    }
+    if let Some(fraction) = args.estimate {
+        if !args.dry_run {
+            return Err(anyhow!("--estimate only applies to sizes reported by --dry-run; real runs measure what they free"));
+        }
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            return Err(anyhow!("Invalid --estimate value: '{}'. Expected a fraction above 0 and at most 1", fraction));
+        }
+    }
+    let size_colors = match args.size_colors {
+        Some(ref sizes) => SizeColors::parse(sizes).with_context(|| format!("Invalid --size-colors value: '{}'", sizes))?,
+        None => SizeColors::default(),
+    };
+    if args.streaming && args.command.is_some() {
+        return Err(anyhow!("--streaming only applies to cleaning; subcommands need every project before they report"));
+    }
+    if args.progress == Some(ProgressFormat::Json) && args.command.is_some() {
+        return Err(anyhow!("--progress json only applies to cleaning; subcommands don't report progress"));
+    }
+    emit_events(args.progress == Some(ProgressFormat::Json));
+    if args.hardlink_dedup && args.command.is_some() {
+        return Err(anyhow!("--hardlink-dedup runs instead of cleaning, so it can't be combined with a subcommand"));
+    }
+    if args.compare.is_some() && args.command.is_some() {
+        return Err(anyhow!("--compare only applies to cleaning; subcommands don't print a summary to compare"));
+    }
+    if args.command.is_some() {
+        for (set, flag) in [(args.errors_only, "--errors-only"), (args.output.is_some(), "--output"), (args.fail_if_empty, "--fail-if-empty")] {
+            if set {
+                return Err(anyhow!("{} only applies to cleaning", flag));
+            }
+        }
+    }
+    // Read before cleaning, so a bad file fails the run before anything is removed
+    let previous_run = args.compare.as_deref().map(load_previous_run).transpose()?;
+    for name in &args.extra_dirs {
+        validate_dir_name(name).with_context(|| format!("Invalid --extra-dir value: '{}'", name))?;
+        if name == "src" || Some(name.as_str()) == args.target_dir_name.as_deref().or(Some(TARGET_DIR_NAME)) {
+            return Err(anyhow!("Invalid --extra-dir value: '{}' is not build output of another tool", name));
+        }
+    }
+
+    // Without cargo every `cargo clean` would quietly fall back to direct removal
+    if args.command.is_none() && !args.dry_run && args.needs_cargo() && !cargo_on_path() {
+        if cargo_program() != Path::new("cargo") {
+            return Err(anyhow!(
+                "cargo could not be run from {:?} (set by --cargo or $CARGO). \
+                 Point it at a working cargo, or pass --no-cargo to remove target directories directly",
+                cargo_program()
+            ));
+        }
+        return Err(anyhow!(
+            "cargo was not found on PATH. Install Rust with rustup (https://rustup.rs), \
+             or pass --no-cargo to remove target directories directly"
+        ));
+    }
+
+    // Only one run at a time may clean a root; dry runs and reports don't delete anything
+    let run_locks = if args.command.is_none() && !args.dry_run {
+        let lock_roots = if roots.is_empty() { vec![root.clone()] } else { roots.clone() };
+        let wait = args.wait_lock.map(std::time::Duration::from_secs);
+        let mut locks = Vec::new();
+        for lock_root in &lock_roots {
+            match runlock::acquire(&lock_dir(), lock_root, wait) {
+                Ok(lock) => {
+                    if let (Some(pid), true) = (lock.stale_pid, args.verbose && args.text_output()) {
+                        println!("{} Broke stale run lock left by PID {}", "[INFO]".blue().bold(), pid);
+                    }
+                    locks.push(lock);
+                }
+                Err(e) => {
+                    if let Some(held) = e.downcast_ref::<LockHeld>() {
+                        eprintln!("{} {}", "[ERROR]".red().bold(), held);
+                        std::process::exit(EXIT_LOCKED);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        locks
+    } else {
+        Vec::new()
+    };
+
+    if args.text_output() {
+        let action = if args.command.is_some() { "Measuring targets" } else { "Starting cargo clean" };
+        if !roots.is_empty() {
+            println!("{} {} in: {:?}", "[INFO]".blue().bold(), action, roots);
+        } else {
+            println!("{} {} from: {:?}", "[INFO]".blue().bold(), action, root);
+        }
+        if explicit_projects.is_none() {
+            println!("{} Searching for Cargo projects...", "[INFO]".blue().bold());
+        }
+        for invalid in &invalid_paths {
+            println!("{} Ignoring {:?} - {}", "[WARNING]".yellow().bold(), invalid.path, invalid.error);
+        }
+    }
+
+    let discovery_options = DiscoveryOptions {
+        assume_no_workspace: args.assume_no_workspace,
+        workspace_search_depth: args.workspace_search_depth,
+        scan_targets: args.scan_targets,
+        follow_symlinks: args.follow_symlinks,
+        allow_escape: args.allow_escape,
+        one_file_system: args.one_file_system,
+        exclude_fs_types: args.exclude_fs_types.clone(),
+        target_dir_name: args.target_dir_name.clone(),
+        manifest_only: args.dry_run_json_only,
+        exclude_root: args.exclude_root,
+    };
+    let scan_roots = if roots.is_empty() { vec![root.clone()] } else { roots.clone() };
+    let cargo_home = cargo_home().ok();
+    let excludes_for = |scan_root: &Path| {
+        let mut excludes = args.exclude_patterns.clone();
+        if args.all_drives {
+            excludes.extend(default_excludes(scan_root, cargo_home.as_deref()));
+        }
+        excludes
+    };
+
+    // With --streaming, discovery runs alongside cleaning further down
+    let projects = match explicit_projects {
+        Some(projects) => projects,
+        None if args.streaming => Vec::new(),
+        None => {
+            let _timer = timing::phase(Phase::Discovery);
+            let mut projects = Vec::new();
+            let mut dirs_walked = 0;
+            for scan_root in &scan_roots {
+                let discovery = discover_projects(scan_root, &excludes_for(scan_root), &discovery_options)
+                    .with_context(|| format!("Failed to find Cargo projects in {:?}", scan_root))?;
+                report_discovery(&args, &discovery);
+                dirs_walked += discovery.dirs_walked;
+                projects.extend(discovery.projects);
+            }
+            sort_projects(&mut projects);
+            emit(&Event::Discovery {
+                dirs: dirs_walked,
+                projects: projects.len(),
+            });
+            projects
+        }
+    };
+
+    if projects.is_empty() && !args.streaming {
+        if args.text_output() {
             println!("{} No Cargo projects found", "[WARNING]".yellow().bold());
         }
+        finish_empty_run(&args, &root, invalid_paths)?;
+        if args.fail_if_empty {
+            return Err(anyhow!("No Cargo projects found in {:?}", root));
+        }
         return Ok(());
     }
 
@@ -105,10 +966,11 @@ fn main() -> Result<()> {
     };
 
     let projects: Vec<_> = if let Some(min_bytes) = min_size_bytes {
+        let _timer = timing::phase(Phase::Sizing);
         projects
             .into_iter()
             .filter(|project| {
-                let target_dir = project.path.join("target");
+                let target_dir = args.target_dir(project);
                 if target_dir.exists() {
                     get_directory_size(&target_dir).unwrap_or(0) >= min_bytes
                 } else {
@@ -120,25 +982,39 @@ fn main() -> Result<()> {
         projects
     };
 
-    if projects.is_empty() {
-        if !args.json {
-            if min_size_bytes.is_some() {
-                println!("{} No projects found above the minimum size threshold", "[INFO]".blue().bold());
-            } else {
-                println!("{} No Cargo projects found", "[WARNING]".yellow().bold());
-            }
-        }
-        return Ok(());
+    // Filter out projects that were built recently
+    let projects: Vec<_> = if let Some(days) = args.target_older_than {
+        let cutoff = cutoff_for_days(days);
+        projects
+            .into_iter()
+            .filter(|project| last_build_time(&args.target_dir(project)).is_some_and(|built| built <= cutoff))
+            .collect()
+    } else {
+        projects
+    };
+
+    if projects.is_empty() && !args.streaming {
+        report_nothing_in_scope(&args);
+        return finish_empty_run(&args, &root, invalid_paths);
+    }
+
+    if let Some(Command::List(ref list)) = args.command {
+        return run_list(&args, list, &root, &projects);
+    }
+    if args.hardlink_dedup {
+        return run_hardlink_dedup(&args, &projects);
     }
 
-    if !args.json {
-        println!("{} Found {} project(s)", "[INFO]".blue().bold(), projects.len());
+    if args.text_output() {
+        if args.streaming {
+            println!("{} Cleaning projects as they are found (--streaming)", "[INFO]".blue().bold());
+        } else {
+            println!("{} Found {} project(s)", "[INFO]".blue().bold(), projects.len());
+        }
         if args.dry_run {
             println!("{} DRY RUN MODE - no changes will be made", "[INFO]".yellow().bold());
         }
-        // If --remove-deps is specified, automatically enable --clean-deps
-        let clean_deps = args.clean_deps || args.remove_deps;
-        if clean_deps {
+        if args.check_deps() {
             println!("{} Dependency cleaning enabled (native detection)", "[INFO]".blue().bold());
             if args.remove_deps {
                 println!("{} Will remove unused dependencies (requires cargo-remove)", "[INFO]".yellow().bold());
@@ -147,172 +1023,1158 @@ fn main() -> Result<()> {
         println!();
     }
 
-    let (multi, overall_pb) = create_progress_bars(projects.len(), !args.json && !args.verbose);
+    let confirm_over = args
+        .confirm_over
+        .as_deref()
+        .map(|size| parse_size(size).with_context(|| format!("Invalid --confirm-over value: '{}'", size)))
+        .transpose()?
+        .filter(|_| !args.dry_run);
+    let time_budget = args
+        .time_budget
+        .as_deref()
+        .map(|budget| parse_duration(budget).with_context(|| format!("Invalid --time-budget value: '{}'", budget)))
+        .transpose()?;
+    let until_free = args
+        .until_free
+        .as_deref()
+        .map(|size| parse_size(size).with_context(|| format!("Invalid --until-free value: '{}'", size)))
+        .transpose()?;
+    let show_progress = args.text_output() && !args.verbose && !events_enabled();
+    show_commands(args.verbose && args.text_output());
+    let pool = build_pool(args.jobs)?;
 
-    let results: Vec<CleanResult> = projects
-        .par_iter()
-        .with_min_len(1)
-        .map(|project| {
-            // Create individual progress bar for this project
-            let project_pb = if let Some(ref multi) = multi {
-                Some(create_project_progress_bar(multi, &project.path))
-            } else {
-                None
-            };
+    // Size every target up front, before anything is deleted: --confirm-over
+    // needs the total, --time-budget and --until-free the order, --timings
+    // measures it, and the overall bar and its ETA advance by bytes rather
+    // than by projects of wildly different sizes
+    let largest_first_order = time_budget.is_some() || until_free.is_some();
+    let needs_sizes = show_progress || confirm_over.is_some() || largest_first_order || args.timings;
+    let target_sizes: Option<Vec<u64>> = needs_sizes.then(|| {
+        let _timer = timing::phase(Phase::Sizing);
+        pool.install(|| {
+            projects
+                .par_iter()
+                .map(|project| {
+                    let _timer = timing::project(Phase::Sizing, &project.path);
+                    match args.estimate {
+                        Some(fraction) => estimate_size(&args.target_dir(project), fraction).bytes,
+                        None => get_directory_size(&args.target_dir(project)).unwrap_or(0),
+                    }
+                })
+                .collect()
+        })
+    });
+    if let (Some(threshold), Some(sizes)) = (confirm_over, &target_sizes) {
+        let stdin = std::io::stdin();
+        let interactive = std::io::IsTerminal::is_terminal(&stdin);
+        if !confirm_large_clean(&args, &projects, sizes, threshold, interactive, &mut stdin.lock(), &mut std::io::stderr())? {
+            if args.text_output() {
+                println!("{} Cancelled, nothing was deleted", "[INFO]".blue().bold());
+            }
+            return Ok(());
+        }
+    }
 
-            if args.verbose && !args.json {
-                println!("{} Cleaning: {:?}", "[INFO]".blue().bold(), project.path);
+    // Detection is heuristic, so show what would go and ask before removing
+    // any of it; the analysis shown is the one acted on
+    let mut remove_deps = args.remove_deps;
+    let mut dependency_checks: HashMap<PathBuf, DependencyCheck> = HashMap::new();
+    if remove_deps && !args.dry_run && !args.yes {
+        dependency_checks = pool.install(|| {
+            projects
+                .par_iter()
+                .filter_map(|project| Some((project.path.clone(), check_unused_dependencies(project).ok()?)))
+                .collect()
+        });
+        let plan: Vec<(&Path, Vec<UnusedDependency>)> = projects
+            .iter()
+            .filter_map(|project| {
+                let (unused, _) = dependency_checks.get(&project.path)?;
+                (!unused.is_empty()).then(|| (project.path.as_path(), unused.clone()))
+            })
+            .collect();
+        let stdin = std::io::stdin();
+        let interactive = std::io::IsTerminal::is_terminal(&stdin);
+        if !confirm_dependency_removal(&args, &plan, interactive, &mut stdin.lock(), &mut std::io::stderr())? {
+            remove_deps = false;
+            if args.text_output() {
+                println!("{} Leaving dependencies in place, only reporting them", "[INFO]".blue().bold());
             }
+        }
+    }
 
-            // Clean target directory
-            let result = clean_project(project, args.dry_run, args.verbose);
-
-            // Clean unused dependencies if requested (--clean-deps or --remove-deps)
-            // Note: --remove-deps automatically enables dependency checking
-            if args.clean_deps || args.remove_deps {
-                let deps_result = clean_dependencies(project, args.dry_run, args.remove_deps, args.verbose);
-                match deps_result {
-                    Ok(deps_clean) => {
-                        if !deps_clean.unused_deps.is_empty() {
-                            if !args.json {
-                                // Always show unused dependencies, not just in verbose mode
-                                println!(
-                                    "{} Found {} unused dependency(ies) in {}:",
-                                    "[INFO]".blue().bold(),
-                                    deps_clean.unused_deps.len(),
-                                    project.path.display()
-                                );
-                                for dep in &deps_clean.unused_deps {
-                                    println!("  {} {} ({})", "•".yellow(), dep.name.bright_yellow(), dep.location);
-                                }
-                                if deps_clean.removed_count > 0 {
-                                    println!(
-                                        "{} Removed {} unused dependency(ies)",
-                                        "[SUCCESS]".green().bold(),
-                                        deps_clean.removed_count
-                                    );
-                                } else if args.remove_deps && !args.dry_run {
-                                    // Check if there was an error
-                                    if let Some(ref error) = deps_clean.error {
-                                        println!(
-                                            "{} Failed to remove dependencies: {}",
-                                            "[ERROR]".red().bold(),
-                                            error
-                                        );
-                                    } else {
-                                        println!(
-                                            "{} Could not remove dependencies (install cargo-remove: cargo install cargo-edit)",
-                                            "[WARNING]".yellow().bold()
-                                        );
-                                    }
-                                } else if args.dry_run {
-                                    println!(
-                                        "{} Would remove {} dependency(ies) (use --remove-deps to actually remove)",
-                                        "[INFO]".blue().bold(),
-                                        deps_clean.unused_deps.len()
-                                    );
-                                }
-                            }
-                        } else if !args.json {
-                            // Show confirmation that check was performed (only in verbose mode to avoid clutter)
-                            if args.verbose {
-                                println!(
-                                    "{} No unused dependencies found in {}",
-                                    "[INFO]".blue().bold(),
-                                    project.path.display()
-                                );
-                            }
-                        }
-                        
-                        // Check if there was an error even when no unused deps were found
-                        // (e.g., cargo-remove not available when --remove-deps was specified)
-                        if let Some(ref error) = deps_clean.error {
-                            if !args.json {
-                                println!(
-                                    "{} Error during dependency removal in {:?}: {}",
-                                    "[ERROR]".red().bold(),
-                                    project.path,
-                                    error
-                                );
-                            }
-                        }
-                    }
+    // Spend a time budget, or work towards a free space goal, with the biggest wins
+    let (projects, target_sizes) = match (largest_first_order, target_sizes) {
+        (true, Some(sizes)) => {
+            let order = largest_first(&sizes);
+            let projects: Vec<Project> = order.iter().map(|&i| projects[i].clone()).collect();
+            (projects, Some(order.iter().map(|&i| sizes[i]).collect()))
+        }
+        (_, sizes) => (projects, sizes),
+    };
+
+    install_handler()?;
+    let (multi, overall_pb) = create_progress_bars(projects.len(), show_progress);
+    // Without the full list there's no total to measure progress against
+    let overall_pb = overall_pb.filter(|_| !args.streaming);
+
+    let installed_rustc = if args.stale_toolchains {
+        let versions = installed_rustc_versions()?;
+        if args.verbose && args.text_output() {
+            for version in &versions {
+                println!("{} Installed compiler: {}", "[INFO]".blue().bold(), version);
+            }
+        }
+        Some(versions)
+    } else {
+        None
+    };
+
+    let parse_patterns = |what: &str, patterns: &[String]| -> Result<Vec<glob::Pattern>> {
+        patterns
+            .iter()
+            .map(|p| glob::Pattern::new(p).with_context(|| format!("Invalid {} pattern: '{}'", what, p)))
+            .collect()
+    };
+
+    let project_paths: Vec<&Path> = projects.iter().map(|p| p.path.as_path()).collect();
+    let mut filesystems = free_space_before(&project_paths);
+
+    let clean_options = CleanOptions {
+        dry_run: args.dry_run,
+        archive_dir: args.archive.clone(),
+        archive_force: args.archive_force,
+        sweep_days: args.sweep,
+        sweep_strict: args.sweep_strict,
+        installed_rustc,
+        triples: parse_patterns("triple", &args.target_triples)?,
+        keep_triples: parse_patterns("triple", &args.keep_triples)?,
+        keep_subdirs: args.keep_subdirs.clone(),
+        dedupe_artifacts: args.dedupe_artifacts,
+        vacuum: args.vacuum,
+        packages: parse_patterns("package", &args.packages)?,
+        default_members_only: args.default_members_only,
+        git_idle: args
+            .git_idle
+            .as_deref()
+            .map(|idle| parse_duration(idle).with_context(|| format!("Invalid --git-idle value: '{}'", idle)))
+            .transpose()?,
+        only_committed: args.only_committed,
+        git_only: args.git_only,
+        src_idle: args
+            .src_idle
+            .as_deref()
+            .map(|idle| parse_duration(idle).with_context(|| format!("Invalid --src-idle value: '{}'", idle)))
+            .transpose()?,
+        verify_build: args.verify_build,
+        force: args.force,
+        retries: args.retries,
+        no_cargo: args.no_cargo,
+        prefer_fallback: args.prefer_fallback,
+        defer_delete: args.defer_delete.is_some(),
+        exclude_private: args.exclude_private,
+        only_private: args.only_private,
+        target_dir_name: args.target_dir_name.clone(),
+        keep_executables: args.keep_executables,
+        keep_executables_to: args.keep_executables_to.clone(),
+        keep_criterion: args.keep_criterion,
+        extra: ExtraDirs {
+            presets: args.extra.clone(),
+            names: args.extra_dirs.clone(),
+        },
+        debris: args.debris,
+        bin_artifacts_only: args.bin_artifacts_only,
+        remove_empty_target: args.remove_empty_target,
+        estimate: args.estimate,
+    };
+
+    // Targets a crashed or interrupted --defer-delete run moved aside but never deleted
+    if !args.dry_run {
+        let stale: Vec<PathBuf> = projects.iter().flat_map(|p| stale_trash(&p.path)).collect();
+        if !stale.is_empty() {
+            if args.text_output() {
+                println!(
+                    "{} Deleting {} leftover target(s) moved aside by an earlier run",
+                    "[INFO]".blue().bold(),
+                    stale.len()
+                );
+            }
+            for (dir, e) in pool.install(|| purge(&stale)) {
+                if args.text_output() {
+                    print_warning(&dir, &format!("Failed to delete: {}", e));
+                }
+            }
+        }
+    }
+
+    // Every real run keeps a journal, so a crash or reboot can be resumed
+    let journal = if args.dry_run {
+        None
+    } else {
+        Some(Journal::open(&journal_dir(), &root, &clean_options.journal_key(), args.resume)?)
+    };
+    if let Some(ref journal) = journal {
+        if args.resume && args.text_output() {
+            println!("{} Resuming: {} project(s) already cleaned", "[INFO]".blue().bold(), journal.cleaned_count());
+        }
+    }
+
+    let audit_log = args
+        .audit_log
+        .as_deref()
+        .map(|path| AuditLog::open(path, &AuditEntry::run(&root, args.dry_run)))
+        .transpose()?;
+
+    let weights: Vec<u64> = match (&overall_pb, target_sizes) {
+        (Some(overall), Some(sizes)) => {
+            let weights: Vec<u64> = sizes.into_iter().map(|bytes| bytes.max(1)).collect();
+            overall.set_length(weights.iter().sum());
+            overall.set_message("Starting...");
+            weights
+        }
+        _ => vec![1; projects.len()],
+    };
+    let projects_done = AtomicUsize::new(0);
+    let advance_overall = |weight: u64| {
+        if let Some(ref overall) = overall_pb {
+            let done = projects_done.fetch_add(1, Ordering::Relaxed) + 1;
+            overall.set_prefix(format!("{}/{}", done, projects.len()));
+            overall.inc(weight);
+        }
+    };
+
+    let breaker = FailureBreaker::new(if args.fail_fast { Some(1) } else { args.max_failures.map(|n| n as usize) });
+    let budget = TimeBudget::start(time_budget);
+    let free_space_goal = FreeSpaceGoal::new(until_free, args.dry_run, &filesystems);
+    let cleaning_timer = timing::phase(Phase::Cleaning);
+    let freed_so_far = AtomicU64::new(0);
+    let clean_one = |project: &Project, weight: u64| -> Result<(CleanResult, Option<DependencyCleanResult>)> {
+        // After Ctrl-C, projects that haven't started are left alone
+        if is_interrupted() {
+            advance_overall(weight);
+            return Ok((CleanResult::skipped(project, SkipReason::Interrupted), None));
+        }
+
+        // Once the failure limit is hit, in-flight projects finish but no new ones start
+        if breaker.tripped() {
+            advance_overall(weight);
+            return Ok((CleanResult::skipped(project, SkipReason::Aborted), None));
+        }
+
+        // Likewise once the time budget is spent
+        if budget.exhausted() {
+            advance_overall(weight);
+            return Ok((CleanResult::skipped(project, SkipReason::BudgetExhausted), None));
+        }
+
+        // Or once its filesystem has as much free space as asked for
+        if free_space_goal.met(&project.path) {
+            advance_overall(weight);
+            return Ok((CleanResult::skipped(project, SkipReason::EnoughFreeSpace), None));
+        }
+
+        if journal.as_ref().is_some_and(|journal| journal.was_cleaned(project)) {
+            advance_overall(weight);
+            return Ok((CleanResult::skipped(project, SkipReason::PreviouslyCleaned), None));
+        }
+        let _timer = timing::project(Phase::Cleaning, &project.path);
+        let path = project.path.to_string_lossy();
+        emit(&Event::ProjectStart { path: &path });
+
+        // Create individual progress bar for this project
+        let project_pb = multi.as_ref().map(|multi| create_project_progress_bar(multi, &project.name));
+
+        if args.verbose && args.text_output() {
+            println!("{} Cleaning: {:?}", "[INFO]".blue().bold(), project.path);
+        }
+
+        let target_dir = args.target_dir(project);
+
+        // Give the pre-hook a chance to veto this project
+        let rejected = match args.pre_hook {
+            Some(ref cmd) => {
+                let ctx = HookContext {
+                    project_path: &project.path,
+                    target_path: &target_dir,
+                    dry_run: args.dry_run,
+                    freed_bytes: None,
+                };
+                match run_hook(cmd, &ctx) {
+                    Ok(status) => !status.success(),
                     Err(e) => {
-                        if !args.json {
-                            println!(
-                                "{} Failed to check dependencies in {:?}: {}",
-                                "[WARNING]".yellow().bold(),
-                                project.path,
-                                e
-                            );
+                        if args.text_output() {
+                            print_error(&project.path, &e.to_string());
+                        } else if args.errors_only {
+                            eprintln!("{}", error_message(&project.path, &e.to_string()));
                         }
+                        true
                     }
                 }
             }
+            None => false,
+        };
 
-            // Finish individual progress bar
-            if let Some(ref pb) = project_pb {
-                let project_name = project.path
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .map(|s| s.to_string())
-                    .unwrap_or_else(|| project.path.to_string_lossy().to_string());
-                pb.finish_with_message(format!("✓ {}", project_name));
-            }
+        // Clean target directory
+        let result = if rejected {
+            Ok(CleanResult::skipped(project, SkipReason::PreHookRejected))
+        } else {
+            clean_project(project, &clean_options)
+        };
 
-            // Update overall progress
-            if let Some(ref overall) = overall_pb {
-                overall.inc(1);
+        // Post-hook failures never fail the clean itself
+        let result = match (result, &args.post_hook) {
+            (Ok(mut r), Some(cmd)) if !r.is_skipped() => {
+                let ctx = HookContext {
+                    project_path: &project.path,
+                    target_path: &target_dir,
+                    dry_run: args.dry_run,
+                    freed_bytes: Some(r.freed_bytes),
+                };
+                match run_hook(cmd, &ctx) {
+                    Ok(status) if status.success() => {}
+                    Ok(status) => r.warnings.push(format!("post-hook exited with {}", status)),
+                    Err(e) => r.warnings.push(e.to_string()),
+                }
+                Ok(r)
             }
+            (result, _) => result,
+        };
 
-            match result {
-                Ok(r) => {
-                    if args.verbose && !args.json {
-                        print_verbose_cleaned(&r);
+        // Clean unused dependencies if requested (--clean-deps, --remove-deps or --duplicates)
+        let deps_result = if args.check_deps() {
+            match clean_dependencies(project, dependency_checks.get(&project.path).cloned(), args.dry_run, remove_deps, args.duplicates, args.verbose) {
+                Ok(deps_clean) => {
+                    if args.text_output() {
+                        print_dependency_result(
+                            &project.path,
+                            &deps_clean,
+                            remove_deps,
+                            args.dry_run,
+                            args.verbose,
+                        );
                     }
-                    Ok(r)
+                    Some(deps_clean)
                 }
                 Err(e) => {
-                    let error_msg = e.to_string();
-                    if !args.json {
-                        print_error(&project.path, &error_msg);
+                    if args.text_output() {
+                        println!(
+                            "{} Failed to check dependencies in {:?}: {:#}",
+                            "[WARNING]".yellow().bold(),
+                            project.path,
+                            e
+                        );
                     }
-                    Ok(CleanResult {
+                    Some(DependencyCleanResult {
                         path: project.path.to_string_lossy().to_string(),
                         success: false,
-                        freed_bytes: 0,
-                        error: Some(error_msg),
+                        unused_deps: Vec::new(),
+                        removed_count: 0,
+                        error: Some(format!("{:#}", e)),
+                        name_collisions: Vec::new(),
+                        duplicates: Vec::new(),
                     })
                 }
             }
-        })
-        .collect::<Result<Vec<_>>>()?;
+        } else {
+            None
+        };
+        // Finish individual progress bar
+        if let Some(ref pb) = project_pb {
+            pb.finish_with_message(format!("{} {}", glyphs().check, project.name));
+        }
 
-    if let Some(ref overall) = overall_pb {
-        overall.finish_with_message("All projects completed!");
+        if let Ok(ref r) = result {
+            free_space_goal.record_freed(&project.path, r.freed_bytes);
+        }
+        emit(&Event::ProjectDone {
+            path: &path,
+            freed: result.as_ref().map_or(0, |r| r.freed_bytes),
+        });
+
+        // Update overall progress with the running total
+        if let Some(ref overall) = overall_pb {
+            let freed = result.as_ref().map(|r| r.freed_bytes).unwrap_or(0);
+            let total = freed_so_far.fetch_add(freed, Ordering::Relaxed) + freed;
+            overall.set_message(freed_so_far_message(total, args.dry_run));
+        }
+        advance_overall(weight);
+
+        let result = match result {
+            Ok(mut r) => {
+                if let Some(ref audit_log) = audit_log {
+                    if let Err(e) = audit_log.record(&r.audit) {
+                        r.warnings.push(e.to_string());
+                    }
+                }
+                if let Some(ref journal) = journal {
+                    if let Err(e) = journal.record(&r) {
+                        r.warnings.push(e.to_string());
+                    }
+                }
+                if args.text_output() {
+                    for warning in &r.warnings {
+                        print_warning(&project.path, warning);
+                    }
+                    if !r.success {
+                        print_failed(&r);
+                    }
+                } else if args.errors_only && !r.success {
+                    eprintln!("{}", failure_message(&r));
+                }
+                if args.dry_run && args.text_output() {
+                    print_dry_run_plan(&r);
+                }
+                if args.verbose && args.text_output() {
+                    print_verbose_cleaned(&r);
+                }
+                Ok(r)
+            }
+            Err(e) => {
+                let error_msg = e.to_string();
+                if args.text_output() {
+                    print_error(&project.path, &error_msg);
+                } else if args.errors_only {
+                    eprintln!("{}", error_message(&project.path, &error_msg));
+                }
+                Ok(CleanResult::failed(project, error_msg))
+            }
+        };
+        if let Ok(ref r) = result {
+            breaker.record(r);
+        }
+        result.map(|r| (r, deps_result))
+    };
+    let outcomes: Vec<(CleanResult, Option<DependencyCleanResult>)> = if args.streaming {
+        // The size and age filters and leftover trash are handled one project at a time
+        let in_scope = |project: &Project| {
+            let target_dir = args.target_dir(project);
+            min_size_bytes.is_none_or(|min_bytes| target_dir.exists() && get_directory_size(&target_dir).unwrap_or(0) >= min_bytes)
+                && args
+                    .target_older_than
+                    .is_none_or(|days| last_build_time(&target_dir).is_some_and(|built| built <= cutoff_for_days(days)))
+        };
+        let clean_found = |project: &Project| {
+            if !args.dry_run {
+                for (dir, e) in purge(&stale_trash(&project.path)) {
+                    if args.text_output() {
+                        print_warning(&dir, &format!("Failed to delete: {}", e));
+                    }
+                }
+            }
+            clean_one(project, 1)
+        };
+        let (outcomes, found_on) = clean_streamed(&args, &pool, &scan_roots, &excludes_for, &discovery_options, &in_scope, &clean_found)?;
+        filesystems = found_on;
+        outcomes
+    } else {
+        pool.install(|| {
+            projects
+                .par_iter()
+                .zip(&weights)
+                .with_min_len(1)
+                .map(|(project, &weight)| clean_one(project, weight))
+                .collect::<Result<Vec<_>>>()
+        })?
+    };
+
+    let (results, dependency_results): (Vec<CleanResult>, Vec<Option<DependencyCleanResult>>) =
+        outcomes.into_iter().unzip();
+    if args.streaming && results.is_empty() {
+        report_nothing_in_scope(&args);
+        return finish_empty_run(&args, &root, invalid_paths);
+    }
+    // Collected in project order, so dependency results are as stable as the project list
+    let dependency_results: Vec<DependencyCleanResult> = dependency_results.into_iter().flatten().collect();
+
+    let interrupted = is_interrupted();
+    if interrupted {
+        if let Some(ref multi) = multi {
+            let _ = multi.clear();
+        }
+        restore_terminal();
+    } else if let Some(ref overall) = overall_pb {
+        // Keep the final running total on screen
+        overall.finish();
+    }
+
+    // Every project is usable again, so what --defer-delete moved aside can go now
+    let deferred: Vec<PathBuf> = results
+        .iter()
+        .filter_map(|r| r.deferred_path.as_deref())
+        .map(PathBuf::from)
+        .collect();
+    if !deferred.is_empty() && !interrupted {
+        let in_background = args.defer_delete == Some(DeferMode::Background)
+            && match spawn_purge(&deferred) {
+                Ok(()) => true,
+                Err(e) => {
+                    if args.text_output() {
+                        println!("{} Failed to start background delete, deleting now: {}", "[WARNING]".yellow().bold(), e);
+                    }
+                    false
+                }
+            };
+        if !in_background {
+            if args.text_output() {
+                println!("{} Deleting {} moved target(s)", "[INFO]".blue().bold(), deferred.len());
+            }
+            for (dir, e) in pool.install(|| purge(&deferred)) {
+                if args.text_output() {
+                    print_warning(&dir, &format!("Failed to delete, the next run will retry: {}", e));
+                }
+            }
+        }
     }
 
-    let cleaned = results.iter().filter(|r| r.success).count();
-    let failed = results.len() - cleaned;
+    drop(cleaning_timer);
+    let reporting_timer = timing::phase(Phase::Reporting);
+    record_free_space_after(&mut filesystems, args.dry_run);
+
+    let skipped = skipped_projects(&results);
+    let cleaned = results.iter().filter(|r| r.success && !r.is_skipped()).count();
+    let failed = results.len() - cleaned - skipped.len();
     let total_freed: u64 = results.iter().map(|r| r.freed_bytes).sum();
+    record_freed_bytes(&mut filesystems, results.iter().map(|r| (Path::new(&r.path), r.freed_bytes)));
+    let total_capacity = filesystems.iter().map(|fs| fs.total_bytes).sum();
+    let estimate = results.iter().any(|r| r.estimate.is_some()).then(|| {
+        results
+            .iter()
+            .map(|r| r.estimate.unwrap_or(SizeEstimate::exact(r.freed_bytes)))
+            .fold(SizeEstimate::exact(0), SizeEstimate::add)
+    });
+    let fs_delta: Option<i64> = filesystems.iter().map(|fs| fs.fs_delta_bytes).sum();
 
-    let summary = Summary {
-        total_projects: projects.len(),
+    let aborted = results.iter().any(|r| r.skip_reason == Some(SkipReason::Aborted));
+    let drives = if args.all_drives { group_by_drive(&results, &roots) } else { Vec::new() };
+    let mut summary = Summary {
+        dry_run: args.dry_run,
+        total_projects: results.len(),
         cleaned,
         failed,
+        skipped_count: skipped.len(),
+        skipped,
+        build_failed: results.iter().filter(|r| r.skip_reason == Some(SkipReason::BuildFailed)).count(),
+        aborted: results.iter().filter(|r| r.skip_reason == Some(SkipReason::Aborted)).count(),
+        budget_exhausted: results.iter().any(|r| r.skip_reason == Some(SkipReason::BudgetExhausted)),
+        until_free_bytes: until_free,
+        until_free_met: until_free.map(|goal| filesystems.iter().all(|fs| free_after(fs, args.dry_run) >= goal)),
         total_freed_bytes: total_freed,
+        total_freed_files: results.iter().filter_map(|r| r.freed_files).sum(),
+        estimate,
+        would_free_bytes: args.dry_run.then_some(total_freed),
+        freed_pct_of_volume: percent_of(total_freed, total_capacity),
+        free_bytes_before: filesystems.iter().map(|fs| fs.free_bytes_before).sum(),
+        free_bytes_after: filesystems.iter().map(|fs| fs.free_bytes_after).sum(),
+        fs_delta_bytes: fs_delta,
+        fs_delta_note: fs_delta.and_then(|delta| fs_delta_note(total_freed, delta)),
+        filesystems,
         results,
+        dependency_results,
+        invalid_paths,
+        drives,
+        timings: None,
+        comparison: None,
     };
+    summary.comparison = previous_run.as_ref().map(|previous| compare_runs(previous, &summary.results));
+    drop(reporting_timer);
+    summary.timings = args.timings.then(timing::snapshot);
 
     if args.json {
         println!("{}", serde_json::to_string_pretty(&summary)?);
+    } else if args.format == Some(OutputFormat::Markdown) {
+        print!("{}", render_markdown_summary(&summary, &root));
+    } else if args.text_output() {
+        if interrupted {
+            println!();
+            println!("{} Interrupted - partial summary of projects processed so far", "[WARNING]".yellow().bold());
+        }
+        print_summary(&summary, &TableStyle::for_stdout(size_colors));
+        if args.check_deps() {
+            print_dependency_summary(&summary.dependency_results);
+        }
+        if let Some(ref timings) = summary.timings {
+            print_timings(timings, args.verbose);
+        }
+    }
+    emit(&Event::Done);
+
+    // Failed projects keep the journal around, so --resume retries just those
+    if let Some(journal) = journal {
+        if !interrupted && failed == 0 {
+            journal.finish()?;
+        }
+    }
+
+    // Exiting skips destructors, so release the run locks first
+    drop(run_locks);
+    if interrupted {
+        std::process::exit(EXIT_INTERRUPTED);
+    }
+    let metrics_written = write_metrics(&args, &summary);
+    let report_written = write_report(&args, &summary);
+    let hook_succeeded = run_on_complete(&args, &root, &summary);
+    if aborted {
+        eprintln!(
+            "{} Aborted after {} failed projects; {} were not started",
+            "[ERROR]".red().bold(),
+            breaker.failures(),
+            summary.aborted
+        );
+        std::process::exit(EXIT_ABORTED);
+    }
+    if failed > 0 || !metrics_written || !report_written || !hook_succeeded {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Say why no project is left to clean, naming the filter that ruled them out
+fn report_nothing_in_scope(args: &Args) {
+    if !args.text_output() {
+        return;
+    }
+    if args.target_older_than.is_some() {
+        println!("{} No projects with build artifacts older than the threshold", "[INFO]".blue().bold());
+    } else if args.min_size.is_some() {
+        println!("{} No projects found above the minimum size threshold", "[INFO]".blue().bold());
     } else {
-        print_summary(&summary);
+        println!("{} No Cargo projects found", "[WARNING]".yellow().bold());
     }
+}
 
-    if failed > 0 {
+/// Print what discovery left out of a scan root
+fn report_discovery(args: &Args, discovery: &Discovery) {
+    if let (Some(path), true) = (&discovery.excluded_root, args.text_output()) {
+        println!("{} Leaving out the project at the scan root {:?} (--exclude-root)", "[INFO]".blue().bold(), path);
+    }
+    if args.verbose && args.text_output() {
+        for mount in &discovery.skipped_mounts {
+            println!("{} Skipping mount point {:?} ({})", "[INFO]".blue().bold(), mount.path, mount.reason);
+        }
+    }
+}
+
+/// How far discovery may get ahead of the workers with `--streaming`, in projects
+const STREAMING_QUEUE: usize = 64;
+
+/// Clean projects as discovery finds them, for `--streaming`
+///
+/// Discovery runs on a thread of its own and feeds a bounded queue that the
+/// pool drains, so cleaning starts with the first project found and the full
+/// list is never held. Projects are deduplicated as they arrive and those
+/// failing `in_scope` are dropped. Returns the outcomes in the order they
+/// finished, and the filesystems the projects are on, with free space
+/// measured before any project there was cleaned.
+fn clean_streamed<T: Send>(
+    args: &Args,
+    pool: &rayon::ThreadPool,
+    scan_roots: &[PathBuf],
+    excludes_for: &(dyn Fn(&Path) -> Vec<String> + Sync),
+    options: &DiscoveryOptions,
+    in_scope: &(dyn Fn(&Project) -> bool + Sync),
+    clean: &(dyn Fn(&Project) -> Result<T> + Sync),
+) -> Result<(Vec<T>, Vec<FilesystemSpace>)> {
+    let (sender, receiver) = std::sync::mpsc::sync_channel::<Project>(STREAMING_QUEUE);
+    std::thread::scope(|scope| {
+        let discovery = scope.spawn(move || -> Result<Vec<FilesystemSpace>> {
+            let _timer = timing::phase(Phase::Discovery);
+            let mut seen = SeenProjects::default();
+            let mut filesystems = Vec::new();
+            let (mut dirs_walked, mut found) = (0, 0);
+            for scan_root in scan_roots {
+                let discovery = stream_projects(scan_root, &excludes_for(scan_root), options, &mut |project| {
+                    // Nothing more gets cleaned after Ctrl-C or once the workers are gone
+                    if is_interrupted() {
+                        return ControlFlow::Break(());
+                    }
+                    if !seen.insert(&project) {
+                        return ControlFlow::Continue(());
+                    }
+                    add_free_space_before(&mut filesystems, &project.path, &SystemSpace);
+                    found += 1;
+                    match sender.send(project) {
+                        Ok(()) => ControlFlow::Continue(()),
+                        Err(_) => ControlFlow::Break(()),
+                    }
+                })
+                .with_context(|| format!("Failed to find Cargo projects in {:?}", scan_root))?;
+                report_discovery(args, &discovery);
+                dirs_walked += discovery.dirs_walked;
+            }
+            emit(&Event::Discovery {
+                dirs: dirs_walked,
+                projects: found,
+            });
+            Ok(filesystems)
+        });
+        let outcomes = pool.install(|| {
+            receiver
+                .into_iter()
+                .par_bridge()
+                .filter(|project| in_scope(project))
+                .map(|project| clean(&project))
+                .collect::<Result<Vec<T>>>()
+        });
+        let filesystems = discovery.join().expect("discovery thread panicked")?;
+        Ok((outcomes?, filesystems))
+    })
+}
+
+/// Finish a run that found nothing to clean
+///
+/// JSON mode and `--output` still get a summary, so consumers always get well-formed output.
+fn finish_empty_run(args: &Args, root: &Path, invalid_paths: Vec<InvalidPath>) -> Result<()> {
+    let summary = Summary {
+        dry_run: args.dry_run,
+        would_free_bytes: args.dry_run.then_some(0),
+        invalid_paths,
+        ..Default::default()
+    };
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    }
+    emit(&Event::Done);
+    if args.command.is_none() {
+        let metrics_written = write_metrics(args, &summary);
+        let report_written = write_report(args, &summary);
+        if !run_on_complete(args, root, &summary) || !metrics_written || !report_written {
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// Write `--metrics-file`, if asked for, and report whether that worked
+///
+/// Written even for dry runs and runs that found nothing, so the metrics
+/// never go stale; `rclean_dry_run` tells them apart.
+fn write_metrics(args: &Args, summary: &Summary) -> bool {
+    let Some(ref path) = args.metrics_file else {
+        return true;
+    };
+    let metrics = render_prometheus_metrics(summary, std::time::SystemTime::now());
+    match write_atomically(path, &metrics) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("{} Failed to write metrics to {:?}: {}", "[WARNING]".yellow().bold(), path, e);
+            false
+        }
+    }
+}
+
+/// Write the JSON summary to `--output`, if asked for, and report whether that worked
+fn write_report(args: &Args, summary: &Summary) -> bool {
+    let Some(ref path) = args.output else {
+        return true;
+    };
+    let report = match serde_json::to_string_pretty(summary) {
+        Ok(json) => json + "\n",
+        Err(e) => {
+            eprintln!("{} Failed to serialize the report: {}", "[ERROR]".red().bold(), e);
+            return false;
+        }
+    };
+    match write_atomically(path, &report) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("{} Failed to write the report to {:?}: {}", "[ERROR]".red().bold(), path, e);
+            false
+        }
+    }
+}
+
+/// Run the `--on-complete` hook, if any, and report whether it succeeded
+///
+/// Messages go to stderr so they never end up in JSON output.
+fn run_on_complete(args: &Args, root: &Path, summary: &Summary) -> bool {
+    let Some(ref cmd) = args.on_complete else {
+        return true;
+    };
+    match run_completion_hook(cmd, root, summary) {
+        Ok(status) if status.success() => true,
+        Ok(status) => {
+            eprintln!("{} on-complete hook exited with {}", "[WARNING]".yellow().bold(), status);
+            false
+        }
+        Err(e) => {
+            eprintln!("{} {:#}", "[WARNING]".yellow().bold(), e);
+            false
+        }
+    }
+}
+
+/// Share identical files across the projects' targets instead of cleaning them
+fn run_hardlink_dedup(args: &Args, projects: &[Project]) -> Result<()> {
+    let warning = "--hardlink-dedup is experimental. Linked files are shared: a build that rewrites one \
+                   in place instead of replacing it changes it in every target linked to it";
+    if args.json {
+        eprintln!("[WARNING] {}", warning);
+    } else {
+        println!("{} {}", "[WARNING]".yellow().bold(), warning);
+    }
+    let targets: Vec<PathBuf> = projects.iter().map(|project| args.target_dir(project)).filter(|dir| dir.is_dir()).collect();
+    let report = build_pool(args.jobs)?.install(|| hardlink_duplicates(&targets, args.dry_run));
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_hardlink_report(&report);
+    }
+    if !report.failures.is_empty() {
         std::process::exit(1);
     }
+    Ok(())
+}
+
+fn build_pool(jobs: usize) -> Result<rayon::ThreadPool> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build()
+        .context("Failed to build thread pool")
+}
+
+/// Report rustup toolchain sizes, optionally uninstalling one
+fn run_toolchains(args: &Args, toolchains: &ToolchainsArgs) -> Result<()> {
+    let home = rustup_home()?;
+    let pool = build_pool(args.jobs)?;
+    let usage = match pool.install(|| measure_toolchains(&home)) {
+        Ok(usage) => usage,
+        Err(e) => {
+            if args.json {
+                println!("{}", serde_json::json!({ "error": e.to_string() }));
+            } else {
+                println!("{} {}", "[WARNING]".yellow().bold(), e);
+            }
+            return Ok(());
+        }
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&usage)?);
+    } else {
+        print_toolchain_report(&usage);
+    }
+
+    if let Some(ref name) = toolchains.uninstall {
+        let toolchain = usage
+            .iter()
+            .find(|tc| &tc.name == name || tc.name.starts_with(&format!("{}-", name)))
+            .ok_or_else(|| anyhow!("Toolchain not installed: {}", name))?;
+        if !confirm(args, &format!(
+            "Uninstall toolchain {} ({})?",
+            toolchain.name,
+            format_bytes(toolchain.bytes, units())
+        ))? {
+            println!("{} Uninstall cancelled", "[INFO]".blue().bold());
+            return Ok(());
+        }
+        uninstall_toolchain(&toolchain.name)?;
+        println!("{} Uninstalled {}", "[SUCCESS]".green().bold(), toolchain.name);
+    }
+
+    Ok(())
+}
+
+/// Check that a target directory name is a plain directory name, not a path
+fn validate_dir_name(name: &str) -> Result<()> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(std::path::Component::Normal(_)), None) => Ok(()),
+        _ => Err(anyhow!("expected a directory name like 'build', not a path")),
+    }
+}
+
+/// Ask a yes/no question on the terminal; anything but "y" or "yes" is a no
+///
+/// `--yes` answers every question without asking. The question goes to
+/// stderr, so it never ends up in JSON output.
+fn confirm(args: &Args, question: &str) -> Result<bool> {
+    use std::io::IsTerminal;
+
+    let stdin = std::io::stdin();
+    let interactive = stdin.is_terminal();
+    confirm_with(question, args.yes, interactive, &mut stdin.lock(), &mut std::io::stderr())
+}
+
+fn confirm_with(
+    question: &str,
+    yes: bool,
+    interactive: bool,
+    input: &mut impl std::io::BufRead,
+    output: &mut impl std::io::Write,
+) -> Result<bool> {
+    if yes {
+        return Ok(true);
+    }
+    if !interactive {
+        return Err(anyhow!("Refusing to continue without an interactive confirmation (pass --yes to skip it)"));
+    }
+    write!(output, "{} [y/N] ", question)?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Show what a run would delete and ask before going ahead, when the targets
+/// add up to more than `--confirm-over`
+fn confirm_large_clean(
+    args: &Args,
+    projects: &[Project],
+    sizes: &[u64],
+    threshold: u64,
+    interactive: bool,
+    input: &mut impl std::io::BufRead,
+    output: &mut impl std::io::Write,
+) -> Result<bool> {
+    let total: u64 = sizes.iter().sum();
+    if total <= threshold {
+        return Ok(true);
+    }
+    if !args.yes {
+        let plan: Vec<(&Path, u64)> = projects.iter().map(|p| p.path.as_path()).zip(sizes.iter().copied()).collect();
+        write!(output, "{}", render_clean_plan(&plan, threshold, glyphs()))?;
+    }
+    let question = format!("Free {} in {} project(s)?", format_bytes(total, units()), projects.len());
+    confirm_with(&question, args.yes, interactive, input, output)
+}
+
+/// List the unused dependencies `--remove-deps` found in each project and
+/// ask before removing them
+fn confirm_dependency_removal(
+    args: &Args,
+    plan: &[(&Path, Vec<UnusedDependency>)],
+    interactive: bool,
+    input: &mut impl std::io::BufRead,
+    output: &mut impl std::io::Write,
+) -> Result<bool> {
+    let count: usize = plan.iter().map(|(_, unused)| unused.len()).sum();
+    if count == 0 {
+        return Ok(true);
+    }
+    if !args.yes {
+        write!(output, "{}", render_removal_plan(plan, glyphs()))?;
+    }
+    let question = format!("Remove {} unused dependenc(ies) from {} project(s)?", count, plan.len());
+    confirm_with(&question, args.yes, interactive, input, output)
+}
+
+/// Report target directory sizes without cleaning anything
+fn run_list(args: &Args, list: &ListArgs, root: &Path, projects: &[Project]) -> Result<()> {
+    let pool = build_pool(args.jobs)?;
+
+    if list.dep_sizes {
+        let mut per_project: Vec<ProjectDepSizes> = pool.install(|| {
+            projects
+                .par_iter()
+                .map(|project| ProjectDepSizes {
+                    path: project.path.clone(),
+                    deps: dep_sizes(&args.target_dir(project)),
+                })
+                .collect()
+        });
+        let mut total = aggregate_dep_sizes(&per_project);
+        total.truncate(list.top);
+        for project in &mut per_project {
+            project.deps.truncate(list.top);
+        }
+
+        if args.json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&serde_json::json!({ "projects": per_project, "total": total }))?
+            );
+        } else {
+            print_dep_sizes(&per_project, &total);
+        }
+        return Ok(());
+    }
+
+    let mut sizes: Vec<ProjectSize> = pool.install(|| {
+        projects
+            .par_iter()
+            .map(|project| ProjectSize {
+                path: project.path.clone(),
+                target_bytes: get_directory_size(&args.target_dir(project)).unwrap_or(0),
+                last_build: last_build_time(&args.target_dir(project)),
+            })
+            .collect()
+    });
+    sizes.sort_by(|a, b| b.target_bytes.cmp(&a.target_bytes).then_with(|| a.path.cmp(&b.path)));
+
+    if list.tree {
+        let tree = build_size_tree(root, &sizes);
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&tree)?);
+        } else {
+            println!();
+            for line in render_size_tree(&tree, glyphs()) {
+                println!("{}", line);
+            }
+        }
+    } else if args.json {
+        println!("{}", serde_json::to_string_pretty(&sizes)?);
+    } else {
+        print_size_list(&sizes);
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            exclude: vec!["vendor".to_string()],
+            keep_subdir: vec!["release".to_string()],
+            min_size: Some("1GB".to_string()),
+            src_idle: Some("14d".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_command_line_overrides_local_config() {
+        let mut args = Args::parse_from(["cargo-deepclean", "-e", "docs", "--min-size", "100MB"]);
+        args.apply_config(config());
+        assert_eq!(args.exclude_patterns, vec!["docs", "vendor"]);
+        assert_eq!(args.keep_subdirs, vec!["release"]);
+        assert_eq!(args.min_size.as_deref(), Some("100MB"));
+        assert_eq!(args.src_idle.as_deref(), Some("14d"));
+
+        // An explicit clean mode isn't combined with the config's keep-subdir
+        let mut args = Args::parse_from(["cargo-deepclean", "--vacuum"]);
+        args.apply_config(config());
+        assert!(args.keep_subdirs.is_empty());
+    }
+
+    #[test]
+    fn test_target_dir_name() {
+        let project = Project::new(PathBuf::from("/src/app"), false);
+        let mut args = Args::parse_from(["cargo-deepclean"]);
+        assert_eq!(args.target_dir(&project), Path::new("/src/app/target"));
+        args.apply_config(Config { target_dir_name: Some("build".to_string()), ..Default::default() });
+        assert_eq!(args.target_dir(&project), Path::new("/src/app/build"));
+
+        let mut args = Args::parse_from(["cargo-deepclean", "--target-dir-name", "out"]);
+        args.apply_config(Config { target_dir_name: Some("build".to_string()), ..Default::default() });
+        assert_eq!(args.target_dir(&project), Path::new("/src/app/out"));
+
+        assert!(validate_dir_name("_build").is_ok());
+        for name in ["", ".", "..", "build/out", "/tmp/build"] {
+            assert!(validate_dir_name(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_confirm_reads_answer_from_input() {
+        let ask = |answer: &str, yes: bool, interactive: bool| {
+            let mut output = Vec::new();
+            let confirmed = confirm_with("Go on?", yes, interactive, &mut answer.as_bytes(), &mut output);
+            (confirmed.ok(), String::from_utf8(output).unwrap())
+        };
+        assert_eq!(ask("y\n", false, true), (Some(true), "Go on? [y/N] ".to_string()));
+        assert_eq!(ask(" YES \n", false, true).0, Some(true));
+        assert_eq!(ask("\n", false, true).0, Some(false));
+        assert_eq!(ask("", false, true).0, Some(false));
+        // Without a terminal there's no one to ask, unless --yes answered already
+        assert_eq!(ask("y\n", false, false).0, None);
+        assert_eq!(ask("", true, false), (Some(true), String::new()));
+    }
+
+    #[test]
+    fn test_confirm_over_prompts_with_plan() {
+        let gib = 1024 * 1024 * 1024;
+        let projects = [Project::new(PathBuf::from("/src/ci-cache"), false), Project::new(PathBuf::from("/src/app"), false)];
+        let sizes = [250 * gib, 50 * gib];
+        let args = Args::parse_from(["cargo-deepclean", "--confirm-over", "100GB"]);
+        let run = |args: &Args, threshold: u64, answer: &str| {
+            let mut output = Vec::new();
+            let confirmed = confirm_large_clean(args, &projects, &sizes, threshold, true, &mut answer.as_bytes(), &mut output);
+            (confirmed.unwrap(), String::from_utf8(output).unwrap())
+        };
+
+        let (confirmed, output) = run(&args, 100 * gib, "n\n");
+        assert!(!confirmed);
+        assert!(output.contains("/src/ci-cache: 250.00 GiB"));
+        assert!(output.contains("/src/app: 50.00 GiB"));
+        assert!(output.ends_with("Free 300.00 GiB in 2 project(s)? [y/N] "));
+        assert!(run(&args, 100 * gib, "y\n").0);
+
+        // Under the limit nothing is asked
+        assert_eq!(run(&args, 300 * gib, ""), (true, String::new()));
+
+        let args = Args::parse_from(["cargo-deepclean", "--confirm-over", "100GB", "-y"]);
+        assert_eq!(run(&args, 100 * gib, ""), (true, String::new()));
+    }
+
+    #[test]
+    fn test_remove_deps_lists_and_asks() {
+        let unused = |names: &[&str]| -> Vec<UnusedDependency> {
+            names
+                .iter()
+                .map(|name| UnusedDependency {
+                    name: name.to_string(),
+                    location: "[dependencies]".to_string(),
+                })
+                .collect()
+        };
+        let plan = [(Path::new("/src/app"), unused(&["regex", "log"])), (Path::new("/src/cli"), unused(&["atty"]))];
+        let args = Args::parse_from(["cargo-deepclean", "--remove-deps"]);
+        let run = |args: &Args, plan: &[(&Path, Vec<UnusedDependency>)], answer: &str, interactive: bool| {
+            let mut output = Vec::new();
+            let confirmed = confirm_dependency_removal(args, plan, interactive, &mut answer.as_bytes(), &mut output);
+            (confirmed.map_err(|e| e.to_string()), String::from_utf8(output).unwrap())
+        };
+
+        let (confirmed, output) = run(&args, &plan, "\n", true);
+        assert_eq!(confirmed, Ok(false));
+        assert!(output.contains("/src/app\n"));
+        assert!(output.contains("regex ([dependencies])"));
+        assert!(output.contains("atty ([dependencies])"));
+        assert!(output.ends_with("Remove 3 unused dependenc(ies) from 2 project(s)? [y/N] "));
+        assert_eq!(run(&args, &plan, "yes\n", true).0, Ok(true));
+        // Without a terminal to ask on, nothing is removed
+        assert!(run(&args, &plan, "y\n", false).0.is_err());
+        // Nothing to remove, nothing to ask
+        assert_eq!(run(&args, &[], "", false), (Ok(true), String::new()));
+
+        let args = Args::parse_from(["cargo-deepclean", "--remove-deps", "--yes"]);
+        assert_eq!(run(&args, &plan, "", false), (Ok(true), String::new()));
+    }
+
+    #[test]
+    fn test_needs_cargo() {
+        assert!(Args::parse_from(["cargo-deepclean"]).needs_cargo());
+        assert!(!Args::parse_from(["cargo-deepclean", "--no-cargo"]).needs_cargo());
+        // cargo is only the backup there, so a missing one isn't fatal up front
+        assert!(!Args::parse_from(["cargo-deepclean", "--prefer-fallback"]).needs_cargo());
+        assert!(!Args::parse_from(["cargo-deepclean", "--sweep", "30"]).needs_cargo());
+        assert!(Args::parse_from(["cargo-deepclean", "--sweep", "30", "--verify-build"]).needs_cargo());
+    }
+
+    #[test]
+    fn test_cli_definition() {
+        use clap::CommandFactory;
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_dry_run_json_only_rejects_spawning_options() {
+        let mut args = Args::parse_from(["cargo-deepclean", "--dry-run-json-only", "--min-size", "1GB"]);
+        args.apply_config(config());
+        assert_eq!(args.spawning_option(), None);
+
+        // Resuming only reads a journal
+        let args = Args::parse_from(["cargo-deepclean", "--dry-run-json-only", "--resume"]);
+        assert_eq!(args.spawning_option(), None);
+        let args = Args::parse_from(["cargo-deepclean", "--dry-run-json-only", "--verify-build"]);
+        assert_eq!(args.spawning_option(), Some("--verify-build"));
+        // Options can come from the local config as well
+        let mut args = Args::parse_from(["cargo-deepclean", "--dry-run-json-only"]);
+        args.apply_config(Config { git_idle: Some("7d".to_string()), ..Default::default() });
+        assert_eq!(args.spawning_option(), Some("--git-idle"));
+    }
+}
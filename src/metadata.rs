@@ -0,0 +1,148 @@
+use anyhow::{anyhow, Result};
+use cargo_metadata::Metadata;
+use crate::timing::{count_metadata_cache_hit, exec_metadata};
+use crate::utils::metadata_command;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Runs `cargo metadata` for a manifest, so tests can count executions
+pub trait MetadataRunner: Sync {
+    fn run(&self, manifest: &Path) -> Result<Metadata>;
+}
+
+/// `cargo metadata --no-deps`: workspace layout only, with no dependency
+/// resolution, and offline unless `--online` was given
+pub struct CargoMetadata;
+
+impl MetadataRunner for CargoMetadata {
+    fn run(&self, manifest: &Path) -> Result<Metadata> {
+        Ok(exec_metadata(&metadata_command(manifest, &["--no-deps"]))?)
+    }
+}
+
+type Entry = Arc<OnceLock<Result<Arc<Metadata>, String>>>;
+
+/// Workspace metadata, read once per manifest for the whole run
+///
+/// Discovery walks up from every member to its workspace root, and cleaning
+/// asks again for members and default members, so without the cache a large
+/// workspace is queried once per member. Entries are keyed by canonical
+/// manifest path and failures are cached too. Dependency resolution for
+/// `--duplicates` needs the full graph and goes to cargo directly.
+pub struct MetadataCache<R: MetadataRunner = CargoMetadata> {
+    runner: R,
+    entries: Mutex<BTreeMap<PathBuf, Entry>>,
+}
+
+static METADATA: MetadataCache = MetadataCache::with_runner(CargoMetadata);
+
+impl<R: MetadataRunner> MetadataCache<R> {
+    pub const fn with_runner(runner: R) -> Self {
+        MetadataCache {
+            runner,
+            entries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Metadata for `manifest`, running cargo only the first time it's asked for
+    ///
+    /// Concurrent callers asking for the same manifest wait for a single run
+    /// rather than starting their own.
+    pub fn get(&self, manifest: &Path) -> Result<Arc<Metadata>> {
+        let key = manifest.canonicalize().unwrap_or_else(|_| manifest.to_path_buf());
+        let entry = self.entries.lock().unwrap().entry(key.clone()).or_default().clone();
+        let mut ran = false;
+        let result = entry.get_or_init(|| {
+            ran = true;
+            self.runner.run(&key).map(Arc::new).map_err(|e| format!("{:#}", e))
+        });
+        if !ran {
+            count_metadata_cache_hit();
+        }
+        result.clone().map_err(|e| anyhow!(e))
+    }
+}
+
+/// Workspace metadata for `manifest`, shared by everything in this run
+pub fn workspace_metadata(manifest: &Path) -> Result<Arc<Metadata>> {
+    METADATA.get(manifest)
+}
+
+/// Answers every manifest with canned metadata for the workspace at `root`
+/// instead of running cargo, counting how often it's asked per manifest
+///
+/// Manifests that don't exist fail, the way cargo would.
+#[cfg(test)]
+pub struct CountingRunner {
+    root: PathBuf,
+    runs: Mutex<BTreeMap<PathBuf, usize>>,
+}
+
+#[cfg(test)]
+impl CountingRunner {
+    pub fn new(root: &Path) -> Self {
+        CountingRunner {
+            root: root.to_path_buf(),
+            runs: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Runs across every manifest
+    pub fn runs(&self) -> usize {
+        self.runs.lock().unwrap().values().sum()
+    }
+
+    /// Runs for one manifest, by its canonical path
+    pub fn runs_for(&self, manifest: &Path) -> usize {
+        self.runs.lock().unwrap().get(manifest).copied().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+impl MetadataRunner for &CountingRunner {
+    fn run(&self, manifest: &Path) -> Result<Metadata> {
+        *self.runs.lock().unwrap().entry(manifest.to_path_buf()).or_default() += 1;
+        if !manifest.is_file() {
+            return Err(anyhow!("manifest path `{}` does not exist", manifest.display()));
+        }
+        Ok(serde_json::from_value(serde_json::json!({
+            "packages": [],
+            "workspace_members": [],
+            "resolve": null,
+            "workspace_root": self.root,
+            "target_directory": self.root.join("target"),
+            "version": 1,
+        }))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_each_manifest_is_queried_once() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join("Cargo.toml"), "").unwrap();
+        let runner = CountingRunner::new(&root);
+        let cache = MetadataCache::with_runner(&runner);
+
+        let manifest = root.join("Cargo.toml");
+        let first = cache.get(&manifest).unwrap();
+        // The same manifest by another spelling is still a hit
+        let second = cache.get(&root.join("src/../Cargo.toml")).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(first.workspace_root, root);
+        assert_eq!(runner.runs(), 1);
+
+        // Failures are remembered too
+        let missing = root.join("missing/Cargo.toml");
+        assert!(cache.get(&missing).is_err());
+        assert!(cache.get(&missing).is_err());
+        assert_eq!(runner.runs(), 2);
+    }
+}
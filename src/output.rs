@@ -1,16 +1,130 @@
-use crate::cleaner::CleanResult;
-use crate::utils::format_bytes;
+use crate::cache::CacheReport;
+use crate::cleaner::{CleanResult, SkipReason};
+use crate::compare::Comparison;
+use crate::deps::{DependencyCleanResult, UnusedDependency};
+use crate::disk::{free_after, FilesystemSpace};
+use crate::drives::DriveSummary;
+use crate::estimate::SizeEstimate;
+use crate::glyphs::{glyphs, Glyphs};
+use crate::hardlink::HardlinkReport;
+use crate::keep::KeptExecutables;
+use crate::project::InvalidPath;
+use crate::render::{render_summary_table, TableStyle};
+use crate::report::{DepSize, ProjectDepSizes, ProjectSize};
+use crate::timing::{ProjectTiming, Timings};
+use crate::toolchain::ToolchainUsage;
+use crate::utils::{format_ago, format_bytes, format_count, units};
 use colored::Colorize;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressState, ProgressStyle};
+use std::collections::BTreeMap;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-#[derive(Debug, serde::Serialize)]
+/// Format of the clean summary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    /// GitHub-flavored Markdown table
+    #[value(alias = "md")]
+    Markdown,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
 pub struct Summary {
+    /// Nothing was deleted; byte counts are what would have been freed
+    pub dry_run: bool,
     pub total_projects: usize,
     pub cleaned: usize,
     pub failed: usize,
+    pub skipped_count: usize,
+    /// Every project left alone and why, in the order of `results`
+    pub skipped: Vec<SkippedProject>,
+    /// Skipped projects that failed `--verify-build` (included in `skipped_count`)
+    pub build_failed: usize,
+    /// Skipped projects never started after `--fail-fast` or `--max-failures` tripped (included in `skipped_count`)
+    pub aborted: usize,
+    /// `--time-budget` ran out before every project was started
+    pub budget_exhausted: bool,
+    /// The `--until-free` goal, in bytes of free space per filesystem
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until_free_bytes: Option<u64>,
+    /// Whether every filesystem reached it (projected, in a dry run)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until_free_met: Option<bool>,
+    /// In a dry run, the projected total (also reported as `would_free_bytes`)
     pub total_freed_bytes: u64,
+    /// Sum of the projects' `freed_files`, which leaves out partial cleans and estimates
+    pub total_freed_files: u64,
+    /// With `--estimate`, marks `total_freed_bytes` as extrapolated and gives its range
+    #[serde(flatten)]
+    pub estimate: Option<SizeEstimate>,
+    /// Projected total of a dry run, so it can be charted apart from actual frees
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub would_free_bytes: Option<u64>,
+    /// `total_freed_bytes` as a percentage of the combined capacity of `filesystems`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub freed_pct_of_volume: Option<f64>,
+    /// Free space summed over the filesystems containing the cleaned projects
+    pub free_bytes_before: u64,
+    pub free_bytes_after: u64,
+    /// Change in free space measured over `filesystems`, to compare with
+    /// `total_freed_bytes`; unset in a dry run
+    pub fs_delta_bytes: Option<i64>,
+    /// Why `fs_delta_bytes` and `total_freed_bytes` may differ, when they differ by more than 10%
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fs_delta_note: Option<String>,
+    pub filesystems: Vec<FilesystemSpace>,
     pub results: Vec<CleanResult>,
+    /// Only populated when dependency checking was requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependency_results: Vec<DependencyCleanResult>,
+    /// Paths read with `--stdin` that were not usable project roots
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub invalid_paths: Vec<InvalidPath>,
+    /// Per-drive totals with `--all-drives`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub drives: Vec<DriveSummary>,
+    /// Phase timings and work counters with `--timings`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<Timings>,
+    /// Changes since the run given to `--compare`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comparison: Option<Comparison>,
+}
+
+/// A project that was left alone, as listed in the summary
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct SkippedProject {
+    pub path: String,
+    pub reason: SkipReason,
+}
+
+/// The skipped projects among `results`
+pub fn skipped_projects(results: &[CleanResult]) -> Vec<SkippedProject> {
+    results
+        .iter()
+        .filter_map(|result| {
+            result.skip_reason.map(|reason| SkippedProject {
+                path: result.path.clone(),
+                reason,
+            })
+        })
+        .collect()
+}
+
+/// How many projects were skipped for each reason, e.g. "2 in use, 1 uncommitted changes"
+fn skip_breakdown(skipped: &[SkippedProject]) -> String {
+    let mut counts: BTreeMap<SkipReason, usize> = BTreeMap::new();
+    for project in skipped {
+        *counts.entry(project.reason).or_default() += 1;
+    }
+    counts
+        .iter()
+        .map(|(reason, count)| format!("{} {}", count, reason))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 /// Create progress bars for cleaning operations
@@ -24,37 +138,72 @@ pub fn create_progress_bars(
 
     let multi = Arc::new(MultiProgress::new());
     let overall_pb = {
-        let pb = multi.add(ProgressBar::new(project_count as u64));
+        // Length and position are in bytes of target directory, set once
+        // every target has been sized; the project count lives in the prefix
+        let pb = multi.add(ProgressBar::new(0));
         pb.set_style(
             ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} projects completed")
+                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {prefix} projects completed - {msg} - ETA {remaining}")
                 .unwrap()
-                .progress_chars("#>-"),
+                .with_key("remaining", |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+                    let _ = w.write_str(&format_remaining(estimate_remaining(state.pos(), state.len().unwrap_or(0), state.elapsed())));
+                })
+                .tick_strings(glyphs().spinner)
+                .progress_chars(glyphs().progress),
         );
-        pb.set_message("Starting...");
+        pb.set_prefix(format!("0/{}", project_count));
+        pb.set_message("Sizing targets...");
         pb
     };
 
     (Some(multi), Some(overall_pb))
 }
 
+/// Time left if the rest goes at the average rate so far
+///
+/// Averaging over the whole run, rather than indicatif's recent-rate `{eta}`,
+/// keeps the estimate steady while one huge target is being deleted.
+/// `None` until something has been processed.
+pub fn estimate_remaining(done: u64, total: u64, elapsed: Duration) -> Option<Duration> {
+    if done == 0 || elapsed.is_zero() {
+        return None;
+    }
+    let remaining = total.saturating_sub(done) as f64;
+    Some(Duration::from_secs_f64(elapsed.as_secs_f64() * remaining / done as f64))
+}
+
+/// An estimate as `HH:MM:SS`, matching `{elapsed_precise}`
+fn format_remaining(remaining: Option<Duration>) -> String {
+    match remaining {
+        Some(remaining) => {
+            let secs = remaining.as_secs();
+            format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+        }
+        None => "--:--:--".to_string(),
+    }
+}
+
+/// Running total shown on the overall progress bar as projects complete
+pub fn freed_so_far_message(freed_bytes: u64, dry_run: bool) -> String {
+    if dry_run {
+        format!("would free {} so far", format_bytes(freed_bytes, units()))
+    } else {
+        format!("freed {} so far", format_bytes(freed_bytes, units()))
+    }
+}
+
 /// Create a progress bar for an individual project
 pub fn create_project_progress_bar(
     multi: &Arc<MultiProgress>,
-    project_path: &std::path::Path,
+    project_name: &str,
 ) -> ProgressBar {
     let pb = multi.add(ProgressBar::new_spinner());
     pb.set_style(
         ProgressStyle::default_spinner()
             .template("{spinner:.green} {msg}")
             .unwrap()
-            .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
+            .tick_strings(glyphs().spinner),
     );
-    let project_name = project_path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .map(|s| s.to_string())
-        .unwrap_or_else(|| project_path.to_string_lossy().to_string());
     pb.set_message(format!("Cleaning: {}", project_name));
     pb.enable_steady_tick(std::time::Duration::from_millis(100));
     pb
@@ -72,61 +221,1250 @@ pub fn print_start_info(root: &std::path::Path, project_count: usize, dry_run: b
 }
 
 /// Print summary
-pub fn print_summary(summary: &Summary) {
+pub fn print_summary(summary: &Summary, table: &TableStyle) {
     println!();
     println!("{} {}", "[INFO]".blue().bold(), "=== SUMMARY ===");
-    println!(
-        "{} Successfully cleaned: {} project(s)",
-        "[SUCCESS]".green().bold(),
-        summary.cleaned
-    );
+    if !summary.results.is_empty() {
+        print!("{}", render_summary_table(summary, table));
+        println!();
+    }
+    if summary.dry_run {
+        println!("{} Would clean: {} project(s)", "[SUCCESS]".green().bold(), summary.cleaned);
+    } else {
+        println!(
+            "{} Successfully cleaned: {} project(s)",
+            "[SUCCESS]".green().bold(),
+            summary.cleaned
+        );
+    }
+
+    print_kept_executables(summary);
+    let criterion: Vec<u64> = summary.results.iter().filter_map(|r| r.kept_criterion_bytes).collect();
+    if !criterion.is_empty() {
+        println!(
+            "{} Kept criterion baselines: {} in {} project(s), not counted as freed",
+            "[INFO]".blue().bold(),
+            format_bytes(criterion.iter().sum(), units()),
+            criterion.len()
+        );
+    }
+
+    if summary.skipped_count > 0 {
+        println!(
+            "{} Skipped {} project(s): {}",
+            "[INFO]".blue().bold(),
+            summary.skipped_count,
+            skip_breakdown(&summary.skipped)
+        );
+    }
 
-    if summary.total_freed_bytes > 0 {
+    if summary.build_failed > 0 {
         println!(
-            "{} Total storage freed: {}",
+            "{} Build failed, not cleaned: {} project(s)",
+            "[WARNING]".yellow().bold(),
+            summary.build_failed
+        );
+    }
+
+    if summary.aborted > 0 {
+        println!(
+            "{} Stopped after {} failure(s), not attempted: {} project(s)",
+            "[WARNING]".yellow().bold(),
+            summary.failed,
+            summary.aborted
+        );
+    }
+
+    if summary.budget_exhausted {
+        let not_started = summary
+            .results
+            .iter()
+            .filter(|r| r.skip_reason == Some(SkipReason::BudgetExhausted))
+            .count();
+        println!(
+            "{} Time budget used up, not started: {} project(s)",
+            "[WARNING]".yellow().bold(),
+            not_started
+        );
+    }
+
+    if let Some(goal) = summary.until_free_bytes {
+        let left_alone = summary
+            .results
+            .iter()
+            .filter(|r| r.skip_reason == Some(SkipReason::EnoughFreeSpace))
+            .count();
+        if left_alone > 0 {
+            println!(
+                "{} Reached {} free, left alone: {} project(s)",
+                "[INFO]".blue().bold(),
+                format_bytes(goal, units()),
+                left_alone
+            );
+        }
+        for fs in summary.filesystems.iter().filter(|fs| free_after(fs, summary.dry_run) < goal) {
+            println!(
+                "{} Only {} free on {} after cleaning, short of the {} asked for",
+                "[WARNING]".yellow().bold(),
+                format_bytes(free_after(fs, summary.dry_run), units()),
+                fs.mount_point.display(),
+                format_bytes(goal, units())
+            );
+        }
+    }
+
+    if summary.dry_run {
+        println!(
+            "{} Total storage that would be freed: {}{}",
             "[SUCCESS]".green().bold(),
-            format_bytes(summary.total_freed_bytes)
+            format_freed(summary.total_freed_bytes, summary.estimate, glyphs()),
+            volume_share(summary)
+        );
+    } else if summary.total_freed_bytes > 0 {
+        println!(
+            "{} Total storage freed: {}{}",
+            "[SUCCESS]".green().bold(),
+            format_bytes(summary.total_freed_bytes, units()),
+            volume_share(summary)
         );
     } else {
         println!("{} No storage was freed", "[INFO]".blue().bold());
     }
 
+    for drive in &summary.drives {
+        println!(
+            "{} {}: {} of {} project(s) cleaned, {} freed",
+            "[INFO]".blue().bold(),
+            drive.drive.display(),
+            drive.cleaned,
+            drive.projects,
+            format_bytes(drive.freed_bytes, units())
+        );
+    }
+
+    for fs in &summary.filesystems {
+        println!(
+            "{} Free space on {}: {} -> {}{}",
+            "[INFO]".blue().bold(),
+            fs.mount_point.display(),
+            format_bytes(fs.free_bytes_before, units()),
+            format_bytes(fs.free_bytes_after, units()),
+            filesystem_share(summary, fs)
+        );
+    }
+    if let Some(ref note) = summary.fs_delta_note {
+        println!("{} Note: {}", "[INFO]".blue().bold(), note);
+    }
+
+    if !summary.invalid_paths.is_empty() {
+        println!(
+            "{} Invalid paths ignored: {}",
+            "[WARNING]".yellow().bold(),
+            summary.invalid_paths.len()
+        );
+        for invalid in &summary.invalid_paths {
+            println!("  {} {} ({})", glyphs().bullet.yellow(), invalid.path, invalid.error);
+        }
+    }
+
+    if let Some(ref comparison) = summary.comparison {
+        print_comparison(comparison);
+    }
+
     if summary.failed > 0 {
         println!(
             "{} Failed to clean: {} project(s)",
             "[ERROR]".red().bold(),
             summary.failed
         );
+        for result in summary.results.iter().filter(|r| r.remaining_bytes.is_some()) {
+            println!("  {} {} ({})", glyphs().bullet.red(), result.path, partial_clean_message(result));
+        }
     } else {
         println!("{} All done!", "[SUCCESS]".green().bold());
     }
 }
 
+/// The share of the volume(s) the freed space amounts to, e.g. " (8% of /home)"
+///
+/// Runs spanning several filesystems give the share of their combined
+/// capacity here and break it down per mount point in `filesystem_share`.
+pub fn volume_share(summary: &Summary) -> String {
+    let Some(pct) = summary.freed_pct_of_volume else {
+        return String::new();
+    };
+    match summary.filesystems.as_slice() {
+        [fs] => format!(" ({} of {})", format_percent(pct), fs.mount_point.display()),
+        filesystems => format!(" ({} of {} volumes)", format_percent(pct), filesystems.len()),
+    }
+}
+
+/// Freed space on one filesystem of a multi-volume run, e.g. " (freed 1.00 GB, 2% of the volume)"
+pub fn filesystem_share(summary: &Summary, fs: &FilesystemSpace) -> String {
+    match fs.freed_pct_of_volume {
+        Some(pct) if summary.filesystems.len() > 1 => {
+            format!(" (freed {}, {} of the volume)", format_bytes(fs.freed_bytes, units()), format_percent(pct))
+        }
+        _ => String::new(),
+    }
+}
+
+/// Print which executables `--keep-executables` copied out, and where to
+fn print_kept_executables(summary: &Summary) {
+    let kept: Vec<(&str, &KeptExecutables)> = summary
+        .results
+        .iter()
+        .filter_map(|r| Some((r.path.as_str(), r.kept_executables.as_ref()?)))
+        .collect();
+    if kept.is_empty() {
+        return;
+    }
+    println!(
+        "{} {} {} executable(s) from {} project(s):",
+        "[INFO]".blue().bold(),
+        if summary.dry_run { "Would keep" } else { "Kept" },
+        kept.iter().map(|(_, k)| k.files.len()).sum::<usize>(),
+        kept.len()
+    );
+    for (path, executables) in kept {
+        println!("  {} {} -> {} ({})", glyphs().bullet.blue(), path, executables.dir, executables.files.join(", "));
+    }
+}
+
+/// Whole percents, with one decimal below 1% so small frees don't show as 0%
+fn format_percent(pct: f64) -> String {
+    if pct == 0.0 || pct >= 1.0 {
+        format!("{:.0}%", pct)
+    } else {
+        format!("{:.1}%", pct)
+    }
+}
+
+/// Print what changed since the run given to `--compare`, largest changes first
+fn print_comparison(comparison: &Comparison) {
+    let overall = match comparison.delta_bytes {
+        0 => "no change overall".to_string(),
+        delta if delta > 0 => format!("+{} overall", format_bytes(delta.unsigned_abs(), units())),
+        delta => format!("-{} overall", format_bytes(delta.unsigned_abs(), units())),
+    };
+    println!(
+        "{} Compared with the previous run: {} new, {} gone, {} grew, {} shrank, {} unchanged ({})",
+        "[INFO]".blue().bold(),
+        comparison.new.len(),
+        comparison.gone.len(),
+        comparison.grew(),
+        comparison.shrank(),
+        comparison.unchanged,
+        overall
+    );
+    let bullet = glyphs().bullet.blue();
+    for project in &comparison.new {
+        println!("  {} {} new ({})", bullet, project.path, format_bytes(project.bytes, units()));
+    }
+    for change in &comparison.changed {
+        let direction = if change.delta_bytes > 0 { "grew" } else { "shrank" };
+        println!(
+            "  {} {} {} {} -> {}",
+            bullet,
+            change.path,
+            direction,
+            format_bytes(change.previous_bytes, units()),
+            format_bytes(change.bytes, units())
+        );
+    }
+    for project in &comparison.gone {
+        println!("  {} {} no longer present", bullet, project.path);
+    }
+    if comparison.previous_dry_run {
+        println!(
+            "{} The previous run was a dry run, so its sizes were never freed",
+            "[INFO]".blue().bold()
+        );
+    }
+}
+
+/// Print the `--timings` breakdown, and the slowest projects when verbose
+pub fn print_timings(timings: &Timings, verbose: bool) {
+    let phases: Vec<String> = timings
+        .phases
+        .iter()
+        .map(|p| format!("{} {}", p.phase, format_millis(p.millis)))
+        .collect();
+    println!("{} Timings: {}", "[INFO]".blue().bold(), phases.join(", "));
+    println!(
+        "{} {} cargo metadata call(s) ({} answered from cache), {} directories walked",
+        "[INFO]".blue().bold(),
+        timings.cargo_metadata_calls,
+        timings.cargo_metadata_cache_hits,
+        timings.dirs_walked
+    );
+    if verbose {
+        print_slowest("sizing", &timings.slowest_sizing);
+        print_slowest("cleaning", &timings.slowest_cleaning);
+    }
+}
+
+fn print_slowest(what: &str, projects: &[ProjectTiming]) {
+    if projects.is_empty() {
+        return;
+    }
+    println!("{} Slowest {}:", "[INFO]".blue().bold(), what);
+    for project in projects {
+        println!("  {} {} ({})", glyphs().bullet.blue(), project.path, format_millis(project.millis));
+    }
+}
+
+/// Milliseconds as seconds with two decimals, e.g. "1.25s"
+fn format_millis(millis: u64) -> String {
+    format!("{:.2}s", millis as f64 / 1000.0)
+}
+
 /// Print verbose output for a cleaned project
 pub fn print_verbose_cleaned(result: &CleanResult) {
-    if result.freed_bytes > 0 {
+    let strategy = result.strategy.map(|s| s.to_string()).unwrap_or_default();
+    if let Some(ref reason) = result.skip_reason {
+        println!(
+            "{} Skipped: {} ({})",
+            "[INFO]".blue().bold(),
+            result.path,
+            reason
+        );
+    } else if let (Some(ref archive), Some(archive_bytes)) = (&result.archive_path, result.archive_bytes) {
+        println!(
+            "{} Archived: {} (freed: {}, archive: {} at {})",
+            "[SUCCESS]".green().bold(),
+            result.path,
+            format_bytes(result.freed_bytes, units()),
+            format_bytes(archive_bytes, units()),
+            archive
+        );
+    } else if let Some(swept) = result.swept_files {
+        println!(
+            "{} Swept: {} ({} stale file(s), freed: {})",
+            "[SUCCESS]".green().bold(),
+            result.path,
+            swept,
+            format_bytes(result.freed_bytes, units())
+        );
+    } else if let Some(ref stale) = result.stale_artifact_sets {
         println!(
-            "{} Cleaned: {} (freed: {})",
+            "{} Stale toolchain artifacts: {} ({} set(s), freed: {})",
             "[SUCCESS]".green().bold(),
             result.path,
-            format_bytes(result.freed_bytes)
+            stale.len(),
+            format_bytes(result.freed_bytes, units())
+        );
+    } else if let Some(ref deduped) = result.deduped {
+        println!(
+            "{} Deduplicated: {} ({} crate(s), {} superseded set(s), freed: {})",
+            "[SUCCESS]".green().bold(),
+            result.path,
+            deduped.len(),
+            deduped.iter().map(|g| g.removed.len()).sum::<usize>(),
+            format_bytes(result.freed_bytes, units())
+        );
+    } else if let Some(ref vacuumed) = result.vacuumed {
+        println!(
+            "{} Vacuumed: {} ({} orphaned package(s), freed: {})",
+            "[SUCCESS]".green().bold(),
+            result.path,
+            vacuumed.len(),
+            format_bytes(result.freed_bytes, units())
+        );
+    } else if let Some(ref packages) = result.cleaned_packages {
+        println!(
+            "{} Cleaned packages: {} ({}, freed: {})",
+            "[SUCCESS]".green().bold(),
+            result.path,
+            packages.join(", "),
+            format_bytes(result.freed_bytes, units())
+        );
+    } else if let Some(ref removed) = result.removed_bin_artifacts {
+        println!(
+            "{} Removed binaries: {} ({} file(s), freed: {})",
+            "[SUCCESS]".green().bold(),
+            result.path,
+            removed.len(),
+            format_bytes(result.freed_bytes, units())
+        );
+    } else if let Some(ref removed) = result.removed_subdirs {
+        println!(
+            "{} Partially cleaned: {} ({} subdirectory(ies), freed: {}{})",
+            "[SUCCESS]".green().bold(),
+            result.path,
+            removed.len(),
+            format_bytes(result.freed_bytes, units()),
+            removal_rate(result)
+        );
+    } else if result.freed_bytes > 0 {
+        let files = match result.freed_files {
+            Some(files) => format!(" across {} file(s)", format_count(files)),
+            None => String::new(),
+        };
+        println!(
+            "{} Cleaned: {} (freed: {}{}, via {}{})",
+            "[SUCCESS]".green().bold(),
+            result.path,
+            format_freed(result.freed_bytes, result.estimate, glyphs()),
+            files,
+            strategy,
+            removal_rate(result)
         );
     } else {
         println!(
-            "{} Cleaned: {} (already clean)",
+            "{} Cleaned: {} (already clean, via {})",
             "[SUCCESS]".green().bold(),
+            result.path,
+            strategy
+        );
+    }
+    if let Some(ref extra) = result.extra_freed_bytes {
+        let dirs: Vec<String> = extra
+            .iter()
+            .map(|(name, bytes)| format!("{}/ ({})", name, format_bytes(*bytes, units())))
+            .collect();
+        println!("  {} including {}", glyphs().bullet.yellow(), dirs.join(", "));
+    }
+    if let Some(pruned) = result.pruned_dirs {
+        println!("  {} pruned {} empty director(ies)", glyphs().bullet.yellow(), pruned);
+    }
+    if let (Some(files), Some(bytes)) = (result.debris_files, result.debris_freed_bytes) {
+        println!("  {} including {} debris file(s) ({})", glyphs().bullet.yellow(), files, format_bytes(bytes, units()));
+    }
+}
+
+/// Bytes freed, or for an estimate its range, e.g. `≈ 12.40–15.10 GB`
+pub fn format_freed(bytes: u64, estimate: Option<SizeEstimate>, glyphs: &Glyphs) -> String {
+    let Some(estimate) = estimate else {
+        return format_bytes(bytes, units());
+    };
+    let (low, high) = (format_bytes(estimate.low, units()), format_bytes(estimate.high, units()));
+    if low == high {
+        return format!("{} {}", glyphs.approx, low);
+    }
+    match (low.split_once(' '), high.split_once(' ')) {
+        (Some((low, low_unit)), Some((high, high_unit))) if low_unit == high_unit => {
+            format!("{} {}{}{} {}", glyphs.approx, low, glyphs.range, high, high_unit)
+        }
+        _ => format!("{} {}{}{}", glyphs.approx, low, glyphs.range, high),
+    }
+}
+
+/// Deletion throughput of a direct removal, e.g. `, 1.20 GB/s`
+///
+/// Empty when nothing was timed or the removal was too quick to measure.
+fn removal_rate(result: &CleanResult) -> String {
+    match result.removal_millis {
+        Some(millis) if millis > 0 && result.freed_bytes > 0 => {
+            format!(", {}/s", format_bytes(result.freed_bytes.saturating_mul(1000) / millis, units()))
+        }
+        _ => String::new(),
+    }
+}
+
+/// Print exactly what a dry run of a partial clean would remove
+pub fn print_dry_run_plan(result: &CleanResult) {
+    if let Some(ref deduped) = result.deduped {
+        if !deduped.is_empty() {
+            println!(
+                "{} Would remove superseded artifacts of {} crate(s) in {}:",
+                "[INFO]".blue().bold(),
+                deduped.len(),
+                result.path
+            );
+        }
+        for group in deduped {
+            println!(
+                "  {} {} ({}): keep {}, remove {}",
+                glyphs().bullet.yellow(),
+                group.name,
+                group.profile,
+                group.kept,
+                group.removed.join(", ")
+            );
+        }
+    }
+
+    if let Some(ref vacuumed) = result.vacuumed {
+        if !vacuumed.is_empty() {
+            println!(
+                "{} Would remove artifacts of {} package(s) missing from Cargo.lock in {}:",
+                "[INFO]".blue().bold(),
+                vacuumed.len(),
+                result.path
+            );
+        }
+        for package in vacuumed {
+            println!(
+                "  {} {} ({} set(s), {})",
+                glyphs().bullet.yellow(),
+                package.name,
+                package.sets,
+                format_bytes(package.bytes, units())
+            );
+        }
+    }
+
+    let plans = [
+        ("stale artifact set(s)", &result.stale_artifact_sets),
+        ("target subdirectory(ies)", &result.removed_subdirs),
+        ("binary artifact(s)", &result.removed_bin_artifacts),
+        ("package(s)", &result.cleaned_packages),
+    ];
+    for (what, items) in plans {
+        let Some(items) = items else {
+            continue;
+        };
+        if items.is_empty() {
+            continue;
+        }
+        println!(
+            "{} Would remove {} {} in {}:",
+            "[INFO]".blue().bold(),
+            items.len(),
+            what,
             result.path
         );
+        for item in items {
+            println!("  {} {}", glyphs().bullet.yellow(), item);
+        }
     }
 }
 
 /// Print error message
 pub fn print_error(project_path: &std::path::Path, error_msg: &str) {
-    println!(
+    println!("{}", error_message(project_path, error_msg));
+}
+
+/// The line `print_error` prints, for `--errors-only` to send to stderr
+pub fn error_message(project_path: &std::path::Path, error_msg: &str) -> String {
+    format!(
         "{} Failed to clean: {:?} - {}",
         "[ERROR]".red().bold(),
         project_path,
         error_msg
+    )
+}
+
+/// What a removal that failed partway still managed, e.g.
+/// `partially cleaned (freed 20.10 GB, 3.20 GB could not be removed)`
+pub fn partial_clean_message(result: &CleanResult) -> String {
+    format!(
+        "partially cleaned (freed {}, {} could not be removed)",
+        format_bytes(result.freed_bytes, units()),
+        format_bytes(result.remaining_bytes.unwrap_or(0), units())
+    )
+}
+
+/// Print a failed result, along with what was freed before the failure
+pub fn print_failed(result: &CleanResult) {
+    println!("{}", failure_message(result));
+}
+
+/// The line `print_failed` prints
+pub fn failure_message(result: &CleanResult) -> String {
+    let error = result.error.as_deref().unwrap_or_default();
+    if result.remaining_bytes.is_some() {
+        error_message(Path::new(&result.path), &format!("{} - {}", partial_clean_message(result), error))
+    } else {
+        error_message(Path::new(&result.path), error)
+    }
+}
+
+/// Print a non-fatal warning for a project
+pub fn print_warning(project_path: &std::path::Path, message: &str) {
+    println!(
+        "{} {:?} - {}",
+        "[WARNING]".yellow().bold(),
+        project_path,
+        message
     );
 }
 
+/// Print the outcome of dependency checking for a single project
+pub fn print_dependency_result(
+    project_path: &std::path::Path,
+    result: &DependencyCleanResult,
+    remove: bool,
+    dry_run: bool,
+    verbose: bool,
+) {
+    if !result.unused_deps.is_empty() {
+        // Always show unused dependencies, not just in verbose mode
+        println!(
+            "{} Found {} unused dependency(ies) in {}:",
+            "[INFO]".blue().bold(),
+            result.unused_deps.len(),
+            project_path.display()
+        );
+        for dep in &result.unused_deps {
+            println!("  {} {} ({})", glyphs().bullet.yellow(), dep.name.bright_yellow(), dep.location);
+        }
+        if result.removed_count > 0 {
+            println!(
+                "{} Removed {} unused dependency(ies)",
+                "[SUCCESS]".green().bold(),
+                result.removed_count
+            );
+        } else if remove && !dry_run {
+            if result.error.is_none() {
+                println!(
+                    "{} Could not remove dependencies (install cargo-remove: cargo install cargo-edit)",
+                    "[WARNING]".yellow().bold()
+                );
+            }
+        } else if dry_run {
+            println!(
+                "{} Would remove {} dependency(ies) (use --remove-deps to actually remove)",
+                "[INFO]".blue().bold(),
+                result.unused_deps.len()
+            );
+        }
+    } else if verbose {
+        // Show confirmation that check was performed (only in verbose mode to avoid clutter)
+        println!(
+            "{} No unused dependencies found in {}",
+            "[INFO]".blue().bold(),
+            project_path.display()
+        );
+    }
+
+    for collision in &result.name_collisions {
+        println!(
+            "{} {} are all referred to as `{}` in code, so their usage can't be told apart",
+            "[WARNING]".yellow().bold(),
+            collision.names.join(" and "),
+            collision.identifier
+        );
+    }
+
+    if !result.duplicates.is_empty() {
+        println!(
+            "{} Found {} crate(s) at more than one version in {}:",
+            "[INFO]".blue().bold(),
+            result.duplicates.len(),
+            project_path.display()
+        );
+        for duplicate in &result.duplicates {
+            println!("  {} {}", glyphs().bullet.yellow(), duplicate.name.bright_yellow());
+            for version in &duplicate.versions {
+                println!("      {}", version.version);
+                for path in &version.paths {
+                    println!("        {}", path.dimmed());
+                }
+            }
+        }
+    }
+
+    // Check if there was an error even when no unused deps were found
+    // (e.g., cargo-remove not available when --remove-deps was specified)
+    if let Some(ref error) = result.error {
+        println!(
+            "{} Failed to remove dependencies in {:?}: {}",
+            "[ERROR]".red().bold(),
+            project_path,
+            error
+        );
+    }
+}
+
+/// Print a summary of dependency checking across all projects
+pub fn print_dependency_summary(results: &[DependencyCleanResult]) {
+    let unused: usize = results.iter().map(|r| r.unused_deps.len()).sum();
+    let removed: usize = results.iter().map(|r| r.removed_count).sum();
+    let affected = results.iter().filter(|r| !r.unused_deps.is_empty()).count();
+    let errors = results.iter().filter(|r| r.error.is_some()).count();
+
+    println!(
+        "{} Unused dependencies: {} across {} project(s) ({} checked)",
+        "[INFO]".blue().bold(),
+        unused,
+        affected,
+        results.len()
+    );
+    if removed > 0 {
+        println!(
+            "{} Removed dependencies: {}",
+            "[SUCCESS]".green().bold(),
+            removed
+        );
+    }
+    let duplicated = results.iter().filter(|r| !r.duplicates.is_empty()).count();
+    if duplicated > 0 {
+        println!(
+            "{} Crates at more than one version: {} across {} project(s)",
+            "[INFO]".blue().bold(),
+            results.iter().map(|r| r.duplicates.len()).sum::<usize>(),
+            duplicated
+        );
+    }
+    if errors > 0 {
+        println!(
+            "{} Dependency checks with errors: {} project(s)",
+            "[ERROR]".red().bold(),
+            errors
+        );
+    }
+}
+
+/// Print the flat size report, largest targets first
+pub fn print_size_list(sizes: &[ProjectSize]) {
+    println!();
+    let now = SystemTime::now();
+    for size in sizes {
+        let built = size.last_build.map_or_else(|| "-".to_string(), |time| format_ago(time, now));
+        println!("{:>12}  {:<16}  {}", format_bytes(size.target_bytes, units()), built, size.path.display());
+    }
+    let total: u64 = sizes.iter().map(|s| s.target_bytes).sum();
+    println!();
+    println!(
+        "{} Total target size: {} across {} project(s)",
+        "[INFO]".blue().bold(),
+        format_bytes(total, units()),
+        sizes.len()
+    );
+}
+
+/// Print the CARGO_HOME cache breakdown and what was (or would be) removed
+/// Print what `--hardlink-dedup` linked, or would link in a dry run
+pub fn print_hardlink_report(report: &HardlinkReport) {
+    let (verb, saved) = if report.dry_run { ("Would link", "would save") } else { ("Linked", "saved") };
+    println!(
+        "{} {} {} duplicate file(s) across {} target(s), {} {} ({} file(s) checked)",
+        "[SUCCESS]".green().bold(),
+        verb,
+        report.linked_files,
+        report.targets,
+        saved,
+        format_bytes(report.bytes_saved, units()),
+        report.files_scanned
+    );
+    if report.cross_device_skipped > 0 {
+        println!(
+            "{} Left {} identical file(s) separate because they're on different filesystems",
+            "[INFO]".blue().bold(),
+            report.cross_device_skipped
+        );
+    }
+    if !report.failures.is_empty() {
+        println!("{} Failed to link {} file(s):", "[WARNING]".yellow().bold(), report.failures.len());
+        for failure in &report.failures {
+            println!("  {} {} ({})", glyphs().bullet.yellow(), failure.path, failure.error);
+        }
+    }
+}
+
+pub fn print_cache_report(report: &CacheReport) {
+    println!("{} Cargo home: {}", "[INFO]".blue().bold(), report.cargo_home.display());
+    println!();
+    for entry in &report.entries {
+        let status = match (entry.removed, report.dry_run) {
+            (true, true) => "would remove".yellow(),
+            (true, false) => "removed".green(),
+            (false, _) => "kept".normal(),
+        };
+        println!("{:>12}  {:<16} {}", format_bytes(entry.bytes, units()), entry.name, status);
+    }
+    if let Some(ref sccache) = report.sccache {
+        let status = if sccache.bytes_after == sccache.bytes_before {
+            "kept".normal()
+        } else if report.dry_run {
+            format!("would shrink to {}", format_bytes(sccache.bytes_after, units())).yellow()
+        } else {
+            format!("shrunk to {}", format_bytes(sccache.bytes_after, units())).green()
+        };
+        println!("{:>12}  {:<16} {}", format_bytes(sccache.bytes_before, units()), "sccache", status);
+    }
+    if !report.pruned.is_empty() {
+        println!();
+        let verb = if report.dry_run { "Would prune" } else { "Pruned" };
+        for krate in &report.pruned {
+            println!(
+                "{} {} {} old version(s) of {} ({}): {}",
+                "[INFO]".blue().bold(),
+                verb,
+                krate.versions.len(),
+                krate.name,
+                format_bytes(krate.freed_bytes, units()),
+                krate.versions.join(", ")
+            );
+            if report.dry_run {
+                for file in &krate.files {
+                    println!("  {} {}", glyphs().bullet.yellow(), file.display());
+                }
+            }
+        }
+    }
+    println!();
+    println!(
+        "{} Total cache size: {}",
+        "[INFO]".blue().bold(),
+        format_bytes(report.total_bytes, units())
+    );
+    if report.freed_bytes > 0 {
+        let verb = if report.dry_run { "Would free" } else { "Total storage freed" };
+        println!("{} {}: {}", "[SUCCESS]".green().bold(), verb, format_bytes(report.freed_bytes, units()));
+    }
+}
+
+/// Print the largest compiled dependencies per project and across all projects
+pub fn print_dep_sizes(projects: &[ProjectDepSizes], total: &[DepSize]) {
+    let print_deps = |deps: &[DepSize]| {
+        for dep in deps {
+            println!("{:>12}  {}", format_bytes(dep.bytes, units()), dep.name);
+        }
+    };
+
+    for project in projects.iter().filter(|p| !p.deps.is_empty()) {
+        println!();
+        println!("{} {}", "[INFO]".blue().bold(), project.path.display());
+        print_deps(&project.deps);
+    }
+    println!();
+    println!("{} Largest dependencies across {} project(s):", "[INFO]".blue().bold(), projects.len());
+    print_deps(total);
+}
+
+/// Print installed toolchains, largest first, with their components
+pub fn print_toolchain_report(toolchains: &[ToolchainUsage]) {
+    println!();
+    for toolchain in toolchains {
+        println!("{:>12}  {}", format_bytes(toolchain.bytes, units()), toolchain.name);
+        if !toolchain.components.is_empty() {
+            println!("{:>12}  {}", "", toolchain.components.join(", ").dimmed());
+        }
+    }
+    let total: u64 = toolchains.iter().map(|t| t.bytes).sum();
+    println!();
+    println!(
+        "{} Total toolchain size: {} across {} toolchain(s)",
+        "[INFO]".blue().bold(),
+        format_bytes(total, units()),
+        toolchains.len()
+    );
+}
+
+/// Render what a run over the `--confirm-over` limit would delete, largest first
+pub fn render_clean_plan(plan: &[(&Path, u64)], threshold: u64, glyphs: &Glyphs) -> String {
+    let total: u64 = plan.iter().map(|(_, bytes)| bytes).sum();
+    let mut plan = plan.to_vec();
+    plan.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    let mut out = format!(
+        "{} {} would be freed, more than the --confirm-over limit of {}:\n",
+        "[WARNING]".yellow().bold(),
+        format_bytes(total, units()),
+        format_bytes(threshold, units())
+    );
+    for (path, bytes) in plan {
+        out.push_str(&format!("  {} {}: {}\n", glyphs.bullet.yellow(), path.display(), format_bytes(bytes, units())));
+    }
+    out
+}
+
+/// Render the unused dependencies `--remove-deps` is about to remove, by project
+pub fn render_removal_plan(plan: &[(&Path, Vec<UnusedDependency>)], glyphs: &Glyphs) -> String {
+    let mut out = format!(
+        "{} These dependencies look unused and would be removed with cargo remove. \
+         Detection is heuristic, so check for any that are still needed:\n",
+        "[WARNING]".yellow().bold()
+    );
+    for (path, unused) in plan {
+        out.push_str(&format!("  {}\n", path.display()));
+        for dep in unused {
+            out.push_str(&format!("    {} {} ({})\n", glyphs.bullet.yellow(), dep.name, dep.location));
+        }
+    }
+    out
+}
+
+/// What happened to a project, as the summary tables show it, e.g. "skipped: in use"
+pub fn result_status(result: &CleanResult) -> String {
+    if let Some(ref reason) = result.skip_reason {
+        format!("skipped: {}", reason)
+    } else if let Some(ref error) = result.error {
+        format!("failed: {}", error)
+    } else {
+        "cleaned".to_string()
+    }
+}
+
+/// Render the clean summary as a GitHub-flavored Markdown table
+///
+/// Project paths are shown relative to `root` when they are below it.
+pub fn render_markdown_summary(summary: &Summary, root: &std::path::Path) -> String {
+    let escape = |text: &str| text.replace('|', "\\|");
+    let freed = if summary.dry_run { "Would free" } else { "Freed" };
+    let mut out = format!("| Project | {} | Status |\n|---|---:|---|\n", freed);
+
+    for result in &summary.results {
+        let path = std::path::Path::new(&result.path);
+        let project = match path.strip_prefix(root) {
+            Ok(rel) if rel.as_os_str().is_empty() => ".".to_string(),
+            Ok(rel) => rel.display().to_string(),
+            Err(_) => result.path.clone(),
+        };
+        out.push_str(&format!(
+            "| {} | {} | {} |\n",
+            escape(&project),
+            format_bytes(result.freed_bytes, units()),
+            escape(&result_status(result))
+        ));
+    }
+
+    out.push_str(&format!(
+        "\n**Total {}: {} across {} project(s)** ({} cleaned, {} failed, {} skipped)\n",
+        freed.to_lowercase(),
+        format_bytes(summary.total_freed_bytes, units()),
+        summary.total_projects,
+        summary.cleaned,
+        summary.failed,
+        summary.skipped_count
+    ));
+    out
+}
+
+/// Render the summary in the Prometheus text exposition format, for
+/// node_exporter's textfile collector
+///
+/// Every metric is a gauge describing the last run, since the file is
+/// rewritten by each run rather than accumulated.
+pub fn render_prometheus_metrics(summary: &Summary, finished: SystemTime) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, help: &str, samples: &[(Option<&Path>, u64)]| {
+        out.push_str(&format!("# HELP {} {}\n# TYPE {} gauge\n", name, escape_help(help), name));
+        for (mount_point, value) in samples {
+            match mount_point {
+                Some(mount_point) => out.push_str(&format!(
+                    "{}{{mountpoint=\"{}\"}} {}\n",
+                    name,
+                    escape_label_value(&mount_point.to_string_lossy()),
+                    value
+                )),
+                None => out.push_str(&format!("{} {}\n", name, value)),
+            }
+        }
+    };
+    let finished = finished.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    metric("rclean_last_run_timestamp_seconds", "Unix time the last run finished.", &[(None, finished)]);
+    metric("rclean_dry_run", "Whether the last run was a dry run.", &[(None, summary.dry_run as u64)]);
+    metric(
+        "rclean_freed_bytes",
+        "Bytes freed by the last run, or that it would have freed if it was a dry run.",
+        &[(None, summary.total_freed_bytes)],
+    );
+    for (name, help, count) in [
+        ("rclean_projects_found", "Projects the last run found.", summary.total_projects),
+        ("rclean_projects_cleaned", "Projects the last run cleaned.", summary.cleaned),
+        ("rclean_projects_failed", "Projects the last run failed to clean.", summary.failed),
+        ("rclean_projects_skipped", "Projects the last run left alone.", summary.skipped_count),
+    ] {
+        metric(name, help, &[(None, count as u64)]);
+    }
+    if !summary.filesystems.is_empty() {
+        let samples = |value: fn(&FilesystemSpace) -> u64| -> Vec<(Option<&Path>, u64)> {
+            summary.filesystems.iter().map(|fs| (Some(fs.mount_point.as_path()), value(fs))).collect()
+        };
+        metric(
+            "rclean_filesystem_freed_bytes",
+            "Bytes the last run freed on each filesystem with projects.",
+            &samples(|fs| fs.freed_bytes),
+        );
+        metric(
+            "rclean_filesystem_free_bytes",
+            "Free space on each filesystem with projects after the last run.",
+            &samples(|fs| fs.free_bytes_after),
+        );
+    }
+    out
+}
+
+/// Escape a label value: backslash, double quote and line feed
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Escape `# HELP` text: backslash and line feed
+fn escape_help(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glyphs::{ASCII, UNICODE};
+    use crate::project::Project;
+    use std::path::{Path, PathBuf};
+
+    fn project(path: &str) -> Project {
+        Project::new(PathBuf::from(path), false)
+    }
+
+    #[test]
+    fn test_estimate_remaining() {
+        let secs = Duration::from_secs;
+        assert_eq!(estimate_remaining(0, 1000, secs(5)), None);
+        assert_eq!(estimate_remaining(250, 1000, secs(10)), Some(secs(30)));
+        assert_eq!(estimate_remaining(1000, 1000, secs(10)), Some(secs(0)));
+        assert_eq!(format_remaining(Some(secs(3 * 3600 + 125))), "03:02:05");
+        assert_eq!(format_remaining(None), "--:--:--");
+    }
+
+    #[test]
+    fn test_partial_clean_message() {
+        let result = CleanResult {
+            freed_bytes: 20 * 1024 * 1024 * 1024,
+            remaining_bytes: Some(3 * 1024 * 1024 * 1024),
+            ..CleanResult::failed(&project("/src/app"), "Permission denied".to_string())
+        };
+        assert_eq!(
+            partial_clean_message(&result),
+            "partially cleaned (freed 20.00 GiB, 3.00 GiB could not be removed)"
+        );
+    }
+
+    #[test]
+    fn test_prometheus_metrics() {
+        let filesystem = |mount: &str, freed_bytes: u64| FilesystemSpace {
+            mount_point: std::path::PathBuf::from(mount),
+            projects: 1,
+            free_bytes_before: 1000,
+            free_bytes_after: 1000 + freed_bytes,
+            total_bytes: 10_000,
+            freed_bytes,
+            freed_pct_of_volume: None,
+            fs_delta_bytes: None,
+        };
+        let summary = Summary {
+            total_projects: 4,
+            cleaned: 2,
+            failed: 1,
+            skipped_count: 1,
+            total_freed_bytes: 3072,
+            filesystems: vec![filesystem("/home", 2048), filesystem("/mnt/my \"disk\"\\", 1024)],
+            ..Default::default()
+        };
+        let finished = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let metrics = render_prometheus_metrics(&summary, finished);
+
+        let lines: Vec<&str> = metrics.lines().collect();
+        for sample in [
+            "rclean_last_run_timestamp_seconds 1700000000",
+            "rclean_dry_run 0",
+            "rclean_freed_bytes 3072",
+            "rclean_projects_found 4",
+            "rclean_projects_cleaned 2",
+            "rclean_projects_failed 1",
+            "rclean_projects_skipped 1",
+            "rclean_filesystem_freed_bytes{mountpoint=\"/home\"} 2048",
+            "rclean_filesystem_free_bytes{mountpoint=\"/mnt/my \\\"disk\\\"\\\\\"} 2024",
+        ] {
+            assert!(lines.contains(&sample), "missing {:?} in\n{}", sample, metrics);
+        }
+        // Each metric has its HELP and TYPE lines, once, right before its samples
+        for (i, line) in lines.iter().enumerate().filter(|(_, l)| !l.starts_with('#')) {
+            let name = line.split(['{', ' ']).next().unwrap();
+            let header = lines[..i].iter().rposition(|l| l.starts_with("# HELP")).unwrap();
+            assert!(lines[header].starts_with(&format!("# HELP {} ", name)), "{}", line);
+            assert_eq!(lines[header + 1], format!("# TYPE {} gauge", name));
+        }
+        assert_eq!(metrics.matches("# TYPE rclean_filesystem_free_bytes ").count(), 1);
+        assert!(metrics.ends_with('\n'));
+
+        // Without filesystems there are no per-filesystem metrics at all
+        let metrics = render_prometheus_metrics(&Summary { dry_run: true, ..Default::default() }, finished);
+        assert!(metrics.contains("rclean_dry_run 1\n"));
+        assert!(!metrics.contains("rclean_filesystem"));
+    }
+
+    #[test]
+    fn test_estimates_are_marked() {
+        let gib = 1024 * 1024 * 1024;
+        let estimate = SizeEstimate {
+            bytes: 13 * gib,
+            estimated: true,
+            low: 12 * gib,
+            high: 15 * gib,
+        };
+        assert_eq!(format_freed(13 * gib, None, &UNICODE), "13.00 GiB");
+        assert_eq!(format_freed(13 * gib, Some(estimate), &UNICODE), "≈ 12.00–15.00 GiB");
+        assert_eq!(format_freed(900, Some(SizeEstimate { low: 900, high: 2048, ..estimate }), &UNICODE), "≈ 900 B–2.00 KiB");
+        assert_eq!(format_freed(900, Some(SizeEstimate::exact(900)), &UNICODE), "≈ 900 B");
+        assert_eq!(format_freed(13 * gib, Some(estimate), &ASCII), "~ 12.00-15.00 GiB");
+
+        let mut result = CleanResult::new(&project("/src/app"), 13 * gib);
+        result.estimate = Some(estimate);
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["freed_bytes"], 13 * gib);
+        assert_eq!(json["estimated"], true);
+        assert_eq!((json["low"].as_u64(), json["high"].as_u64()), (Some(12 * gib), Some(15 * gib)));
+
+        // Measured sizes carry no estimate fields at all
+        let json = serde_json::to_value(Summary::default()).unwrap();
+        assert!(json.get("estimated").is_none() && json.get("low").is_none());
+    }
+
+    #[test]
+    fn test_empty_summary_is_well_formed() {
+        let json = serde_json::to_value(Summary::default()).unwrap();
+        assert_eq!(json["total_projects"], 0);
+        assert_eq!(json["total_freed_bytes"], 0);
+        assert_eq!(json["dry_run"], false);
+        assert!(json.get("would_free_bytes").is_none());
+        assert_eq!(json["budget_exhausted"], false);
+        assert!(json.get("until_free_met").is_none());
+        assert_eq!(json["results"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_summary_json_has_fs_delta() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut filesystems = crate::disk::free_space_before(&[temp_dir.path()]);
+        crate::disk::record_free_space_after(&mut filesystems, false);
+        let summary = Summary {
+            fs_delta_bytes: filesystems[0].fs_delta_bytes,
+            filesystems,
+            ..Default::default()
+        };
+
+        let json = serde_json::to_value(&summary).unwrap();
+        assert!(json["fs_delta_bytes"].is_i64());
+        assert!(json["filesystems"][0]["fs_delta_bytes"].is_i64());
+        assert!(json["total_freed_bytes"].is_u64());
+        // The note only appears when the two disagree
+        assert!(json.get("fs_delta_note").is_none());
+    }
+
+    #[test]
+    fn test_freed_space_as_share_of_volume() {
+        let gib = 1024 * 1024 * 1024;
+        let filesystem = |mount: &str, freed_bytes: u64, total_bytes: u64| FilesystemSpace {
+            mount_point: std::path::PathBuf::from(mount),
+            projects: 1,
+            free_bytes_before: 0,
+            free_bytes_after: freed_bytes,
+            total_bytes,
+            freed_bytes,
+            freed_pct_of_volume: crate::disk::percent_of(freed_bytes, total_bytes),
+            fs_delta_bytes: None,
+        };
+        let mut summary = Summary {
+            total_freed_bytes: 8 * gib,
+            freed_pct_of_volume: Some(8.0),
+            filesystems: vec![filesystem("/home", 8 * gib, 100 * gib)],
+            ..Default::default()
+        };
+        assert_eq!(volume_share(&summary), " (8% of /home)");
+        // A single volume is already covered by the total
+        assert_eq!(filesystem_share(&summary, &summary.filesystems[0]), "");
+        assert_eq!(serde_json::to_value(&summary).unwrap()["freed_pct_of_volume"], 8.0);
+
+        summary.freed_pct_of_volume = Some(0.5);
+        summary.filesystems = vec![filesystem("/home", gib, 100 * gib), filesystem("/mnt/data", 0, 100 * gib)];
+        assert_eq!(volume_share(&summary), " (0.5% of 2 volumes)");
+        assert_eq!(filesystem_share(&summary, &summary.filesystems[0]), " (freed 1.00 GiB, 1% of the volume)");
+        assert_eq!(filesystem_share(&summary, &summary.filesystems[1]), " (freed 0 B, 0% of the volume)");
+
+        summary.freed_pct_of_volume = None;
+        assert_eq!(volume_share(&summary), "");
+    }
+
+    #[test]
+    fn test_render_clean_plan() {
+        let gib = 1024 * 1024 * 1024;
+        let plan = [(Path::new("/src/small"), gib), (Path::new("/src/big"), 200 * gib), (Path::new("/src/mid"), 99 * gib)];
+        let rendered = render_clean_plan(&plan, 50 * gib, &UNICODE);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].ends_with("300.00 GiB would be freed, more than the --confirm-over limit of 50.00 GiB:"));
+        assert!(lines[1].ends_with("/src/big: 200.00 GiB"));
+        assert!(lines[2].ends_with("/src/mid: 99.00 GiB"));
+        assert!(lines[3].ends_with("/src/small: 1.00 GiB"));
+        assert!(render_clean_plan(&plan, 50 * gib, &ASCII).is_ascii());
+    }
+
+    #[test]
+    fn test_skipped_projects_by_reason() {
+        let results = vec![
+            CleanResult::skipped(&project("/src/a"), SkipReason::UncommittedChanges),
+            CleanResult::new(&project("/src/b"), 2048),
+            CleanResult::skipped(&project("/src/c"), SkipReason::InUse),
+            CleanResult::failed(&project("/src/d"), "locked".to_string()),
+            CleanResult::skipped(&project("/src/e"), SkipReason::InUse),
+        ];
+        let skipped = skipped_projects(&results);
+        let paths: Vec<&str> = skipped.iter().map(|p| p.path.as_str()).collect();
+        assert_eq!(paths, vec!["/src/a", "/src/c", "/src/e"]);
+        assert_eq!(skip_breakdown(&skipped), "2 in use, 1 uncommitted changes");
+
+        let summary = Summary {
+            skipped_count: skipped.len(),
+            skipped,
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["skipped_count"], 3);
+        assert_eq!(json["skipped"][0], serde_json::json!({"path": "/src/a", "reason": "uncommitted changes"}));
+        assert_eq!(json["skipped"][2]["reason"], "in use");
+    }
+
+    #[test]
+    fn test_render_markdown_summary() {
+        let results = vec![
+            CleanResult::new(&project("/src/app"), 2048),
+            CleanResult::failed(&project("/src/a|b"), "locked".to_string()),
+            CleanResult::skipped(&project("/elsewhere/lib"), SkipReason::PreHookRejected),
+        ];
+        let summary = Summary {
+            dry_run: false,
+            total_projects: 3,
+            cleaned: 1,
+            failed: 1,
+            skipped_count: 1,
+            skipped: skipped_projects(&results),
+            build_failed: 0,
+            aborted: 0,
+            budget_exhausted: false,
+            until_free_bytes: None,
+            until_free_met: None,
+            total_freed_bytes: 2048,
+            total_freed_files: 0,
+            estimate: None,
+            would_free_bytes: None,
+            freed_pct_of_volume: None,
+            free_bytes_before: 0,
+            free_bytes_after: 0,
+            fs_delta_bytes: None,
+            fs_delta_note: None,
+            filesystems: Vec::new(),
+            results,
+            dependency_results: Vec::new(),
+            invalid_paths: Vec::new(),
+            drives: Vec::new(),
+            timings: None,
+            comparison: None,
+        };
+
+        assert_eq!(
+            render_markdown_summary(&summary, Path::new("/src")),
+            "| Project | Freed | Status |\n\
+             |---|---:|---|\n\
+             | app | 2.00 KiB | cleaned |\n\
+             | a\\|b | 0 B | failed: locked |\n\
+             | /elsewhere/lib | 0 B | skipped: pre-hook rejected |\n\
+             \n\
+             **Total freed: 2.00 KiB across 3 project(s)** (1 cleaned, 1 failed, 1 skipped)\n"
+        );
+
+        let summary = Summary {
+            dry_run: true,
+            would_free_bytes: Some(2048),
+            ..summary
+        };
+        let markdown = render_markdown_summary(&summary, Path::new("/src"));
+        assert!(markdown.starts_with("| Project | Would free | Status |"));
+        assert!(markdown.contains("**Total would free: 2.00 KiB across 3 project(s)**"));
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["would_free_bytes"], 2048);
+    }
+}
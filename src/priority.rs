@@ -0,0 +1,153 @@
+use std::io;
+
+/// Niceness `--nice` runs at, the lowest CPU priority there is
+pub const NICE_LEVEL: i32 = 19;
+
+/// The calls that lower this process's scheduling priority
+///
+/// Both only affect threads and processes started afterwards besides the
+/// calling thread (on Linux priorities are per thread), so they must be made
+/// before any worker threads exist. Spawned `cargo clean` processes then
+/// inherit them.
+pub trait Scheduler {
+    /// Set the CPU niceness
+    fn set_niceness(&self, niceness: i32) -> io::Result<()>;
+    /// Only give this process disk time nothing else wants
+    ///
+    /// Fails with `Unsupported` where the OS has no such notion.
+    fn set_idle_io(&self) -> io::Result<()>;
+}
+
+/// The real OS calls
+pub struct System;
+
+#[cfg(unix)]
+impl Scheduler for System {
+    fn set_niceness(&self, niceness: i32) -> io::Result<()> {
+        // SAFETY: setpriority has no memory-safety preconditions
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, niceness) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_idle_io(&self) -> io::Result<()> {
+        const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+        const IOPRIO_CLASS_IDLE: libc::c_long = 3;
+        const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+        // SAFETY: ioprio_set only takes integers
+        let ret = unsafe {
+            libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT)
+        };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn set_idle_io(&self) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+#[cfg(windows)]
+impl Scheduler for System {
+    fn set_niceness(&self, niceness: i32) -> io::Result<()> {
+        use windows_sys::Win32::System::Threading::{
+            GetCurrentProcess, SetPriorityClass, BELOW_NORMAL_PRIORITY_CLASS, IDLE_PRIORITY_CLASS,
+        };
+        let class = if niceness >= NICE_LEVEL { IDLE_PRIORITY_CLASS } else { BELOW_NORMAL_PRIORITY_CLASS };
+        // SAFETY: the pseudo handle for the current process is always valid
+        if unsafe { SetPriorityClass(GetCurrentProcess(), class) } != 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    fn set_idle_io(&self) -> io::Result<()> {
+        use windows_sys::Win32::System::Threading::{GetCurrentProcess, SetPriorityClass, PROCESS_MODE_BACKGROUND_BEGIN};
+        // SAFETY: the pseudo handle for the current process is always valid
+        if unsafe { SetPriorityClass(GetCurrentProcess(), PROCESS_MODE_BACKGROUND_BEGIN) } != 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+/// Lower CPU and I/O priority for `--nice`, returning warnings for whatever
+/// the OS refused
+///
+/// A refusal (e.g. a sandbox forbidding `ioprio_set`) never stops the run.
+pub fn lower_priority(scheduler: &impl Scheduler) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if let Err(e) = scheduler.set_niceness(NICE_LEVEL) {
+        warnings.push(format!("Could not lower CPU priority: {}", e));
+    }
+    match scheduler.set_idle_io() {
+        Err(e) if e.kind() != io::ErrorKind::Unsupported => {
+            warnings.push(format!("Could not lower I/O priority: {}", e));
+        }
+        _ => {}
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Records the calls made, failing each with the given error
+    #[derive(Default)]
+    struct MockScheduler {
+        niceness_error: Option<io::ErrorKind>,
+        io_error: Option<io::ErrorKind>,
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl Scheduler for MockScheduler {
+        fn set_niceness(&self, niceness: i32) -> io::Result<()> {
+            self.calls.borrow_mut().push(format!("nice {}", niceness));
+            self.niceness_error.map_or(Ok(()), |kind| Err(kind.into()))
+        }
+
+        fn set_idle_io(&self) -> io::Result<()> {
+            self.calls.borrow_mut().push("idle io".to_string());
+            self.io_error.map_or(Ok(()), |kind| Err(kind.into()))
+        }
+    }
+
+    #[test]
+    fn test_lower_priority_sets_cpu_and_io() {
+        let scheduler = MockScheduler::default();
+        assert!(lower_priority(&scheduler).is_empty());
+        assert_eq!(*scheduler.calls.borrow(), vec!["nice 19", "idle io"]);
+    }
+
+    #[test]
+    fn test_refused_calls_warn_and_continue() {
+        let scheduler = MockScheduler {
+            niceness_error: Some(io::ErrorKind::PermissionDenied),
+            io_error: Some(io::ErrorKind::PermissionDenied),
+            ..Default::default()
+        };
+        let warnings = lower_priority(&scheduler);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].starts_with("Could not lower CPU priority"));
+        assert!(warnings[1].starts_with("Could not lower I/O priority"));
+        assert_eq!(scheduler.calls.borrow().len(), 2);
+
+        // No I/O priority to set isn't worth a warning
+        let scheduler = MockScheduler {
+            io_error: Some(io::ErrorKind::Unsupported),
+            ..Default::default()
+        };
+        assert!(lower_priority(&scheduler).is_empty());
+    }
+}
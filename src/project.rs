@@ -1,6 +1,13 @@
-use anyhow::Result;
-use cargo_metadata::MetadataCommand;
-use std::collections::HashSet;
+use anyhow::{anyhow, Context, Result};
+use cargo_metadata::Metadata;
+use crate::disk::{device_id, mounts_of_type, Mount};
+use crate::metadata::workspace_metadata;
+use crate::timing::count_dirs_walked;
+use crate::utils::{validate_directory, TARGET_DIR_NAME};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+use std::ops::ControlFlow;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
@@ -8,14 +15,295 @@ use walkdir::WalkDir;
 pub struct Project {
     pub path: PathBuf,
     pub is_workspace: bool,
+    /// Package name, or the directory name for virtual workspaces
+    pub name: String,
+    pub version: Option<String>,
+    /// Number of members, for workspaces
+    pub workspace_members: Option<usize>,
+    /// Other paths at which the same directory was discovered
+    pub aliases: Vec<PathBuf>,
+    /// Discovered by following a symlink (`--follow-symlinks`)
+    pub via_symlink: bool,
+    /// The manifest sets `publish = false` (or `publish = []`)
+    pub private: bool,
 }
 
-/// Find all Cargo projects in the given directory
-pub fn find_cargo_projects(root: &Path, exclude_patterns: &[String]) -> Result<Vec<Project>> {
+impl Project {
+    /// Describe the project at `path`, reading its name and version from the manifest
+    ///
+    /// Falls back to the directory name when the manifest has no `[package]`
+    /// (virtual workspaces) or can't be read.
+    pub fn new(path: PathBuf, is_workspace: bool) -> Self {
+        let manifest: Option<toml::Value> = std::fs::read_to_string(path.join("Cargo.toml"))
+            .ok()
+            .and_then(|content| toml::from_str(&content).ok());
+        let package = manifest.as_ref().and_then(|m| m.get("package"));
+        let dir_name = path
+            .canonicalize()
+            .unwrap_or_else(|_| path.clone())
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string_lossy().into_owned());
+
+        let workspace_members = match manifest.as_ref().and_then(|m| m.get("workspace")) {
+            Some(workspace) if is_workspace => Some(count_workspace_members(&path, workspace, package.is_some())),
+            _ => None,
+        };
+        Project {
+            name: package
+                .and_then(|p| p.get("name")?.as_str())
+                .map(str::to_string)
+                .unwrap_or(dir_name),
+            // `version.workspace = true` is left unresolved
+            version: package
+                .and_then(|p| p.get("version")?.as_str())
+                .map(str::to_string),
+            workspace_members,
+            aliases: Vec::new(),
+            via_symlink: false,
+            private: manifest.as_ref().is_some_and(is_private),
+            path,
+            is_workspace,
+        }
+    }
+}
+
+/// Whether a manifest forbids publishing
+///
+/// Reads `[package] publish`, following `publish.workspace = true` to
+/// `[workspace.package]`, which is also used for virtual workspaces.
+fn is_private(manifest: &toml::Value) -> bool {
+    let workspace_publish = manifest.get("workspace").and_then(|w| w.get("package")?.get("publish"));
+    let publish = match manifest.get("package") {
+        Some(package) => match package.get("publish") {
+            Some(publish) if publish.get("workspace").and_then(|w| w.as_bool()) == Some(true) => workspace_publish,
+            publish => publish,
+        },
+        None => workspace_publish,
+    };
+    match publish {
+        Some(toml::Value::Boolean(publish)) => !publish,
+        Some(toml::Value::Array(registries)) => registries.is_empty(),
+        _ => false,
+    }
+}
+
+/// Count the packages matched by a `[workspace]` table's `members` globs
+///
+/// The root package counts as a member too, as it does for cargo.
+fn count_workspace_members(root: &Path, workspace: &toml::Value, has_root_package: bool) -> usize {
+    let patterns = |key: &str| -> Vec<String> {
+        workspace
+            .get(key)
+            .and_then(|v| v.as_array())
+            .map(|items| items.iter().filter_map(|i| i.as_str()).map(str::to_string).collect())
+            .unwrap_or_default()
+    };
+    let excluded: Vec<PathBuf> = patterns("exclude").iter().map(|e| root.join(e)).collect();
+
+    let mut members: HashSet<PathBuf> = HashSet::new();
+    for pattern in patterns("members") {
+        let Ok(paths) = glob::glob(&root.join(&pattern).to_string_lossy()) else {
+            continue;
+        };
+        members.extend(
+            paths
+                .filter_map(|p| p.ok())
+                .filter(|p| p.join("Cargo.toml").is_file() && !excluded.contains(p)),
+        );
+    }
+    if has_root_package {
+        members.insert(root.to_path_buf());
+    }
+    members.len()
+}
+
+/// A path given on stdin that could not be used as a project root
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct InvalidPath {
+    pub path: String,
+    pub error: String,
+}
+
+/// Options controlling project discovery
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveryOptions {
+    /// Skip workspace detection (one `cargo metadata` call per ancestor
+    /// manifest) and treat every `Cargo.toml` as a standalone project
+    pub assume_no_workspace: bool,
+    /// Check at most this many ancestors of a `Cargo.toml` for the workspace
+    /// it belongs to; ancestors above the scan root are never checked
+    pub workspace_search_depth: Option<usize>,
+    /// Descend into `target/` directories next to a `Cargo.toml`, which are
+    /// build output and normally pruned
+    pub scan_targets: bool,
+    /// Descend into symlinked directories, each real directory at most once
+    pub follow_symlinks: bool,
+    /// Follow symlinks that lead outside the root
+    pub allow_escape: bool,
+    /// Stay on the filesystem of the root
+    pub one_file_system: bool,
+    /// Skip mounts of these filesystem types (Linux only)
+    pub exclude_fs_types: Vec<String>,
+    /// Name of the build output directories to prune, when it isn't `target`
+    pub target_dir_name: Option<String>,
+    /// Tell workspace roots by their `[workspace]` table instead of running
+    /// `cargo metadata`, so discovery spawns no processes
+    pub manifest_only: bool,
+    /// Leave out a project at the scan root itself, keeping only the ones below it
+    pub exclude_root: bool,
+}
+
+/// A mount point discovery did not descend into
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SkippedMount {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Projects found by discovery
+#[derive(Debug, Default)]
+pub struct Discovery {
+    pub projects: Vec<Project>,
+    pub skipped_mounts: Vec<SkippedMount>,
+    /// The project at the scan root, left out because of `exclude_root`
+    pub excluded_root: Option<PathBuf>,
+    /// Directories the walk visited
+    pub dirs_walked: u64,
+}
+
+/// Filesystem boundaries discovery must not cross
+#[derive(Debug, Default)]
+struct MountBoundary {
+    /// Device of the root, with `--one-file-system`
+    root_device: Option<u64>,
+    /// Mounts of excluded filesystem types
+    excluded: Vec<Mount>,
+}
+
+impl MountBoundary {
+    fn new(root: &Path, options: &DiscoveryOptions) -> Result<Self> {
+        Ok(MountBoundary {
+            root_device: if options.one_file_system { device_id(root) } else { None },
+            excluded: if options.exclude_fs_types.is_empty() {
+                Vec::new()
+            } else {
+                mounts_of_type(&options.exclude_fs_types)?
+            },
+        })
+    }
+
+    fn is_active(&self) -> bool {
+        self.root_device.is_some() || !self.excluded.is_empty()
+    }
+
+    /// Why the directory at `path`, on device `device`, lies beyond the boundary
+    fn crossing(&self, path: &Path, device: Option<u64>) -> Option<String> {
+        if let Some(mount) = self.excluded.iter().find(|mount| mount.mount_point == path) {
+            return Some(format!("{} filesystem", mount.fs_type));
+        }
+        match (self.root_device, device) {
+            (Some(root_device), Some(device)) if device != root_device => Some("different filesystem".to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Find all Cargo projects in the given directory, noting the mount points skipped
+pub fn discover_projects(root: &Path, exclude_patterns: &[String], options: &DiscoveryOptions) -> Result<Discovery> {
+    discover_projects_with(root, exclude_patterns, options, &workspace_root_check(options))
+}
+
+/// Find Cargo projects like `discover_projects`, handing each to `found` as
+/// soon as it's seen instead of collecting them
+///
+/// Projects arrive in walk order and may repeat (see `SeenProjects`), and
+/// the returned `Discovery` has no projects of its own. The walk ends early
+/// when `found` breaks.
+pub fn stream_projects(
+    root: &Path,
+    exclude_patterns: &[String],
+    options: &DiscoveryOptions,
+    found: &mut dyn FnMut(Project) -> ControlFlow<()>,
+) -> Result<Discovery> {
+    let mut excluded_root = None;
+    let (skipped_mounts, dirs_walked) = walk_projects(root, exclude_patterns, options, &workspace_root_check(options), &mut |project| {
+        if options.exclude_root && project.path == root {
+            excluded_root = Some(project.path);
+            ControlFlow::Continue(())
+        } else {
+            found(project)
+        }
+    })?;
+    Ok(Discovery {
+        projects: Vec::new(),
+        skipped_mounts,
+        excluded_root,
+        dirs_walked,
+    })
+}
+
+/// How discovery tells whether an ancestor manifest is a workspace root
+fn workspace_root_check(options: &DiscoveryOptions) -> Box<dyn Fn(&Path) -> bool> {
+    if options.manifest_only {
+        Box::new(declares_workspace)
+    } else {
+        Box::new(|manifest| workspace_metadata(manifest).is_ok_and(|metadata| is_root_of(manifest, &metadata)))
+    }
+}
+
+/// `discover_projects`, asking `is_workspace_root` whether an ancestor manifest is a workspace root
+fn discover_projects_with(
+    root: &Path,
+    exclude_patterns: &[String],
+    options: &DiscoveryOptions,
+    is_workspace_root: &dyn Fn(&Path) -> bool,
+) -> Result<Discovery> {
     let mut projects = Vec::new();
+    let (skipped_mounts, dirs_walked) = walk_projects(root, exclude_patterns, options, is_workspace_root, &mut |project| {
+        projects.push(project);
+        ControlFlow::Continue(())
+    })?;
+
+    // A workspace at the root takes its members with it, since they share its target
+    let mut excluded_root = None;
+    if options.exclude_root {
+        let found = projects.len();
+        projects.retain(|p| p.path != root);
+        if projects.len() < found {
+            excluded_root = Some(root.to_path_buf());
+        }
+    }
+
+    sort_projects(&mut projects);
+    Ok(Discovery {
+        projects,
+        skipped_mounts,
+        excluded_root,
+        dirs_walked,
+    })
+}
+
+/// Walk `root` for Cargo projects, handing each to `found` as it's seen
+/// until it breaks, and return the mount points left out and the number of
+/// directories walked
+fn walk_projects(
+    root: &Path,
+    exclude_patterns: &[String],
+    options: &DiscoveryOptions,
+    is_workspace_root: &dyn Fn(&Path) -> bool,
+    found: &mut dyn FnMut(Project) -> ControlFlow<()>,
+) -> Result<(Vec<SkippedMount>, u64)> {
     let mut seen_workspaces = HashSet::new();
+    // Real directories already walked, so symlink cycles end
+    let mut visited_dirs: HashSet<PathBuf> = HashSet::new();
+    let canonical_root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let boundary = MountBoundary::new(root, options)?;
+    let mut skipped_mounts = Vec::new();
+    let mut dirs_walked = 0;
 
-    for entry in WalkDir::new(root)
+    'walk: for entry in WalkDir::new(root)
+        .follow_links(options.follow_symlinks)
         .into_iter()
         .filter_entry(|e| {
             // Skip hidden directories and common exclusions
@@ -24,6 +312,39 @@ pub fn find_cargo_projects(root: &Path, exclude_patterns: &[String]) -> Result<V
                 return false;
             }
 
+            if boundary.is_active() && e.file_type().is_dir() {
+                let device = boundary.root_device.and_then(|_| device_id(e.path()));
+                if let Some(reason) = boundary.crossing(e.path(), device) {
+                    skipped_mounts.push(SkippedMount {
+                        path: e.path().to_path_buf(),
+                        reason,
+                    });
+                    return false;
+                }
+            }
+
+            if options.follow_symlinks && e.file_type().is_dir() {
+                let Ok(real_dir) = e.path().canonicalize() else {
+                    return false;
+                };
+                if e.path_is_symlink() && !options.allow_escape && !real_dir.starts_with(&canonical_root) {
+                    return false;
+                }
+                if !visited_dirs.insert(real_dir) {
+                    return false;
+                }
+            }
+
+            // Never discover projects inside build output
+            if !options.scan_targets
+                && e.depth() > 0
+                && name == options.target_dir_name.as_deref().unwrap_or(TARGET_DIR_NAME)
+                && e.file_type().is_dir()
+                && e.path().parent().is_some_and(|p| p.join("Cargo.toml").is_file())
+            {
+                return false;
+            }
+
             // Check exclude patterns
             for pattern in exclude_patterns {
                 if glob::Pattern::new(pattern)
@@ -42,69 +363,905 @@ pub fn find_cargo_projects(root: &Path, exclude_patterns: &[String]) -> Result<V
             true
         })
     {
-        let entry = entry?;
+        let entry = match entry {
+            Ok(entry) => entry,
+            // Dangling links are only visible when following symlinks
+            Err(_) if options.follow_symlinks => continue,
+            Err(error) => return Err(error.into()),
+        };
+        if entry.file_type().is_dir() {
+            dirs_walked += 1;
+        }
         if entry.file_name() == "Cargo.toml" {
             let project_dir = entry.path().parent().unwrap().to_path_buf();
+            let via_symlink = options.follow_symlinks && reached_via_symlink(root, &canonical_root, &project_dir);
 
-            // Check if this is part of a workspace
+            // Check if this is part of a workspace, looking no higher than the root:
+            // a workspace above it is one the user didn't ask about
             let mut is_workspace_member = false;
-            let mut current = project_dir.parent();
-            while let Some(parent) = current {
+            let max_depth = if options.assume_no_workspace { 0 } else { options.workspace_search_depth.unwrap_or(usize::MAX) };
+            let ancestors = project_dir.ancestors().skip(1).take_while(|dir| dir.starts_with(root)).take(max_depth);
+            for parent in ancestors {
                 let workspace_toml = parent.join("Cargo.toml");
-                if workspace_toml.exists() {
-                    // Try to parse as workspace
-                    if let Ok(metadata) = MetadataCommand::new()
-                        .manifest_path(&workspace_toml)
-                        .exec()
-                    {
-                        if metadata.workspace_root == parent {
-                            // This is a workspace member
-                            let workspace_path: PathBuf = metadata.workspace_root.into();
-                            if !seen_workspaces.contains(&workspace_path) {
-                                seen_workspaces.insert(workspace_path.clone());
-                                projects.push(Project {
-                                    path: workspace_path,
-                                    is_workspace: true,
-                                });
-                            }
-                            is_workspace_member = true;
-                            break;
+                if workspace_toml.exists() && is_workspace_root(&workspace_toml) {
+                    // This is a workspace member
+                    let workspace_path = parent.to_path_buf();
+                    if !seen_workspaces.contains(&workspace_path) {
+                        seen_workspaces.insert(workspace_path.clone());
+                        let mut project = Project::new(workspace_path, true);
+                        project.via_symlink = via_symlink;
+                        if found(project).is_break() {
+                            break 'walk;
                         }
                     }
+                    is_workspace_member = true;
+                    break;
                 }
-                current = parent.parent();
             }
 
             // If not a workspace member, add as standalone project
             if !is_workspace_member {
-                projects.push(Project {
-                    path: project_dir,
-                    is_workspace: false,
-                });
+                let mut project = Project::new(project_dir, false);
+                project.via_symlink = via_symlink;
+                if found(project).is_break() {
+                    break;
+                }
+            }
+        }
+    }
+    count_dirs_walked(dirs_walked);
+    Ok((skipped_mounts, dirs_walked))
+}
+
+/// Whether cargo resolved `manifest` to a workspace rooted right there
+fn is_root_of(manifest: &Path, metadata: &Metadata) -> bool {
+    manifest.parent().is_some_and(|dir| metadata.workspace_root == dir)
+}
+
+/// Whether a manifest has a `[workspace]` table, read without running cargo
+fn declares_workspace(manifest: &Path) -> bool {
+    std::fs::read_to_string(manifest)
+        .ok()
+        .and_then(|content| toml::from_str::<toml::Value>(&content).ok())
+        .is_some_and(|manifest| manifest.get("workspace").is_some())
+}
+
+/// Whether `dir`, found below `root`, resolves somewhere other than its path suggests
+fn reached_via_symlink(root: &Path, canonical_root: &Path, dir: &Path) -> bool {
+    let Ok(relative) = dir.strip_prefix(root) else {
+        return false;
+    };
+    dir.canonicalize()
+        .is_ok_and(|real_dir| real_dir != canonical_root.join(relative))
+}
+
+/// Key used to order projects: the canonicalized path as a string
+///
+/// Falls back to the path as given when it can't be canonicalized (e.g. it
+/// has been removed since discovery).
+fn ordering_key(project: &Project) -> String {
+    project
+        .path
+        .canonicalize()
+        .unwrap_or_else(|_| project.path.clone())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Sort projects into a deterministic order and remove duplicates
+///
+/// Projects are ordered by the bytes of their canonicalized path string
+/// (case-sensitive, `/` compared like any other character), so the order
+/// doesn't depend on the filesystem's directory iteration order or on
+/// platform-specific `PathBuf` comparison.
+///
+/// Duplicates are first found by canonicalized path, then by filesystem
+/// identity, which also catches the same directory reached through a bind
+/// mount. The paths of dropped duplicates are kept as aliases.
+pub fn sort_projects(projects: &mut Vec<Project>) {
+    projects.sort_by_cached_key(ordering_key);
+    projects.dedup_by(|duplicate, kept| {
+        if ordering_key(duplicate) != ordering_key(kept) {
+            return false;
+        }
+        add_alias(kept, duplicate);
+        true
+    });
+
+    let mut first_by_id: HashMap<(u64, u64), usize> = HashMap::new();
+    let mut unique: Vec<Project> = Vec::with_capacity(projects.len());
+    for mut project in projects.drain(..) {
+        match file_id(&project.path).map(|id| first_by_id.entry(id)) {
+            Some(Entry::Occupied(first)) => add_alias(&mut unique[*first.get()], &mut project),
+            Some(Entry::Vacant(first)) => {
+                first.insert(unique.len());
+                unique.push(project);
             }
+            None => unique.push(project),
         }
     }
+    *projects = unique;
+}
+
+/// Projects already handed out by streaming discovery, to drop repeats
+///
+/// Catches the same duplicates as `sort_projects`, but the first one seen is
+/// kept and later ones are dropped without being recorded as its aliases.
+#[derive(Debug, Default)]
+pub struct SeenProjects {
+    keys: HashSet<String>,
+    ids: HashSet<(u64, u64)>,
+}
+
+impl SeenProjects {
+    /// Whether `project` is new, remembering it if so
+    pub fn insert(&mut self, project: &Project) -> bool {
+        if !self.keys.insert(ordering_key(project)) {
+            return false;
+        }
+        file_id(&project.path).is_none_or(|id| self.ids.insert(id))
+    }
+}
+
+/// Record `duplicate` (and its own aliases) as aliases of `kept`
+fn add_alias(kept: &mut Project, duplicate: &mut Project) {
+    let paths = std::iter::once(duplicate.path.clone()).chain(duplicate.aliases.drain(..));
+    for path in paths {
+        if path != kept.path && !kept.aliases.contains(&path) {
+            kept.aliases.push(path);
+        }
+    }
+}
+
+/// Filesystem identity of the file at `path`: device and inode number
+#[cfg(unix)]
+fn file_id(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// Filesystem identity of the file at `path`: volume serial number and file index
+#[cfg(windows)]
+fn file_id(path: &Path) -> Option<(u64, u64)> {
+    let handle = winapi_util::Handle::from_path_any(path).ok()?;
+    let information = winapi_util::file::information(&handle).ok()?;
+    Some((information.volume_serial_number(), information.file_index()))
+}
+
+/// Without file identities, only paths are compared
+#[cfg(not(any(unix, windows)))]
+fn file_id(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+/// Package names of the members of the workspace (or single package) at `root`
+pub fn workspace_members(root: &Path) -> Result<Vec<String>> {
+    let metadata = workspace_metadata(&root.join("Cargo.toml"))
+        .with_context(|| format!("Failed to read workspace metadata for {:?}", root))?;
+    let mut members: Vec<String> = metadata
+        .workspace_packages()
+        .iter()
+        .map(|p| p.name.to_string())
+        .collect();
+    members.sort();
+    Ok(members)
+}
+
+/// Package names of the workspace's `default-members`
+///
+/// Returns `None` when the root manifest doesn't declare `default-members`
+/// (cargo would then default to every member, or the root package) or when
+/// cargo is too old to report them.
+pub fn default_members(root: &Path) -> Result<Option<Vec<String>>> {
+    let manifest = root.join("Cargo.toml");
+    let content = std::fs::read_to_string(&manifest).with_context(|| format!("Failed to read {:?}", manifest))?;
+    let toml: toml::Value = toml::from_str(&content).with_context(|| format!("Failed to parse {:?}", manifest))?;
+    if toml.get("workspace").and_then(|w| w.get("default-members")).is_none() {
+        return Ok(None);
+    }
 
-    // Remove duplicates
-    projects.sort_by_key(|p| p.path.clone());
-    projects.dedup_by_key(|p| p.path.clone());
+    let metadata = workspace_metadata(&manifest)
+        .with_context(|| format!("Failed to read workspace metadata for {:?}", root))?;
+    if !metadata.workspace_default_members.is_available() {
+        return Ok(None);
+    }
+    let mut members: Vec<String> = metadata
+        .workspace_default_packages()
+        .iter()
+        .map(|p| p.name.to_string())
+        .collect();
+    members.sort();
+    Ok(Some(members))
+}
 
-    Ok(projects)
+/// Build a single project from an explicit Cargo.toml path
+///
+/// The project is marked as a workspace when `cargo metadata` reports that
+/// the manifest is the root of a workspace with more than one member (or a
+/// virtual workspace without a root package).
+pub fn project_from_manifest(manifest: &Path) -> Result<Project> {
+    if manifest.file_name().is_none_or(|name| name != "Cargo.toml") {
+        return Err(anyhow!("--manifest-path must point to a Cargo.toml file, got {:?}", manifest));
+    }
+    if !manifest.is_file() {
+        return Err(anyhow!("Manifest does not exist: {:?}", manifest));
+    }
+
+    let manifest = manifest
+        .canonicalize()
+        .with_context(|| format!("Failed to canonicalize path: {:?}", manifest))?;
+    let project_dir = manifest.parent().unwrap().to_path_buf();
+
+    let is_workspace = workspace_metadata(&manifest)
+        .map(|metadata| {
+            metadata.workspace_root == project_dir
+                && (metadata.workspace_members.len() > 1 || metadata.root_package().is_none())
+        })
+        .unwrap_or(false);
+
+    Ok(Project::new(project_dir, is_workspace))
+}
+
+/// Find the project enclosing `start` when `start` is not a project root itself
+///
+/// Walks up to the nearest `Cargo.toml` and resolves it to its workspace
+/// root, the way cargo does when run from a subdirectory. Returns `None` when
+/// `start` has its own `Cargo.toml` (discovery already handles it) or no
+/// ancestor does.
+pub fn enclosing_project(start: &Path) -> Result<Option<Project>> {
+    let start = start
+        .canonicalize()
+        .with_context(|| format!("Failed to canonicalize path: {:?}", start))?;
+    if start.join("Cargo.toml").is_file() {
+        return Ok(None);
+    }
+    let Some(manifest) = start
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join("Cargo.toml"))
+        .find(|manifest| manifest.is_file())
+    else {
+        return Ok(None);
+    };
+
+    let root_manifest = workspace_root_manifest(&manifest).unwrap_or(manifest);
+    project_from_manifest(&root_manifest).map(Some)
+}
+
+/// The root `Cargo.toml` of the workspace `manifest` is a member of
+///
+/// Returns `None` when the manifest is its own workspace root or cargo can't
+/// read it.
+pub fn workspace_root_manifest(manifest: &Path) -> Option<PathBuf> {
+    let project_dir = manifest.canonicalize().ok()?.parent()?.to_path_buf();
+    let metadata = workspace_metadata(manifest).ok()?;
+    let workspace_root = PathBuf::from(metadata.workspace_root.clone());
+    (workspace_root != project_dir).then(|| workspace_root.join("Cargo.toml"))
+}
+
+/// Read newline-separated project roots, e.g. from stdin
+///
+/// Each line is treated as a project root without discovery. Lines naming a
+/// `Cargo.toml` use its directory, so the output of `find . -name Cargo.toml`
+/// can be piped in directly. Blank lines are ignored; lines that aren't
+/// usable directories are returned separately instead of failing the run.
+pub fn projects_from_lines(reader: impl BufRead) -> Result<(Vec<Project>, Vec<InvalidPath>)> {
+    let mut projects = Vec::new();
+    let mut invalid = Vec::new();
+
+    for line in reader.lines() {
+        let line = line.context("Failed to read project paths from stdin")?;
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let path = Path::new(line);
+        let dir = if path.file_name().is_some_and(|name| name == "Cargo.toml") && path.is_file() {
+            path.parent().unwrap_or(Path::new("."))
+        } else {
+            path
+        };
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+
+        let project = validate_directory(dir).and_then(|()| {
+            let manifest = dir.join("Cargo.toml");
+            if manifest.is_file() {
+                project_from_manifest(&manifest)
+            } else {
+                let path = dir
+                    .canonicalize()
+                    .with_context(|| format!("Failed to canonicalize path: {:?}", dir))?;
+                Ok(Project::new(path, false))
+            }
+        });
+        match project {
+            Ok(project) => projects.push(project),
+            Err(e) => invalid.push(InvalidPath {
+                path: line.to_string(),
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    sort_projects(&mut projects);
+    Ok((projects, invalid))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::metadata::{CountingRunner, MetadataCache};
     use std::fs;
     use tempfile::TempDir;
 
+    #[test]
+    fn test_sort_projects_is_independent_of_input_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let names = ["b", "a-b", "a", "a/b", "B", "c"];
+        for name in names {
+            fs::create_dir_all(temp_dir.path().join(name)).unwrap();
+        }
+        let projects: Vec<Project> = names
+            .iter()
+            .map(|name| Project::new(temp_dir.path().join(name), false))
+            .collect();
+
+        let sorted = |mut projects: Vec<Project>| {
+            sort_projects(&mut projects);
+            projects.into_iter().map(|p| p.path).collect::<Vec<_>>()
+        };
+        let expected = sorted(projects.clone());
+
+        let mut reversed = projects.clone();
+        reversed.reverse();
+        assert_eq!(sorted(reversed), expected);
+        for shift in 1..projects.len() {
+            let mut rotated = projects.clone();
+            rotated.rotate_left(shift);
+            assert_eq!(sorted(rotated), expected);
+        }
+
+        let root = temp_dir.path().canonicalize().unwrap();
+        let relative: Vec<_> = expected
+            .iter()
+            .map(|p| p.strip_prefix(&root).unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(relative, vec!["B", "a", "a-b", "a/b", "b", "c"]);
+    }
+
+    #[test]
+    fn test_sort_projects_removes_duplicates() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = Project::new(temp_dir.path().to_path_buf(), false);
+        let via_dot = Project::new(temp_dir.path().join("."), false);
+        let mut projects = vec![project.clone(), via_dot];
+        sort_projects(&mut projects);
+        assert_eq!(projects.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sort_projects_records_symlinked_duplicates() {
+        let temp_dir = TempDir::new().unwrap();
+        let app = temp_dir.path().join("app");
+        let link = temp_dir.path().join("link");
+        fs::create_dir_all(&app).unwrap();
+        fs::write(app.join("Cargo.toml"), "[package]\nname = \"app\"\n").unwrap();
+        std::os::unix::fs::symlink(&app, &link).unwrap();
+        assert_eq!(file_id(&link), file_id(&app));
+
+        let mut projects = vec![Project::new(link.clone(), false), Project::new(app.clone(), false)];
+        sort_projects(&mut projects);
+        assert_eq!(projects.len(), 1);
+        assert_eq!(projects[0].name, "app");
+        let mut paths = vec![projects[0].path.clone(), projects[0].aliases[0].clone()];
+        paths.sort();
+        assert_eq!(paths, vec![app, link]);
+    }
+
+    #[test]
+    fn test_projects_from_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let app = temp_dir.path().join("app");
+        let lib = temp_dir.path().join("lib");
+        fs::create_dir_all(&app).unwrap();
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("Cargo.toml"), "not toml").unwrap();
+        fs::write(temp_dir.path().join("file"), "").unwrap();
+
+        let input = format!(
+            "{}\n\n{}\r\n{}\n{}\n",
+            app.display(),
+            lib.join("Cargo.toml").display(),
+            temp_dir.path().join("missing").display(),
+            temp_dir.path().join("file").display(),
+        );
+        let (projects, invalid) = projects_from_lines(input.as_bytes()).unwrap();
+
+        let paths: Vec<_> = projects.into_iter().map(|p| p.path).collect();
+        assert_eq!(paths, vec![app.canonicalize().unwrap(), lib.canonicalize().unwrap()]);
+        assert_eq!(invalid.len(), 2);
+        assert!(invalid[0].error.contains("does not exist"));
+        assert!(invalid[1].error.contains("expected a directory"));
+    }
+
     #[test]
     fn test_find_cargo_projects_empty() {
         let temp_dir = TempDir::new().unwrap();
-        let projects = find_cargo_projects(temp_dir.path(), &[]).unwrap();
+        let projects = discover_projects(temp_dir.path(), &[], &DiscoveryOptions::default()).unwrap().projects;
         assert_eq!(projects.len(), 0);
     }
 
+    #[test]
+    fn test_find_cargo_projects_assume_no_workspace() {
+        let temp_dir = TempDir::new().unwrap();
+        // TempDir names start with a dot, which discovery treats as hidden
+        let root = temp_dir.path().join("crates");
+        let member = root.join("member");
+        fs::create_dir_all(member.join("src")).unwrap();
+        fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = [\"member\"]\n").unwrap();
+        fs::write(member.join("Cargo.toml"), "[package]\nname = \"member\"\nversion = \"0.1.0\"\n").unwrap();
+        fs::write(member.join("src/lib.rs"), "").unwrap();
+
+        let projects = discover_projects(
+            &root,
+            &[],
+            &DiscoveryOptions {
+                assume_no_workspace: true,
+                ..Default::default()
+            },
+        ).unwrap().projects;
+        assert_eq!(projects.len(), 2);
+        assert!(projects.iter().all(|p| !p.is_workspace));
+    }
+
+    #[test]
+    fn test_workspace_search_stays_below_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let outer = temp_dir.path().join("outer");
+        let root = outer.join("code");
+        for dir in ["app/src", "ws/crates/deep/a/src"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        // A workspace above the scan root that claims a project below it
+        fs::write(outer.join("Cargo.toml"), "[workspace]\nmembers = [\"code/app\"]\n").unwrap();
+        fs::write(root.join("ws/Cargo.toml"), "[workspace]\nmembers = [\"crates/deep/a\"]\n").unwrap();
+        for (dir, name) in [("app", "app"), ("ws/crates/deep/a", "a")] {
+            fs::write(
+                root.join(dir).join("Cargo.toml"),
+                format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name),
+            ).unwrap();
+            fs::write(root.join(dir).join("src/lib.rs"), "").unwrap();
+        }
+
+        let found = |depth: Option<usize>| -> Vec<PathBuf> {
+            let options = DiscoveryOptions {
+                workspace_search_depth: depth,
+                ..Default::default()
+            };
+            discover_projects(&root, &[], &options)
+                .unwrap()
+                .projects
+                .into_iter()
+                .map(|p| p.path)
+                .collect()
+        };
+        assert_eq!(found(None), vec![root.join("app"), root.join("ws")]);
+        // The workspace three levels up is out of reach at depth 2
+        assert_eq!(found(Some(2)), vec![root.join("app"), root.join("ws"), root.join("ws/crates/deep/a")]);
+        assert_eq!(found(Some(3)), found(None));
+    }
+
+    #[test]
+    fn test_manifest_only_discovery_matches_cargo() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("code");
+        for dir in ["ws/a/src", "solo/src"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("ws/Cargo.toml"), "[workspace]\nmembers = [\"a\"]\n").unwrap();
+        for (dir, name) in [("ws/a", "a"), ("solo", "solo")] {
+            fs::write(
+                root.join(dir).join("Cargo.toml"),
+                format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name),
+            ).unwrap();
+            fs::write(root.join(dir).join("src/lib.rs"), "").unwrap();
+        }
+
+        let paths = |manifest_only: bool| -> Vec<PathBuf> {
+            let options = DiscoveryOptions { manifest_only, ..Default::default() };
+            discover_projects(&root, &[], &options).unwrap().projects.into_iter().map(|p| p.path).collect()
+        };
+        assert_eq!(paths(true), vec![root.join("solo"), root.join("ws")]);
+        assert_eq!(paths(true), paths(false));
+    }
+
+    #[test]
+    fn test_exclude_root_project() {
+        let temp_dir = TempDir::new().unwrap();
+        let code = temp_dir.path().join("code");
+        let root = code.join("app");
+        for dir in ["src", "examples/demo/src"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for (dir, name) in [(root.clone(), "app"), (root.join("examples/demo"), "demo")] {
+            fs::write(dir.join("Cargo.toml"), format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name)).unwrap();
+            fs::write(dir.join("src/lib.rs"), "").unwrap();
+        }
+
+        let discover = |root: &Path, exclude_root: bool| {
+            let options = DiscoveryOptions { exclude_root, manifest_only: true, ..Default::default() };
+            discover_projects(root, &[], &options).unwrap()
+        };
+        // By default a scan root that is itself a project is cleaned along with the ones below it
+        let discovery = discover(&root, false);
+        let paths: Vec<&PathBuf> = discovery.projects.iter().map(|p| &p.path).collect();
+        assert_eq!(paths, vec![&root, &root.join("examples/demo")]);
+        assert_eq!(discovery.excluded_root, None);
+
+        let discovery = discover(&root, true);
+        let paths: Vec<&PathBuf> = discovery.projects.iter().map(|p| &p.path).collect();
+        assert_eq!(paths, vec![&root.join("examples/demo")]);
+        assert_eq!(discovery.excluded_root, Some(root.clone()));
+
+        // A workspace at the root is left out with its members
+        fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = [\"examples/demo\"]\n").unwrap();
+        assert!(discover(&root, true).projects.is_empty());
+        // Only the root itself: projects below a plain directory are all kept
+        let discovery = discover(&code, true);
+        assert_eq!(discovery.projects.len(), 1);
+        assert_eq!(discovery.excluded_root, None);
+    }
+
+    #[test]
+    fn test_workspace_root_is_queried_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap().join("workspace");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = [\"api\", \"cli\", \"core\"]\n").unwrap();
+        for name in ["api", "cli", "core"] {
+            fs::create_dir_all(root.join(name)).unwrap();
+            fs::write(
+                root.join(name).join("Cargo.toml"),
+                format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name),
+            ).unwrap();
+        }
+        let runner = CountingRunner::new(&root);
+        let cache = MetadataCache::with_runner(&runner);
+
+        let is_workspace_root = |manifest: &Path| cache.get(manifest).is_ok_and(|metadata| is_root_of(manifest, &metadata));
+        let projects = discover_projects_with(&root, &[], &DiscoveryOptions::default(), &is_workspace_root)
+            .unwrap()
+            .projects;
+        assert_eq!(projects.len(), 1);
+        // Every member walks up to the root, but cargo only runs for it once
+        assert_eq!(runner.runs(), 1);
+        assert_eq!(runner.runs_for(&root.join("Cargo.toml")), 1);
+    }
+
+    #[test]
+    fn test_project_reads_name_and_members() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("workspace");
+        fs::create_dir_all(root.join("crates/old")).unwrap();
+        fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = [\"crates/*\"]\nexclude = [\"crates/old\"]\n").unwrap();
+        fs::write(root.join("crates/old/Cargo.toml"), "").unwrap();
+        for name in ["api", "cli"] {
+            fs::create_dir_all(root.join("crates").join(name)).unwrap();
+            fs::write(
+                root.join("crates").join(name).join("Cargo.toml"),
+                format!("[package]\nname = \"{}\"\nversion = \"1.2.3\"\n", name),
+            )
+            .unwrap();
+        }
+
+        let workspace = Project::new(root.clone(), true);
+        assert_eq!(workspace.name, "workspace");
+        assert_eq!(workspace.version, None);
+        assert_eq!(workspace.workspace_members, Some(2));
+
+        let member = Project::new(root.join("crates/api"), false);
+        assert_eq!(member.name, "api");
+        assert_eq!(member.version.as_deref(), Some("1.2.3"));
+        assert_eq!(member.workspace_members, None);
+    }
+
+    #[test]
+    fn test_project_from_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        let manifest = temp_dir.path().join("Cargo.toml");
+        fs::write(&manifest, "[package]\nname = \"test\"\nversion = \"0.1.0\"\n").unwrap();
+        fs::create_dir(temp_dir.path().join("src")).unwrap();
+        fs::write(temp_dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+
+        let project = project_from_manifest(&manifest).unwrap();
+        assert_eq!(project.path, temp_dir.path().canonicalize().unwrap());
+        assert!(!project.is_workspace);
+    }
+
+    #[test]
+    fn test_enclosing_project_finds_workspace_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = [\"crates/*\"]\nresolver = \"2\"\n").unwrap();
+        for name in ["foo", "bar"] {
+            let member = root.join("crates").join(name);
+            fs::create_dir_all(member.join("src")).unwrap();
+            fs::write(
+                member.join("Cargo.toml"),
+                format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n", name),
+            )
+            .unwrap();
+            fs::write(member.join("src/lib.rs"), "").unwrap();
+        }
+
+        let project = enclosing_project(&root.join("crates/foo/src")).unwrap().unwrap();
+        assert_eq!(project.path, root);
+        assert!(project.is_workspace);
+
+        // Project roots and directories outside any project are scanned as given
+        assert!(enclosing_project(&root.join("crates/foo")).unwrap().is_none());
+        assert!(enclosing_project(&root).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_member_manifest_resolves_to_workspace_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = [\"member\"]\nresolver = \"2\"\n").unwrap();
+        fs::create_dir_all(root.join("member/src")).unwrap();
+        fs::write(
+            root.join("member/Cargo.toml"),
+            "[package]\nname = \"member\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+        fs::write(root.join("member/src/lib.rs"), "").unwrap();
+
+        let root_manifest = workspace_root_manifest(&root.join("member/Cargo.toml")).unwrap();
+        assert_eq!(root_manifest, root.join("Cargo.toml"));
+        let project = project_from_manifest(&root_manifest).unwrap();
+        assert_eq!(project.path, root);
+        assert!(workspace_root_manifest(&root_manifest).is_none());
+    }
+
+    #[test]
+    fn test_project_from_manifest_rejects_other_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let other = temp_dir.path().join("Other.toml");
+        fs::write(&other, "").unwrap();
+        assert!(project_from_manifest(&other).is_err());
+        assert!(project_from_manifest(temp_dir.path()).is_err());
+        assert!(project_from_manifest(&temp_dir.path().join("missing/Cargo.toml")).is_err());
+    }
+
+    #[test]
+    fn test_find_cargo_projects_prunes_target_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("code");
+        let app = root.join("app");
+        let vendored = app.join("target/debug/build/checkout");
+        fs::create_dir_all(&vendored).unwrap();
+        fs::write(app.join("Cargo.toml"), "[package]\nname = \"app\"\n").unwrap();
+        fs::write(vendored.join("Cargo.toml"), "[package]\nname = \"vendored\"\n").unwrap();
+        // A directory named target that isn't build output is still scanned
+        fs::create_dir_all(root.join("target/tool")).unwrap();
+        fs::write(root.join("target/tool/Cargo.toml"), "[package]\nname = \"tool\"\n").unwrap();
+
+        let mut options = DiscoveryOptions {
+            assume_no_workspace: true,
+            ..Default::default()
+        };
+        let names = |options: &DiscoveryOptions| -> Vec<String> {
+            discover_projects(&root, &[], options)
+                .unwrap().projects
+                .into_iter()
+                .map(|p| p.name)
+                .collect()
+        };
+        assert_eq!(names(&options), vec!["app", "tool"]);
+
+        options.scan_targets = true;
+        assert_eq!(names(&options), vec!["app", "vendored", "tool"]);
+
+        // With a custom target name, that's the directory pruned instead
+        options.scan_targets = false;
+        options.target_dir_name = Some("build".to_string());
+        let custom = app.join("build/debug/build/checkout");
+        fs::create_dir_all(&custom).unwrap();
+        fs::write(custom.join("Cargo.toml"), "[package]\nname = \"custom\"\n").unwrap();
+        assert_eq!(names(&options), vec!["app", "vendored", "tool"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_duplicate_root_is_cleaned_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("code");
+        let link = temp_dir.path().join("code-link");
+        for name in ["app", "lib"] {
+            fs::create_dir_all(root.join(name)).unwrap();
+            fs::write(root.join(name).join("Cargo.toml"), format!("[package]\nname = \"{}\"\n", name)).unwrap();
+        }
+        std::os::unix::fs::symlink(&root, &link).unwrap();
+
+        // Both roots given on the command line, the way `main` combines them
+        let options = DiscoveryOptions {
+            assume_no_workspace: true,
+            ..Default::default()
+        };
+        let mut projects = Vec::new();
+        for scan_root in [&link, &root] {
+            projects.extend(discover_projects(scan_root, &[], &options).unwrap().projects);
+        }
+        assert_eq!(projects.len(), 4);
+        sort_projects(&mut projects);
+
+        let names: Vec<_> = projects.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["app", "lib"]);
+        for project in &projects {
+            assert_eq!(project.aliases.len(), 1);
+            let mut paths = vec![project.path.canonicalize().unwrap(), project.aliases[0].canonicalize().unwrap()];
+            paths.dedup();
+            assert_eq!(paths.len(), 1);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_streamed_projects_match_discovery() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("code");
+        let link = temp_dir.path().join("code-link");
+        for name in ["app", "lib", "tool"] {
+            fs::create_dir_all(root.join(name)).unwrap();
+            fs::write(root.join(name).join("Cargo.toml"), format!("[package]\nname = \"{}\"\n", name)).unwrap();
+        }
+        fs::write(root.join("Cargo.toml"), "[package]\nname = \"root\"\n").unwrap();
+        std::os::unix::fs::symlink(&root, &link).unwrap();
+        let options = DiscoveryOptions {
+            assume_no_workspace: true,
+            exclude_root: true,
+            ..Default::default()
+        };
+
+        // The same projects arrive one at a time, repeats across roots dropped
+        let mut seen = SeenProjects::default();
+        let mut streamed = Vec::new();
+        for scan_root in [&root, &link] {
+            let discovery = stream_projects(scan_root, &[], &options, &mut |project| {
+                if seen.insert(&project) {
+                    streamed.push(project.path);
+                }
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+            assert!(discovery.projects.is_empty());
+            assert_eq!(discovery.excluded_root.as_ref(), Some(scan_root));
+        }
+        streamed.sort();
+        let discovered: Vec<PathBuf> = discover_projects(&root, &[], &options).unwrap().projects.into_iter().map(|p| p.path).collect();
+        assert_eq!(streamed, discovered);
+
+        // Breaking stops the walk
+        let mut found = 0;
+        stream_projects(&root, &[], &options, &mut |_| {
+            found += 1;
+            ControlFlow::Break(())
+        })
+        .unwrap();
+        assert_eq!(found, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_symlinks_breaks_cycles() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("code");
+        let app = root.join("app");
+        fs::create_dir_all(&app).unwrap();
+        fs::write(app.join("Cargo.toml"), "[package]\nname = \"app\"\n").unwrap();
+        std::os::unix::fs::symlink(&root, app.join("loop")).unwrap();
+        std::os::unix::fs::symlink(root.join("missing"), root.join("dangling")).unwrap();
+
+        let options = DiscoveryOptions {
+            assume_no_workspace: true,
+            follow_symlinks: true,
+            ..Default::default()
+        };
+        let projects = discover_projects(&root, &[], &options).unwrap().projects;
+        assert_eq!(projects.len(), 1);
+        assert_eq!(projects[0].path, app);
+        assert!(!projects[0].via_symlink);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_symlinks_outside_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("code");
+        let shared = temp_dir.path().join("shared/lib");
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&shared).unwrap();
+        fs::write(shared.join("Cargo.toml"), "[package]\nname = \"lib\"\n").unwrap();
+        std::os::unix::fs::symlink(&shared, root.join("lib")).unwrap();
+
+        let mut options = DiscoveryOptions {
+            assume_no_workspace: true,
+            ..Default::default()
+        };
+        assert!(discover_projects(&root, &[], &options).unwrap().projects.is_empty());
+
+        options.follow_symlinks = true;
+        assert!(discover_projects(&root, &[], &options).unwrap().projects.is_empty());
+
+        options.allow_escape = true;
+        let projects = discover_projects(&root, &[], &options).unwrap().projects;
+        assert_eq!(projects.len(), 1);
+        assert_eq!(projects[0].path, root.join("lib"));
+        assert!(projects[0].via_symlink);
+    }
+
+    #[test]
+    fn test_private_projects() {
+        let private = |manifest: &str| is_private(&toml::from_str(manifest).unwrap());
+        assert!(!private("[package]\nname = \"a\"\n"));
+        assert!(private("[package]\nname = \"a\"\npublish = false\n"));
+        assert!(private("[package]\nname = \"a\"\npublish = []\n"));
+        assert!(!private("[package]\nname = \"a\"\npublish = [\"internal\"]\n"));
+        assert!(private(
+            "[package]\nname = \"a\"\npublish.workspace = true\n\n[workspace.package]\npublish = false\n"
+        ));
+        assert!(private("[workspace]\nmembers = []\n\n[workspace.package]\npublish = false\n"));
+        assert!(!private("[workspace]\nmembers = []\n"));
+    }
+
+    #[test]
+    fn test_mount_boundary_crossing() {
+        let boundary = MountBoundary {
+            root_device: Some(1),
+            excluded: vec![Mount {
+                mount_point: PathBuf::from("/home/me/nas"),
+                fs_type: "nfs".to_string(),
+            }],
+        };
+        assert_eq!(boundary.crossing(Path::new("/home/me/code"), Some(1)), None);
+        assert_eq!(boundary.crossing(Path::new("/home/me/code"), None), None);
+        assert_eq!(
+            boundary.crossing(Path::new("/home/me/backup"), Some(2)).as_deref(),
+            Some("different filesystem")
+        );
+        assert_eq!(
+            boundary.crossing(Path::new("/home/me/nas"), Some(1)).as_deref(),
+            Some("nfs filesystem")
+        );
+
+        let without_one_file_system = MountBoundary {
+            root_device: None,
+            ..boundary
+        };
+        assert_eq!(without_one_file_system.crossing(Path::new("/home/me/backup"), Some(2)), None);
+    }
+
+    #[test]
+    fn test_one_file_system_keeps_same_device() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("code");
+        fs::create_dir_all(root.join("app")).unwrap();
+        fs::write(root.join("app/Cargo.toml"), "[package]\nname = \"app\"\n").unwrap();
+
+        let options = DiscoveryOptions {
+            assume_no_workspace: true,
+            one_file_system: true,
+            ..Default::default()
+        };
+        let discovery = discover_projects(&root, &[], &options).unwrap();
+        assert_eq!(discovery.projects.len(), 1);
+        assert!(discovery.skipped_mounts.is_empty());
+    }
+
     #[test]
     fn test_find_cargo_projects_standalone() {
         let temp_dir = TempDir::new().unwrap();
@@ -119,7 +1276,7 @@ mod tests {
         fs::create_dir(project_dir.join("src")).unwrap();
         fs::write(project_dir.join("src/main.rs"), "fn main() {}").unwrap();
 
-        let projects = find_cargo_projects(temp_dir.path(), &[]).unwrap();
+        let projects = discover_projects(temp_dir.path(), &[], &DiscoveryOptions::default()).unwrap().projects;
         // Note: The test might find 0 or 1 depending on cargo-metadata behavior
         // The important thing is it doesn't crash
         assert!(projects.len() <= 1);
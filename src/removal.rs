@@ -0,0 +1,208 @@
+use crate::utils::long_path;
+use rayon::prelude::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Directories at least this large are removed in parallel
+pub const PARALLEL_REMOVAL_MIN_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Remove a directory tree of `bytes` bytes, spreading the work over the
+/// current rayon pool when it's large
+///
+/// On Windows the tree is removed by its long path, so dependencies nested
+/// past MAX_PATH go too.
+pub fn remove_tree(dir: &Path, bytes: u64) -> io::Result<()> {
+    let dir = long_path(dir);
+    if bytes >= PARALLEL_REMOVAL_MIN_BYTES {
+        remove_dir_parallel(&dir, &remove_leaf)
+    } else {
+        fs::remove_dir_all(&dir)
+    }
+}
+
+/// Remove `dir` with one task per directory entry, then `dir` itself
+///
+/// Subdirectories are removed the same way, so a target holding little more
+/// than `debug/` still spreads out over `deps/`, `build/` and so on. Tasks run
+/// on the current rayon pool, which is the pool projects are cleaned on: a
+/// large target borrows threads idle projects aren't using instead of adding
+/// threads beyond `--jobs`. `remove` deletes everything that isn't a directory.
+///
+/// Every entry is attempted even after a failure, and the first error is
+/// returned, so as much as possible is freed.
+pub fn remove_dir_parallel(dir: &Path, remove: &(impl Fn(&Path) -> io::Result<()> + Sync)) -> io::Result<()> {
+    let entries: Vec<(PathBuf, bool)> = fs::read_dir(dir)?
+        .map(|entry| {
+            let entry = entry?;
+            Ok((entry.path(), entry.file_type()?.is_dir()))
+        })
+        .collect::<io::Result<_>>()?;
+
+    let first_error = entries
+        .par_iter()
+        .filter_map(|(path, is_dir)| {
+            if *is_dir {
+                remove_dir_parallel(path, remove).err()
+            } else {
+                remove(path).err()
+            }
+        })
+        .reduce_with(|first, _| first);
+    match first_error {
+        Some(e) => Err(e),
+        None => fs::remove_dir(dir),
+    }
+}
+
+/// Remove the empty directories below `root`, deepest first, and `root`
+/// itself with `remove_root` if that leaves it empty, returning how many went
+///
+/// Directories that empty out as their children go are removed too. Ones
+/// that still hold anything, or can't be removed, are left without error.
+/// Symlinks are never followed.
+pub fn prune_empty_dirs(root: &Path, remove_root: bool) -> usize {
+    let root = long_path(root);
+    let mut pruned = WalkDir::new(&root)
+        .min_depth(1)
+        .contents_first(true)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_dir() && fs::remove_dir(e.path()).is_ok())
+        .count();
+    if remove_root && fs::remove_dir(&root).is_ok() {
+        pruned += 1;
+    }
+    pruned
+}
+
+/// Remove a file or symlink
+fn remove_leaf(path: &Path) -> io::Result<()> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::FileTypeExt;
+        if fs::symlink_metadata(path)?.file_type().is_symlink_dir() {
+            return fs::remove_dir(path);
+        }
+    }
+    fs::remove_file(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    /// `width` directories of `width` files each, a couple of levels deep
+    fn wide_tree(root: &Path, width: usize) -> usize {
+        let mut files = 0;
+        for i in 0..width {
+            let dir = root.join(format!("dir{}", i)).join("deps");
+            fs::create_dir_all(&dir).unwrap();
+            for j in 0..width {
+                fs::write(dir.join(format!("file{}", j)), b"artifact").unwrap();
+                files += 1;
+            }
+        }
+        fs::write(root.join("CACHEDIR.TAG"), b"tag").unwrap();
+        files + 1
+    }
+
+    #[test]
+    fn test_parallel_removal_removes_everything() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        let outside = temp_dir.path().join("outside");
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("keep"), b"keep").unwrap();
+        wide_tree(&target, 16);
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&outside, target.join("dir0/link")).unwrap();
+
+        remove_dir_parallel(&target, &remove_leaf).unwrap();
+        assert!(!target.exists());
+        // Symlinks are removed, never followed
+        assert!(outside.join("keep").exists());
+
+        // Small trees take the plain path
+        wide_tree(&target, 2);
+        remove_tree(&target, 100).unwrap();
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn test_prune_empty_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        for dir in [
+            "debug/.fingerprint/app-1",
+            "debug/.fingerprint/app-2",
+            "debug/build/app-3/out/nested",
+            "debug/incremental/app-4",
+            "release/deps",
+        ] {
+            fs::create_dir_all(target.join(dir)).unwrap();
+        }
+        fs::write(target.join("release/deps/libapp.rlib"), b"rlib").unwrap();
+        fs::write(target.join("debug/incremental/app-4/query-cache.bin"), b"cache").unwrap();
+
+        // Every empty directory and the parents that empty out with them, but not the target
+        assert_eq!(prune_empty_dirs(&target, true), 7);
+        let mut left: Vec<String> = WalkDir::new(&target)
+            .min_depth(1)
+            .into_iter()
+            .map(|e| e.unwrap().path().strip_prefix(&target).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect();
+        left.sort();
+        assert_eq!(
+            left,
+            vec![
+                "debug",
+                "debug/incremental",
+                "debug/incremental/app-4",
+                "debug/incremental/app-4/query-cache.bin",
+                "release",
+                "release/deps",
+                "release/deps/libapp.rlib",
+            ]
+        );
+
+        // The target itself goes only when asked and only when nothing is left
+        fs::remove_file(target.join("release/deps/libapp.rlib")).unwrap();
+        fs::remove_file(target.join("debug/incremental/app-4/query-cache.bin")).unwrap();
+        assert_eq!(prune_empty_dirs(&target, false), 5);
+        assert!(target.is_dir());
+        assert_eq!(prune_empty_dirs(&target, true), 1);
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn test_parallel_removal_stays_within_pool() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        let files = wide_tree(&target, 8);
+
+        let active = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let removed = AtomicUsize::new(0);
+        let instrumented = |path: &Path| {
+            let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(1));
+            let outcome = fs::remove_file(path);
+            removed.fetch_add(1, Ordering::SeqCst);
+            active.fetch_sub(1, Ordering::SeqCst);
+            outcome
+        };
+
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(3).build().unwrap();
+        pool.install(|| remove_dir_parallel(&target, &instrumented)).unwrap();
+        assert!(!target.exists());
+        assert_eq!(removed.load(Ordering::SeqCst), files);
+        let peak = peak.load(Ordering::SeqCst);
+        assert!((2..=3).contains(&peak), "peak concurrency {}", peak);
+    }
+}
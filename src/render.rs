@@ -0,0 +1,341 @@
+use crate::cleaner::CleanResult;
+use crate::glyphs::{glyphs, Glyphs};
+use crate::output::{format_freed, result_status, Summary};
+use crate::utils::parse_size;
+use anyhow::{anyhow, Context, Result};
+use colored::{ColoredString, Colorize};
+
+/// Narrowest the project column gets before the table is left to wrap
+const MIN_PATH_WIDTH: usize = 24;
+
+/// Rows are indented like the summary's other lists
+const INDENT: &str = "  ";
+
+const GAP: &str = "  ";
+
+/// Sizes below which a size is shown green, yellow and bold yellow; larger ones are red
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeColors {
+    pub small: u64,
+    pub medium: u64,
+    pub large: u64,
+}
+
+impl Default for SizeColors {
+    fn default() -> Self {
+        SizeColors {
+            small: 100 * 1024 * 1024,
+            medium: 1024 * 1024 * 1024,
+            large: 10 * 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// How big a size is, as its color tells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Magnitude {
+    Small,
+    Medium,
+    Large,
+    Huge,
+}
+
+impl SizeColors {
+    /// Parse three ascending sizes, e.g. "100MB,1GB,10GB"
+    pub fn parse(value: &str) -> Result<Self> {
+        let sizes = value
+            .split(',')
+            .map(|size| parse_size(size).with_context(|| format!("Invalid size '{}'", size.trim())))
+            .collect::<Result<Vec<u64>>>()?;
+        match sizes[..] {
+            [small, medium, large] if small < medium && medium < large => Ok(SizeColors { small, medium, large }),
+            [_, _, _] => Err(anyhow!("Expected the sizes in ascending order")),
+            _ => Err(anyhow!("Expected three sizes like '100MB,1GB,10GB'")),
+        }
+    }
+
+    fn magnitude(&self, bytes: u64) -> Magnitude {
+        if bytes < self.small {
+            Magnitude::Small
+        } else if bytes < self.medium {
+            Magnitude::Medium
+        } else if bytes < self.large {
+            Magnitude::Large
+        } else {
+            Magnitude::Huge
+        }
+    }
+
+    fn paint(&self, bytes: u64, text: &str) -> ColoredString {
+        match self.magnitude(bytes) {
+            Magnitude::Small => text.green(),
+            Magnitude::Medium => text.yellow(),
+            Magnitude::Large => text.yellow().bold(),
+            Magnitude::Huge => text.red().bold(),
+        }
+    }
+}
+
+/// How the summary table is laid out for where it's printed
+#[derive(Debug, Clone, Copy)]
+pub struct TableStyle {
+    /// Columns to fit in; without one nothing is truncated
+    pub width: Option<usize>,
+    pub color: bool,
+    pub size_colors: SizeColors,
+    pub glyphs: &'static Glyphs,
+}
+
+impl TableStyle {
+    /// Fit the terminal on stdout, or print plainly and in full when it's not a terminal
+    pub fn for_stdout(size_colors: SizeColors) -> Self {
+        let width = console::Term::stdout().size_checked().map(|(_, columns)| columns as usize);
+        TableStyle {
+            width,
+            color: width.is_some() && colored::control::SHOULD_COLORIZE.should_colorize(),
+            size_colors,
+            glyphs: glyphs(),
+        }
+    }
+}
+
+/// Shorten `text` to `width` characters, keeping its end, e.g. `…/code/app`
+fn truncate_start(text: &str, width: usize, ellipsis: &str) -> String {
+    let len = text.chars().count();
+    if len <= width {
+        return text.to_string();
+    }
+    let keep = width.saturating_sub(ellipsis.chars().count());
+    let tail: String = text.chars().skip(len - keep).collect();
+    format!("{}{}", ellipsis, tail)
+}
+
+/// Shorten `text` to `width` characters, keeping its start
+fn truncate_end(text: &str, width: usize, ellipsis: &str) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let head: String = text.chars().take(width.saturating_sub(ellipsis.chars().count())).collect();
+    format!("{}{}", head, ellipsis)
+}
+
+/// Left-align `text` in `width` characters
+fn pad_right(text: &str, width: usize) -> String {
+    format!("{}{}", text, " ".repeat(width.saturating_sub(text.chars().count())))
+}
+
+/// Right-align `text` in `width` characters
+fn pad_left(text: &str, width: usize) -> String {
+    format!("{}{}", " ".repeat(width.saturating_sub(text.chars().count())), text)
+}
+
+/// One row of the table, before it's fitted
+struct Row {
+    path: String,
+    bytes: u64,
+    size: String,
+    status: String,
+}
+
+impl Row {
+    fn of(result: &CleanResult, glyphs: &Glyphs) -> Self {
+        Row {
+            path: result.path.clone(),
+            bytes: result.freed_bytes,
+            size: format_freed(result.freed_bytes, result.estimate, glyphs),
+            status: result_status(result),
+        }
+    }
+}
+
+/// Render the clean summary as a table: one aligned row per project, sizes
+/// right-aligned and colored by magnitude, and the totals in a footer row
+///
+/// With a width, the project column gives way first, losing the start of
+/// each path, then the status column loses its end.
+pub fn render_summary_table(summary: &Summary, style: &TableStyle) -> String {
+    let footer = Row {
+        path: format!("Total ({} project(s))", summary.total_projects),
+        bytes: summary.total_freed_bytes,
+        size: format_freed(summary.total_freed_bytes, summary.estimate, style.glyphs),
+        status: format!(
+            "{} cleaned, {} failed, {} skipped",
+            summary.cleaned, summary.failed, summary.skipped_count
+        ),
+    };
+    let header = Row {
+        path: "Project".to_string(),
+        bytes: 0,
+        size: if summary.dry_run { "Would free" } else { "Freed" }.to_string(),
+        status: "Status".to_string(),
+    };
+    let rows: Vec<Row> = summary.results.iter().map(|result| Row::of(result, style.glyphs)).collect();
+
+    let widest = |column: fn(&Row) -> &str| {
+        rows.iter()
+            .chain([&header, &footer])
+            .map(|row| column(row).chars().count())
+            .max()
+            .unwrap_or(0)
+    };
+    let size_width = widest(|row| &row.size);
+    let status_width = widest(|row| &row.status);
+    let mut path_width = widest(|row| &row.path);
+    let mut status_room = status_width;
+    if let Some(width) = style.width {
+        let fixed = INDENT.len() + 2 * GAP.len() + size_width;
+        let room = width.saturating_sub(fixed + status_width).max(MIN_PATH_WIDTH);
+        path_width = path_width.min(room);
+        status_room = width.saturating_sub(fixed + path_width).max(1);
+    }
+
+    let line = |row: &Row, paint: &dyn Fn(&Row, String) -> String| {
+        let path = pad_right(&truncate_start(&row.path, path_width, style.glyphs.ellipsis), path_width);
+        let size = paint(row, pad_left(&row.size, size_width));
+        let status = truncate_end(&row.status, status_room, style.glyphs.ellipsis);
+        format!("{}{}{}{}{}{}", INDENT, path, GAP, size, GAP, status).trim_end().to_string()
+    };
+    let plain = |_: &Row, text: String| text;
+    let by_magnitude = |row: &Row, text: String| {
+        if style.color {
+            style.size_colors.paint(row.bytes, &text).to_string()
+        } else {
+            text
+        }
+    };
+
+    let header_line = line(&header, &plain);
+    let mut out = if style.color { header_line.bold().to_string() } else { header_line };
+    out.push('\n');
+    for row in &rows {
+        out.push_str(&line(row, &by_magnitude));
+        out.push('\n');
+    }
+    let rule_width = path_width + 2 * GAP.len() + size_width + status_width.min(status_room);
+    out.push_str(&format!("{}{}\n", INDENT, style.glyphs.rule.repeat(rule_width)));
+    out.push_str(&line(&footer, &by_magnitude));
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cleaner::SkipReason;
+    use crate::glyphs::{ASCII, UNICODE};
+    use crate::output::skipped_projects;
+    use crate::project::Project;
+    use std::path::PathBuf;
+
+    const MB: u64 = 1024 * 1024;
+
+    fn summary() -> Summary {
+        let project = |path: &str| Project::new(PathBuf::from(path), false);
+        let results = vec![
+            CleanResult::new(&project("/home/me/code/app"), 12 * 1024 * MB),
+            CleanResult::new(&project("/home/me/code/tools/very-long-project-name"), 300 * MB),
+            CleanResult::skipped(&project("/home/me/code/lib"), SkipReason::InUse),
+            CleanResult::failed(&project("/srv/shared"), "Permission denied (os error 13)".to_string()),
+        ];
+        let skipped = skipped_projects(&results);
+        Summary {
+            total_projects: 4,
+            cleaned: 2,
+            failed: 1,
+            skipped_count: skipped.len(),
+            skipped,
+            total_freed_bytes: 12 * 1024 * MB + 300 * MB,
+            results,
+            ..Default::default()
+        }
+    }
+
+    fn plain(width: Option<usize>) -> TableStyle {
+        TableStyle {
+            width,
+            color: false,
+            size_colors: SizeColors::default(),
+            glyphs: &UNICODE,
+        }
+    }
+
+    #[test]
+    fn test_table_without_terminal_is_untruncated() {
+        assert_eq!(
+            render_summary_table(&summary(), &plain(None)),
+            "  Project                                          Freed  Status\n  \
+             /home/me/code/app                            12.00 GiB  cleaned\n  \
+             /home/me/code/tools/very-long-project-name  300.00 MiB  cleaned\n  \
+             /home/me/code/lib                                  0 B  skipped: in use\n  \
+             /srv/shared                                        0 B  failed: Permission denied (os error 13)\n  \
+             ───────────────────────────────────────────────────────────────────────────────────────────────\n  \
+             Total (4 project(s))                         12.29 GiB  2 cleaned, 1 failed, 1 skipped\n"
+        );
+    }
+
+    #[test]
+    fn test_table_fits_the_terminal() {
+        let table = render_summary_table(&summary(), &plain(Some(80)));
+        assert_eq!(
+            table,
+            "  Project                         Freed  Status\n  \
+             /home/me/code/app           12.00 GiB  cleaned\n  \
+             …s/very-long-project-name  300.00 MiB  cleaned\n  \
+             /home/me/code/lib                 0 B  skipped: in use\n  \
+             /srv/shared                       0 B  failed: Permission denied (os error 13)\n  \
+             ──────────────────────────────────────────────────────────────────────────────\n  \
+             Total (4 project(s))        12.29 GiB  2 cleaned, 1 failed, 1 skipped\n"
+        );
+        assert!(table.lines().all(|line| line.chars().count() <= 80));
+
+        // Too narrow for the full status: the project column stops shrinking and the status gives way
+        let table = render_summary_table(&summary(), &plain(Some(50)));
+        assert_eq!(
+            table,
+            "  Project                        Freed  Status\n  \
+             /home/me/code/app          12.00 GiB  cleaned\n  \
+             …/very-long-project-name  300.00 MiB  cleaned\n  \
+             /home/me/code/lib                0 B  skipped: …\n  \
+             /srv/shared                      0 B  failed: P…\n  \
+             ────────────────────────────────────────────────\n  \
+             Total (4 project(s))       12.29 GiB  2 cleaned…\n"
+        );
+        assert!(table.lines().all(|line| line.chars().count() <= 50));
+    }
+
+    #[test]
+    fn test_ascii_table() {
+        let style = TableStyle {
+            glyphs: &ASCII,
+            ..plain(Some(60))
+        };
+        let mut summary = summary();
+        summary.results[0].estimate = Some(crate::estimate::SizeEstimate {
+            bytes: 12 * 1024 * MB,
+            estimated: true,
+            low: 11 * 1024 * MB,
+            high: 14 * 1024 * MB,
+        });
+        let table = render_summary_table(&summary, &style);
+        assert!(table.is_ascii(), "{}", table);
+        assert!(table.contains("  ...ery-long-project-name         300.00 MiB  cleaned\n"));
+        assert!(table.contains("  /home/me/code/app         ~ 11.00-14.00 GiB  cleaned\n"));
+        assert!(table.contains("  /srv/shared                             0 B  failed: Pe...\n"));
+        assert!(table.lines().all(|line| line.len() <= 60));
+    }
+
+    #[test]
+    fn test_sizes_are_colored_by_magnitude() {
+        let colors = SizeColors::default();
+        assert_eq!(colors.magnitude(99 * MB), Magnitude::Small);
+        assert_eq!(colors.magnitude(100 * MB), Magnitude::Medium);
+        assert_eq!(colors.magnitude(1024 * MB), Magnitude::Large);
+        assert_eq!(colors.magnitude(10 * 1024 * MB), Magnitude::Huge);
+
+        let colors = SizeColors::parse("1MB, 10MB,1GB").unwrap();
+        assert_eq!(colors.magnitude(20 * MB), Magnitude::Large);
+        assert!(SizeColors::parse("1GB,10MB,20GB").is_err());
+        assert!(SizeColors::parse("1GB,10GB").is_err());
+        assert!(SizeColors::parse("1GB,lots,20GB").is_err());
+    }
+}
@@ -0,0 +1,359 @@
+use crate::glyphs::Glyphs;
+use crate::utils::{format_bytes, get_directory_size, profile_dirs, serialize_rfc3339, units};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// Target directory size of a single discovered project
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ProjectSize {
+    pub path: PathBuf,
+    pub target_bytes: u64,
+    /// When the target was last built into
+    #[serde(serialize_with = "serialize_rfc3339")]
+    pub last_build: Option<SystemTime>,
+}
+
+/// A node in the size tree: either a project or a directory grouping projects
+#[derive(Debug, serde::Serialize)]
+pub struct SizeNode {
+    pub name: String,
+    pub total_bytes: u64,
+    pub is_project: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<SizeNode>,
+}
+
+impl SizeNode {
+    fn new(name: String) -> Self {
+        SizeNode {
+            name,
+            total_bytes: 0,
+            is_project: false,
+            children: Vec::new(),
+        }
+    }
+}
+
+/// Group projects by their directory prefixes below `root`
+///
+/// Every node carries the aggregate target size of the projects beneath it.
+/// Chains of directories with a single child are collapsed into one node and
+/// children are sorted by size, largest first.
+pub fn build_size_tree(root: &Path, projects: &[ProjectSize]) -> SizeNode {
+    let mut tree = SizeNode::new(root.display().to_string());
+
+    for project in projects {
+        let relative = project.path.strip_prefix(root).unwrap_or(&project.path);
+        let mut node = &mut tree;
+        node.total_bytes += project.target_bytes;
+
+        for component in relative.components() {
+            let name = match component {
+                Component::Normal(name) => name.to_string_lossy().to_string(),
+                _ => continue,
+            };
+            let idx = match node.children.iter().position(|c| c.name == name) {
+                Some(idx) => idx,
+                None => {
+                    node.children.push(SizeNode::new(name));
+                    node.children.len() - 1
+                }
+            };
+            node = &mut node.children[idx];
+            node.total_bytes += project.target_bytes;
+        }
+        node.is_project = true;
+    }
+
+    for child in &mut tree.children {
+        collapse(child);
+    }
+    sort_by_size(&mut tree);
+    tree
+}
+
+/// Merge single-child chains of non-project directories into one node
+fn collapse(node: &mut SizeNode) {
+    while !node.is_project && node.children.len() == 1 {
+        let child = node.children.pop().unwrap();
+        node.name = format!("{}/{}", node.name, child.name);
+        node.is_project = child.is_project;
+        node.children = child.children;
+    }
+    for child in &mut node.children {
+        collapse(child);
+    }
+}
+
+fn sort_by_size(node: &mut SizeNode) {
+    node.children
+        .sort_by(|a, b| b.total_bytes.cmp(&a.total_bytes).then_with(|| a.name.cmp(&b.name)));
+    for child in &mut node.children {
+        sort_by_size(child);
+    }
+}
+
+/// Render the size tree as indented lines
+pub fn render_size_tree(tree: &SizeNode, glyphs: &Glyphs) -> Vec<String> {
+    let mut lines = vec![format!("{} ({})", tree.name, format_bytes(tree.total_bytes, units()))];
+    render_children(tree, "", glyphs, &mut lines);
+    lines
+}
+
+fn render_children(node: &SizeNode, prefix: &str, glyphs: &Glyphs, lines: &mut Vec<String>) {
+    let count = node.children.len();
+    for (i, child) in node.children.iter().enumerate() {
+        let last = i + 1 == count;
+        let branch = if last { glyphs.last_branch } else { glyphs.branch };
+        lines.push(format!(
+            "{}{}{} ({})",
+            prefix,
+            branch,
+            child.name,
+            format_bytes(child.total_bytes, units())
+        ));
+        let next_prefix = format!("{}{}", prefix, if last { "    " } else { glyphs.pipe });
+        render_children(child, &next_prefix, glyphs, lines);
+    }
+}
+
+/// Compiled size attributed to one dependency
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct DepSize {
+    pub name: String,
+    pub bytes: u64,
+}
+
+/// Largest compiled dependencies of one project
+#[derive(Debug, serde::Serialize)]
+pub struct ProjectDepSizes {
+    pub path: PathBuf,
+    pub deps: Vec<DepSize>,
+}
+
+/// Extensions of compiler outputs in `deps/`, and whether they carry a `lib` prefix
+const ARTIFACT_EXTENSIONS: &[(&str, bool)] = &[
+    ("rlib", true),
+    ("rmeta", true),
+    ("so", true),
+    ("dylib", true),
+    ("a", true),
+    ("d", false),
+    ("dll", false),
+    ("dll.lib", false),
+    ("dll.exp", false),
+    ("lib", false),
+    ("pdb", false),
+    ("exe", false),
+    ("wasm", false),
+];
+
+/// Crate name of an artifact in `deps/` or a directory in `build/`
+///
+/// Artifacts follow `[lib]<name>-<16 hex digit hash>[.<ext>]`. Dashes in the
+/// name are normalized to underscores so a crate's `build/` output groups
+/// with its `deps/` files.
+pub fn parse_artifact_name(file_name: &str) -> Option<String> {
+    let (stem, lib_prefixed) = ARTIFACT_EXTENSIONS
+        .iter()
+        .filter_map(|(ext, lib)| Some((file_name.strip_suffix(ext)?.strip_suffix('.')?, *lib)))
+        // Longest extension first, so `.dll.lib` wins over `.lib`
+        .min_by_key(|(stem, _)| stem.len())
+        .unwrap_or((file_name, false));
+
+    let (name, hash) = stem.rsplit_once('-')?;
+    if hash.len() != 16 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let name = if lib_prefixed { name.strip_prefix("lib")? } else { name };
+    if name.is_empty() {
+        return None;
+    }
+    Some(name.replace('-', "_"))
+}
+
+/// Sum compiled artifact sizes per crate across every profile of a target
+///
+/// Covers files in `deps/` and build-script directories in `build/`.
+/// Results are sorted largest first.
+pub fn dep_sizes(target_dir: &Path) -> Vec<DepSize> {
+    let mut sizes: HashMap<String, u64> = HashMap::new();
+
+    for profile in profile_dirs(target_dir) {
+        for dir in ["deps", "build"] {
+            let Ok(entries) = std::fs::read_dir(profile.join(dir)) else {
+                continue;
+            };
+            for entry in entries.filter_map(|e| e.ok()) {
+                let Some(name) = parse_artifact_name(&entry.file_name().to_string_lossy()) else {
+                    continue;
+                };
+                let path = entry.path();
+                let bytes = if path.is_dir() {
+                    get_directory_size(&path).unwrap_or(0)
+                } else {
+                    entry.metadata().map(|m| m.len()).unwrap_or(0)
+                };
+                *sizes.entry(name).or_default() += bytes;
+            }
+        }
+    }
+
+    sort_dep_sizes(sizes)
+}
+
+/// Add up dependency sizes across projects, largest first
+pub fn aggregate_dep_sizes(projects: &[ProjectDepSizes]) -> Vec<DepSize> {
+    let mut sizes: HashMap<String, u64> = HashMap::new();
+    for dep in projects.iter().flat_map(|p| &p.deps) {
+        *sizes.entry(dep.name.clone()).or_default() += dep.bytes;
+    }
+    sort_dep_sizes(sizes)
+}
+
+fn sort_dep_sizes(sizes: HashMap<String, u64>) -> Vec<DepSize> {
+    let mut sizes: Vec<DepSize> = sizes.into_iter().map(|(name, bytes)| DepSize { name, bytes }).collect();
+    sizes.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glyphs::{ASCII, UNICODE};
+
+    fn project(path: &str, target_bytes: u64) -> ProjectSize {
+        ProjectSize {
+            path: PathBuf::from(path),
+            target_bytes,
+            last_build: None,
+        }
+    }
+
+    #[test]
+    fn test_build_size_tree_aggregates_and_sorts() {
+        let projects = vec![
+            project("/src/small", 10),
+            project("/src/group/a", 100),
+            project("/src/group/b", 300),
+        ];
+        let tree = build_size_tree(Path::new("/src"), &projects);
+
+        assert_eq!(tree.total_bytes, 410);
+        assert_eq!(tree.children.len(), 2);
+        assert_eq!(tree.children[0].name, "group");
+        assert_eq!(tree.children[0].total_bytes, 400);
+        assert_eq!(tree.children[0].children[0].name, "b");
+        assert_eq!(tree.children[1].name, "small");
+        assert!(tree.children[1].is_project);
+    }
+
+    #[test]
+    fn test_build_size_tree_collapses_single_child_chains() {
+        let projects = vec![
+            project("/src/work/rust/deep/app", 50),
+            project("/src/other", 5),
+        ];
+        let tree = build_size_tree(Path::new("/src"), &projects);
+
+        assert_eq!(tree.children[0].name, "work/rust/deep/app");
+        assert!(tree.children[0].is_project);
+        assert!(tree.children[0].children.is_empty());
+    }
+
+    #[test]
+    fn test_build_size_tree_keeps_nested_projects() {
+        let projects = vec![project("/src/ws", 20), project("/src/ws/inner/crate", 30)];
+        let tree = build_size_tree(Path::new("/src"), &projects);
+
+        let ws = &tree.children[0];
+        assert_eq!(ws.name, "ws");
+        assert!(ws.is_project);
+        assert_eq!(ws.total_bytes, 50);
+        assert_eq!(ws.children[0].name, "inner/crate");
+    }
+
+    #[test]
+    fn test_render_size_tree() {
+        let projects = vec![project("/src/a", 2048), project("/src/b/c", 1024)];
+        let tree = build_size_tree(Path::new("/src"), &projects);
+        let lines = render_size_tree(&tree, &UNICODE);
+
+        assert_eq!(
+            lines,
+            vec![
+                "/src (3.00 KiB)".to_string(),
+                "├── a (2.00 KiB)".to_string(),
+                "└── b/c (1.00 KiB)".to_string(),
+            ]
+        );
+
+        let projects = vec![project("/src/a", 2048), project("/src/a/x", 10), project("/src/a/y", 20), project("/src/b", 1)];
+        let tree = build_size_tree(Path::new("/src"), &projects);
+        assert_eq!(
+            render_size_tree(&tree, &ASCII),
+            vec!["/src (2.03 KiB)", "|-- a (2.03 KiB)", "|   |-- y (20 B)", "|   `-- x (10 B)", "`-- b (1 B)"]
+        );
+    }
+
+    #[test]
+    fn test_parse_artifact_name() {
+        let cases = [
+            ("libserde-1f2e3d4c5b6a7980.rlib", Some("serde")),
+            ("libserde-1f2e3d4c5b6a7980.rmeta", Some("serde")),
+            ("serde-1f2e3d4c5b6a7980.d", Some("serde")),
+            ("libserde_json-0123456789abcdef.rlib", Some("serde_json")),
+            ("liblibc-0123456789abcdef.rlib", Some("libc")),
+            ("libc-0123456789abcdef.d", Some("libc")),
+            ("libproc_macro2-0123456789abcdef.so", Some("proc_macro2")),
+            ("libserde_derive-0123456789abcdef.dylib", Some("serde_derive")),
+            ("serde_derive-0123456789abcdef.dll", Some("serde_derive")),
+            ("serde_derive-0123456789abcdef.dll.lib", Some("serde_derive")),
+            ("cargo_deepclean-47d21e877045447d", Some("cargo_deepclean")),
+            ("app-47d21e877045447d.exe", Some("app")),
+            ("app-47d21e877045447d.pdb", Some("app")),
+            ("crossbeam-utils-55d8ca1cbc0542c4", Some("crossbeam_utils")),
+            ("libfoo.rlib", None),
+            ("libfoo-nothex-xyz.rlib", None),
+            ("lib-0123456789abcdef.rlib", None),
+            ("build-script-build", None),
+        ];
+        for (file_name, expected) in cases {
+            assert_eq!(
+                parse_artifact_name(file_name).as_deref(),
+                expected,
+                "parsing {}",
+                file_name
+            );
+        }
+    }
+
+    #[test]
+    fn test_dep_sizes_groups_deps_and_build_dirs() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        let files = [
+            ("debug/deps/libserde-0123456789abcdef.rlib", 300),
+            ("debug/deps/libserde-0123456789abcdef.rmeta", 100),
+            ("debug/deps/serde-0123456789abcdef.d", 5),
+            ("debug/build/serde-fedcba9876543210/build-script-build", 50),
+            ("wasm32-unknown-unknown/release/deps/libserde-1111111111111111.rlib", 45),
+            ("debug/deps/libitoa-0123456789abcdef.rlib", 40),
+        ];
+        for (path, size) in files {
+            let path = target.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, vec![0u8; size]).unwrap();
+        }
+
+        let sizes = dep_sizes(&target);
+        assert_eq!(
+            sizes,
+            vec![
+                DepSize { name: "serde".to_string(), bytes: 500 },
+                DepSize { name: "itoa".to_string(), bytes: 40 },
+            ]
+        );
+    }
+}
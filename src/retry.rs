@@ -0,0 +1,112 @@
+use std::io;
+use std::time::Duration;
+
+/// Wait before the first retry; doubled for each one after that
+pub const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Whether a failed operation is worth trying again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Likely to succeed shortly: a stale NFS handle, a busy or shared file,
+    /// or a directory another client was still writing to
+    Transient,
+    Permanent,
+}
+
+impl ErrorClass {
+    pub fn of(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::StaleNetworkFileHandle | io::ErrorKind::ResourceBusy | io::ErrorKind::DirectoryNotEmpty => {
+                ErrorClass::Transient
+            }
+            // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+            _ if cfg!(windows) && matches!(error.raw_os_error(), Some(32 | 33)) => ErrorClass::Transient,
+            _ => ErrorClass::Permanent,
+        }
+    }
+}
+
+/// The OS error a failed command reported last, e.g. `Directory not empty (os error 39)`
+///
+/// Falls back to an error carrying the whole message, which is never transient.
+pub fn error_from_output(stderr: &str) -> io::Error {
+    stderr
+        .rmatch_indices("(os error ")
+        .find_map(|(start, marker)| {
+            let code = &stderr[start + marker.len()..];
+            code[..code.find(')')?].parse().ok()
+        })
+        .map(io::Error::from_raw_os_error)
+        .unwrap_or_else(|| io::Error::other(stderr.trim().to_string()))
+}
+
+/// Run `op`, retrying transient failures up to `retries` times with
+/// exponential backoff starting at `backoff`
+///
+/// Returns the final outcome and how many attempts were made.
+pub fn with_retries<T>(retries: u32, backoff: Duration, mut op: impl FnMut() -> io::Result<T>) -> (io::Result<T>, u32) {
+    let mut attempts = 0;
+    let mut delay = backoff;
+    loop {
+        attempts += 1;
+        match op() {
+            Err(e) if attempts <= retries && ErrorClass::of(&e) == ErrorClass::Transient => {
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            outcome => return (outcome, attempts),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// A removal that fails with `error` on its first call and succeeds after
+    fn flaky_removal(error: io::ErrorKind) -> impl FnMut() -> io::Result<()> {
+        let calls = Cell::new(0);
+        move || {
+            calls.set(calls.get() + 1);
+            if calls.get() == 1 {
+                Err(io::Error::from(error))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn test_transient_failure_is_retried() {
+        let (outcome, attempts) = with_retries(2, Duration::ZERO, flaky_removal(io::ErrorKind::StaleNetworkFileHandle));
+        assert!(outcome.is_ok());
+        assert_eq!(attempts, 2);
+
+        // Without retries the first failure stands
+        let (outcome, attempts) = with_retries(0, Duration::ZERO, flaky_removal(io::ErrorKind::DirectoryNotEmpty));
+        assert!(outcome.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_permanent_failure_is_not_retried() {
+        let (outcome, attempts) = with_retries(3, Duration::ZERO, flaky_removal(io::ErrorKind::PermissionDenied));
+        assert_eq!(outcome.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_error_from_cargo_output() {
+        let stderr = "error: failed to remove build artifact\n\nCaused by:\n  Directory not empty (os error 39)\n";
+        let error = error_from_output(stderr);
+        if cfg!(target_os = "linux") {
+            assert_eq!(ErrorClass::of(&error), ErrorClass::Transient);
+        }
+        assert_eq!(error.raw_os_error(), Some(39));
+
+        let error = error_from_output("error: could not find `Cargo.toml`\n");
+        assert_eq!(ErrorClass::of(&error), ErrorClass::Permanent);
+        assert_eq!(error.to_string(), "error: could not find `Cargo.toml`");
+    }
+}
@@ -0,0 +1,161 @@
+use anyhow::{Context, Result};
+use crate::utils::fnv1a;
+use fs2::FileExt;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Exit code when another run holds the lock (EX_TEMPFAIL: try again later)
+pub const EXIT_LOCKED: i32 = 75;
+
+/// How often a waiting run retries the lock
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Another run is cleaning the same root
+#[derive(Debug)]
+pub struct LockHeld {
+    pub root: PathBuf,
+    pub lock_path: PathBuf,
+    /// PID recorded by the holder, if it could be read
+    pub pid: Option<u32>,
+}
+
+impl std::fmt::Display for LockHeld {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.pid {
+            Some(pid) => write!(f, "Another deepclean run (PID {}) is already cleaning {:?}", pid, self.root),
+            None => write!(f, "Another deepclean run is already cleaning {:?}", self.root),
+        }?;
+        write!(f, " (lock file {:?}; use --wait-lock to wait for it)", self.lock_path)
+    }
+}
+
+impl std::error::Error for LockHeld {}
+
+/// Exclusive claim on a scan root, released when dropped
+#[derive(Debug)]
+pub struct RunLock {
+    file: File,
+    /// PID left in the lock file by a run that exited without releasing it
+    pub stale_pid: Option<u32>,
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        // An empty lock file marks a clean release; closing the file unlocks it
+        let _ = self.file.set_len(0);
+    }
+}
+
+/// Directory holding run locks: `$XDG_RUNTIME_DIR`, or the temp directory
+pub fn lock_dir() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// Lock file for a scan root, named after a hash of its path
+pub fn lock_path(lock_dir: &Path, root: &Path) -> PathBuf {
+    let hash = fnv1a(root.to_string_lossy().as_bytes());
+    lock_dir.join(format!("deepclean-{:016x}.lock", hash))
+}
+
+/// Take the run lock for `root`, waiting up to `wait` for another run to finish
+///
+/// The lock is an advisory `flock`, which the OS releases when its holder
+/// exits, so a crashed run never blocks later ones; the PID it left behind is
+/// reported as `stale_pid`. Fails with `LockHeld` if the lock stays taken.
+pub fn acquire(lock_dir: &Path, root: &Path, wait: Option<Duration>) -> Result<RunLock> {
+    let lock_path = lock_path(lock_dir, root);
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .with_context(|| format!("Failed to open lock file {:?}", lock_path))?;
+
+    let deadline = Instant::now() + wait.unwrap_or_default();
+    while file.try_lock_exclusive().is_err() {
+        if Instant::now() >= deadline {
+            return Err(LockHeld {
+                root: root.to_path_buf(),
+                pid: read_pid(&lock_path),
+                lock_path,
+            }
+            .into());
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+
+    let stale_pid = read_pid(&lock_path).filter(|&pid| pid != std::process::id());
+    file.set_len(0)
+        .and_then(|()| file.rewind())
+        .and_then(|()| write!(file, "{}", std::process::id()))
+        .with_context(|| format!("Failed to write lock file {:?}", lock_path))?;
+    Ok(RunLock { file, stale_pid })
+}
+
+fn read_pid(lock_path: &Path) -> Option<u32> {
+    let mut content = String::new();
+    File::open(lock_path).ok()?.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_second_run_backs_off() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("code");
+
+        // flock locks belong to the open file, so a second open in this
+        // process contends too; tests/run_lock.rs covers separate processes
+        let first = acquire(temp_dir.path(), &root, None).unwrap();
+        let error = acquire(temp_dir.path(), &root, Some(Duration::from_millis(250))).unwrap_err();
+        let held = error.downcast_ref::<LockHeld>().unwrap();
+        assert_eq!(held.pid, Some(std::process::id()));
+        assert!(error.to_string().contains(&format!("PID {}", std::process::id())));
+
+        // Other roots are independent
+        assert!(acquire(temp_dir.path(), &temp_dir.path().join("other"), None).is_ok());
+
+        drop(first);
+        assert!(acquire(temp_dir.path(), &root, None).is_ok());
+    }
+
+    #[test]
+    fn test_waiting_run_gets_the_lock_once_released() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("code");
+        let first = acquire(temp_dir.path(), &root, None).unwrap();
+
+        let releaser = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            drop(first);
+        });
+        assert!(acquire(temp_dir.path(), &root, Some(Duration::from_secs(10))).is_ok());
+        releaser.join().unwrap();
+    }
+
+    #[test]
+    fn test_stale_lock_is_broken() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("code");
+        // A crashed run leaves its PID behind, but no lock
+        std::fs::write(lock_path(temp_dir.path(), &root), "999999999").unwrap();
+
+        let lock = acquire(temp_dir.path(), &root, None).unwrap();
+        assert_eq!(lock.stale_pid, Some(999_999_999));
+        assert_eq!(read_pid(&lock_path(temp_dir.path(), &root)), Some(std::process::id()));
+
+        // A clean release leaves nothing to report
+        drop(lock);
+        assert_eq!(acquire(temp_dir.path(), &root, None).unwrap().stale_pid, None);
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::{Context, Result};
+use crate::utils::get_directory_size;
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+#[derive(Debug, serde::Serialize)]
+pub struct SccacheReport {
+    pub path: PathBuf,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    pub removed_files: usize,
+}
+
+/// Resolve sccache's local cache directory
+///
+/// `SCCACHE_DIR` wins; otherwise this is sccache's platform default
+/// (`$XDG_CACHE_HOME/sccache` or `~/.cache/sccache` on Linux,
+/// `~/Library/Caches/Mozilla.sccache` on macOS and
+/// `%LOCALAPPDATA%\Mozilla\sccache\cache` on Windows).
+pub fn resolve_sccache_dir(
+    sccache_dir_env: Option<OsString>,
+    cache_home_env: Option<OsString>,
+    home_dir: Option<PathBuf>,
+) -> Option<PathBuf> {
+    if let Some(dir) = sccache_dir_env.filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    if cfg!(windows) {
+        cache_home_env.map(|local| PathBuf::from(local).join("Mozilla").join("sccache").join("cache"))
+    } else if cfg!(target_os = "macos") {
+        home_dir.map(|home| home.join("Library/Caches/Mozilla.sccache"))
+    } else {
+        cache_home_env
+            .filter(|d| !d.is_empty())
+            .map(|cache| PathBuf::from(cache).join("sccache"))
+            .or_else(|| home_dir.map(|home| home.join(".cache/sccache")))
+    }
+}
+
+/// sccache's cache directory for the current process
+pub fn sccache_dir() -> Option<PathBuf> {
+    let cache_home = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA")
+    } else {
+        std::env::var_os("XDG_CACHE_HOME")
+    };
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+    resolve_sccache_dir(std::env::var_os("SCCACHE_DIR"), cache_home, home)
+}
+
+/// Whether an `sccache` binary can be run from PATH
+fn sccache_on_path() -> bool {
+    Command::new("sccache")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Most recent use of a cache entry: the later of its access and modification times
+fn last_used(metadata: &fs::Metadata) -> SystemTime {
+    match (metadata.accessed(), metadata.modified()) {
+        (Ok(a), Ok(m)) => a.max(m),
+        (Ok(t), _) | (_, Ok(t)) => t,
+        _ => SystemTime::UNIX_EPOCH,
+    }
+}
+
+/// Remove the least recently used files until the cache is at most `max_bytes`
+///
+/// Returns the number of files removed (or that would be with `dry_run`)
+/// and the resulting cache size.
+fn prune_oldest(dir: &Path, max_bytes: u64, dry_run: bool) -> Result<(usize, u64)> {
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| {
+            let metadata = e.metadata().ok()?;
+            Some((last_used(&metadata), metadata.len(), e.into_path()))
+        })
+        .collect();
+    files.sort();
+
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    let mut removed = 0;
+    for (_, len, path) in files {
+        if total <= max_bytes {
+            break;
+        }
+        if !dry_run {
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e).with_context(|| format!("Failed to remove {:?}", path)),
+            }
+        }
+        total -= len;
+        removed += 1;
+    }
+    Ok((removed, total))
+}
+
+/// Measure (and with `clean`, shrink) sccache's local cache
+///
+/// With `max_bytes`, the least recently used entries are pruned until the
+/// cache fits; otherwise the whole cache is deleted. When sccache is on PATH
+/// its statistics are zeroed after a real clean. With `dry_run`, the report
+/// describes the projected result. Returns `None` when there is no cache
+/// directory.
+pub fn clean_sccache(dir: &Path, clean: bool, max_bytes: Option<u64>, dry_run: bool) -> Result<Option<SccacheReport>> {
+    if !dir.is_dir() {
+        return Ok(None);
+    }
+
+    let bytes_before = get_directory_size(dir).unwrap_or(0);
+    let (removed_files, bytes_after) = match (clean, max_bytes) {
+        (false, _) => (0, bytes_before),
+        (true, Some(max_bytes)) => prune_oldest(dir, max_bytes, dry_run)?,
+        (true, None) => {
+            let files = WalkDir::new(dir)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+                .count();
+            if !dry_run {
+                fs::remove_dir_all(dir).with_context(|| format!("Failed to remove {:?}", dir))?;
+            }
+            (files, 0)
+        }
+    };
+
+    if clean && !dry_run && removed_files > 0 && sccache_on_path() {
+        let _ = Command::new("sccache")
+            .arg("--zero-stats")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+
+    Ok(Some(SccacheReport {
+        path: dir.to_path_buf(),
+        bytes_before,
+        bytes_after,
+        removed_files,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::cutoff_for_days;
+    use filetime::FileTime;
+    use tempfile::TempDir;
+
+    fn write_entry(dir: &Path, name: &str, bytes: usize, age_days: u64) {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, vec![0u8; bytes]).unwrap();
+        let time = FileTime::from_system_time(cutoff_for_days(age_days));
+        filetime::set_file_times(&path, time, time).unwrap();
+    }
+
+    fn fake_cache() -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        write_entry(temp_dir.path(), "a/b/oldest", 100, 30);
+        write_entry(temp_dir.path(), "c/d/older", 100, 20);
+        write_entry(temp_dir.path(), "e/f/newest", 100, 1);
+        temp_dir
+    }
+
+    #[test]
+    fn test_resolve_sccache_dir_prefers_env() {
+        assert_eq!(
+            resolve_sccache_dir(Some("/tmp/sccache".into()), None, Some("/home/me".into())),
+            Some(PathBuf::from("/tmp/sccache"))
+        );
+        #[cfg(target_os = "linux")]
+        assert_eq!(
+            resolve_sccache_dir(None, None, Some("/home/me".into())),
+            Some(PathBuf::from("/home/me/.cache/sccache"))
+        );
+    }
+
+    #[test]
+    fn test_missing_cache_is_skipped() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(clean_sccache(&temp_dir.path().join("none"), true, None, false)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_prune_to_max_size_removes_oldest() {
+        let cache = fake_cache();
+        let report = clean_sccache(cache.path(), true, Some(150), false).unwrap().unwrap();
+        assert_eq!(report.bytes_before, 300);
+        assert_eq!(report.bytes_after, 100);
+        assert_eq!(report.removed_files, 2);
+        assert!(!cache.path().join("a/b/oldest").exists());
+        assert!(!cache.path().join("c/d/older").exists());
+        assert!(cache.path().join("e/f/newest").exists());
+    }
+
+    #[test]
+    fn test_clean_without_max_size_deletes_everything() {
+        let cache = fake_cache();
+        let report = clean_sccache(cache.path(), true, None, true).unwrap().unwrap();
+        assert_eq!(report.removed_files, 3);
+        assert!(cache.path().join("a/b/oldest").exists());
+
+        let report = clean_sccache(cache.path(), true, None, false).unwrap().unwrap();
+        assert_eq!(report.bytes_after, 0);
+        assert!(!cache.path().exists());
+    }
+}
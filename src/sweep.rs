@@ -0,0 +1,160 @@
+use anyhow::Result;
+use crate::utils::{profile_dirs, PROFILE_MARKERS};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SweepStats {
+    pub freed_bytes: u64,
+    pub removed_files: usize,
+}
+
+/// Find the sweepable artifact directories (deps, .fingerprint, build and
+/// incremental) below a target directory
+fn sweep_roots(target_dir: &Path) -> Vec<PathBuf> {
+    profile_dirs(target_dir)
+        .iter()
+        .flat_map(|profile| PROFILE_MARKERS.iter().map(move |d| profile.join(d)))
+        .filter(|d| d.is_dir())
+        .collect()
+}
+
+/// Most recent use of a file: the later of its access and modification times
+fn last_used(metadata: &fs::Metadata) -> Option<SystemTime> {
+    match (metadata.accessed().ok(), metadata.modified().ok()) {
+        (Some(a), Some(m)) => Some(a.max(m)),
+        (a, m) => a.or(m),
+    }
+}
+
+/// Delete artifacts in a target directory that haven't been used since `cutoff`
+///
+/// Files that disappear while sweeping (a concurrent build or clean) are
+/// silently ignored. With `dry_run`, nothing is removed but the stats
+/// describe what would have been.
+pub fn sweep_target(target_dir: &Path, cutoff: SystemTime, dry_run: bool) -> Result<SweepStats> {
+    let mut stats = SweepStats::default();
+
+    for root in sweep_roots(target_dir) {
+        for entry in WalkDir::new(&root).into_iter().filter_map(|e| e.ok()) {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            match last_used(&metadata) {
+                Some(used) if used < cutoff => {}
+                _ => continue,
+            }
+
+            if !dry_run {
+                match fs::remove_file(entry.path()) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            stats.freed_bytes += metadata.len();
+            stats.removed_files += 1;
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Mount options of the filesystem containing `path`, from /proc/self/mounts
+#[cfg(target_os = "linux")]
+fn mount_options(path: &Path) -> Option<Vec<String>> {
+    let path = path.canonicalize().ok()?;
+    let mounts = fs::read_to_string("/proc/self/mounts").ok()?;
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let mount_point = fields.get(1)?.replace("\\040", " ");
+            let options = fields.get(3)?;
+            path.starts_with(&mount_point).then(|| (mount_point, options.to_string()))
+        })
+        .max_by_key(|(mount_point, _)| mount_point.len())
+        .map(|(_, options)| options.split(',').map(|o| o.to_string()).collect())
+}
+
+/// Whether access times on the target's filesystem can be trusted for a sweep
+///
+/// `noatime` mounts never update access times, and `relatime` only refreshes
+/// them once a day, which is too coarse for cutoffs shorter than a day.
+#[cfg(target_os = "linux")]
+pub fn timestamps_reliable(target_dir: &Path, days: u64) -> bool {
+    match mount_options(target_dir) {
+        Some(options) => {
+            let has = |opt: &str| options.iter().any(|o| o == opt);
+            !has("noatime") && (days > 0 || !has("relatime"))
+        }
+        None => false,
+    }
+}
+
+/// Mount options can't be inspected here, so strict sweeps never trust timestamps
+#[cfg(not(target_os = "linux"))]
+pub fn timestamps_reliable(_target_dir: &Path, _days: u64) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::cutoff_for_days;
+    use filetime::FileTime;
+    use tempfile::TempDir;
+
+    fn write_aged(path: &Path, bytes: usize, age_days: u64) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, vec![0u8; bytes]).unwrap();
+        let time = FileTime::from_system_time(cutoff_for_days(age_days));
+        filetime::set_file_times(path, time, time).unwrap();
+    }
+
+    #[test]
+    fn test_sweep_removes_only_stale_artifacts() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        write_aged(&target.join("debug/deps/libold-abc.rlib"), 100, 30);
+        write_aged(&target.join("debug/.fingerprint/old-abc/lib-old"), 10, 30);
+        write_aged(&target.join("debug/deps/libnew-def.rlib"), 200, 0);
+        write_aged(&target.join("wasm32-unknown-unknown/release/build/old-123/output"), 50, 30);
+        // Outside the sweepable directories: never touched
+        write_aged(&target.join("debug/app"), 300, 30);
+
+        let stats = sweep_target(&target, cutoff_for_days(7), false).unwrap();
+        assert_eq!(stats.removed_files, 3);
+        assert_eq!(stats.freed_bytes, 160);
+        assert!(!target.join("debug/deps/libold-abc.rlib").exists());
+        assert!(!target.join("wasm32-unknown-unknown/release/build/old-123/output").exists());
+        assert!(target.join("debug/deps/libnew-def.rlib").exists());
+        assert!(target.join("debug/app").exists());
+    }
+
+    #[test]
+    fn test_sweep_dry_run_keeps_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        write_aged(&target.join("release/incremental/app-xyz/s-1/dep-graph.bin"), 64, 10);
+
+        let stats = sweep_target(&target, cutoff_for_days(7), true).unwrap();
+        assert_eq!(stats.removed_files, 1);
+        assert_eq!(stats.freed_bytes, 64);
+        assert!(target.join("release/incremental/app-xyz/s-1/dep-graph.bin").exists());
+    }
+
+    #[test]
+    fn test_sweep_missing_target() {
+        let temp_dir = TempDir::new().unwrap();
+        let stats = sweep_target(&temp_dir.path().join("target"), cutoff_for_days(1), false).unwrap();
+        assert_eq!(stats, SweepStats::default());
+    }
+}
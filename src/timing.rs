@@ -0,0 +1,273 @@
+use cargo_metadata::{Metadata, MetadataCommand};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How many of the slowest projects are reported per phase
+pub const SLOWEST_PROJECTS: usize = 5;
+
+/// Parts of a run `--timings` reports on, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Discovery,
+    /// Time spent in `cargo metadata`, during discovery and cleaning
+    WorkspaceResolution,
+    Sizing,
+    Cleaning,
+    Reporting,
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Phase::Discovery => write!(f, "discovery"),
+            Phase::WorkspaceResolution => write!(f, "workspace resolution"),
+            Phase::Sizing => write!(f, "sizing"),
+            Phase::Cleaning => write!(f, "cleaning"),
+            Phase::Reporting => write!(f, "reporting"),
+        }
+    }
+}
+
+/// Where a run's time went, as reported by `--timings`
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct Timings {
+    /// Phases that ran, in order
+    pub phases: Vec<PhaseTiming>,
+    pub cargo_metadata_calls: u64,
+    /// Lookups answered from the metadata cache instead of running cargo
+    pub cargo_metadata_cache_hits: u64,
+    pub dirs_walked: u64,
+    /// Up to `SLOWEST_PROJECTS` projects, slowest first
+    pub slowest_sizing: Vec<ProjectTiming>,
+    pub slowest_cleaning: Vec<ProjectTiming>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct PhaseTiming {
+    pub phase: Phase,
+    pub millis: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ProjectTiming {
+    pub path: String,
+    pub millis: u64,
+}
+
+/// Collects phase durations and work counters
+///
+/// Always on: a run records a handful of durations and bumps two counters,
+/// which costs nothing next to the I/O being measured.
+#[derive(Default)]
+pub struct Recorder {
+    phases: Mutex<BTreeMap<Phase, Duration>>,
+    projects: Mutex<Vec<(Phase, String, Duration)>>,
+    cargo_metadata_calls: AtomicU64,
+    cargo_metadata_cache_hits: AtomicU64,
+    dirs_walked: AtomicU64,
+}
+
+static RECORDER: Recorder = Recorder::new();
+
+impl Recorder {
+    pub const fn new() -> Self {
+        Recorder {
+            phases: Mutex::new(BTreeMap::new()),
+            projects: Mutex::new(Vec::new()),
+            cargo_metadata_calls: AtomicU64::new(0),
+            cargo_metadata_cache_hits: AtomicU64::new(0),
+            dirs_walked: AtomicU64::new(0),
+        }
+    }
+
+    /// Time a phase until the returned guard is dropped
+    ///
+    /// A phase timed more than once adds up.
+    pub fn phase(&self, phase: Phase) -> Timer<'_> {
+        self.timer(phase, None)
+    }
+
+    /// Time one project's share of a phase until the returned guard is dropped
+    pub fn project(&self, phase: Phase, path: &Path) -> Timer<'_> {
+        self.timer(phase, Some(path.to_string_lossy().to_string()))
+    }
+
+    fn timer(&self, phase: Phase, project: Option<String>) -> Timer<'_> {
+        Timer {
+            recorder: self,
+            phase,
+            project,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn count_cargo_metadata(&self) {
+        self.cargo_metadata_calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count_metadata_cache_hit(&self) {
+        self.cargo_metadata_cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count_dirs_walked(&self, dirs: u64) {
+        self.dirs_walked.fetch_add(dirs, Ordering::Relaxed);
+    }
+
+    /// Everything recorded so far
+    pub fn snapshot(&self) -> Timings {
+        let phases = self
+            .phases
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&phase, elapsed)| PhaseTiming {
+                phase,
+                millis: elapsed.as_millis() as u64,
+            })
+            .collect();
+        let projects = self.projects.lock().unwrap();
+        let slowest = |phase: Phase| {
+            let mut timings: Vec<&(Phase, String, Duration)> = projects.iter().filter(|(p, _, _)| *p == phase).collect();
+            timings.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.1.cmp(&b.1)));
+            timings
+                .into_iter()
+                .take(SLOWEST_PROJECTS)
+                .map(|(_, path, elapsed)| ProjectTiming {
+                    path: path.clone(),
+                    millis: elapsed.as_millis() as u64,
+                })
+                .collect()
+        };
+        Timings {
+            phases,
+            cargo_metadata_calls: self.cargo_metadata_calls.load(Ordering::Relaxed),
+            cargo_metadata_cache_hits: self.cargo_metadata_cache_hits.load(Ordering::Relaxed),
+            dirs_walked: self.dirs_walked.load(Ordering::Relaxed),
+            slowest_sizing: slowest(Phase::Sizing),
+            slowest_cleaning: slowest(Phase::Cleaning),
+        }
+    }
+}
+
+/// Adds the time until it's dropped to its phase, or to its project's share of it
+#[must_use = "the time is recorded when the timer is dropped"]
+pub struct Timer<'a> {
+    recorder: &'a Recorder,
+    phase: Phase,
+    project: Option<String>,
+    started: Instant,
+}
+
+impl Drop for Timer<'_> {
+    fn drop(&mut self) {
+        let elapsed = self.started.elapsed();
+        match self.project.take() {
+            Some(path) => self.recorder.projects.lock().unwrap().push((self.phase, path, elapsed)),
+            None => *self.recorder.phases.lock().unwrap().entry(self.phase).or_default() += elapsed,
+        }
+    }
+}
+
+/// Time a phase of this run, e.g. `let _timer = timing::phase(Phase::Sizing);`
+pub fn phase(phase: Phase) -> Timer<'static> {
+    RECORDER.phase(phase)
+}
+
+/// Time one project's share of a phase of this run
+pub fn project(phase: Phase, path: &Path) -> Timer<'static> {
+    RECORDER.project(phase, path)
+}
+
+/// Count directories visited by a walk
+pub fn count_dirs_walked(dirs: u64) {
+    RECORDER.count_dirs_walked(dirs);
+}
+
+/// Count a `cargo metadata` lookup answered from the cache
+pub fn count_metadata_cache_hit() {
+    RECORDER.count_metadata_cache_hit();
+}
+
+/// Run `cargo metadata`, counting the call and timing it as workspace resolution
+pub fn exec_metadata(command: &MetadataCommand) -> cargo_metadata::Result<Metadata> {
+    crate::utils::ensure_may_spawn("cargo metadata")?;
+    let _timer = phase(Phase::WorkspaceResolution);
+    RECORDER.count_cargo_metadata();
+    command.exec()
+}
+
+/// Everything this run recorded so far
+pub fn snapshot() -> Timings {
+    RECORDER.snapshot()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timers_record_on_drop() {
+        let recorder = Recorder::new();
+        drop(recorder.phase(Phase::Cleaning));
+        {
+            let _timer = recorder.phase(Phase::Discovery);
+            let _nested = recorder.phase(Phase::WorkspaceResolution);
+        }
+        drop(recorder.phase(Phase::Discovery));
+        recorder.count_cargo_metadata();
+        recorder.count_metadata_cache_hit();
+        recorder.count_metadata_cache_hit();
+        recorder.count_dirs_walked(40);
+        recorder.count_dirs_walked(2);
+
+        let timings = recorder.snapshot();
+        // One entry per phase that ran, in run order
+        let phases: Vec<Phase> = timings.phases.iter().map(|p| p.phase).collect();
+        assert_eq!(phases, vec![Phase::Discovery, Phase::WorkspaceResolution, Phase::Cleaning]);
+        assert_eq!(timings.cargo_metadata_calls, 1);
+        assert_eq!(timings.cargo_metadata_cache_hits, 2);
+        assert_eq!(timings.dirs_walked, 42);
+        assert!(timings.slowest_sizing.is_empty());
+    }
+
+    #[test]
+    fn test_slowest_projects() {
+        let recorder = Recorder::new();
+        for i in 0..8u64 {
+            let path = format!("/src/app{}", i);
+            recorder
+                .projects
+                .lock()
+                .unwrap()
+                .push((Phase::Sizing, path, Duration::from_millis(i * 10)));
+        }
+        drop(recorder.project(Phase::Cleaning, Path::new("/src/app0")));
+
+        let timings = recorder.snapshot();
+        let sizing: Vec<&str> = timings.slowest_sizing.iter().map(|p| p.path.as_str()).collect();
+        assert_eq!(sizing, vec!["/src/app7", "/src/app6", "/src/app5", "/src/app4", "/src/app3"]);
+        assert_eq!(timings.slowest_sizing[0].millis, 70);
+        assert_eq!(timings.slowest_cleaning.len(), 1);
+        // Per-project timers don't add to the phase itself
+        assert!(timings.phases.is_empty());
+    }
+
+    #[test]
+    fn test_timings_json_structure() {
+        let recorder = Recorder::new();
+        drop(recorder.phase(Phase::Sizing));
+        drop(recorder.project(Phase::Sizing, Path::new("/src/app")));
+
+        let json = serde_json::to_value(recorder.snapshot()).unwrap();
+        assert_eq!(json["phases"][0]["phase"], "sizing");
+        assert!(json["phases"][0]["millis"].is_u64());
+        assert_eq!(json["cargo_metadata_calls"], 0);
+        assert_eq!(json["dirs_walked"], 0);
+        assert_eq!(json["slowest_sizing"][0]["path"], "/src/app");
+        assert_eq!(json["slowest_cleaning"], serde_json::json!([]));
+    }
+}
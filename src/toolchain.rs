@@ -0,0 +1,513 @@
+use anyhow::{anyhow, Context, Result};
+use crate::utils::{get_directory_size, profile_dirs};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+/// Version line (`rustc 1.75.0 (82e1608df 2023-12-21)`) of every installed compiler
+///
+/// Uses `rustup toolchain list` when rustup is available and falls back to
+/// the `rustc` on PATH otherwise.
+pub fn installed_rustc_versions() -> Result<Vec<String>> {
+    let rustc_version = |cmd: &mut Command| -> Option<String> {
+        let output = cmd.output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        String::from_utf8_lossy(&output.stdout).lines().next().map(|l| l.trim().to_string())
+    };
+
+    let toolchains = Command::new("rustup")
+        .args(["toolchain", "list"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| parse_toolchain_list(&String::from_utf8_lossy(&o.stdout)));
+
+    let mut versions: Vec<String> = match toolchains {
+        Some(toolchains) => toolchains
+            .iter()
+            .filter_map(|tc| rustc_version(Command::new("rustup").args(["run", tc, "rustc", "-V"])))
+            .collect(),
+        None => rustc_version(Command::new("rustc").arg("-V")).into_iter().collect(),
+    };
+
+    versions.sort();
+    versions.dedup();
+    if versions.is_empty() {
+        return Err(anyhow!("Could not determine installed rustc versions (is rustc or rustup on PATH?)"));
+    }
+    Ok(versions)
+}
+
+/// Toolchain names from `rustup toolchain list` output
+pub fn parse_toolchain_list(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|name| !name.is_empty() && *name != "no")
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// Version line of the compiler that last built into `target_dir`, from `.rustc_info.json`
+pub fn last_used_rustc(target_dir: &Path) -> Option<String> {
+    let content = fs::read_to_string(target_dir.join(".rustc_info.json")).ok()?;
+    let info: serde_json::Value = serde_json::from_str(&content).ok()?;
+    info.get("outputs")?
+        .as_object()?
+        .values()
+        .filter_map(|output| output.get("stdout")?.as_str())
+        .find(|stdout| stdout.starts_with("rustc ") && stdout.contains("\nrelease: "))
+        .and_then(|stdout| stdout.lines().next())
+        .map(|line| line.trim().to_string())
+}
+
+/// Artifacts in one profile directory sharing a fingerprint (`<name>-<hash>`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArtifactSet {
+    pub profile_dir: PathBuf,
+    pub stem: String,
+}
+
+/// A group of artifact sets built by the same compiler
+#[derive(Debug)]
+struct CompilerGroup {
+    newest: SystemTime,
+    sets: Vec<ArtifactSet>,
+}
+
+/// Group fingerprints by the compiler hash recorded in their JSON files
+fn group_by_compiler(target_dir: &Path) -> HashMap<u64, CompilerGroup> {
+    let mut groups: HashMap<u64, CompilerGroup> = HashMap::new();
+
+    for profile_dir in profile_dirs(target_dir) {
+        let Ok(entries) = fs::read_dir(profile_dir.join(".fingerprint")) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let fingerprint_dir = entry.path();
+            let Some(rustc_hash) = fingerprint_rustc_hash(&fingerprint_dir) else {
+                continue;
+            };
+            let modified = newest_mtime(&fingerprint_dir);
+            let group = groups.entry(rustc_hash).or_insert(CompilerGroup {
+                newest: SystemTime::UNIX_EPOCH,
+                sets: Vec::new(),
+            });
+            group.newest = group.newest.max(modified);
+            group.sets.push(ArtifactSet {
+                profile_dir: profile_dir.clone(),
+                stem: entry.file_name().to_string_lossy().to_string(),
+            });
+        }
+    }
+
+    groups
+}
+
+/// The `rustc` hash from the first readable fingerprint JSON in a directory
+fn fingerprint_rustc_hash(fingerprint_dir: &Path) -> Option<u64> {
+    fs::read_dir(fingerprint_dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+        .find_map(|p| {
+            let content = fs::read_to_string(&p).ok()?;
+            let json: serde_json::Value = serde_json::from_str(&content).ok()?;
+            json.get("rustc")?.as_u64()
+        })
+}
+
+/// Latest modification time of the entries directly inside `dir`
+pub fn newest_mtime(dir: &Path) -> SystemTime {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .filter_map(|e| e.metadata().ok()?.modified().ok())
+                .max()
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        })
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Find artifact sets built by compilers that are no longer installed
+///
+/// Cargo records an opaque compiler hash in every fingerprint, so versions
+/// can't be read directly. The newest group of fingerprints is attributed to
+/// the compiler named in `.rustc_info.json`; it is stale when that compiler is
+/// not installed. Older groups can only be attributed by elimination: they
+/// are stale when every installed compiler is already accounted for by the
+/// newest group. Anything more ambiguous is kept.
+pub fn find_stale_artifact_sets(target_dir: &Path, installed: &[String]) -> Vec<ArtifactSet> {
+    let Some(last_used) = last_used_rustc(target_dir) else {
+        return Vec::new();
+    };
+
+    let mut groups: Vec<CompilerGroup> = group_by_compiler(target_dir).into_values().collect();
+    groups.sort_by_key(|g| std::cmp::Reverse(g.newest));
+
+    let current_installed = installed.contains(&last_used);
+    let unaccounted_installed = installed.len() - usize::from(current_installed);
+
+    let mut stale = Vec::new();
+    for (i, group) in groups.into_iter().enumerate() {
+        let is_stale = if i == 0 {
+            !current_installed
+        } else {
+            unaccounted_installed == 0
+        };
+        if is_stale {
+            stale.extend(group.sets);
+        }
+    }
+    stale.sort_by(|a, b| a.profile_dir.cmp(&b.profile_dir).then_with(|| a.stem.cmp(&b.stem)));
+    stale
+}
+
+/// Group the artifact sets of every profile by package name, keeping the
+/// packages for which `select` returns true
+pub fn artifact_sets_by_package(target_dir: &Path, select: impl Fn(&str) -> bool) -> BTreeMap<String, Vec<ArtifactSet>> {
+    let mut packages: BTreeMap<String, Vec<ArtifactSet>> = BTreeMap::new();
+
+    for profile_dir in profile_dirs(target_dir) {
+        let Ok(entries) = fs::read_dir(profile_dir.join(".fingerprint")) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let stem = entry.file_name().to_string_lossy().to_string();
+            let Some((name, _)) = stem.rsplit_once('-') else {
+                continue;
+            };
+            if !select(name) {
+                continue;
+            }
+            packages.entry(name.to_string()).or_default().push(ArtifactSet {
+                profile_dir: profile_dir.clone(),
+                stem,
+            });
+        }
+    }
+
+    packages
+}
+
+/// Whether a `deps/` file name belongs to the artifact set `stem`
+pub fn belongs_to_set(file_name: &str, stem: &str) -> bool {
+    // Package names may use dashes, crate file names always use underscores
+    let normalized = match stem.rsplit_once('-') {
+        Some((name, hash)) => format!("{}-{}", name.replace('-', "_"), hash),
+        None => stem.to_string(),
+    };
+    [normalized.clone(), format!("lib{}", normalized)]
+        .iter()
+        .any(|prefix| {
+            file_name == prefix.as_str()
+                || file_name
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+}
+
+/// Delete the fingerprint, build output and deps files of an artifact set
+///
+/// Returns the number of bytes freed, or that would be freed with `dry_run`.
+pub fn remove_artifact_set(set: &ArtifactSet, dry_run: bool) -> Result<u64> {
+    let mut freed = 0;
+
+    for dir in [
+        set.profile_dir.join(".fingerprint").join(&set.stem),
+        set.profile_dir.join("build").join(&set.stem),
+    ] {
+        if dir.exists() {
+            freed += get_directory_size(&dir).unwrap_or(0);
+            if !dry_run {
+                fs::remove_dir_all(&dir)?;
+            }
+        }
+    }
+
+    if let Ok(entries) = fs::read_dir(set.profile_dir.join("deps")) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            if !belongs_to_set(&entry.file_name().to_string_lossy(), &set.stem) {
+                continue;
+            }
+            let path = entry.path();
+            if path.is_dir() {
+                freed += get_directory_size(&path).unwrap_or(0);
+                if !dry_run {
+                    fs::remove_dir_all(&path)?;
+                }
+            } else {
+                freed += entry.metadata().map(|m| m.len()).unwrap_or(0);
+                if !dry_run {
+                    fs::remove_file(&path)?;
+                }
+            }
+        }
+    }
+
+    Ok(freed)
+}
+
+/// Disk usage of one installed rustup toolchain
+#[derive(Debug, serde::Serialize)]
+pub struct ToolchainUsage {
+    pub name: String,
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub components: Vec<String>,
+}
+
+/// Resolve RUSTUP_HOME from the environment, defaulting to `~/.rustup`
+pub fn resolve_rustup_home(rustup_home_env: Option<OsString>, home_dir: Option<PathBuf>) -> Result<PathBuf> {
+    match rustup_home_env {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+        _ => home_dir
+            .map(|home| home.join(".rustup"))
+            .ok_or_else(|| anyhow!("Cannot locate RUSTUP_HOME: neither RUSTUP_HOME nor HOME is set")),
+    }
+}
+
+/// RUSTUP_HOME for the current process
+pub fn rustup_home() -> Result<PathBuf> {
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+    resolve_rustup_home(std::env::var_os("RUSTUP_HOME"), home)
+}
+
+/// Components installed in a toolchain, from `lib/rustlib/components`
+fn toolchain_components(toolchain_dir: &Path) -> Vec<String> {
+    fs::read_to_string(toolchain_dir.join("lib/rustlib/components"))
+        .map(|content| {
+            let mut components: Vec<String> = content
+                .lines()
+                .map(|line| line.trim().to_string())
+                .filter(|line| !line.is_empty())
+                .collect();
+            components.sort();
+            components
+        })
+        .unwrap_or_default()
+}
+
+/// Measure every toolchain in `RUSTUP_HOME/toolchains`, largest first
+///
+/// Sizes are computed in parallel on the current rayon pool.
+pub fn measure_toolchains(rustup_home: &Path) -> Result<Vec<ToolchainUsage>> {
+    let toolchains_dir = rustup_home.join("toolchains");
+    if !toolchains_dir.is_dir() {
+        return Err(anyhow!(
+            "rustup does not appear to be installed: no toolchains directory at {:?}",
+            toolchains_dir
+        ));
+    }
+
+    let dirs: Vec<PathBuf> = fs::read_dir(&toolchains_dir)
+        .with_context(|| format!("Failed to read {:?}", toolchains_dir))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+
+    let mut usage: Vec<ToolchainUsage> = dirs
+        .par_iter()
+        .map(|dir| ToolchainUsage {
+            name: dir.file_name().unwrap().to_string_lossy().to_string(),
+            path: dir.clone(),
+            bytes: get_directory_size(dir).unwrap_or(0),
+            components: toolchain_components(dir),
+        })
+        .collect();
+    usage.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+    Ok(usage)
+}
+
+/// Uninstall a toolchain through `rustup toolchain uninstall`
+pub fn uninstall_toolchain(name: &str) -> Result<()> {
+    let status = Command::new("rustup")
+        .args(["toolchain", "uninstall", name])
+        .status()
+        .context("Failed to run rustup")?;
+    if !status.success() {
+        return Err(anyhow!("rustup toolchain uninstall {} exited with {}", name, status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filetime::FileTime;
+    use tempfile::TempDir;
+
+    const OLD_RUSTC: &str = "rustc 1.70.0 (90c541806 2023-05-31)";
+    const NEW_RUSTC: &str = "rustc 1.75.0 (82e1608df 2023-12-21)";
+
+    fn write_rustc_info(target: &Path, version_line: &str) {
+        let info = serde_json::json!({
+            "rustc_fingerprint": 1,
+            "outputs": {
+                "1": { "success": true, "status": "", "code": 0, "stdout": "___\nlib___.rlib\n", "stderr": "" },
+                "2": {
+                    "success": true, "status": "", "code": 0,
+                    "stdout": format!("{}\nbinary: rustc\nrelease: 1.x\n", version_line),
+                    "stderr": ""
+                }
+            }
+        });
+        fs::write(target.join(".rustc_info.json"), info.to_string()).unwrap();
+    }
+
+    fn write_set(target: &Path, stem: &str, crate_name: &str, rustc_hash: u64, age_secs: u64) {
+        let profile = target.join("debug");
+        let fingerprint = profile.join(".fingerprint").join(stem);
+        fs::create_dir_all(&fingerprint).unwrap();
+        fs::create_dir_all(profile.join("deps")).unwrap();
+        let json = fingerprint.join(format!("lib-{}.json", crate_name));
+        fs::write(&json, format!("{{\"rustc\":{},\"features\":\"[]\"}}", rustc_hash)).unwrap();
+        let hash = stem.rsplit('-').next().unwrap();
+        let rlib = profile.join("deps").join(format!("lib{}-{}.rlib", crate_name, hash));
+        fs::write(&rlib, vec![0u8; 100]).unwrap();
+        fs::write(profile.join("deps").join(format!("{}-{}.d", crate_name, hash)), "dep").unwrap();
+
+        let time = FileTime::from_system_time(SystemTime::now() - std::time::Duration::from_secs(age_secs));
+        filetime::set_file_mtime(&json, time).unwrap();
+    }
+
+    /// A small target shaped like a real one: two compilers have built into it
+    fn fixture() -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        fs::create_dir_all(&target).unwrap();
+        write_rustc_info(&target, NEW_RUSTC);
+        write_set(&target, "serde-1111aaaa", "serde", 42, 86_400 * 30);
+        write_set(&target, "proc-macro2-2222bbbb", "proc_macro2", 42, 86_400 * 30);
+        write_set(&target, "serde-3333cccc", "serde", 77, 60);
+        temp_dir
+    }
+
+    #[test]
+    fn test_parse_toolchain_list() {
+        let output = "stable-x86_64-unknown-linux-gnu (active, default)\nnightly-x86_64-unknown-linux-gnu\n";
+        assert_eq!(
+            parse_toolchain_list(output),
+            vec!["stable-x86_64-unknown-linux-gnu", "nightly-x86_64-unknown-linux-gnu"]
+        );
+        assert!(parse_toolchain_list("no installed toolchains\n").is_empty());
+    }
+
+    #[test]
+    fn test_last_used_rustc() {
+        let temp_dir = fixture();
+        assert_eq!(last_used_rustc(&temp_dir.path().join("target")).as_deref(), Some(NEW_RUSTC));
+    }
+
+    #[test]
+    fn test_old_compiler_sets_are_stale() {
+        let temp_dir = fixture();
+        let target = temp_dir.path().join("target");
+        let stale = find_stale_artifact_sets(&target, &[NEW_RUSTC.to_string()]);
+        let stems: Vec<&str> = stale.iter().map(|s| s.stem.as_str()).collect();
+        assert_eq!(stems, vec!["proc-macro2-2222bbbb", "serde-1111aaaa"]);
+    }
+
+    #[test]
+    fn test_ambiguous_groups_are_kept() {
+        let temp_dir = fixture();
+        let target = temp_dir.path().join("target");
+        // The old group may belong to the second installed compiler
+        let installed = vec![NEW_RUSTC.to_string(), OLD_RUSTC.to_string()];
+        assert!(find_stale_artifact_sets(&target, &installed).is_empty());
+    }
+
+    #[test]
+    fn test_uninstalled_current_compiler_is_stale() {
+        let temp_dir = fixture();
+        let target = temp_dir.path().join("target");
+        let stale = find_stale_artifact_sets(&target, &[OLD_RUSTC.to_string(), "rustc 1.80.0".to_string()]);
+        let stems: Vec<&str> = stale.iter().map(|s| s.stem.as_str()).collect();
+        assert_eq!(stems, vec!["serde-3333cccc"]);
+    }
+
+    #[test]
+    fn test_remove_artifact_set() {
+        let temp_dir = fixture();
+        let target = temp_dir.path().join("target");
+        let set = ArtifactSet {
+            profile_dir: target.join("debug"),
+            stem: "proc-macro2-2222bbbb".to_string(),
+        };
+
+        let would_free = remove_artifact_set(&set, true).unwrap();
+        assert!(target.join("debug/deps/libproc_macro2-2222bbbb.rlib").exists());
+
+        let freed = remove_artifact_set(&set, false).unwrap();
+        assert_eq!(freed, would_free);
+        assert!(!target.join("debug/.fingerprint/proc-macro2-2222bbbb").exists());
+        assert!(!target.join("debug/deps/libproc_macro2-2222bbbb.rlib").exists());
+        assert!(!target.join("debug/deps/proc_macro2-2222bbbb.d").exists());
+        assert!(target.join("debug/deps/libserde-1111aaaa.rlib").exists());
+    }
+
+    #[test]
+    fn test_belongs_to_set() {
+        assert!(belongs_to_set("libserde-abc.rlib", "serde-abc"));
+        assert!(belongs_to_set("serde-abc.d", "serde-abc"));
+        assert!(belongs_to_set("my_app-abc", "my-app-abc"));
+        assert!(!belongs_to_set("libserde-abcd.rlib", "serde-abc"));
+        assert!(!belongs_to_set("libserde_json-abc.rlib", "serde-abc"));
+    }
+
+    #[test]
+    fn test_resolve_rustup_home() {
+        assert_eq!(
+            resolve_rustup_home(Some("/opt/rustup".into()), Some("/home/me".into())).unwrap(),
+            PathBuf::from("/opt/rustup")
+        );
+        assert_eq!(
+            resolve_rustup_home(None, Some("/home/me".into())).unwrap(),
+            PathBuf::from("/home/me/.rustup")
+        );
+    }
+
+    #[test]
+    fn test_measure_toolchains() {
+        let temp_dir = TempDir::new().unwrap();
+        let toolchains = temp_dir.path().join("toolchains");
+        let stable = toolchains.join("stable-x86_64-unknown-linux-gnu");
+        let nightly = toolchains.join("nightly-x86_64-unknown-linux-gnu");
+        fs::create_dir_all(stable.join("lib/rustlib")).unwrap();
+        fs::create_dir_all(nightly.join("bin")).unwrap();
+        fs::write(
+            stable.join("lib/rustlib/components"),
+            "rustc-x86_64-unknown-linux-gnu\ncargo-x86_64-unknown-linux-gnu\n",
+        )
+        .unwrap();
+        fs::write(nightly.join("bin/rustc"), vec![0u8; 1000]).unwrap();
+
+        let usage = measure_toolchains(temp_dir.path()).unwrap();
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage[0].name, "nightly-x86_64-unknown-linux-gnu");
+        assert_eq!(usage[0].bytes, 1000);
+        assert!(usage[0].components.is_empty());
+        assert_eq!(
+            usage[1].components,
+            vec!["cargo-x86_64-unknown-linux-gnu", "rustc-x86_64-unknown-linux-gnu"]
+        );
+    }
+
+    #[test]
+    fn test_measure_toolchains_without_rustup() {
+        let temp_dir = TempDir::new().unwrap();
+        let err = measure_toolchains(temp_dir.path()).unwrap_err();
+        assert!(err.to_string().contains("rustup does not appear to be installed"));
+    }
+}
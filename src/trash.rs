@@ -0,0 +1,165 @@
+use crate::utils::long_path;
+use rayon::prelude::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Name prefix of targets moved aside by `--defer-delete`, followed by `<pid>-<n>`
+pub const TRASH_PREFIX: &str = ".rclean-trash-";
+
+/// Hidden flag a background purge runs with
+pub const PURGE_FLAG: &str = "--purge-trash";
+
+/// When `--defer-delete` deletes the targets it moved aside
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DeferMode {
+    /// After the last project, before the summary
+    Wait,
+    /// In a detached process, so the run returns right away
+    Background,
+}
+
+/// Trash directories created by this run so far
+static TRASHED: AtomicUsize = AtomicUsize::new(0);
+
+/// Move a target directory to a trash directory next to it
+///
+/// Renaming within a filesystem is instant however large the target is, so
+/// the project can be built again right away. Returns `None` when the rename
+/// fails, e.g. with `EXDEV` because `target/` is a mount of its own; the
+/// target then has to be deleted in place.
+pub fn move_to_trash(target_dir: &Path) -> Option<PathBuf> {
+    move_to_trash_with(target_dir, |from, to| fs::rename(from, to))
+}
+
+fn move_to_trash_with(target_dir: &Path, rename: impl Fn(&Path, &Path) -> io::Result<()>) -> Option<PathBuf> {
+    let n = TRASHED.fetch_add(1, Ordering::SeqCst);
+    let trash = target_dir
+        .parent()?
+        .join(format!("{}{}-{}", TRASH_PREFIX, std::process::id(), n));
+    rename(target_dir, &trash).ok()?;
+    Some(trash)
+}
+
+/// Trash directories in a project left behind by other runs, e.g. one that
+/// crashed before deleting them
+pub fn stale_trash(project_dir: &Path) -> Vec<PathBuf> {
+    let own_prefix = format!("{}{}-", TRASH_PREFIX, std::process::id());
+    let Ok(entries) = fs::read_dir(project_dir) else {
+        return Vec::new();
+    };
+    let mut stale: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            name.starts_with(TRASH_PREFIX) && !name.starts_with(&own_prefix)
+        })
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|e| e.path())
+        .collect();
+    stale.sort();
+    stale
+}
+
+/// Delete trash directories in parallel, returning the ones that failed
+///
+/// Paths that aren't trash directories are never touched. One already gone
+/// (say, deleted by a concurrent purge) counts as deleted.
+pub fn purge(dirs: &[PathBuf]) -> Vec<(PathBuf, io::Error)> {
+    dirs.par_iter()
+        .filter(|dir| is_trash(dir))
+        .filter_map(|dir| match fs::remove_dir_all(long_path(dir)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Some((dir.clone(), e)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Purge trash directories from a detached copy of this binary
+///
+/// The purge outlives this process and ignores Ctrl-C in the terminal it
+/// was started from. Anything it can't delete is picked up by the next run.
+pub fn spawn_purge(dirs: &[PathBuf]) -> io::Result<()> {
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .arg(PURGE_FLAG)
+        .args(dirs)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const DETACHED_PROCESS: u32 = 0x0000_0008;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        command.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+    }
+    // Deliberately not waited for
+    command.spawn().map(drop)
+}
+
+fn is_trash(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with(TRASH_PREFIX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn target_fixture() -> (TempDir, PathBuf) {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        fs::create_dir_all(target.join("debug/deps")).unwrap();
+        fs::write(target.join("debug/deps/app"), vec![0u8; 100]).unwrap();
+        (temp_dir, target)
+    }
+
+    #[test]
+    fn test_move_to_trash_renames_next_to_target() {
+        let (temp_dir, target) = target_fixture();
+        let trash = move_to_trash(&target).unwrap();
+
+        assert!(!target.exists());
+        assert_eq!(trash.parent(), Some(temp_dir.path()));
+        assert!(trash.join("debug/deps/app").exists());
+        // Trash from this run isn't stale
+        assert!(stale_trash(temp_dir.path()).is_empty());
+
+        assert!(purge(std::slice::from_ref(&trash)).is_empty());
+        assert!(!trash.exists());
+    }
+
+    #[test]
+    fn test_failed_rename_leaves_target_in_place() {
+        let (_temp_dir, target) = target_fixture();
+        let cross_device = |_: &Path, _: &Path| Err(io::Error::from(io::ErrorKind::CrossesDevices));
+        assert_eq!(move_to_trash_with(&target, cross_device), None);
+        assert!(target.join("debug/deps/app").exists());
+    }
+
+    #[test]
+    fn test_stale_trash_is_picked_up() {
+        let (temp_dir, target) = target_fixture();
+        let crashed = temp_dir.path().join(format!("{}4294967295-0", TRASH_PREFIX));
+        fs::create_dir_all(crashed.join("debug")).unwrap();
+        fs::write(temp_dir.path().join(format!("{}file", TRASH_PREFIX)), "").unwrap();
+
+        let stale = stale_trash(temp_dir.path());
+        assert_eq!(stale, vec![crashed.clone()]);
+        assert!(purge(&stale).is_empty());
+        assert!(!crashed.exists());
+
+        // Purging never deletes anything but trash
+        assert!(purge(std::slice::from_ref(&target)).is_empty());
+        assert!(target.exists());
+    }
+}
@@ -1,22 +1,65 @@
 use anyhow::Result;
-use std::path::Path;
+use cargo_metadata::MetadataCommand;
+use colored::Colorize;
+use crate::timing::count_dirs_walked;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
 use walkdir::WalkDir;
 
-/// Format bytes into human-readable string
-pub fn format_bytes(bytes: u64) -> String {
-    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+/// Which convention sizes are shown in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Units {
+    /// Powers of 1024, labeled KiB, MiB, GiB (like `du -h`)
+    #[default]
+    Binary,
+    /// Powers of 1000, labeled KB, MB, GB (like `df -H`)
+    Decimal,
+}
+
+impl Units {
+    /// Bytes per step up, and the label of each step
+    fn scale(self) -> (f64, &'static [&'static str]) {
+        match self {
+            Units::Binary => (1024.0, &["B", "KiB", "MiB", "GiB", "TiB"]),
+            Units::Decimal => (1000.0, &["B", "KB", "MB", "GB", "TB"]),
+        }
+    }
+}
+
+/// Format bytes into human-readable string, e.g. "1.50 KiB", or "1.54 KB" in decimal units
+pub fn format_bytes(bytes: u64, units: Units) -> String {
+    let (step, labels) = units.scale();
     let mut size = bytes as f64;
     let mut unit_idx = 0;
 
-    while size >= 1024.0 && unit_idx < UNITS.len() - 1 {
-        size /= 1024.0;
+    while size >= step && unit_idx < labels.len() - 1 {
+        size /= step;
         unit_idx += 1;
     }
 
     if unit_idx == 0 {
-        format!("{} {}", bytes, UNITS[unit_idx])
+        format!("{} {}", bytes, labels[unit_idx])
+    } else {
+        format!("{:.2} {}", size, labels[unit_idx])
+    }
+}
+
+static DECIMAL_UNITS: AtomicBool = AtomicBool::new(false);
+
+/// Show sizes in these units from now on (`--units`)
+pub fn use_units(units: Units) {
+    DECIMAL_UNITS.store(units == Units::Decimal, Ordering::Relaxed);
+}
+
+/// The units sizes are shown in
+pub fn units() -> Units {
+    if DECIMAL_UNITS.load(Ordering::Relaxed) {
+        Units::Decimal
     } else {
-        format!("{:.2} {}", size, UNITS[unit_idx])
+        Units::Binary
     }
 }
 
@@ -27,19 +70,650 @@ pub fn get_directory_size(path: &Path) -> Result<u64> {
         return Ok(0);
     }
 
-    for entry in WalkDir::new(path) {
+    let mut dirs = 0;
+    for entry in WalkDir::new(long_path(path)) {
         let entry = entry?;
         if entry.file_type().is_file() {
             total += entry.metadata()?.len();
+        } else if entry.file_type().is_dir() {
+            dirs += 1;
         }
     }
+    count_dirs_walked(dirs);
     Ok(total)
 }
 
-/// Parse size string (e.g., "100MB", "1GB") to bytes
+/// Format a count with thousands separators, e.g. "412,331"
+pub fn format_count(count: u64) -> String {
+    let digits = count.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// What one walk of a directory found below it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DirUsage {
+    pub bytes: u64,
+    pub files: u64,
+    /// Including the directory itself
+    pub dirs: u64,
+}
+
+/// Get the size of whatever can be read below a directory
+///
+/// Unlike `get_directory_size`, entries that can't be read are passed over
+/// instead of failing the whole measurement.
+pub fn readable_size(path: &Path) -> u64 {
+    readable_usage(path).bytes
+}
+
+/// Measure whatever can be read below a directory, counting its files and
+/// directories along with the bytes
+///
+/// Files whose size can't be read are still counted.
+pub fn readable_usage(path: &Path) -> DirUsage {
+    let mut usage = DirUsage::default();
+    for entry in WalkDir::new(long_path(path)).into_iter().filter_map(|e| e.ok()) {
+        if entry.file_type().is_dir() {
+            usage.dirs += 1;
+        } else if entry.file_type().is_file() {
+            usage.files += 1;
+            usage.bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
+        }
+    }
+    count_dirs_walked(usage.dirs);
+    usage
+}
+
+/// `path` in a form Windows accepts past MAX_PATH (260 characters)
+///
+/// Dependency trees in a target nest deep enough to go over it, and then
+/// removing or measuring them fails. Windows lifts the limit for absolute
+/// paths prefixed with `\\?\`. Elsewhere the path is returned as is.
+pub fn long_path(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    {
+        let absolute = std::path::absolute(path).ok();
+        if let Some(verbatim) = absolute.as_deref().and_then(Path::to_str).and_then(verbatim_path) {
+            return PathBuf::from(verbatim);
+        }
+    }
+    path.to_path_buf()
+}
+
+/// An absolute Windows path with the `\\?\` prefix, or `None` when it
+/// already has one or isn't anchored to a drive or share
+///
+/// Verbatim paths are passed to the filesystem untouched, so `path` must
+/// already be normalized, with backslashes and no `.` or `..`.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn verbatim_path(path: &str) -> Option<String> {
+    if path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+        return None;
+    }
+    if let Some(share) = path.strip_prefix(r"\\") {
+        return Some(format!(r"\\?\UNC\{}", share));
+    }
+    let drive = path.as_bytes();
+    if drive.len() >= 3 && drive[0].is_ascii_alphabetic() && drive[1] == b':' && drive[2] == b'\\' {
+        return Some(format!(r"\\?\{}", path));
+    }
+    None
+}
+
+/// Best guess at where a recursive removal of `dir` got stuck
+///
+/// That's the first directory left behind that can't be read, or else the
+/// directory holding the first file left behind.
+pub fn stuck_path(dir: &Path) -> PathBuf {
+    for entry in WalkDir::new(dir) {
+        match entry {
+            Err(e) => return e.path().unwrap_or(dir).to_path_buf(),
+            Ok(entry) if !entry.file_type().is_dir() => {
+                return entry.path().parent().unwrap_or(dir).to_path_buf();
+            }
+            Ok(_) => {}
+        }
+    }
+    dir.to_path_buf()
+}
+
+/// Subdirectories that mark a directory inside `target/` as a profile directory
+pub const PROFILE_MARKERS: &[&str] = &["deps", ".fingerprint", "build", "incremental"];
+
+/// Find the profile directories below a target directory
+///
+/// Handles both host profiles (`target/debug`) and cross-compiled profiles
+/// (`target/<triple>/debug`). The result is sorted.
+pub fn profile_dirs(target_dir: &Path) -> Vec<PathBuf> {
+    let is_profile = |dir: &Path| PROFILE_MARKERS.iter().any(|m| dir.join(m).is_dir());
+    let subdirs = |dir: &Path| -> Vec<PathBuf> {
+        std::fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|p| p.is_dir())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let mut profiles = Vec::new();
+    for dir in subdirs(target_dir) {
+        if is_profile(&dir) {
+            profiles.push(dir);
+        } else {
+            // Possibly a target triple directory containing profiles
+            profiles.extend(subdirs(&dir).into_iter().filter(|p| is_profile(p)));
+        }
+    }
+    profiles.sort();
+    profiles
+}
+
+/// Name of the build output directory cargo uses unless configured otherwise
+pub const TARGET_DIR_NAME: &str = "target";
+
+/// Whether a directory looks like cargo build output
+///
+/// Cargo tags the target directories it creates with `CACHEDIR.TAG` and
+/// records the compiler it used in `.rustc_info.json`. A directory with
+/// neither was most likely not made by cargo.
+pub fn looks_like_target(dir: &Path) -> bool {
+    dir.join("CACHEDIR.TAG").is_file() || dir.join(".rustc_info.json").is_file()
+}
+
+/// `cargo <subcommand>` run in a project, told about its target directory
+/// when that isn't the default `target/`
+pub fn cargo_command(subcommand: &str, project_dir: &Path, target_dir: &Path) -> Command {
+    let mut cmd = cargo();
+    cmd.arg(subcommand).current_dir(project_dir);
+    if target_dir != project_dir.join(TARGET_DIR_NAME) {
+        cmd.arg("--target-dir").arg(target_dir);
+    }
+    cmd
+}
+
+static ONLINE: AtomicBool = AtomicBool::new(false);
+
+/// Let cargo use the network (`--online`); by default every cargo command runs offline
+pub fn allow_network(enabled: bool) {
+    ONLINE.store(enabled, Ordering::Relaxed);
+}
+
+fn offline() -> bool {
+    !ONLINE.load(Ordering::Relaxed)
+}
+
+/// Toolchain a cargo command is retried on when the one a project pins isn't installed
+pub const DEFAULT_FALLBACK_TOOLCHAIN: &str = "stable";
+
+static CARGO_PROGRAM: OnceLock<PathBuf> = OnceLock::new();
+static FALLBACK_TOOLCHAIN: OnceLock<String> = OnceLock::new();
+
+/// Choose the cargo binary to run (`--cargo`, else `$CARGO`, else `cargo` from
+/// PATH) and the toolchain to retry on (`--fallback-toolchain`)
+///
+/// Cargo sets `$CARGO` for the subcommands it runs, so `cargo deepclean`
+/// cleans with the very cargo that started it.
+pub fn use_cargo(program: Option<PathBuf>, fallback_toolchain: Option<String>) {
+    let program = program
+        .or_else(|| std::env::var_os("CARGO").filter(|p| !p.is_empty()).map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("cargo"));
+    let _ = CARGO_PROGRAM.set(program);
+    let _ = FALLBACK_TOOLCHAIN.set(fallback_toolchain.unwrap_or_else(|| DEFAULT_FALLBACK_TOOLCHAIN.to_string()));
+}
+
+/// The cargo binary every cargo command runs
+pub fn cargo_program() -> &'static Path {
+    CARGO_PROGRAM.get().map_or(Path::new("cargo"), |p| p.as_path())
+}
+
+fn fallback_toolchain() -> &'static str {
+    FALLBACK_TOOLCHAIN.get().map_or(DEFAULT_FALLBACK_TOOLCHAIN, |t| t.as_str())
+}
+
+/// `cargo`, kept off the network unless `--online` was given
+///
+/// Cleaning never needs the registry, but cargo may still try to update the
+/// index when it re-reads a manifest, and hang for minutes on an airgapped
+/// machine. `--offline` goes before the subcommand, where cargo accepts it for
+/// every command, and `CARGO_NET_OFFLINE` covers anything cargo runs in turn.
+pub fn cargo() -> Command {
+    cargo_at(cargo_program(), offline())
+}
+
+fn cargo_at(program: impl AsRef<std::ffi::OsStr>, offline: bool) -> Command {
+    let mut cmd = Command::new(program);
+    if offline {
+        cmd.arg("--offline").env("CARGO_NET_OFFLINE", "true");
+    }
+    cmd
+}
+
+/// `cargo metadata` for a manifest, kept off the network like `cargo()`
+pub fn metadata_command(manifest: &Path, options: &[&str]) -> MetadataCommand {
+    metadata_command_with(manifest, options, offline())
+}
+
+fn metadata_command_with(manifest: &Path, options: &[&str], offline: bool) -> MetadataCommand {
+    let mut cmd = MetadataCommand::new();
+    cmd.cargo_path(cargo_program()).manifest_path(manifest);
+    let mut options: Vec<String> = options.iter().map(|o| o.to_string()).collect();
+    if offline {
+        options.push("--offline".to_string());
+        cmd.env("CARGO_NET_OFFLINE", "true");
+    }
+    cmd.other_options(options);
+    cmd
+}
+
+/// A hint to append to a cargo error caused by running offline, or `""`
+pub fn offline_hint(stderr: &str) -> &'static str {
+    if offline() && stderr.contains("offline") {
+        " (cargo ran offline; pass --online to let it use the network)"
+    } else {
+        ""
+    }
+}
+
+static NO_SUBPROCESSES: AtomicBool = AtomicBool::new(false);
+
+/// Refuse to run any other program from now on (`--dry-run-json-only`)
+pub fn forbid_subprocesses() {
+    NO_SUBPROCESSES.store(true, Ordering::Relaxed);
+}
+
+/// An error instead of running `program` once subprocesses are forbidden
+///
+/// Options that need other programs are rejected up front; this catches
+/// anything that slips past that check.
+pub fn ensure_may_spawn(program: &str) -> std::io::Result<()> {
+    if NO_SUBPROCESSES.load(Ordering::Relaxed) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("refusing to run `{}` with --dry-run-json-only", program),
+        ));
+    }
+    Ok(())
+}
+
+static SHOW_COMMANDS: AtomicBool = AtomicBool::new(false);
+
+/// Print each command before `run_command` runs it, and its output when it fails (`--verbose`)
+pub fn show_commands(enabled: bool) {
+    SHOW_COMMANDS.store(enabled, Ordering::Relaxed);
+}
+
+/// Run a command to completion, capturing its output
+///
+/// With `show_commands` on, prints the exact command line and working
+/// directory first, and everything the command wrote if it fails, since the
+/// error callers report is usually just its last line.
+pub fn run_command(cmd: &mut Command) -> std::io::Result<Output> {
+    ensure_may_spawn(&command_line(cmd))?;
+    let show = SHOW_COMMANDS.load(Ordering::Relaxed);
+    if show {
+        println!("{} Running {}", "[DEBUG]".cyan(), describe_command(cmd));
+    }
+    let output = cmd.output()?;
+    if show && !output.status.success() {
+        println!("{} `{}` failed ({})", "[DEBUG]".cyan(), command_line(cmd), output.status);
+        for (stream, bytes) in [("stdout", &output.stdout), ("stderr", &output.stderr)] {
+            for line in String::from_utf8_lossy(bytes).lines() {
+                println!("  {} {}", format!("{}:", stream).dimmed(), line);
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// Whether a failed cargo command was stopped by rustup because the
+/// toolchain a project pins (e.g. in `rust-toolchain.toml`) isn't installed
+pub fn toolchain_missing(output: &Output) -> bool {
+    !output.status.success()
+        && String::from_utf8_lossy(&output.stderr)
+            .lines()
+            .any(|line| line.contains("toolchain '") && line.contains("is not installed"))
+}
+
+/// The same command, run through rustup's proxy on another toolchain (`cargo +stable ...`)
+pub fn on_toolchain(cmd: &Command, toolchain: &str) -> Command {
+    let mut retry = Command::new(cmd.get_program());
+    retry.arg(format!("+{}", toolchain)).args(cmd.get_args());
+    for (key, value) in cmd.get_envs() {
+        match value {
+            Some(value) => retry.env(key, value),
+            None => retry.env_remove(key),
+        };
+    }
+    if let Some(dir) = cmd.get_current_dir() {
+        retry.current_dir(dir);
+    }
+    retry
+}
+
+/// Run a cargo command, retrying it once on the fallback toolchain when the
+/// toolchain the project pins isn't installed
+///
+/// Otherwise such a project's `cargo clean` fails with a rustup error and
+/// drops to direct removal, though any cargo could have cleaned it. Returns
+/// the last output and the toolchain it was retried on, if it was. `prepare`
+/// is applied to both runs, e.g. to shield them from Ctrl-C.
+pub fn run_cargo(
+    mut cmd: Command,
+    prepare: impl Fn(&mut Command) -> &mut Command,
+) -> std::io::Result<(Output, Option<&'static str>)> {
+    run_cargo_with(&mut cmd, fallback_toolchain(), prepare)
+}
+
+fn run_cargo_with<'a>(
+    cmd: &mut Command,
+    fallback: &'a str,
+    prepare: impl Fn(&mut Command) -> &mut Command,
+) -> std::io::Result<(Output, Option<&'a str>)> {
+    let output = run_command(prepare(cmd))?;
+    if !toolchain_missing(&output) {
+        return Ok((output, None));
+    }
+    let output = run_command(prepare(&mut on_toolchain(cmd, fallback)))?;
+    Ok((output, Some(fallback)))
+}
+
+/// A command line as it could be pasted into a shell, e.g. `cargo clean -p 'my app'`
+pub fn command_line(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| shell_quote(&arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The command line in backticks, and the directory it runs in if it was set
+pub fn describe_command(cmd: &Command) -> String {
+    match cmd.get_current_dir() {
+        Some(dir) => format!("`{}` in {}", command_line(cmd), dir.display()),
+        None => format!("`{}`", command_line(cmd)),
+    }
+}
+
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/' | ':' | '=' | '+' | ',' | '@'));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Replace a file's contents in one step, so readers never see it half-written
+///
+/// The contents go to a temporary file next to `path`, which is renamed over it.
+pub fn write_atomically(path: &Path, contents: &str) -> std::io::Result<()> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let partial = path.with_file_name(format!(".{}.partial", file_name));
+    std::fs::write(&partial, contents)?;
+    std::fs::rename(&partial, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&partial);
+    })
+}
+
+/// Whether a cargo process holds a build lock in this target directory
+///
+/// Cargo holds an exclusive lock on `target/.cargo-lock` and on
+/// `<profile>/.cargo-lock` while building. The lock files are never created
+/// here, so a target without them is not in use.
+pub fn target_in_use(target_dir: &Path) -> bool {
+    std::iter::once(target_dir.to_path_buf())
+        .chain(profile_dirs(target_dir))
+        .map(|dir| dir.join(".cargo-lock"))
+        .filter_map(|lock| std::fs::File::open(lock).ok())
+        .any(|file| fs2::FileExt::try_lock_exclusive(&file).is_err())
+}
+
+/// Find the per-triple directories (`target/<triple>`) below a target directory
+///
+/// A subdirectory counts as a triple directory when it is not a profile
+/// itself but contains at least one profile directory.
+pub fn triple_dirs(target_dir: &Path) -> Vec<PathBuf> {
+    let mut triples: Vec<PathBuf> = profile_dirs(target_dir)
+        .into_iter()
+        .filter_map(|profile| {
+            let parent = profile.parent()?;
+            (parent != target_dir).then(|| parent.to_path_buf())
+        })
+        .collect();
+    triples.dedup();
+    triples
+}
+
+/// Whether a path contains glob metacharacters
+pub fn is_glob(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+/// Expand a glob like `repos/*/backend` into the directories it matches
+///
+/// Matches are canonicalized, sorted and deduplicated; it is an error when
+/// no directory matches.
+pub fn expand_directory_glob(pattern: &Path) -> Result<Vec<PathBuf>> {
+    use anyhow::{anyhow, Context};
+
+    let pattern_str = pattern.to_string_lossy();
+    let mut dirs: Vec<PathBuf> = glob::glob(&pattern_str)
+        .with_context(|| format!("Invalid directory pattern: {:?}", pattern))?
+        .filter_map(|entry| entry.ok())
+        .filter(|path| path.is_dir())
+        .filter_map(|path| path.canonicalize().ok())
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    if dirs.is_empty() {
+        return Err(anyhow!("No directories match {:?}", pattern));
+    }
+    Ok(dirs)
+}
+
+/// Resolve directory arguments to canonical scan roots
+///
+/// Arguments that don't exist but contain glob metacharacters are expanded,
+/// so quoted patterns work like shell-expanded ones.
+pub fn resolve_roots(directories: &[&PathBuf]) -> Result<Vec<PathBuf>> {
+    use anyhow::Context;
+
+    let mut roots = Vec::new();
+    for dir in directories {
+        if is_glob(dir) && !dir.exists() {
+            roots.extend(expand_directory_glob(dir)?);
+        } else {
+            validate_directory(dir)?;
+            roots.push(dir.canonicalize().with_context(|| format!("Failed to canonicalize path: {:?}", dir))?);
+        }
+    }
+    roots.sort();
+    roots.dedup();
+    Ok(roots)
+}
+
+/// Deepest directory containing every path (empty when they share no prefix)
+pub fn common_ancestor(paths: &[PathBuf]) -> PathBuf {
+    let Some((first, rest)) = paths.split_first() else {
+        return PathBuf::new();
+    };
+    let mut ancestor = first.clone();
+    while !rest.iter().all(|path| path.starts_with(&ancestor)) {
+        if !ancestor.pop() {
+            return PathBuf::new();
+        }
+    }
+    ancestor
+}
+
+/// FNV-1a hash, for file names that must stay stable across builds (unlike `DefaultHasher`)
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Check that `path` is a readable directory, with an actionable error if not
+pub fn validate_directory(path: &Path) -> Result<()> {
+    use anyhow::anyhow;
+    use std::io::ErrorKind;
+
+    match std::fs::metadata(path) {
+        Ok(metadata) if !metadata.is_dir() => {
+            Err(anyhow!("Path is a file, expected a directory: {:?}", path))
+        }
+        Ok(_) => match std::fs::read_dir(path) {
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+                Err(anyhow!("Permission denied: cannot read directory {:?}", path))
+            }
+            _ => Ok(()),
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            Err(anyhow!("Directory does not exist: {:?}", path))
+        }
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+            Err(anyhow!("Permission denied: cannot access {:?}", path))
+        }
+        Err(e) => Err(anyhow!("Cannot access {:?}: {}", path, e)),
+    }
+}
+
+/// Compute the point in time `days` days ago
+pub fn cutoff_for_days(days: u64) -> SystemTime {
+    SystemTime::now()
+        .checked_sub(Duration::from_secs(days * 24 * 60 * 60))
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// When a target directory was last built into
+///
+/// This is the latest modification time of `.rustc_info.json`, of the
+/// profile directories and of the files in their `.fingerprint` directories,
+/// which cargo rewrites on every build that does work. Returns `None` when
+/// there is no target or nothing was built into it. Used both for
+/// `--target-older-than` and for reporting.
+pub fn last_build_time(target_dir: &Path) -> Option<SystemTime> {
+    let modified = |path: &Path| std::fs::metadata(path).ok()?.modified().ok();
+
+    let mut times: Vec<SystemTime> = modified(&target_dir.join(".rustc_info.json")).into_iter().collect();
+    for profile_dir in profile_dirs(target_dir) {
+        times.extend(modified(&profile_dir));
+        times.extend(
+            WalkDir::new(profile_dir.join(".fingerprint"))
+                .max_depth(2)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+                .filter_map(|e| e.metadata().ok()?.modified().ok()),
+        );
+    }
+    times.into_iter().max()
+}
+
+/// Serialize an optional timestamp as an RFC 3339 string (or null)
+pub fn serialize_rfc3339<S: serde::Serializer>(time: &Option<SystemTime>, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => serializer.serialize_str(&format_rfc3339(*time)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Format a timestamp as RFC 3339 in UTC, e.g. "2024-03-09T14:05:00Z"
+pub fn format_rfc3339(time: SystemTime) -> String {
+    let secs = time.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Render how long ago `time` was, e.g. "3 weeks ago"
+pub fn format_ago(time: SystemTime, now: SystemTime) -> String {
+    let secs = now.duration_since(time).map(|d| d.as_secs()).unwrap_or(0);
+    let (amount, unit) = match secs {
+        0..=59 => return "just now".to_string(),
+        60..=3599 => (secs / 60, "minute"),
+        3600..=86_399 => (secs / 3600, "hour"),
+        86_400..=604_799 => (secs / 86_400, "day"),
+        604_800..=2_591_999 => (secs / 604_800, "week"),
+        2_592_000..=31_535_999 => (secs / 2_592_000, "month"),
+        _ => (secs / 31_536_000, "year"),
+    };
+    format!("{} {}{} ago", amount, unit, if amount == 1 { "" } else { "s" })
+}
+
+/// Directories holding a package's sources, relative to its manifest
+pub const SOURCE_DIRS: &[&str] = &["src", "tests", "benches", "examples"];
+
+/// Check whether any source file of a project was modified after `cutoff`
+///
+/// Sources are the manifests and the files under `SOURCE_DIRS` of the
+/// project and of any packages nested in it (workspace members); target
+/// directories named `target_dir_name` and hidden directories are never
+/// entered. Stops walking as soon as one recent file is found.
+pub fn has_source_newer_than(project_dir: &Path, target_dir_name: &str, cutoff: SystemTime) -> bool {
+    WalkDir::new(project_dir)
+        .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name().to_string_lossy();
+            e.depth() == 0 || !e.file_type().is_dir() || (name != target_dir_name && !name.starts_with('.'))
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| {
+            e.file_name() == "Cargo.toml"
+                || e.path()
+                    .strip_prefix(project_dir)
+                    .is_ok_and(|rel| rel.components().any(|c| SOURCE_DIRS.iter().any(|d| c.as_os_str() == *d)))
+        })
+        .any(|e| {
+            e.metadata()
+                .ok()
+                .and_then(|m| m.modified().ok())
+                .is_some_and(|modified| modified > cutoff)
+        })
+}
+
+/// Parse size string (e.g., "100MB", "1GB", or "1GiB" as sizes are printed) to bytes, counting in powers of 1024
 pub fn parse_size(size_str: &str) -> Result<u64> {
     use anyhow::anyhow;
+    // Sizes are binary either way, so "1GiB" as printed reads back as "1GB"
     let size_str = size_str.trim().to_uppercase();
+    let size_str = match size_str.strip_suffix("IB") {
+        Some(number) if number.ends_with(['K', 'M', 'G', 'T']) => format!("{}B", number),
+        _ => size_str,
+    };
     let (number_str, unit) = if size_str.ends_with("B") {
         if size_str.ends_with("KB") {
             (&size_str[..size_str.len() - 2], "KB")
@@ -73,18 +747,53 @@ pub fn parse_size(size_str: &str) -> Result<u64> {
     Ok((number * multiplier as f64) as u64)
 }
 
+/// Parse a duration string (e.g., "60d", "12h", "2w"); a bare number means days
+pub fn parse_duration(duration_str: &str) -> Result<Duration> {
+    use anyhow::anyhow;
+    let duration_str = duration_str.trim().to_lowercase();
+    let split = duration_str
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration_str.len());
+    let (number_str, unit) = duration_str.split_at(split);
+
+    let number: u64 = number_str
+        .parse()
+        .map_err(|_| anyhow!("Invalid duration format: expected format like '60d', '12h' or '2w'"))?;
+    let seconds = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "" | "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        other => return Err(anyhow!("Unknown duration unit: {}", other)),
+    };
+
+    Ok(Duration::from_secs(number * seconds))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_format_bytes() {
-        assert_eq!(format_bytes(0), "0 B");
-        assert_eq!(format_bytes(512), "512 B");
-        assert_eq!(format_bytes(1024), "1.00 KB");
-        assert_eq!(format_bytes(1536), "1.50 KB");
-        assert_eq!(format_bytes(1048576), "1.00 MB");
-        assert_eq!(format_bytes(1073741824), "1.00 GB");
+        assert_eq!(format_bytes(0, Units::Binary), "0 B");
+        assert_eq!(format_bytes(512, Units::Binary), "512 B");
+        assert_eq!(format_bytes(1024, Units::Binary), "1.00 KiB");
+        assert_eq!(format_bytes(1536, Units::Binary), "1.50 KiB");
+        assert_eq!(format_bytes(1048576, Units::Binary), "1.00 MiB");
+        assert_eq!(format_bytes(1073741824, Units::Binary), "1.00 GiB");
+        assert_eq!(format_bytes(1099511627776, Units::Binary), "1.00 TiB");
+    }
+
+    #[test]
+    fn test_format_bytes_decimal() {
+        assert_eq!(format_bytes(999, Units::Decimal), "999 B");
+        assert_eq!(format_bytes(1000, Units::Decimal), "1.00 KB");
+        assert_eq!(format_bytes(1536, Units::Decimal), "1.54 KB");
+        assert_eq!(format_bytes(1_500_000, Units::Decimal), "1.50 MB");
+        assert_eq!(format_bytes(1073741824, Units::Decimal), "1.07 GB");
+        assert_eq!(format_bytes(2_000_000_000_000_000, Units::Decimal), "2000.00 TB");
     }
 
     #[test]
@@ -94,14 +803,329 @@ mod tests {
         assert_eq!(parse_size("1MB").unwrap(), 1048576);
         assert_eq!(parse_size("1GB").unwrap(), 1073741824);
         assert_eq!(parse_size("1.5MB").unwrap(), 1572864);
+        assert_eq!(parse_size("1.5MiB").unwrap(), 1572864);
+        assert_eq!(parse_size("2gib").unwrap(), 2 * 1073741824);
+        assert!(parse_size("1IB").is_err());
         assert!(parse_size("invalid").is_err());
     }
 
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("60").unwrap(), Duration::from_secs(60 * 86400));
+        assert_eq!(parse_duration("60d").unwrap(), Duration::from_secs(60 * 86400));
+        assert_eq!(parse_duration("12h").unwrap(), Duration::from_secs(12 * 3600));
+        assert_eq!(parse_duration("2W").unwrap(), Duration::from_secs(14 * 86400));
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+    }
+
+    #[test]
+    fn test_last_build_time() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        assert_eq!(last_build_time(&target), None);
+
+        let fingerprint = target.join("debug/.fingerprint/app-1234");
+        std::fs::create_dir_all(&fingerprint).unwrap();
+        std::fs::write(fingerprint.join("lib-app"), "0").unwrap();
+        std::fs::write(target.join(".rustc_info.json"), "{}").unwrap();
+        let old = cutoff_for_days(30);
+        let older = cutoff_for_days(40);
+        for path in [target.join(".rustc_info.json"), target.join("debug"), fingerprint.join("lib-app")] {
+            filetime::set_file_mtime(&path, filetime::FileTime::from_system_time(older)).unwrap();
+        }
+        filetime::set_file_mtime(fingerprint.join("lib-app"), filetime::FileTime::from_system_time(old)).unwrap();
+
+        // Truncate to whole seconds to compare with what the filesystem stored
+        let secs = |t: SystemTime| t.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+        assert_eq!(last_build_time(&target).map(secs), Some(secs(old)));
+    }
+
+    #[test]
+    fn test_format_rfc3339() {
+        assert_eq!(format_rfc3339(SystemTime::UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let leap_day = SystemTime::UNIX_EPOCH + Duration::from_secs(1_709_215_445);
+        assert_eq!(format_rfc3339(leap_day), "2024-02-29T14:04:05Z");
+    }
+
+    #[test]
+    fn test_format_ago() {
+        let now = SystemTime::now();
+        assert_eq!(format_ago(now, now), "just now");
+        assert_eq!(format_ago(now - Duration::from_secs(3600), now), "1 hour ago");
+        assert_eq!(format_ago(now - Duration::from_secs(21 * 86400), now), "3 weeks ago");
+    }
+
+    #[test]
+    fn test_has_source_newer_than() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let old_time = filetime::FileTime::from_system_time(cutoff_for_days(30));
+        for file in ["Cargo.toml", "crates/app/Cargo.toml", "crates/app/src/main.rs", "README.md", "target/debug/app"] {
+            let path = temp_dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, "").unwrap();
+            if !file.starts_with("target") && file != "README.md" {
+                filetime::set_file_mtime(&path, old_time).unwrap();
+            }
+        }
+
+        // Fresh build output and non-source files don't count
+        assert!(!has_source_newer_than(temp_dir.path(), TARGET_DIR_NAME, cutoff_for_days(7)));
+
+        let main_rs = temp_dir.path().join("crates/app/src/main.rs");
+        filetime::set_file_mtime(&main_rs, filetime::FileTime::now()).unwrap();
+        assert!(has_source_newer_than(temp_dir.path(), TARGET_DIR_NAME, cutoff_for_days(7)));
+    }
+
+    #[test]
+    fn test_looks_like_target() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let build = temp_dir.path().join("build");
+        std::fs::create_dir_all(build.join("debug")).unwrap();
+        assert!(!looks_like_target(&build));
+        std::fs::write(build.join("CACHEDIR.TAG"), "Signature: 8a477f597d28d172789f06886806bc55").unwrap();
+        assert!(looks_like_target(&build));
+
+        let cmd = cargo_command("clean", temp_dir.path(), &build);
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(args, ["--offline".as_ref(), "clean".as_ref(), "--target-dir".as_ref(), build.as_os_str()]);
+        let cmd = cargo_command("clean", temp_dir.path(), &temp_dir.path().join(TARGET_DIR_NAME));
+        assert_eq!(cmd.get_args().count(), 2);
+    }
+
+    #[test]
+    fn test_describe_command() {
+        let mut cmd = cargo_command("clean", Path::new("/src/app"), Path::new("/mnt/build cache"));
+        cmd.args(["-p", "it's"]);
+        assert_eq!(
+            describe_command(&cmd),
+            "`cargo --offline clean --target-dir '/mnt/build cache' -p 'it'\\''s'` in /src/app"
+        );
+        assert_eq!(command_line(&Command::new("cargo")), "cargo");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("--target=x86_64-unknown-linux-gnu"), "--target=x86_64-unknown-linux-gnu");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cargo_is_kept_offline() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // A stub cargo that records its arguments and network setting
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let log = temp_dir.path().join("argv.log");
+        let stub = temp_dir.path().join("cargo");
+        std::fs::write(
+            &stub,
+            format!("#!/bin/sh\necho \"$CARGO_NET_OFFLINE $*\" >> '{}'\nexit 1\n", log.display()),
+        ).unwrap();
+        std::fs::set_permissions(&stub, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let manifest = temp_dir.path().join("Cargo.toml");
+
+        let _ = cargo_at(&stub, true).args(["clean", "-p", "app"]).output();
+        let _ = cargo_at(&stub, true).args(["remove", "serde"]).output();
+        let _ = metadata_command_with(&manifest, &["--no-deps"], true).cargo_path(&stub).exec();
+        let _ = metadata_command_with(&manifest, &["--locked"], true).cargo_path(&stub).exec();
+        let _ = cargo_at(&stub, false).arg("build").output();
+        let _ = metadata_command_with(&manifest, &["--locked"], false).cargo_path(&stub).exec();
+
+        let log = std::fs::read_to_string(&log).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 6);
+        for line in &lines[..4] {
+            assert!(line.starts_with("true "), "{}", line);
+            assert!(line.contains("--offline"), "{}", line);
+        }
+        assert!(lines[2].contains("--no-deps"));
+        // --online lets cargo use the network again
+        for line in &lines[4..] {
+            assert!(!line.contains("offline") && !line.starts_with("true"), "{}", line);
+        }
+
+        // Every cargo this tool runs goes through these
+        assert_eq!(cargo().get_program(), "cargo");
+        assert!(cargo_command("build", temp_dir.path(), &temp_dir.path().join(TARGET_DIR_NAME))
+            .get_envs()
+            .any(|(key, value)| key == "CARGO_NET_OFFLINE" && value == Some("true".as_ref())));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_missing_toolchain_is_retried_on_fallback() {
+        use std::cell::Cell;
+        use std::os::unix::fs::PermissionsExt;
+
+        // A rustup proxy stub: only the stable toolchain is installed
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let log = temp_dir.path().join("argv.log");
+        let stub = temp_dir.path().join("cargo");
+        std::fs::write(
+            &stub,
+            format!(
+                "#!/bin/sh\necho \"$*\" >> '{}'\n\
+                 case \"$1\" in +stable) exit 0;; esac\n\
+                 case \"$*\" in *fine*) exit 0;; *broken*) echo 'error: could not find `Cargo.toml`' >&2; exit 101;; esac\n\
+                 echo \"error: toolchain 'nightly-2020-01-01-x86_64-unknown-linux-gnu' is not installed\" >&2\nexit 1\n",
+                log.display()
+            ),
+        ).unwrap();
+        std::fs::set_permissions(&stub, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let runs = |subcommand: &str, fallback: &str| {
+            std::fs::write(&log, "").unwrap();
+            let prepared = Cell::new(0);
+            let mut cmd = cargo_at(&stub, true);
+            cmd.arg(subcommand).current_dir(temp_dir.path());
+            let (output, toolchain) = run_cargo_with(&mut cmd, fallback, |cmd| {
+                prepared.set(prepared.get() + 1);
+                cmd
+            })
+            .unwrap();
+            let lines: Vec<String> = std::fs::read_to_string(&log).unwrap().lines().map(String::from).collect();
+            assert_eq!(prepared.get(), lines.len());
+            (output.status.success(), toolchain.map(String::from), lines)
+        };
+
+        // The pinned toolchain is missing, so the same command runs again on stable
+        let (success, toolchain, lines) = runs("clean", "stable");
+        assert!(success);
+        assert_eq!(toolchain.as_deref(), Some("stable"));
+        assert_eq!(lines, vec!["--offline clean", "+stable --offline clean"]);
+
+        // Retried once only, even when the fallback is missing too
+        let (success, toolchain, lines) = runs("clean", "beta");
+        assert!(!success);
+        assert_eq!(toolchain.as_deref(), Some("beta"));
+        assert_eq!(lines.len(), 2);
+
+        // Other failures and successes aren't retried
+        for subcommand in ["broken", "fine"] {
+            let (success, toolchain, lines) = runs(subcommand, "stable");
+            assert_eq!(success, subcommand == "fine");
+            assert_eq!(toolchain, None);
+            assert_eq!(lines.len(), 1);
+        }
+
+        // The retry keeps the environment and working directory
+        let mut cmd = cargo_at(&stub, true);
+        cmd.arg("clean").current_dir(temp_dir.path());
+        let retry = on_toolchain(&cmd, "stable");
+        assert_eq!(command_line(&retry), format!("{} +stable --offline clean", stub.display()));
+        assert_eq!(retry.get_current_dir(), Some(temp_dir.path()));
+        assert!(retry.get_envs().any(|(key, _)| key == "CARGO_NET_OFFLINE"));
+    }
+
+    #[test]
+    fn test_run_command_captures_output() {
+        let output = run_command(Command::new("cargo").arg("--version")).unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).starts_with("cargo"));
+    }
+
+    #[test]
+    fn test_validate_directory() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        assert!(validate_directory(temp_dir.path()).is_ok());
+
+        let missing = validate_directory(&temp_dir.path().join("missing")).unwrap_err();
+        assert!(missing.to_string().contains("does not exist"));
+
+        let file = temp_dir.path().join("Cargo.toml");
+        std::fs::write(&file, "").unwrap();
+        let not_dir = validate_directory(&file).unwrap_err();
+        assert!(not_dir.to_string().contains("expected a directory"));
+    }
+
+    #[test]
+    fn test_readable_usage() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        std::fs::create_dir_all(target.join("debug/deps")).unwrap();
+        std::fs::create_dir_all(target.join("debug/build/empty")).unwrap();
+        std::fs::write(target.join("CACHEDIR.TAG"), "tag").unwrap();
+        for i in 0..5 {
+            std::fs::write(target.join(format!("debug/deps/lib{}.rlib", i)), "rlib").unwrap();
+        }
+        let usage = readable_usage(&target);
+        assert_eq!(usage, DirUsage { bytes: 23, files: 6, dirs: 5 });
+        assert_eq!(readable_size(&target), usage.bytes);
+        assert_eq!(readable_usage(&temp_dir.path().join("missing")), DirUsage::default());
+    }
+
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(0), "0");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1000), "1,000");
+        assert_eq!(format_count(412_331), "412,331");
+        assert_eq!(format_count(12_345_678), "12,345,678");
+    }
+
     #[test]
     fn test_get_directory_size_nonexistent() {
         let size = get_directory_size(Path::new("/nonexistent/path"));
         assert!(size.is_ok());
         assert_eq!(size.unwrap(), 0);
     }
-}
 
+    #[test]
+    fn test_verbatim_path() {
+        assert_eq!(verbatim_path(r"C:\code\app\target").as_deref(), Some(r"\\?\C:\code\app\target"));
+        assert_eq!(verbatim_path(r"\\server\share\app\target").as_deref(), Some(r"\\?\UNC\server\share\app\target"));
+        // Already verbatim, or a device path
+        assert_eq!(verbatim_path(r"\\?\C:\code\app\target"), None);
+        assert_eq!(verbatim_path(r"\\.\pipe\cargo"), None);
+        // Relative paths can't be made verbatim
+        assert_eq!(verbatim_path(r"app\target"), None);
+        assert_eq!(verbatim_path(r"C:app\target"), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_long_paths_are_measured_and_removed() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        // Well past MAX_PATH, made by its long path since plain APIs can't
+        let deep = (0..30).fold(target.clone(), |dir, i| dir.join(format!("dependency-{:02}", i)));
+        std::fs::create_dir_all(long_path(&deep)).unwrap();
+        std::fs::write(long_path(&deep.join("lib.rlib")), b"artifact").unwrap();
+
+        assert_eq!(get_directory_size(&target).unwrap(), 8);
+        crate::removal::remove_tree(&target, 8).unwrap();
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn test_expand_directory_glob() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repos = temp_dir.path().canonicalize().unwrap().join("repos");
+        for dir in ["a/backend", "b/backend", "c/frontend"] {
+            std::fs::create_dir_all(repos.join(dir)).unwrap();
+        }
+        std::fs::create_dir_all(repos.join("d")).unwrap();
+        std::fs::write(repos.join("d/backend"), "not a directory").unwrap();
+
+        let pattern = repos.join("*/backend");
+        assert!(is_glob(&pattern));
+        assert!(!is_glob(&repos));
+        let roots = expand_directory_glob(&pattern).unwrap();
+        assert_eq!(roots, vec![repos.join("a/backend"), repos.join("b/backend")]);
+        assert_eq!(common_ancestor(&roots), repos);
+
+        let error = expand_directory_glob(&repos.join("*/nothing")).unwrap_err();
+        assert!(error.to_string().contains("No directories match"));
+
+        let frontend = repos.join("c/frontend");
+        let roots = resolve_roots(&[&pattern, &frontend, &repos.join("a/backend")]).unwrap();
+        assert_eq!(roots.len(), 3);
+        assert!(resolve_roots(&[&repos.join("missing")]).is_err());
+    }
+
+    #[test]
+    fn test_common_ancestor() {
+        let paths = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+        assert_eq!(common_ancestor(&paths(&["/a/b/c", "/a/b/d", "/a/b"])), PathBuf::from("/a/b"));
+        assert_eq!(common_ancestor(&paths(&["/a/bc", "/a/b"])), PathBuf::from("/a"));
+        assert_eq!(common_ancestor(&paths(&["/x"])), PathBuf::from("/x"));
+        assert_eq!(common_ancestor(&[]), PathBuf::new());
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::{Context, Result};
+use crate::interrupt::shield_from_interrupt;
+use crate::toolchain::{artifact_sets_by_package, remove_artifact_set, ArtifactSet};
+use crate::utils::{cargo_command, run_cargo};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// Artifacts of a package that is no longer in the lockfile
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct OrphanedPackage {
+    pub name: String,
+    /// Number of artifact sets (fingerprint hashes) removed
+    pub sets: usize,
+    pub bytes: u64,
+}
+
+/// Package and crate names can differ only in dashes vs underscores
+fn normalize(name: &str) -> String {
+    name.replace('-', "_")
+}
+
+/// Names of every package in a `Cargo.lock`, normalized to crate names
+pub fn lockfile_packages(lockfile: &Path) -> Result<HashSet<String>> {
+    let content = fs::read_to_string(lockfile).with_context(|| format!("Failed to read {:?}", lockfile))?;
+    let lock: toml::Value = toml::from_str(&content).with_context(|| format!("Failed to parse {:?}", lockfile))?;
+
+    Ok(lock
+        .get("package")
+        .and_then(|p| p.as_array())
+        .map(|packages| {
+            packages
+                .iter()
+                .filter_map(|p| p.get("name")?.as_str())
+                .map(normalize)
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Group the artifact sets of packages missing from `expected` by package name
+pub fn find_orphaned_sets(target_dir: &Path, expected: &HashSet<String>) -> BTreeMap<String, Vec<ArtifactSet>> {
+    artifact_sets_by_package(target_dir, |name| !expected.contains(&normalize(name)))
+}
+
+/// Remove the artifacts of packages that no longer appear in the lockfile
+///
+/// `cargo clean -p` is tried first; it refuses packages missing from the
+/// lockfile, in which case the artifact sets are removed directly. Returns
+/// `None` when the project has no `Cargo.lock`.
+pub fn vacuum_project(project_dir: &Path, target_dir: &Path, dry_run: bool) -> Result<Option<Vec<OrphanedPackage>>> {
+    let lockfile = project_dir.join("Cargo.lock");
+    if !lockfile.exists() {
+        return Ok(None);
+    }
+    let expected = lockfile_packages(&lockfile)?;
+
+    let mut purged = Vec::new();
+    for (name, sets) in find_orphaned_sets(target_dir, &expected) {
+        let mut bytes = 0;
+        for set in &sets {
+            bytes += remove_artifact_set(set, true)?;
+        }
+
+        if !dry_run {
+            let mut cmd = cargo_command("clean", project_dir, target_dir);
+            cmd.args(["--frozen", "-p", &name]);
+            let _ = run_cargo(cmd, shield_from_interrupt);
+            for set in &sets {
+                remove_artifact_set(set, false)
+                    .with_context(|| format!("Failed to remove orphaned artifacts {:?} in {:?}", set.stem, set.profile_dir))?;
+            }
+        }
+
+        purged.push(OrphanedPackage {
+            name,
+            sets: sets.len(),
+            bytes,
+        });
+    }
+
+    Ok(Some(purged))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const LOCKFILE: &str = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["serde-json"]
+
+[[package]]
+name = "serde-json"
+version = "1.0.120"
+"#;
+
+    fn write_set(profile: &Path, name: &str, hash: &str) {
+        let crate_name = normalize(name);
+        let fingerprint = profile.join(".fingerprint").join(format!("{}-{}", name, hash));
+        fs::create_dir_all(&fingerprint).unwrap();
+        fs::create_dir_all(profile.join("deps")).unwrap();
+        fs::write(fingerprint.join(format!("lib-{}", crate_name)), "0").unwrap();
+        fs::write(
+            profile.join("deps").join(format!("lib{}-{}.rlib", crate_name, hash)),
+            vec![0u8; 100],
+        )
+        .unwrap();
+    }
+
+    fn fixture() -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("Cargo.lock"), LOCKFILE).unwrap();
+        let debug = temp_dir.path().join("target/debug");
+        write_set(&debug, "app", "1111111111111111");
+        write_set(&debug, "serde-json", "2222222222222222");
+        write_set(&debug, "old-dep", "3333333333333333");
+        write_set(&debug, "old-dep", "4444444444444444");
+        temp_dir
+    }
+
+    #[test]
+    fn test_lockfile_packages() {
+        let temp_dir = fixture();
+        let packages = lockfile_packages(&temp_dir.path().join("Cargo.lock")).unwrap();
+        assert_eq!(packages.len(), 2);
+        assert!(packages.contains("serde_json"));
+    }
+
+    #[test]
+    fn test_orphaned_packages_are_purged() {
+        let temp_dir = fixture();
+        let debug = temp_dir.path().join("target/debug");
+
+        let purged = vacuum_project(temp_dir.path(), &temp_dir.path().join("target"), true).unwrap().unwrap();
+        assert_eq!(purged.len(), 1);
+        assert_eq!(purged[0].name, "old-dep");
+        assert_eq!(purged[0].sets, 2);
+        assert!(purged[0].bytes >= 200);
+        assert!(debug.join("deps/libold_dep-3333333333333333.rlib").exists());
+
+        vacuum_project(temp_dir.path(), &temp_dir.path().join("target"), false).unwrap();
+        assert!(!debug.join("deps/libold_dep-3333333333333333.rlib").exists());
+        assert!(!debug.join(".fingerprint/old-dep-4444444444444444").exists());
+        assert!(debug.join("deps/libserde_json-2222222222222222.rlib").exists());
+        assert!(debug.join("deps/libapp-1111111111111111.rlib").exists());
+    }
+
+    #[test]
+    fn test_missing_lockfile_is_skipped() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(vacuum_project(temp_dir.path(), &temp_dir.path().join("target"), false).unwrap().is_none());
+    }
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use common::{temp_root, write_project};
+use std::path::Path;
+use std::process::Output;
+
+/// Run with a UTF-8 locale, so only `--ascii` keeps the output plain
+fn deepclean(root: &Path, args: &[&str]) -> Output {
+    let output = common::deepclean(root)
+        .args(args)
+        .arg(root)
+        .env("LC_ALL", "C.UTF-8")
+        .env("CLICOLOR_FORCE", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+fn assert_ascii(output: &Output) {
+    for (stream, bytes) in [("stdout", &output.stdout), ("stderr", &output.stderr)] {
+        if let Some(position) = bytes.iter().position(|b| !b.is_ascii()) {
+            let text = String::from_utf8_lossy(bytes);
+            panic!("non-ASCII byte in {} at {}:\n{}", stream, position, text);
+        }
+    }
+}
+
+#[test]
+fn test_ascii_output_has_no_bytes_above_0x7f() {
+    let (_temp_dir, root) = temp_root("ascii");
+    write_project(&root.join("alpha"), "alpha", 1000);
+    write_project(&root.join("nested/beta"), "beta", 2_000_000);
+
+    let text = ["--ascii", "--no-auto-root", "--no-cargo"];
+    assert_ascii(&deepclean(&root, &["list", "--tree", "--ascii"]));
+    assert_ascii(&deepclean(&root, &[&text[..], &["--dry-run", "--verbose", "--timings"]].concat()));
+    assert_ascii(&deepclean(&root, &[&text[..], &["--timings"]].concat()));
+    assert!(!root.join("nested/beta/target").exists());
+}
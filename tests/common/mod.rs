@@ -0,0 +1,65 @@
+//! Fixtures shared by the integration tests
+//!
+//! Each test crate compiles its own copy and uses only some of these.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+/// A scan root in a fresh temp directory, canonicalized so it matches the
+/// paths in reports
+///
+/// Not tempfile's default `.tmp` name, since discovery passes over hidden
+/// directories.
+pub fn temp_root(prefix: &str) -> (TempDir, PathBuf) {
+    let temp_dir = tempfile::Builder::new().prefix(prefix).tempdir().unwrap();
+    let root = temp_dir.path().canonicalize().unwrap();
+    (temp_dir, root)
+}
+
+/// A library package with `target_bytes` of build output in its target
+pub fn write_project(dir: &Path, name: &str, target_bytes: usize) {
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n", name),
+    )
+    .unwrap();
+    std::fs::write(dir.join("src/lib.rs"), "").unwrap();
+    std::fs::create_dir_all(dir.join("target/debug/deps")).unwrap();
+    std::fs::write(dir.join("target/debug/deps/lib.rlib"), vec![0u8; target_bytes]).unwrap();
+}
+
+/// The binary under test, confined to `root`
+///
+/// Callers add their flags and then the directory to scan.
+pub fn deepclean(root: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_cargo-deepclean"));
+    command
+        // Whatever the run keeps under the home directory stays in the fixture
+        .env("HOME", root)
+        .env("RUST_BACKTRACE", "0");
+    command
+}
+
+/// An executable shell script at `path`
+#[cfg(unix)]
+pub fn write_script(path: &Path, body: &str) {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::write(path, format!("#!/bin/sh\n{}", body)).unwrap();
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+/// A cargo, to pass with `--cargo` or `$CARGO`, that answers the startup
+/// check and fails everything else
+#[cfg(unix)]
+pub fn failing_cargo(dir: &Path) -> PathBuf {
+    let stub = dir.join("cargo-stub");
+    write_script(
+        &stub,
+        "for arg in \"$@\"; do [ \"$arg\" = --version ] && exit 0; done\necho 'error: broken' >&2\nexit 101\n",
+    );
+    stub
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::{temp_root, write_project};
+use std::path::Path;
+use std::process::Output;
+
+fn deepclean(root: &Path, args: &[&str]) -> Output {
+    common::deepclean(root)
+        .args(["--errors-only", "--no-auto-root"])
+        .args(args)
+        .arg(root)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_errors_only_is_silent_when_all_goes_well() {
+    let (_temp_dir, root) = temp_root("errors-only");
+    write_project(&root.join("alpha"), "alpha", 1000);
+    let report = root.join("report.json");
+
+    let output = deepclean(&root, &["--no-cargo", "--output", report.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert!(!root.join("alpha/target").exists());
+
+    // The full report still lands on disk
+    let summary: serde_json::Value = serde_json::from_slice(&std::fs::read(&report).unwrap()).unwrap();
+    assert_eq!(summary["cleaned"], 1);
+    assert_eq!(summary["total_freed_bytes"], 1000);
+}
+
+#[test]
+fn test_errors_only_with_nothing_found() {
+    let (_temp_dir, root) = temp_root("errors-only");
+
+    let output = deepclean(&root, &["--no-cargo"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+
+    let output = deepclean(&root, &["--no-cargo", "--fail-if-empty"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No Cargo projects found"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_errors_only_reports_failures_on_stderr() {
+    let (_temp_dir, root) = temp_root("errors-only");
+    write_project(&root.join("alpha"), "alpha", 1000);
+    let stub = common::failing_cargo(&root);
+    let report = root.join("report.json");
+
+    // `cargo clean -p` has no direct-removal fallback, so the project fails
+    let output = deepclean(
+        &root,
+        &["--cargo", stub.to_str().unwrap(), "--package", "alpha", "--output", report.to_str().unwrap()],
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Failed to clean") && stderr.contains("alpha"), "{}", stderr);
+
+    let summary: serde_json::Value = serde_json::from_slice(&std::fs::read(&report).unwrap()).unwrap();
+    assert_eq!(summary["failed"], 1);
+}
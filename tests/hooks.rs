@@ -0,0 +1,101 @@
+// Hooks are shell scripts here
+#![cfg(unix)]
+
+mod common;
+
+use common::{temp_root, write_project, write_script};
+use std::path::Path;
+
+fn clean_with_hooks(root: &Path, args: &[&str]) -> serde_json::Value {
+    let output = common::deepclean(root)
+        .args(["--json", "--no-auto-root", "--no-cargo"])
+        .args(args)
+        .arg(root)
+        .output()
+        .unwrap();
+    serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("no report ({}): {}", e, String::from_utf8_lossy(&output.stderr)))
+}
+
+fn result_for<'a>(summary: &'a serde_json::Value, project: &Path) -> &'a serde_json::Value {
+    summary["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|r| r["path"] == project.to_string_lossy().as_ref())
+        .unwrap_or_else(|| panic!("no result for {:?}", project))
+}
+
+#[test]
+fn test_rejecting_pre_hook_skips_the_project() {
+    let (_temp_dir, root) = temp_root("hooks");
+    write_project(&root.join("alpha"), "alpha", 1000);
+    write_project(&root.join("beta"), "beta", 1000);
+    let markers = root.join("markers");
+    std::fs::create_dir(&markers).unwrap();
+    let hook = root.join("pre-hook.sh");
+    write_script(
+        &hook,
+        &format!(
+            "name=$(basename \"$RCLEAN_PROJECT_PATH\")\necho \"$RCLEAN_TARGET_PATH\" > {}/$name.pre\n[ \"$name\" != beta ]\n",
+            markers.display()
+        ),
+    );
+
+    let summary = clean_with_hooks(&root, &["--pre-hook", hook.to_str().unwrap()]);
+    let alpha = result_for(&summary, &root.join("alpha"));
+    assert_eq!(alpha["success"], true);
+    assert!(alpha["skip_reason"].is_null());
+    assert!(!root.join("alpha/target").exists());
+
+    let beta = result_for(&summary, &root.join("beta"));
+    assert_eq!(beta["skip_reason"], "pre-hook rejected");
+    assert!(root.join("beta/target").exists());
+
+    // The hook ran for both, and saw where each target is
+    let seen = std::fs::read_to_string(markers.join("beta.pre")).unwrap();
+    assert_eq!(seen.trim(), root.join("beta/target").to_string_lossy());
+    assert!(markers.join("alpha.pre").exists());
+}
+
+#[test]
+fn test_failing_post_hook_only_warns() {
+    let (_temp_dir, root) = temp_root("hooks");
+    write_project(&root.join("alpha"), "alpha", 1000);
+    let marker = root.join("post-ran");
+    let hook = root.join("post-hook.sh");
+    write_script(&hook, &format!("echo \"$RCLEAN_FREED_BYTES\" > {}\nexit 2\n", marker.display()));
+
+    let summary = clean_with_hooks(&root, &["--post-hook", hook.to_str().unwrap()]);
+    let alpha = result_for(&summary, &root.join("alpha"));
+    assert_eq!(alpha["success"], true);
+    assert_eq!(alpha["freed_bytes"], 1000);
+    let warnings = alpha["warnings"].as_array().unwrap();
+    assert!(warnings.iter().any(|w| w.as_str().unwrap().contains("post-hook exited with")), "{:?}", warnings);
+    assert_eq!(summary["failed"], 0);
+    assert_eq!(std::fs::read_to_string(&marker).unwrap().trim(), "1000");
+}
+
+#[test]
+fn test_hooks_run_on_the_worker_threads() {
+    let (_temp_dir, root) = temp_root("hooks");
+    write_project(&root.join("alpha"), "alpha", 1000);
+    write_project(&root.join("beta"), "beta", 1000);
+    let markers = root.join("markers");
+    std::fs::create_dir(&markers).unwrap();
+    // Each hook waits until both have started, so hooks run one after the
+    // other would time out and reject their project
+    let hook = root.join("pre-hook.sh");
+    write_script(
+        &hook,
+        &format!(
+            "touch {dir}/$(basename \"$RCLEAN_PROJECT_PATH\")\n\
+             for _ in $(seq 100); do [ $(ls {dir} | wc -l) -ge 2 ] && exit 0; sleep 0.1; done\n\
+             exit 1\n",
+            dir = markers.display()
+        ),
+    );
+
+    let summary = clean_with_hooks(&root, &["--jobs", "2", "--pre-hook", hook.to_str().unwrap()]);
+    assert_eq!(summary["cleaned"], 2, "{}", summary);
+}
@@ -0,0 +1,61 @@
+// The failing cargo stub is a shell script
+#![cfg(unix)]
+
+mod common;
+
+use common::{failing_cargo, temp_root, write_project};
+use std::path::Path;
+use std::process::Output;
+
+/// Exit code for a run stopped by `--max-failures` / `--fail-fast`
+const EXIT_ABORTED: i32 = 3;
+
+fn deepclean(root: &Path, cargo: &Path, args: &[&str]) -> Output {
+    common::deepclean(root)
+        // One job, so projects are dispatched strictly one after another
+        .args(["--json", "--no-auto-root", "--jobs", "1"])
+        .args(args)
+        .arg(root)
+        .env("CARGO", cargo)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_max_failures_stops_dispatch_and_exits_aborted() {
+    let (_temp_dir, root) = temp_root("max-failures");
+    for i in 0..5 {
+        write_project(&root.join(format!("app{}", i)), &format!("app{}", i), 1000);
+    }
+    let stub = failing_cargo(&root);
+
+    // `cargo clean -p` has no direct-removal fallback, so every project fails
+    let output = deepclean(&root, &stub, &["--package", "*", "--max-failures", "2"]);
+    assert_eq!(output.status.code(), Some(EXIT_ABORTED));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Aborted after 2 failed projects; 3 were not started"), "{}", stderr);
+
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(summary["failed"], 2);
+    assert_eq!(summary["aborted"], 3);
+    assert!(root.join("app4/target").exists());
+
+    // Without a limit every project is attempted and the run just fails
+    let output = deepclean(&root, &stub, &["--package", "*"]);
+    assert_eq!(output.status.code(), Some(1));
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(summary["failed"], 5);
+}
+
+#[test]
+fn test_fail_fast_aborts_on_first_failure() {
+    let (_temp_dir, root) = temp_root("fail-fast");
+    write_project(&root.join("alpha"), "alpha", 1000);
+    write_project(&root.join("beta"), "beta", 1000);
+    let stub = failing_cargo(&root);
+
+    let output = deepclean(&root, &stub, &["--package", "*", "--fail-fast"]);
+    assert_eq!(output.status.code(), Some(EXIT_ABORTED));
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!((summary["failed"].as_u64(), summary["aborted"].as_u64()), (Some(1), Some(1)));
+}
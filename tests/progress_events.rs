@@ -0,0 +1,45 @@
+mod common;
+
+use common::{temp_root, write_project};
+
+#[test]
+fn test_progress_json_events_on_stderr() {
+    let (_temp_dir, root) = temp_root("progress-events");
+    write_project(&root.join("alpha"), "alpha", 1000);
+    write_project(&root.join("beta"), "beta", 2000);
+
+    let output = common::deepclean(&root)
+        .args(["--dry-run", "--json", "--progress", "json", "--no-auto-root"])
+        .arg(&root)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // Stdout still carries just the report
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(summary["total_projects"], 2);
+
+    let events: Vec<serde_json::Value> = String::from_utf8(output.stderr)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("not an event: {:?} ({})", line, e)))
+        .collect();
+    let kinds: Vec<&str> = events.iter().map(|event| event["event"].as_str().unwrap()).collect();
+    assert_eq!(kinds.first(), Some(&"discovery"));
+    assert_eq!(kinds.last(), Some(&"done"));
+    assert_eq!(events[0]["projects"], 2);
+    assert!(events[0]["dirs"].as_u64().unwrap() >= 3);
+
+    for (name, freed) in [("alpha", 1000), ("beta", 2000)] {
+        let path = root.join(name).to_string_lossy().to_string();
+        let position = |kind: &str| {
+            events
+                .iter()
+                .position(|event| event["event"] == kind && event["path"] == path.as_str())
+                .unwrap_or_else(|| panic!("no {} event for {}", kind, name))
+        };
+        assert!(position("project_start") < position("project_done"));
+        assert_eq!(events[position("project_done")]["freed"], freed);
+    }
+    assert_eq!(kinds.len(), 6);
+}
@@ -0,0 +1,92 @@
+// Hooks are shell scripts here
+#![cfg(unix)]
+
+mod common;
+
+use common::{temp_root, write_project, write_script};
+use std::collections::HashSet;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+fn deepclean(root: &Path, scan_root: &Path, args: &[&str]) -> Command {
+    let mut command = common::deepclean(root);
+    command
+        // One job, so projects are cleaned strictly one after another
+        .args(["--json", "--no-auto-root", "--no-cargo", "--jobs", "1"])
+        .args(args)
+        .arg(scan_root)
+        .env("XDG_CACHE_HOME", root.join("cache"))
+        .env("XDG_RUNTIME_DIR", root);
+    command
+}
+
+#[test]
+fn test_resumed_run_never_cleans_a_project_twice() {
+    let (_temp_dir, root) = temp_root("resume");
+    let scan_root = root.join("code");
+    for i in 0..4 {
+        write_project(&scan_root.join(format!("app{}", i)), &format!("app{}", i), 1000);
+    }
+
+    // Every finished clean is logged by the post-hook
+    let cleaned_log = root.join("cleaned.log");
+    let post_hook = root.join("post-hook.sh");
+    write_script(&post_hook, &format!("basename \"$RCLEAN_PROJECT_PATH\" >> {}\n", cleaned_log.display()));
+
+    // The third project's pre-hook signals the test and hangs until the run is killed
+    let started_log = root.join("started.log");
+    let killed_here = root.join("killed-here");
+    let killing_hook = root.join("killing-hook.sh");
+    write_script(
+        &killing_hook,
+        &format!(
+            "echo x >> {log}\n[ $(wc -l < {log}) -eq 3 ] || exit 0\ntouch {marker}\nsleep 60\n",
+            log = started_log.display(),
+            marker = killed_here.display()
+        ),
+    );
+
+    let mut first = deepclean(&root, &scan_root, &["--pre-hook", killing_hook.to_str().unwrap(), "--post-hook", post_hook.to_str().unwrap()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while !killed_here.exists() {
+        assert!(Instant::now() < deadline, "the run never reached the third project");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    first.kill().unwrap();
+    first.wait().unwrap();
+    let cleaned_before: Vec<String> = std::fs::read_to_string(&cleaned_log).unwrap().lines().map(String::from).collect();
+    assert_eq!(cleaned_before, ["app0", "app1"]);
+
+    let output = deepclean(&root, &scan_root, &["--resume", "--post-hook", post_hook.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let skip_reasons: Vec<(String, serde_json::Value)> = summary["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| (r["name"].as_str().unwrap().to_string(), r["skip_reason"].clone()))
+        .collect();
+    assert_eq!(
+        skip_reasons,
+        [
+            ("app0".to_string(), serde_json::json!("previously cleaned")),
+            ("app1".to_string(), serde_json::json!("previously cleaned")),
+            ("app2".to_string(), serde_json::Value::Null),
+            ("app3".to_string(), serde_json::Value::Null),
+        ]
+    );
+
+    // Across both runs every project was cleaned exactly once
+    let cleaned: Vec<String> = std::fs::read_to_string(&cleaned_log).unwrap().lines().map(String::from).collect();
+    let unique: HashSet<&String> = cleaned.iter().collect();
+    assert_eq!(cleaned.len(), 4);
+    assert_eq!(unique.len(), cleaned.len());
+    assert!((0..4).all(|i| !scan_root.join(format!("app{}/target", i)).exists()));
+}
@@ -0,0 +1,73 @@
+// The slow pre-hook is a shell script
+#![cfg(unix)]
+
+mod common;
+
+use common::{temp_root, write_project, write_script};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Exit code when another run holds the lock for the same root
+const EXIT_LOCKED: i32 = 75;
+
+fn deepclean(root: &Path, lock_dir: &Path) -> Command {
+    let mut command = common::deepclean(root);
+    command
+        .args(["--json", "--no-auto-root", "--no-cargo"])
+        .env("XDG_RUNTIME_DIR", lock_dir);
+    command
+}
+
+fn wait_for(path: &Path) {
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while !path.exists() {
+        assert!(Instant::now() < deadline, "{:?} never appeared", path);
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn test_second_process_backs_off() {
+    let (_temp_dir, root) = temp_root("run-lock");
+    let scan_root = root.join("code");
+    write_project(&scan_root.join("alpha"), "alpha", 1000);
+    let lock_dir = root.join("locks");
+    std::fs::create_dir(&lock_dir).unwrap();
+
+    // Holds the first run inside its clean, and so its lock, until released
+    let started = root.join("started");
+    let release = root.join("release");
+    let hook = root.join("pre-hook.sh");
+    write_script(
+        &hook,
+        &format!(
+            "touch {}\nfor _ in $(seq 300); do [ -e {} ] && exit 0; sleep 0.1; done\nexit 1\n",
+            started.display(),
+            release.display()
+        ),
+    );
+    let first = deepclean(&root, &lock_dir)
+        .args(["--pre-hook", hook.to_str().unwrap()])
+        .arg(&scan_root)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    wait_for(&started);
+
+    let second = deepclean(&root, &lock_dir).arg(&scan_root).output().unwrap();
+    std::fs::write(&release, "").unwrap();
+    let first_pid = first.id();
+    let first = first.wait_with_output().unwrap();
+
+    assert_eq!(second.status.code(), Some(EXIT_LOCKED));
+    let stderr = String::from_utf8_lossy(&second.stderr);
+    assert!(stderr.contains(&format!("PID {}", first_pid)), "{}", stderr);
+    assert!(first.status.success(), "{}", String::from_utf8_lossy(&first.stderr));
+    assert!(!scan_root.join("alpha/target").exists());
+
+    // Once the first run is done the root is free again
+    let third = deepclean(&root, &lock_dir).arg(&scan_root).output().unwrap();
+    assert!(third.status.success(), "{}", String::from_utf8_lossy(&third.stderr));
+}